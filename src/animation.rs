@@ -0,0 +1,114 @@
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use x11rb::protocol::xproto::*;
+
+use crate::connections::Aux;
+use crate::timer::Timeout;
+use crate::utils::Rect;
+
+const FRAME: Duration = Duration::from_millis(16);
+
+struct Animation {
+    from: Rect,
+    to: Rect,
+    border: u16,
+    start: Instant,
+}
+
+#[derive(Default)]
+pub struct Animations {
+    pub enabled: bool,
+    pub duration: Duration,
+    active: RefCell<HashMap<Window, Animation>>,
+}
+
+impl Animations {
+    pub fn new() -> Self {
+        Self {
+            duration: Duration::from_millis(150),
+            ..Self::default()
+        }
+    }
+
+    pub fn forget(&self, win: Window) {
+        self.active.borrow_mut().remove(&win);
+    }
+}
+
+fn lerp(from: i32, to: i32, t: f32) -> i32 {
+    from + ((to - from) as f32 * t).round() as i32
+}
+
+impl Aux {
+    // from is the geometry last applied to the client, if any
+    pub fn configure_client(
+        &self,
+        win: Window,
+        from: Option<Rect>,
+        rect: &Rect,
+        border: u16,
+    ) -> Result<()> {
+        match from {
+            Some(from) if self.animations.enabled && from != *rect => {
+                let mut active = self.animations.active.borrow_mut();
+                // an animation already in flight continues from wherever it currently is
+                let from = active
+                    .remove(&win)
+                    .map(|anim| anim.at(Instant::now(), self.animations.duration))
+                    .unwrap_or(from);
+                active.insert(
+                    win,
+                    Animation {
+                        from,
+                        to: rect.clone(),
+                        border,
+                        start: Instant::now(),
+                    },
+                );
+                self.timers.set(Timeout::AnimationFrame, FRAME)?;
+            }
+            _ => {
+                self.animations.active.borrow_mut().remove(&win);
+                configure_window(&self.dpy, win, &rect.aux(border))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn animation_frame(&self) -> Result<()> {
+        let now = Instant::now();
+        let mut active = self.animations.active.borrow_mut();
+        for (win, anim) in active.iter() {
+            configure_window(
+                &self.dpy,
+                *win,
+                &anim.at(now, self.animations.duration).aux(anim.border),
+            )?;
+        }
+        active.retain(|_, anim| now < anim.start + self.animations.duration);
+        if !active.is_empty() {
+            self.timers.set(Timeout::AnimationFrame, FRAME)?;
+        }
+        Ok(())
+    }
+}
+
+impl Animation {
+    fn at(&self, now: Instant, duration: Duration) -> Rect {
+        let t = if duration.as_millis() == 0 {
+            1.0
+        } else {
+            (now.duration_since(self.start).as_secs_f32() / duration.as_secs_f32()).min(1.0)
+        };
+        // ease out
+        let t = 1.0 - (1.0 - t) * (1.0 - t);
+        Rect::new(
+            lerp(self.from.x as i32, self.to.x as i32, t) as i16,
+            lerp(self.from.y as i32, self.to.y as i32, t) as i16,
+            lerp(self.from.width as i32, self.to.width as i32, t) as u16,
+            lerp(self.from.height as i32, self.to.height as i32, t) as u16,
+        )
+    }
+}
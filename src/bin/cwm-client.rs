@@ -1,14 +1,22 @@
 use anyhow::{bail, Error, Result};
 use cwm::connections::{
-    ClientRequest, CwmResponse, HiddenSelection, Rule as Rule_, SetArg, Side as Side_, StackLayer,
-    Stream, TagSelection,
+    socket_path, ClientRequest, Corner as Corner_, CwmResponse, CycleFilter, FloatPlacement,
+    FocusIndicator, FrameAction, Geometry, Hello, HelloReply, HiddenSelection, HideStrategy,
+    Length, MatchSpec, Minimap, MotionKind, NodeSelector, OverflowPolicy, PointerBindings,
+    Rule as Rule_, SetArg, Side as Side_, Split, StackLayer, StateArg, Stream, Swipe as Swipe_,
+    TagSelection, TagState, TagSwitchMode, TagTemplate, TemplateSpawn, ThemeField, TreeNode,
+    PROTOCOL_VERSION,
 };
+use log::error;
 use nix::poll::{poll, PollFd, PollFlags};
+use serde::de::DeserializeOwned;
 use simplelog::*;
+use std::io::Write;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
 
-use struct_args::{parse_u32, Arg};
+use std::convert::TryFrom;
+use struct_args::{parse_u32, Amount, Arg, Usage};
 
 struct Monitor(Option<u32>);
 impl Arg for Monitor {
@@ -24,6 +32,24 @@ impl Arg for Monitor {
             },
         ))
     }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<mon|-f>")
+    }
+}
+
+// "WxH+X+Y", where any part can be a percentage of the monitor like "50%x50%+25%+25%"
+struct GeometryArg(Geometry);
+impl Arg for GeometryArg {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        Ok(Self(
+            args.pop()
+                .ok_or_else(|| Error::msg("geometry: No argument provided"))?
+                .parse()?,
+        ))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<w>x<h>+<x>+<y>")
+    }
 }
 
 struct Tag(TagSelection, bool);
@@ -51,6 +77,11 @@ impl Arg for Tag {
                 }
                 TagSelection::Name(name)
             }
+            "id" => TagSelection::Id(parse_u32(
+                &args
+                    .pop()
+                    .ok_or_else(|| Error::msg("tag: No argument provided"))?,
+            )?),
             "focused" | "f" => TagSelection::Focused(None),
             "next" => TagSelection::Next(None),
             "prev" => TagSelection::Prev(None),
@@ -59,6 +90,9 @@ impl Arg for Tag {
         };
         Ok(Self(tag, toggle))
     }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("[~]<tag>")
+    }
 }
 
 struct Side(Side_);
@@ -79,6 +113,58 @@ impl Arg for Side {
             },
         ))
     }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<left|right|top|bottom>")
+    }
+}
+
+struct SwitchMode(TagSwitchMode);
+impl Arg for SwitchMode {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        Ok(Self(
+            match args
+                .pop()
+                .ok_or_else(|| Error::msg("mode: No argument provided"))?
+                .as_str()
+            {
+                "swap" => TagSwitchMode::Swap,
+                "greedy" => TagSwitchMode::Greedy,
+                "noop" => TagSwitchMode::Noop,
+                s => bail!("invalid tag switch mode: {}", s),
+            },
+        ))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<swap|greedy|noop>")
+    }
+}
+
+struct Window(u32);
+impl Arg for Window {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        Ok(Self(parse_u32(
+            args.pop()
+                .ok_or_else(|| Error::msg("window: No argument provided"))?
+                .as_str(),
+        )?))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<window>")
+    }
+}
+
+struct State(StateArg);
+impl Arg for State {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        Ok(Self(
+            args.pop()
+                .ok_or_else(|| Error::msg("state: No argument provided"))?
+                .parse()?,
+        ))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<on|off|toggle>")
+    }
 }
 
 struct Node(Option<u32>);
@@ -95,26 +181,237 @@ impl Arg for Node {
             },
         ))
     }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<node|-f>")
+    }
+}
+
+// a node of the layout tree, @<index> is an index shown by the tree of the focused tag
+struct Subtree(NodeSelector);
+impl Arg for Subtree {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        let item = args
+            .pop()
+            .ok_or_else(|| Error::msg("subtree: No argument provided"))?;
+        Ok(Self(match item.as_str() {
+            "-f" => NodeSelector::Focused,
+            "sel" => NodeSelector::Selection,
+            item => match item.strip_prefix('@') {
+                Some(index) => NodeSelector::Index(TagSelection::Focused(None), index.parse()?),
+                None => NodeSelector::Window(parse_u32(item)?),
+            },
+        }))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<window|@index|-f|sel>")
+    }
+}
+
+struct Color(u32);
+impl Arg for Color {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        Ok(Self(parse_u32(
+            args.pop()
+                .ok_or_else(|| Error::msg("color: No argument provided"))?
+                .as_str(),
+        )?))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<color>")
+    }
+}
+
+// a value or "unset"
+struct Unset<T>(Option<T>);
+impl<T: Arg> Arg for Unset<T> {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        match args.last().map(String::as_str) {
+            Some("unset") => {
+                args.pop();
+                Ok(Self(None))
+            }
+            _ => Ok(Self(Some(T::parse_args(args)?))),
+        }
+    }
+    fn usage() -> Vec<Usage> {
+        T::usage()
+            .into_iter()
+            .map(|usage| Usage {
+                args: match usage.args.strip_suffix('>') {
+                    Some(args) => format!("{}|unset>", args),
+                    None => format!("<{}|unset>", usage.args),
+                },
+                ..usage
+            })
+            .collect()
+    }
 }
 
 mod node {
     use super::*;
+    use std::io::BufRead;
+
+    pub(super) struct Filter(CycleFilter);
+    impl Arg for Filter {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("filter: No argument provided"))?
+                    .as_str()
+                {
+                    "floating" => CycleFilter::Floating,
+                    "tiled" => CycleFilter::Tiled,
+                    "class" => CycleFilter::Class,
+                    s => bail!("invalid cycle filter: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<floating|tiled|class>")
+        }
+    }
+
+    pub(super) struct Motion(MotionKind);
+    impl Arg for Motion {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("motion: No argument provided"))?
+                    .as_str()
+                {
+                    "move" => MotionKind::Move,
+                    "resize" => MotionKind::Resize(false),
+                    "resize-proportional" => MotionKind::Resize(true),
+                    s => bail!("invalid motion: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<move|resize|resize-proportional>")
+        }
+    }
+
+    pub(super) struct Corner(Corner_);
+    impl Arg for Corner {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("corner: No argument provided"))?
+                    .as_str()
+                {
+                    "top-left" => Corner_::TopLeft,
+                    "top-right" => Corner_::TopRight,
+                    "bottom-left" => Corner_::BottomLeft,
+                    "bottom-right" => Corner_::BottomRight,
+                    s => bail!("invalid corner: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<top-left|top-right|bottom-left|bottom-right>")
+        }
+    }
+
     #[derive(Arg)]
     pub(super) enum Args {
+        /// set or toggle flags on a node
         Set(Node, NodeFlags),
+        /// set the stacking layer of a node
         #[struct_args_match(ND, "set-layer")]
         SetLayer(Node, Layer),
-        Kill(Node),
-        Close(Node),
+        /// move a node to the top of its layer without focusing it
+        Raise(Node),
+        /// move a node to the bottom of its layer
+        Lower(Node),
+        /// kill the client of a node, --force is needed for protected nodes
+        Kill(#[struct_args_match("--force")] bool, Node),
+        /// ask the client of a node to close, --force is needed for protected nodes
+        Close(#[struct_args_match("--force")] bool, Node),
+        /// move a node to another tag
         #[struct_args_match(ND, "move-tag")]
         MoveTag(Node, Tag),
-        Cycle,
+        /// move a node to another tag and focus it there
+        #[struct_args_match(ND, "follow-tag")]
+        FollowTag(Node, Tag),
+        /// focus the next node, only going through floating, tiled or same class nodes if given
+        Cycle(Option<Filter>),
+        /// focus the previous node, only going through floating, tiled or same class nodes if given
         #[struct_args_match(ND, "!cycle")]
-        CycleRev,
+        CycleRev(Option<Filter>),
+        /// focus the next node in the window group of the focused node
+        #[struct_args_match(ND, "cycle-group")]
+        CycleGroup,
+        /// focus the previous node in the window group of the focused node
+        #[struct_args_match(ND, "!cycle-group")]
+        CycleGroupRev,
+        /// focus the next window with the class of the focused node, on any tag
+        #[struct_args_match(ND, "cycle-class")]
+        CycleClass,
+        /// focus the previous window with the class of the focused node, on any tag
+        #[struct_args_match(ND, "!cycle-class")]
+        CycleClassRev,
+        /// move every node in the window group of a node to another tag
+        #[struct_args_match(ND, "move-group")]
+        MoveGroup(Node, Tag),
+        /// focus the nth most recently focused node of a tag, with ~ the node focused before it is
+        /// focused instead if it already has focus
+        #[struct_args_match(ND, "focus-recent")]
+        FocusRecent(Tag, usize),
+        /// focus the tiling neighbour of a node
         Select(Node, Side),
+        /// move a floating node or swap a tiled node with its neighbour
         Move(Node, Side, u16),
-        Resize(Node, Side, i16),
+        /// move or resize a node by each amount read from stdin, one per line, steps arriving
+        /// faster than a frame are applied together
+        Motion(Node, Side, Motion),
+        /// make a node floating and sticky, a fraction of the monitor width in size and kept in a
+        /// corner of the monitor as it changes
+        Pip(Node, Corner, f32),
+        /// flash the border of a node like the visual bell
+        Bell(Node),
+        /// grow or shrink a side of a node, --proportional keeps the size of the nodes beside it
+        /// and shrinks everything across the moved edge evenly
+        Resize(
+            #[struct_args_match("--proportional")] bool,
+            Node,
+            Side,
+            Amount,
+        ),
+        /// set where a node floats, --relative places it relative to the monitor of its tag or the
+        /// given one, percentages are always relative
+        Geometry(
+            #[struct_args_match("--relative")] bool,
+            Node,
+            GeometryArg,
+            Option<u32>,
+        ),
+        /// ignore the size hints of a node
         IgnoreSizeHints(Node),
+        /// unhide a hidden window and focus it
+        Restore(Window),
+        /// override the border width of a node, unset follows the theme again
+        #[struct_args_match(ND, "border-width")]
+        BorderWidth(Node, Unset<u16>),
+        /// override the border color of a node in both focus states, unset follows the theme again
+        #[struct_args_match(ND, "border-color")]
+        BorderColor(Node, Unset<Color>),
+        /// rotate a subtree clockwise
+        #[struct_args_match(ND, "rotate")]
+        RotateSubtree(Subtree),
+        /// rotate a subtree counter clockwise
+        #[struct_args_match(ND, "!rotate")]
+        RotateSubtreeRev(Subtree),
+        /// reset every split ratio in a subtree to half
+        Equalize(Subtree),
+        /// move a subtree next to another node of the same tag
+        #[struct_args_match(ND, "move-subtree")]
+        MoveSubtree(Subtree, Subtree),
+        /// keep the split ratio of a subtree fixed while other windows are resized or equalized
+        #[struct_args_match(ND, "lock-ratio")]
+        LockRatio(Subtree, bool),
     }
 
     impl Args {
@@ -133,75 +430,166 @@ mod node {
                     if let Some(args) = flags.sticky {
                         stream.send_value(&ClientRequest::SetSticky(node, args))?
                     }
+                    if let Some(args) = flags.aot {
+                        stream.send_value(&ClientRequest::SetAot(node, args))?
+                    }
+                    if let Some(args) = flags.passthrough {
+                        stream.send_value(&ClientRequest::SetPassthrough(node, args))?
+                    }
+                    if let Some(args) = flags.protected {
+                        stream.send_value(&ClientRequest::SetProtected(node, args))?
+                    }
+                    if let Some(args) = flags.inhibit_fullscreen {
+                        stream.send_value(&ClientRequest::SetInhibitFullscreen(node, args))?
+                    }
                     Ok(())
                 }
+                Self::Restore(Window(win)) => stream.send_value(&ClientRequest::ShowSpecific(win)),
                 Self::SetLayer(Node(node), Layer(layer, toggle)) => {
                     stream.send_value(&ClientRequest::SetLayer(node, SetArg(layer, toggle)))
                 }
-                Self::Kill(Node(node)) => {
-                    stream.send_value(&ClientRequest::CloseClient(node, true))
+                Self::Raise(Node(node)) => stream.send_value(&ClientRequest::Raise(node)),
+                Self::Lower(Node(node)) => stream.send_value(&ClientRequest::Lower(node)),
+                Self::Kill(force, Node(node)) => close(stream, node, true, force),
+                Self::Close(force, Node(node)) => close(stream, node, false, force),
+                Self::MoveTag(Node(node), Tag(tag, toggle)) => {
+                    stream.send_value(&ClientRequest::SetWindowTag(node, tag, toggle, false))
                 }
-                Self::Close(Node(node)) => {
-                    stream.send_value(&ClientRequest::CloseClient(node, false))
+                Self::FollowTag(Node(node), Tag(tag, toggle)) => {
+                    stream.send_value(&ClientRequest::SetWindowTag(node, tag, toggle, true))
                 }
-                Self::MoveTag(Node(node), Tag(tag, toggle)) => {
-                    stream.send_value(&ClientRequest::SetWindowTag(node, tag, toggle))
+                Self::Cycle(filter) => stream.send_value(&ClientRequest::CycleWindow(
+                    false,
+                    filter.map_or(CycleFilter::All, |Filter(filter)| filter),
+                )),
+                Self::CycleRev(filter) => stream.send_value(&ClientRequest::CycleWindow(
+                    true,
+                    filter.map_or(CycleFilter::All, |Filter(filter)| filter),
+                )),
+                Self::CycleGroup => stream.send_value(&ClientRequest::CycleGroup(false)),
+                Self::CycleGroupRev => stream.send_value(&ClientRequest::CycleGroup(true)),
+                Self::CycleClass => stream.send_value(&ClientRequest::CycleClass(false)),
+                Self::CycleClassRev => stream.send_value(&ClientRequest::CycleClass(true)),
+                Self::MoveGroup(Node(node), Tag(tag, _)) => {
+                    stream.send_value(&ClientRequest::MoveGroupToTag(node, tag))
+                }
+                Self::FocusRecent(Tag(tag, toggle), n) => {
+                    stream.send_value(&ClientRequest::FocusNthRecent(tag, n, toggle))
                 }
-                Self::Cycle => stream.send_value(&ClientRequest::CycleWindow(false)),
-                Self::CycleRev => stream.send_value(&ClientRequest::CycleWindow(true)),
                 Self::Select(Node(node), Side(side)) => {
                     stream.send_value(&ClientRequest::SelectNeighbour(node, side))
                 }
                 Self::Move(Node(node), Side(side), amt) => {
                     stream.send_value(&ClientRequest::MoveWindow(node, side, amt))
                 }
-                Self::Resize(Node(node), Side(side), amt) => {
-                    stream.send_value(&ClientRequest::ResizeWindow(node, side, amt))
+                Self::Bell(Node(node)) => stream.send_value(&ClientRequest::VisualBell(node)),
+                Self::Pip(Node(node), Corner(corner), scale) => {
+                    if !(scale > 0.0 && scale <= 1.0) {
+                        bail!("the scale is a fraction of the monitor width")
+                    }
+                    stream.send_value(&ClientRequest::Pip(node, corner, scale))
+                }
+                Self::Motion(Node(node), Side(side), Motion(kind)) => {
+                    stream.send_value(&ClientRequest::BeginMotion(node, side, kind))?;
+                    for line in std::io::stdin().lock().lines() {
+                        let amt = line?.trim().parse()?;
+                        stream.send_value(&ClientRequest::MotionStep(amt))?;
+                    }
+                    stream.send_value(&ClientRequest::EndMotion)
+                }
+                Self::Resize(proportional, Node(node), Side(side), Amount::Pixels(amt)) => {
+                    let amt =
+                        i16::try_from(amt).map_err(|_| Error::msg("resize: amount too large"))?;
+                    stream.send_value(&ClientRequest::ResizeWindow(node, side, amt, proportional))
+                }
+                Self::Resize(proportional, Node(node), Side(side), Amount::Percent(amt)) => stream
+                    .send_value(&ClientRequest::ResizeWindowPercent(
+                        node,
+                        side,
+                        amt,
+                        proportional,
+                    )),
+                Self::Geometry(relative, Node(node), GeometryArg(mut geometry), mon) => {
+                    geometry.relative = relative;
+                    stream.send_value(&ClientRequest::SetFloatingGeometry(node, geometry, mon))
                 }
                 Self::IgnoreSizeHints(Node(node)) => {
                     stream.send_value(&ClientRequest::IgnoreSizeHints(node))
                 }
+                Self::BorderWidth(Node(node), Unset(width)) => {
+                    stream.send_value(&ClientRequest::SetBorderWidth(node, width))
+                }
+                Self::BorderColor(Node(node), Unset(color)) => stream.send_value(
+                    &ClientRequest::SetBorderColor(node, color.map(|Color(color)| color)),
+                ),
+                Self::RotateSubtree(Subtree(node)) => {
+                    stream.send_value(&ClientRequest::RotateNode(node, false))
+                }
+                Self::RotateSubtreeRev(Subtree(node)) => {
+                    stream.send_value(&ClientRequest::RotateNode(node, true))
+                }
+                Self::Equalize(Subtree(node)) => {
+                    stream.send_value(&ClientRequest::EqualizeNode(node))
+                }
+                Self::MoveSubtree(Subtree(node), Subtree(target)) => {
+                    stream.send_value(&ClientRequest::MoveSubtree(node, target))
+                }
+                Self::LockRatio(Subtree(node), locked) => {
+                    stream.send_value(&ClientRequest::LockRatio(node, locked))
+                }
             }
         }
     }
 
+    // protected clients are left open and reported as an error
+    fn close(mut stream: ClientStream, node: Option<u32>, kill: bool, force: bool) -> Result<()> {
+        stream.send_value(&ClientRequest::CloseClient(node, kill, force))?;
+        match stream.get_value()?.1 {
+            CwmResponse::Ack(Ok(())) => Ok(()),
+            CwmResponse::Ack(Err(e)) => bail!(e),
+            _ => bail!("invalid response from server"),
+        }
+    }
+
     #[derive(Default)]
     pub struct NodeFlags {
-        hidden: Option<SetArg<bool>>,
-        floating: Option<SetArg<bool>>,
-        fullscreen: Option<SetArg<bool>>,
-        sticky: Option<SetArg<bool>>,
+        hidden: Option<StateArg>,
+        floating: Option<StateArg>,
+        fullscreen: Option<StateArg>,
+        sticky: Option<StateArg>,
+        aot: Option<StateArg>,
+        passthrough: Option<StateArg>,
+        protected: Option<StateArg>,
+        inhibit_fullscreen: Option<StateArg>,
     }
     impl Arg for NodeFlags {
         fn parse_args(args: &mut Vec<String>) -> Result<Self> {
             let mut flags = NodeFlags::default();
-            for mut item in args
+            let mut flag = args
                 .pop()
-                .ok_or_else(|| Error::msg("flags: No argument provided"))?
-                .as_str()
-                .split('.')
-            {
-                let mut toggle = false;
-                let mut set = true;
-                if let Some(item_) = item.strip_prefix('~') {
-                    toggle = true;
-                    item = item_;
-                }
-                if let Some(item_) = item.strip_prefix('!') {
-                    set = false;
-                    item = item_;
-                }
-                match item {
-                    "hidden" => flags.hidden = flags.hidden.or(Some(SetArg(set, toggle))),
-                    "floating" => flags.floating = flags.floating.or(Some(SetArg(set, toggle))),
-                    "fullscreen" => {
-                        flags.fullscreen = flags.fullscreen.or(Some(SetArg(set, toggle)))
-                    }
-                    "sticky" => flags.sticky = flags.sticky.or(Some(SetArg(set, toggle))),
+                .ok_or_else(|| Error::msg("flag: No argument provided"))?;
+            loop {
+                let State(state) = State::parse_args(args)?;
+                let field = match flag.as_str() {
+                    "hidden" => &mut flags.hidden,
+                    "floating" => &mut flags.floating,
+                    "fullscreen" => &mut flags.fullscreen,
+                    "sticky" => &mut flags.sticky,
+                    "aot" => &mut flags.aot,
+                    "passthrough" => &mut flags.passthrough,
+                    "protected" => &mut flags.protected,
+                    "inhibit-fullscreen" => &mut flags.inhibit_fullscreen,
                     arg => bail!("node set: unknown arg '{}'", arg),
-                }
+                };
+                field.get_or_insert(state);
+                flag = match args.pop() {
+                    Some(flag) => flag,
+                    None => return Ok(flags),
+                };
             }
-            Ok(flags)
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<flag> <on|off|toggle>...")
         }
     }
 
@@ -225,6 +613,9 @@ mod node {
             };
             Ok(Self(layer, toggle))
         }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("[~]<above|normal|below>")
+        }
     }
 }
 
@@ -232,10 +623,23 @@ mod tag {
     use super::*;
     #[derive(Arg)]
     pub(super) enum Args {
+        /// show hidden nodes of a tag, nth counts from 0 in the order of query hidden
         Show(Tag, Show),
+        /// set or toggle flags on a tag
         Set(Tag, TagFlags),
-        Add(String),
+        /// add a tag, --volatile removes it once its last node leaves while it is not shown
+        Add(#[struct_args_match("--volatile")] bool, String),
+        /// remove a tag
         Remove(Tag),
+        /// register a template for add-from
+        #[struct_args_match(ND, "add-template")]
+        AddTemplate(String, Template),
+        /// add a tag from a template and run its spawn commands
+        #[struct_args_match(ND, "add-from")]
+        AddFrom(String),
+        /// clear the urgency of every node of a tag or of all tags
+        #[struct_args_match(ND, "clear-urgent")]
+        ClearUrgent(TagOrAll),
     }
 
     impl Args {
@@ -247,33 +651,100 @@ mod tag {
                 Self::Set(Tag(tag, _), TagFlags(arg)) => {
                     stream.send_value(&ClientRequest::SetMonocle(tag, arg))
                 }
-                Self::Add(name) => stream.send_value(&ClientRequest::AddTag(name)),
+                Self::Add(volatile, name) => {
+                    stream.send_value(&ClientRequest::AddTag(name, volatile))
+                }
                 Self::Remove(Tag(tag, _)) => stream.send_value(&ClientRequest::RemoveTag(tag)),
+                Self::AddTemplate(name, Template(monocle, spawn)) => {
+                    stream.send_value(&ClientRequest::AddTemplate(TagTemplate {
+                        name,
+                        monocle,
+                        spawn,
+                    }))
+                }
+                Self::AddFrom(name) => stream.send_value(&ClientRequest::AddTagFromTemplate(name)),
+                Self::ClearUrgent(TagOrAll(tag)) => {
+                    stream.send_value(&ClientRequest::ClearUrgent(tag))
+                }
             }
         }
     }
 
-    pub struct TagFlags(SetArg<bool>);
-    impl Arg for TagFlags {
+    pub struct TagOrAll(Option<TagSelection>);
+    impl Arg for TagOrAll {
         fn parse_args(args: &mut Vec<String>) -> Result<Self> {
-            let item = args
-                .pop()
-                .ok_or_else(|| Error::msg("flags: No argument provided"))?;
-            let mut s = item.as_str();
-            let mut toggle = false;
-            let mut set = true;
-            if let Some(s_) = s.strip_prefix('~') {
-                toggle = true;
-                s = s_;
+            match args.pop() {
+                Some(item) if item == "all" => Ok(Self(None)),
+                Some(item) => {
+                    args.push(item);
+                    let Tag(tag, _) = Tag::parse_args(args)?;
+                    Ok(Self(Some(tag)))
+                }
+                None => bail!("tag: No argument provided"),
             }
-            if let Some(s_) = s.strip_prefix('!') {
-                set = false;
-                s = s_;
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<tag|all>")
+        }
+    }
+
+    // "monocle" and any number of "spawn <class> <command>", each optionally followed by
+    // "split <side> <ratio>" for where its window goes
+    pub struct Template(bool, Vec<TemplateSpawn>);
+    impl Arg for Template {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let mut template = Self(false, Vec::new());
+            while let Some(item) = args.pop() {
+                match item.as_str() {
+                    "monocle" => template.0 = true,
+                    "spawn" => template.1.push(TemplateSpawn {
+                        class: args
+                            .pop()
+                            .ok_or_else(|| Error::msg("spawn: No class provided"))?,
+                        command: args
+                            .pop()
+                            .ok_or_else(|| Error::msg("spawn: No command provided"))?,
+                        split: None,
+                    }),
+                    "split" => {
+                        let Side(side) = Side::parse_args(args)?;
+                        let ratio = args
+                            .pop()
+                            .ok_or_else(|| Error::msg("split: No ratio provided"))?
+                            .parse()?;
+                        template
+                            .1
+                            .last_mut()
+                            .ok_or_else(|| Error::msg("split: must follow a spawn"))?
+                            .split = Some((side, ratio));
+                    }
+                    _ => {
+                        args.push(item);
+                        break;
+                    }
+                }
             }
-            if s != "monocle" {
-                bail!("tag set: unknown arg '{}'", s);
+            Ok(template)
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("[monocle] [spawn <class> <command> [split <side> <ratio>]]...")
+        }
+    }
+
+    pub struct TagFlags(StateArg);
+    impl Arg for TagFlags {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let flag = args
+                .pop()
+                .ok_or_else(|| Error::msg("flag: No argument provided"))?;
+            if flag != "monocle" {
+                bail!("tag set: unknown arg '{}'", flag);
             }
-            Ok(Self(SetArg(set, toggle)))
+            let State(state) = State::parse_args(args)?;
+            Ok(Self(state))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("monocle <on|off|toggle>")
         }
     }
 
@@ -288,10 +759,19 @@ mod tag {
                 "first" => HiddenSelection::First,
                 "last" => HiddenSelection::Last,
                 "all" => HiddenSelection::All,
+                "nth" => HiddenSelection::Nth(
+                    args.pop()
+                        .ok_or_else(|| Error::msg("n: No argument provided"))?
+                        .parse()?,
+                ),
+                "window" => HiddenSelection::Window(Window::parse_args(args)?.0),
                 arg => bail!("hidden selction: unknown arg '{}'", arg),
             };
             Ok(Self(selection))
         }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<first|last|all|nth <n>|window <window>>")
+        }
     }
 }
 
@@ -299,16 +779,70 @@ mod monitor {
     use super::*;
     #[derive(Arg)]
     pub(super) enum Args {
+        /// show a tag on a monitor
         #[struct_args_match(ND, "set-tag")]
         SetTag(Monitor, Tag),
+        /// show a tag on a monitor with the given switch mode
+        #[struct_args_match(ND, "set-tag-with")]
+        SetTagWith(Monitor, Tag, SwitchMode),
+        /// hide a panel until the pointer reaches its edge of the monitor
+        #[struct_args_match(ND, "panel-auto-hide")]
+        PanelAutoHide(Window, bool),
+        /// use monitors with the given geometries instead of the ones randr reports
+        Fake(Rects),
+        /// go back to the monitors randr reports
+        #[struct_args_match(ND, "fake-clear")]
+        FakeClear,
+    }
+
+    // each remaining argument is one monitor, in pixels
+    pub(super) struct Rects(Vec<(i16, i16, u16, u16)>);
+    impl Arg for Rects {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let mut rects = Vec::new();
+            while let Some(arg) = args.pop() {
+                let geometry: Geometry = arg.parse()?;
+                match (geometry.width, geometry.height, geometry.x, geometry.y) {
+                    (
+                        Length::Pixels(width),
+                        Length::Pixels(height),
+                        Length::Pixels(x),
+                        Length::Pixels(y),
+                    ) if width > 0 && height > 0 => {
+                        rects.push((x as i16, y as i16, width as u16, height as u16))
+                    }
+                    _ => bail!(
+                        "monitors: expected a size and position in pixels, got '{}'",
+                        arg
+                    ),
+                }
+            }
+            if rects.is_empty() {
+                bail!("monitors: No argument provided")
+            }
+            Ok(Self(rects))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<w>x<h>+<x>+<y>...")
+        }
     }
 
     impl Args {
         pub(super) fn process(self, mut stream: ClientStream) -> Result<()> {
             match self {
                 Self::SetTag(Monitor(mon), Tag(tag, toggle)) => {
-                    stream.send_value(&ClientRequest::FocusTag(mon, tag, toggle))
+                    stream.send_value(&ClientRequest::FocusTag(mon, tag, toggle, None))
+                }
+                Self::SetTagWith(Monitor(mon), Tag(tag, toggle), SwitchMode(mode)) => {
+                    stream.send_value(&ClientRequest::FocusTag(mon, tag, toggle, Some(mode)))
+                }
+                Self::PanelAutoHide(Window(win), enable) => {
+                    stream.send_value(&ClientRequest::PanelAutoHide(win, enable))
+                }
+                Self::Fake(Rects(rects)) => {
+                    stream.send_value(&ClientRequest::SetFakeMonitors(rects))
                 }
+                Self::FakeClear => stream.send_value(&ClientRequest::ClearFakeMonitors),
             }
         }
     }
@@ -316,29 +850,86 @@ mod monitor {
 
 mod subscribe {
     use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const MIN_BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
     #[derive(Arg)]
     pub(super) enum Args {
-        Tags(Monitor),
-        Focused(Monitor),
+        /// print the state of the tags whenever it changes, --count adds the number of nodes as (n),
+        /// --hidden adds a [n] badge for hidden nodes and --click wraps each tag in a lemonbar click
+        /// area that shows it
+        Tags(
+            #[struct_args_match("--retry")] bool,
+            #[struct_args_match("--count")] bool,
+            #[struct_args_match("--hidden")] bool,
+            #[struct_args_match("--click")] bool,
+            Monitor,
+        ),
+        /// print the name of the focused window whenever it changes
+        Focused(#[struct_args_match("--retry")] bool, Monitor),
+        /// print "enter|leave <monitor> <window>" whenever a shown client changes fullscreen
+        Fullscreen(#[struct_args_match("--retry")] bool),
+        /// print "<monitor> <window>" whenever a tiled node is hidden for being too small
+        Collapsed(#[struct_args_match("--retry")] bool),
+        /// print "idle" after the idle timeout passes without input or focus changes and "active"
+        /// once either happens again
+        Idle(#[struct_args_match("--retry")] bool),
+        /// print "expired <window>" whenever a presel is cancelled by the presel timeout, the
+        /// window is - if the node is gone
+        Presel(#[struct_args_match("--retry")] bool),
+        /// print "<monitor> <window>:<w>x<h>+<x>+<y>..." with every shown window of a monitor
+        /// whenever one of them moves, resizes, appears or goes away
+        #[struct_args_match(ND, "layout-geometry")]
+        Layout(#[struct_args_match("--retry")] bool),
+        /// print the tags and the focused window in the input format of a status bar
+        Statusline(#[struct_args_match("--retry")] bool, BarFormat, Monitor),
+        /// print "<window> <atom> <value>" whenever a property of a client changes, the value is -
+        /// once the property is deleted
+        Property(#[struct_args_match("--retry")] bool, Window, String),
     }
 
     impl Args {
-        pub(super) fn process(self, mut stream: ClientStream) -> Result<()> {
-            match self {
-                Self::Tags(Monitor(mon)) => {
-                    let mon = if let Some(mon) = mon {
-                        mon
-                    } else {
-                        stream.send_value(&ClientRequest::FocusedMonitor)?;
-                        let (done, response) = stream.get_value()?;
-                        if done {
-                            bail!("server hung up")
-                        } else if let CwmResponse::FocusedMonitor(mon) = response {
-                            mon
-                        } else {
-                            bail!("invalid response from server")
-                        }
-                    };
+        pub(super) fn process(self, stream: ClientStream) -> Result<()> {
+            // --retry reconnects and resubscribes whenever the server goes away
+            let retry = match self {
+                Self::Tags(retry, ..)
+                | Self::Focused(retry, _)
+                | Self::Fullscreen(retry)
+                | Self::Collapsed(retry)
+                | Self::Idle(retry)
+                | Self::Presel(retry)
+                | Self::Layout(retry)
+                | Self::Statusline(retry, ..)
+                | Self::Property(retry, ..) => retry,
+            };
+            let mut stream = Some(stream);
+            let mut backoff = MIN_BACKOFF;
+            loop {
+                let res = match stream.take() {
+                    Some(stream) => self.subscribe(stream),
+                    None => ClientStream::new().and_then(|stream| {
+                        backoff = MIN_BACKOFF;
+                        self.subscribe(stream)
+                    }),
+                };
+                if !retry {
+                    return res;
+                }
+                if let Err(e) = res {
+                    error!("{}, retrying in {:?}", e, backoff);
+                }
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+
+        fn subscribe(&self, mut stream: ClientStream) -> Result<()> {
+            match *self {
+                Self::Tags(_, count, badge, click, Monitor(mon)) => {
+                    let mon = resolve_monitor(&mut stream, mon)?;
                     stream.send_value(&ClientRequest::TagState)?;
                     loop {
                         let (done, response) = stream.get_value()?;
@@ -346,7 +937,20 @@ mod subscribe {
                             println!(
                                 "{}",
                                 tags.iter()
-                                    .map(|tag| tag.format(mon, focused_mon))
+                                    .map(|tag| {
+                                        let mut text = tag.format(mon, focused_mon);
+                                        if count && tag.client_count > 0 {
+                                            text += &format!("({})", tag.client_count);
+                                        }
+                                        if badge && tag.hidden > 0 {
+                                            text += &format!("[{}]", tag.hidden);
+                                        }
+                                        if click {
+                                            clickable(&text, mon, tag.id)
+                                        } else {
+                                            text
+                                        }
+                                    })
                                     .reduce(|info, tag| info + "\t" + tag.as_str())
                                     .unwrap()
                             );
@@ -356,7 +960,7 @@ mod subscribe {
                         }
                     }
                 }
-                Self::Focused(Monitor(mon)) => {
+                Self::Focused(_, Monitor(mon)) => {
                     stream.send_value(&ClientRequest::MonitorFocus(mon))?;
                     loop {
                         let (done, response) = stream.get_value()?;
@@ -370,8 +974,298 @@ mod subscribe {
                         }
                     }
                 }
+                Self::Statusline(_, BarFormat(format), Monitor(mon)) => {
+                    let mon = resolve_monitor(&mut stream, mon)?;
+                    statusline(stream, format, mon)
+                }
+                Self::Collapsed(_) => {
+                    stream.send_value(&ClientRequest::SubscribeCollapsed)?;
+                    loop {
+                        let (done, response) = stream.get_value()?;
+                        if let CwmResponse::Collapsed(mon, win) = response {
+                            println!("{} {}", mon, win);
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                }
+                Self::Idle(_) => {
+                    stream.send_value(&ClientRequest::SubscribeIdle)?;
+                    loop {
+                        let (done, response) = stream.get_value()?;
+                        if let CwmResponse::Idle(idle) = response {
+                            println!("{}", if idle { "idle" } else { "active" });
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                }
+                Self::Presel(_) => {
+                    stream.send_value(&ClientRequest::SubscribePresel)?;
+                    loop {
+                        let (done, response) = stream.get_value()?;
+                        if let CwmResponse::PreselExpired(win) = response {
+                            match win {
+                                Some(win) => println!("expired {}", win),
+                                None => println!("expired -"),
+                            }
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                }
+                Self::Property(_, Window(win), ref atom) => {
+                    stream.send_value(&ClientRequest::WatchProperty(win, atom.clone()))?;
+                    loop {
+                        let (done, response) = stream.get_value()?;
+                        if let CwmResponse::Property(win, atom, value) = response {
+                            println!("{} {} {}", win, atom, value.as_deref().unwrap_or("-"));
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                }
+                Self::Layout(_) => {
+                    stream.send_value(&ClientRequest::SubscribeLayout)?;
+                    loop {
+                        let (done, response) = stream.get_value()?;
+                        if let CwmResponse::Layout(mon, windows) = response {
+                            let windows: String = windows
+                                .iter()
+                                .map(|(win, (x, y, w, h))| {
+                                    format!(" {}:{}x{}{:+}{:+}", win, w, h, x, y)
+                                })
+                                .collect();
+                            println!("{}{}", mon, windows);
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                }
+                Self::Fullscreen(_) => {
+                    stream.send_value(&ClientRequest::SubscribeFullscreen)?;
+                    loop {
+                        let (done, response) = stream.get_value()?;
+                        if let CwmResponse::Fullscreen(mon, win, entered) = response {
+                            let event = if entered { "enter" } else { "leave" };
+                            println!("{} {} {}", event, mon, win);
+                        }
+                        if done {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn resolve_monitor(stream: &mut ClientStream, mon: Option<u32>) -> Result<u32> {
+        if let Some(mon) = mon {
+            return Ok(mon);
+        }
+        stream.send_value(&ClientRequest::FocusedMonitor)?;
+        let (done, response) = stream.get_value()?;
+        if done {
+            bail!("server hung up")
+        } else if let CwmResponse::FocusedMonitor(mon) = response {
+            Ok(mon)
+        } else {
+            bail!("invalid response from server")
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    pub enum Format {
+        I3bar,
+        Lemonbar,
+        Waybar,
+    }
+
+    pub struct BarFormat(Format);
+    impl Arg for BarFormat {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            match args.pop().as_deref() {
+                Some("--format") => (),
+                _ => bail!("statusline: --format is required"),
+            }
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("format: No argument provided"))?
+                    .as_str()
+                {
+                    "i3bar" => Format::I3bar,
+                    "lemonbar" => Format::Lemonbar,
+                    "waybar" => Format::Waybar,
+                    s => bail!("invalid bar format: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("--format <i3bar|lemonbar|waybar>")
+        }
+    }
+
+    enum Update {
+        Tags(Vec<TagState>, u32),
+        Focused(Option<String>),
+        Closed(Result<()>),
+    }
+
+    // forwards the responses of a subscription until the server hangs up
+    fn forward(
+        mut stream: ClientStream,
+        tx: mpsc::Sender<Update>,
+        f: fn(CwmResponse) -> Option<Update>,
+    ) {
+        std::thread::spawn(move || loop {
+            let (done, update) = match stream.get_value() {
+                Ok((done, response)) => (done, f(response)),
+                Err(e) => (true, Some(Update::Closed(Err(e)))),
+            };
+            if let Some(update) = update {
+                if tx.send(update).is_err() {
+                    return;
+                }
+            }
+            if done {
+                tx.send(Update::Closed(Ok(()))).ok();
+                return;
+            }
+        });
+    }
+
+    // every subscription needs its own connection, so tags and focus are read on two threads
+    fn statusline(mut stream: ClientStream, format: Format, mon: u32) -> Result<()> {
+        let mut focus = ClientStream::new()?;
+        stream.send_value(&ClientRequest::TagState)?;
+        focus.send_value(&ClientRequest::MonitorFocus(Some(mon)))?;
+        let (tx, rx) = mpsc::channel();
+        forward(stream, tx.clone(), |response| match response {
+            CwmResponse::TagState(tags, focused_mon) => Some(Update::Tags(tags, focused_mon)),
+            _ => None,
+        });
+        forward(focus, tx, |response| match response {
+            CwmResponse::MonitorFocusedClient(name) => Some(Update::Focused(name)),
+            _ => None,
+        });
+        if let Format::I3bar = format {
+            println!("{{\"version\":1}}");
+            println!("[");
+        }
+        let mut tags = None;
+        let mut title = None;
+        for update in rx {
+            match update {
+                Update::Tags(tags_, focused_mon) => tags = Some((tags_, focused_mon)),
+                Update::Focused(title_) => title = title_,
+                Update::Closed(res) => return res,
+            }
+            if let Some((tags, focused_mon)) = &tags {
+                let line = match format {
+                    Format::I3bar => i3bar(tags, title.as_deref(), mon, *focused_mon),
+                    Format::Lemonbar => lemonbar(tags, title.as_deref(), mon, *focused_mon),
+                    Format::Waybar => waybar(tags, title.as_deref(), mon, *focused_mon),
+                };
+                println!("{}", line);
+                std::io::stdout().flush()?;
             }
         }
+        Ok(())
+    }
+
+    // a block per tag and one for the title, the instance of a tag block is its id
+    fn i3bar(tags: &[TagState], title: Option<&str>, mon: u32, focused_mon: u32) -> String {
+        let mut blocks: Vec<_> = tags
+            .iter()
+            .map(|tag| {
+                let color = match tag.format(mon, focused_mon).chars().next() {
+                    Some('!') => "#ff5555",
+                    Some('#') => "#ffffff",
+                    Some('+') | Some('%') | Some('-') => "#aaaaaa",
+                    Some(':') => "#888888",
+                    _ => "#555555",
+                };
+                serde_json::json!({
+                    "name": "tag",
+                    "instance": tag.id.to_string(),
+                    "full_text": tag.name,
+                    "color": color,
+                    "urgent": tag.urgent,
+                })
+            })
+            .collect();
+        if let Some(title) = title {
+            blocks.push(serde_json::json!({ "name": "title", "full_text": title }));
+        }
+        serde_json::Value::Array(blocks).to_string() + ","
+    }
+
+    // lemonbar prints the command when the area is clicked, the output is meant to be piped to sh
+    fn clickable(text: &str, mon: u32, tag: u32) -> String {
+        format!(
+            "%{{A:cwm-client mon set-tag {} id {}:}}{}%{{A}}",
+            mon, tag, text
+        )
+    }
+
+    // clicking a tag shows it on the monitor of the bar
+    fn lemonbar(tags: &[TagState], title: Option<&str>, mon: u32, focused_mon: u32) -> String {
+        let escape = |text: &str| text.replace('%', "%%");
+        let mut line = String::from("%{l}");
+        for tag in tags {
+            let format = tag.format(mon, focused_mon);
+            let (start, end) = match format.chars().next() {
+                Some('#') => ("%{R}", "%{R}"),
+                Some('!') => ("%{+u}", "%{-u}"),
+                _ => ("", ""),
+            };
+            let text = format!("{} {} {}", start, escape(&tag.name), end);
+            line += &clickable(&text, mon, tag.id);
+        }
+        if let Some(title) = title {
+            line += &format!("%{{c}}{}", escape(title));
+        }
+        line
+    }
+
+    // a custom module with return-type json, the tags are the text and the title the tooltip
+    fn waybar(tags: &[TagState], title: Option<&str>, mon: u32, focused_mon: u32) -> String {
+        let text = tags
+            .iter()
+            .filter(|tag| !tag.empty || tag.focused.is_some())
+            .map(|tag| {
+                // the text is pango markup
+                let name = tag
+                    .name
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                if tag.focused == Some(mon) {
+                    format!("<b>{}</b>", name)
+                } else {
+                    name
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let class = if tags.iter().any(|tag| tag.urgent) {
+            "urgent"
+        } else if mon == focused_mon {
+            "focused"
+        } else {
+            "unfocused"
+        };
+        serde_json::json!({
+            "text": text,
+            "tooltip": title.unwrap_or_default(),
+            "class": class,
+        })
+        .to_string()
     }
 }
 
@@ -379,12 +1273,48 @@ mod query {
     use super::*;
     #[derive(Arg)]
     pub(super) enum Args {
+        /// print the focused monitor, tag or node
         #[struct_args_match("-f")]
         Focused(FocusedArgs),
+        /// print the name of a monitor or tag
         Name(NameArgs),
+        /// print the stacking layers of a tag
         Layers(Tag),
+        /// print the stacking order of a tag
         Stack(Tag),
+        /// print the clients of a tag
         Clients(Tag),
+        /// check that the server is running
+        Ping,
+        /// write the icon of a window to stdout as a pam image
+        Icon(Window),
+        /// print the class to tag assignments
+        Assignments,
+        /// print the layout of the tag on a monitor, redrawn on every change with --watch
+        Tree(#[struct_args_match("--watch")] bool, Monitor),
+        /// print the last lines logged by the server
+        Log(usize),
+        /// dump the internal state of the server and check it for corruption
+        #[struct_args_match(ND, "debug-dump")]
+        DebugDump,
+        /// print the layers and x stacking order of every monitor
+        #[struct_args_match(ND, "monitor-layers")]
+        MonitorLayers,
+        /// print the hidden windows of a tag with their class, name and last geometry
+        Hidden(Tag),
+        /// print client counts, event and request rates and relayout timings
+        Metrics,
+        /// wait for a matching window to be managed and print its id, 0 ms waits forever
+        #[struct_args_match(ND, "wait-window")]
+        WaitWindow(Match, u32),
+        /// print the width of the built in system tray, 0 when it has no icons
+        Tray,
+        /// print the geometry of a node, --relative subtracts the position of its monitor
+        Geometry(#[struct_args_match("--relative")] bool, Node),
+        /// print "<flag> <true|false>" for each flag of a node and "layer <layer>"
+        Flags(Node),
+        /// print the size of a tag and its shown windows bottom to top, --svg draws it instead
+        Minimap(#[struct_args_match("--svg")] bool, Tag),
     }
 
     #[derive(Arg)]
@@ -435,6 +1365,339 @@ mod query {
         Ok(())
     }
 
+    fn tray(mut stream: ClientStream) -> Result<()> {
+        stream.send_value(&ClientRequest::TrayWidth)?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::TrayWidth(width) = response {
+            println!("{}", width);
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn geometry(mut stream: ClientStream, relative: bool, node: Option<u32>) -> Result<()> {
+        stream.send_value(&ClientRequest::QueryGeometry(node, relative))?;
+        let (_, response) = stream.get_value()?;
+        match response {
+            CwmResponse::Geometry(Some((x, y, width, height))) => {
+                println!("{}x{}{:+}{:+}", width, height, x, y)
+            }
+            CwmResponse::Geometry(None) => bail!("no such node"),
+            _ => bail!("invalid response from server"),
+        }
+        Ok(())
+    }
+
+    fn flags(mut stream: ClientStream, node: Option<u32>) -> Result<()> {
+        stream.send_value(&ClientRequest::GetFlags(node))?;
+        let (_, response) = stream.get_value()?;
+        match response {
+            CwmResponse::Flags(Some(flags)) => {
+                println!("floating {}", flags.floating);
+                println!("fullscreen {}", flags.fullscreen);
+                println!("sticky {}", flags.sticky);
+                println!("hidden {}", flags.hidden);
+                println!("urgent {}", flags.urgent);
+                let layer = match flags.layer {
+                    StackLayer::Below => "below",
+                    StackLayer::Normal => "normal",
+                    StackLayer::Above => "above",
+                };
+                println!("layer {}", layer);
+            }
+            CwmResponse::Flags(None) => bail!("no such node"),
+            _ => bail!("invalid response from server"),
+        }
+        Ok(())
+    }
+
+    fn minimap(mut stream: ClientStream, svg: bool, Tag(tag, _): Tag) -> Result<()> {
+        stream.send_value(&ClientRequest::RenderLayout(tag))?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::Minimap(minimap) = response {
+            if svg {
+                print!("{}", render_svg(&minimap));
+            } else {
+                println!("{}x{}", minimap.width, minimap.height);
+                for window in minimap.windows {
+                    let (x, y, width, height) = window.rect;
+                    let mut flags = Vec::new();
+                    if window.focused {
+                        flags.push("focused");
+                    }
+                    if window.floating {
+                        flags.push("floating");
+                    }
+                    if window.urgent {
+                        flags.push("urgent");
+                    }
+                    println!(
+                        "{}\t{}x{}{:+}{:+}\t{}",
+                        window.win,
+                        width,
+                        height,
+                        x,
+                        y,
+                        flags.join(" ")
+                    );
+                }
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    // the view box is the tag so the image scales to whatever size it is drawn at
+    fn render_svg(minimap: &Minimap) -> String {
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">\n",
+            minimap.width, minimap.height
+        );
+        out.push_str("<rect width=\"100%\" height=\"100%\" fill=\"#222222\"/>\n");
+        for window in &minimap.windows {
+            let (x, y, width, height) = window.rect;
+            let stroke = if window.focused {
+                "#5294e2"
+            } else if window.urgent {
+                "#e25252"
+            } else {
+                "#888888"
+            };
+            out.push_str(&format!(
+                "<rect id=\"0x{:x}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#444444\" stroke=\"{}\" stroke-width=\"8\"/>\n",
+                window.win, x, y, width, height, stroke
+            ));
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    fn hidden(mut stream: ClientStream, Tag(tag, _): Tag) -> Result<()> {
+        stream.send_value(&ClientRequest::ViewHidden(tag))?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::Hidden(clients) = response {
+            let show = |val: Option<String>| val.unwrap_or_else(|| "-".to_string());
+            for client in clients {
+                let geometry = client
+                    .geometry
+                    .map(|(x, y, width, height)| format!("{}x{}+{}+{}", width, height, x, y))
+                    .unwrap_or_else(|| "-".to_string());
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    client.win,
+                    geometry,
+                    show(client.class),
+                    show(client.name)
+                );
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn icon(mut stream: ClientStream, win: u32) -> Result<()> {
+        stream.send_value(&ClientRequest::ClientIcon(win))?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::ClientIcon(icon) = response {
+            let icon = icon.ok_or_else(|| Error::msg("window has no icon"))?;
+            let mut stdout = std::io::stdout();
+            write!(
+                stdout,
+                "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+                icon.width, icon.height
+            )?;
+            stdout.write_all(&icon.data)?;
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn tree(mut stream: ClientStream, watch: bool, mon: Option<u32>) -> Result<()> {
+        if !watch {
+            stream.send_value(&ClientRequest::ViewTree(TagSelection::Focused(mon)))?;
+            let (_, response) = stream.get_value()?;
+            if let CwmResponse::Tree(tree) = response {
+                print!("{}", render_tree(&tree));
+            } else {
+                bail!("invalid response from server")
+            }
+            return Ok(());
+        }
+        stream.send_value(&ClientRequest::SubscribeTree(mon))?;
+        loop {
+            let (done, response) = stream.get_value()?;
+            if let CwmResponse::Tree(tree) = response {
+                // clear the terminal before redrawing
+                print!("\x1b[2J\x1b[H{}", render_tree(&tree));
+                std::io::stdout().flush()?;
+            }
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    fn render_tree(tree: &TreeNode) -> String {
+        fn render(
+            node: &TreeNode,
+            prefix: &str,
+            branch: &str,
+            child_prefix: &str,
+            out: &mut String,
+        ) {
+            out.push_str(prefix);
+            out.push_str(branch);
+            match node {
+                TreeNode::Split {
+                    node,
+                    split,
+                    ratio,
+                    locked,
+                    first,
+                    second,
+                } => {
+                    let split = match split {
+                        Split::Horizontal => "horizontal",
+                        Split::Vertical => "vertical",
+                    };
+                    let locked = if *locked { " locked" } else { "" };
+                    out.push_str(&format!("@{} {} {:.2}{}\n", node, split, ratio, locked));
+                    let prefix = prefix.to_string() + child_prefix;
+                    render(first, &prefix, "├── ", "│   ", out);
+                    render(second, &prefix, "└── ", "    ", out);
+                }
+                TreeNode::Leaf {
+                    node,
+                    win,
+                    name,
+                    focused,
+                    absent,
+                } => out.push_str(&format!(
+                    "@{} {}{} (0x{:x}){}\n",
+                    node,
+                    if *focused { "* " } else { "" },
+                    name.as_deref().unwrap_or("<unnamed>"),
+                    win,
+                    if *absent { " absent" } else { "" }
+                )),
+                TreeNode::Empty => out.push_str("empty\n"),
+            }
+        }
+        let mut out = String::new();
+        render(tree, "", "", "", &mut out);
+        out
+    }
+
+    fn log(mut stream: ClientStream, n: usize) -> Result<()> {
+        stream.send_value(&ClientRequest::TailLog(n))?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::Log(lines) = response {
+            for line in lines {
+                println!("{}", line);
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn debug_dump(mut stream: ClientStream) -> Result<()> {
+        stream.send_value(&ClientRequest::DebugDump)?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::DebugDump(dump, errors) = response {
+            print!("{}", dump);
+            for error in errors.iter() {
+                eprintln!("invariant violated: {}", error);
+            }
+            if !errors.is_empty() {
+                bail!("{} invariants violated", errors.len())
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn metrics(mut stream: ClientStream) -> Result<()> {
+        stream.send_value(&ClientRequest::Metrics)?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::Metrics(metrics) = response {
+            for (tag, count) in metrics.clients {
+                println!("clients\t{}\t{}", tag, count);
+            }
+            println!(
+                "events\t{}\t{:.1}/s",
+                metrics.events, metrics.events_per_sec
+            );
+            println!(
+                "requests\t{}\t{:.1}/s",
+                metrics.requests, metrics.requests_per_sec
+            );
+            println!(
+                "relayouts\t{}\tavg {}us\tmax {}us",
+                metrics.relayouts, metrics.relayout_avg_us, metrics.relayout_max_us
+            );
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn wait_window(mut stream: ClientStream, spec: MatchSpec, timeout: u32) -> Result<()> {
+        stream.send_value(&ClientRequest::WaitForWindow(spec, timeout))?;
+        let (_, response) = stream.get_value()?;
+        match response {
+            CwmResponse::Window(Some(win)) => println!("{}", win),
+            CwmResponse::Window(None) => bail!("timed out waiting for a window"),
+            _ => bail!("invalid response from server"),
+        }
+        Ok(())
+    }
+
+    fn monitor_layers(mut stream: ClientStream) -> Result<()> {
+        stream.send_value(&ClientRequest::ViewMonitorLayers)?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::MonitorLayers(monitors) = response {
+            for mon in monitors {
+                println!(
+                    "{}\t{}\tlayers: {:?}\tstacking: {:?}",
+                    mon.monitor, mon.tag, mon.layers, mon.stacking
+                );
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn assignments(mut stream: ClientStream) -> Result<()> {
+        stream.send_value(&ClientRequest::ListAssignments)?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::Assignments(assignments) = response {
+            for (class, tag) in assignments {
+                println!("{}\t{}", class, tag);
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
+    fn ping(mut stream: ClientStream) -> Result<()> {
+        stream.send_value(&ClientRequest::Ping)?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::Pong = response {
+            println!("pong");
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+
     impl Args {
         pub(super) fn process(self, stream: ClientStream) -> Result<()> {
             match self {
@@ -443,6 +1706,20 @@ mod query {
                 Self::Layers(tag) => layers(stream, tag),
                 Self::Stack(tag) => stack(stream, tag),
                 Self::Clients(tag) => clients(stream, tag),
+                Self::Ping => ping(stream),
+                Self::Icon(Window(win)) => icon(stream, win),
+                Self::Assignments => assignments(stream),
+                Self::MonitorLayers => monitor_layers(stream),
+                Self::Metrics => metrics(stream),
+                Self::Tray => tray(stream),
+                Self::Geometry(relative, Node(node)) => geometry(stream, relative, node),
+                Self::Flags(Node(node)) => flags(stream, node),
+                Self::Minimap(svg, tag) => minimap(stream, svg, tag),
+                Self::Hidden(tag) => hidden(stream, tag),
+                Self::WaitWindow(Match(spec), timeout) => wait_window(stream, spec, timeout),
+                Self::DebugDump => debug_dump(stream),
+                Self::Log(n) => log(stream, n),
+                Self::Tree(watch, Monitor(mon)) => tree(stream, watch, mon),
             }
         }
     }
@@ -506,21 +1783,46 @@ mod command {
     use super::*;
     #[derive(Arg)]
     pub(super) enum Args {
+        /// exit the window manager
         Quit,
+        /// rerun the config
         Reload,
+        /// run the window manager binary again in place, keeping the windows, tags and flags
+        Restart,
+        /// select a node
         #[struct_args_match("sel")]
         Select(Node),
+        /// add a node to the multi selection or remove it, commands without a node apply to all
+        #[struct_args_match(ND, "sel-toggle")]
+        SelectToggle(Node),
+        /// select a child of the selection or preselect a side
         #[struct_args_match(ND, "sel-dir")]
         SelectDir(Side),
+        /// select the parent of the selection
         #[struct_args_match(ND, "sel-parent")]
         SelectParent,
+        /// change the size of the preselection
         #[struct_args_match(ND, "presel-amt")]
         PreselAmt(f32),
+        /// clear the selection and the multi selection
         #[struct_args_match(ND, "sel-cancel")]
         SelectionCancel,
+        /// rotate the selection clockwise
         Rotate,
+        /// rotate the selection counter clockwise
         #[struct_args_match(ND, "!rotate")]
         RotateRev,
+        /// set the log level of the server
+        #[struct_args_match(ND, "log-level")]
+        LogLevel(LevelFilter),
+        /// stop the pointer from changing focus
+        #[struct_args_match(ND, "focus-lock")]
+        FocusLock(State),
+        /// show or hide a window of a command across the top of the monitor, spawning it if needed
+        Dropdown(String, f32),
+        /// spawn a command whose first window fills the current presel, wherever focus has gone
+        #[struct_args_match(ND, "spawn-presel")]
+        SpawnPresel(String),
     }
 
     impl Args {
@@ -528,13 +1830,25 @@ mod command {
             match self {
                 Self::Quit => stream.send_value(&ClientRequest::Quit),
                 Self::Reload => stream.send_value(&ClientRequest::Reload),
+                Self::Restart => stream.send_value(&ClientRequest::Restart),
                 Self::Select(Node(node)) => stream.send_value(&ClientRequest::Select(node)),
+                Self::SelectToggle(Node(node)) => {
+                    stream.send_value(&ClientRequest::SelectToggle(node))
+                }
                 Self::SelectDir(Side(side)) => stream.send_value(&ClientRequest::SelectDir(side)),
                 Self::SelectParent => stream.send_value(&ClientRequest::SelectParent),
                 Self::PreselAmt(amt) => stream.send_value(&ClientRequest::PreselAmt(amt)),
                 Self::SelectionCancel => stream.send_value(&ClientRequest::SelectionCancel),
                 Self::Rotate => stream.send_value(&ClientRequest::Rotate(false)),
                 Self::RotateRev => stream.send_value(&ClientRequest::Rotate(true)),
+                Self::LogLevel(level) => stream.send_value(&ClientRequest::SetLogLevel(level)),
+                Self::FocusLock(State(arg)) => stream.send_value(&ClientRequest::SetFocusLock(arg)),
+                Self::Dropdown(command, height) => {
+                    stream.send_value(&ClientRequest::DropdownToggle(command, height))
+                }
+                Self::SpawnPresel(command) => {
+                    stream.send_value(&ClientRequest::SpawnInPresel(command))
+                }
             }
         }
     }
@@ -544,24 +1858,330 @@ mod config {
     use super::*;
     #[derive(Arg)]
     pub(super) enum Args {
+        /// border color of focused nodes
         #[struct_args_match(ND, "color-focused")]
         BorderFocused(Color),
+        /// border color of unfocused nodes
         #[struct_args_match(ND, "color-unfocused")]
         BorderUnfocused(Color),
+        /// border color of the focused node of a monitor that doesn't have input focus
+        #[struct_args_match(ND, "color-inactive")]
+        BorderInactive(Color),
+        /// border width
         #[struct_args_match(ND, "border-width")]
-        BorderWidth(u16),
-        Gap(u16),
+        BorderWidth(#[struct_args(range(0, 100))] u16),
+        /// gap between tiled nodes, a percentage is of the smaller side of each monitor
+        Gap(GapAmount),
+        /// margin on a side of each monitor
         Margin(Side, i16),
+        /// grow or shrink the margin on a side of each monitor
+        #[struct_args_match(ND, "margin-delta")]
+        MarginDelta(Side, i16),
+        /// grow or shrink the margins on every side of each monitor
+        #[struct_args_match(ND, "margin-delta-all")]
+        MarginDeltaAll(i16),
+        /// what happens when a tag shown on another monitor is focused
+        #[struct_args_match(ND, "tag-switch-mode")]
+        TagSwitchMode(SwitchMode),
+        /// enable animations and set their duration in ms
+        Animations(bool, #[struct_args(range(0, 5000))] u16),
+        /// how hidden windows and the windows of hidden tags are hidden
+        #[struct_args_match(ND, "hide-strategy")]
+        HideStrategy(Strategy),
+        /// the most tiled nodes a tag takes, more new nodes are hidden or go to the next empty tag
+        Overflow(Unset<usize>, Overflow),
+        /// hide the least recently focused tiled node while any tile is smaller than this or the
+        /// min size of its node, unset stops it
+        #[struct_args_match(ND, "min-tile-size")]
+        MinTileSize(Unset<TileSize>),
+        /// clear the urgency of a node after some ms, 0 keeps it until the node is focused
+        #[struct_args_match(ND, "urgent-timeout")]
+        UrgentTimeout(u32),
+        /// tell idle subscribers after some ms without input or focus changes, 0 disables it
+        #[struct_args_match(ND, "idle-timeout")]
+        IdleTimeout(u32),
+        /// cancel a presel after some ms without it being used or changed, 0 keeps it
+        #[struct_args_match(ND, "presel-timeout")]
+        PreselTimeout(u32),
+        /// wait until tag switches stop for some ms before focusing and running the tag hooks, 0
+        /// focuses after every switch
+        #[struct_args_match(ND, "focus-settle")]
+        FocusSettle(u32),
+        /// keep the space of a closed node empty for some ms so compositors can fade it out, 0
+        /// fills it right away
+        #[struct_args_match(ND, "close-fade")]
+        CloseFade(u32),
+        /// open floating nodes where the last node of their class and instance was floating, kept
+        /// across sessions
+        #[struct_args_match(ND, "remember-floating")]
+        RememberFloating(bool),
+        /// center floating nodes without a position of their own, or offset each from the last
+        #[struct_args_match(ND, "float-placement")]
+        FloatPlacement(Placement),
+        /// override the gap, border width or a margin of a monitor
+        #[struct_args_match(ND, "monitor-theme")]
+        MonitorTheme(String, Field, Override),
+        /// move the selection to the focused node instead of cancelling it on focus or tag changes
+        #[struct_args_match(ND, "selection-follow")]
+        SelectionFollow(bool),
+        /// move focus off a monitor that goes off without being removed and back once it is on
+        #[struct_args_match(ND, "refocus-off-monitors")]
+        RefocusOffMonitors(bool),
+        /// only ask compositors to draw shadows on floating nodes
+        #[struct_args_match(ND, "floating-shadows-only")]
+        FloatingShadowsOnly(bool),
+        /// flash the border of the focused node in a color after switching tags, 0 ms disables it
+        #[struct_args_match(ND, "focus-flash")]
+        FocusFlash(u32, Color),
+        /// flash the border of a node in a color when it or the keyboard rings the bell, 0 ms
+        /// disables it
+        #[struct_args_match(ND, "visual-bell")]
+        VisualBell(u32, Color),
+        /// mark the focused node with a frame or a corner square, for setups without borders
+        #[struct_args_match(ND, "focus-indicator")]
+        FocusIndicator(Indicator, Option<Color>),
+        /// split the tiled node under the pointer for new nodes instead of the focused one
+        #[struct_args_match(ND, "insert-at-pointer")]
+        InsertAtPointer(bool),
+        /// hide and show the whole window group of a node together
+        #[struct_args_match(ND, "group-hide")]
+        GroupHide(bool),
+        /// host a system tray at the top right of the focused monitor with icons of a size
+        Tray(bool, u16),
+        /// whether panels stack above fullscreen nodes and desktop windows let clicks through
+        #[struct_args_match(ND, "panel-layer")]
+        PanelLayer(bool, bool),
+        /// the modifiers and buttons for moving and resizing nodes with the pointer
+        #[struct_args_match(ND, "pointer-bindings")]
+        PointerBindings(Mods, u8, u8),
+        /// switch tags by dragging sideways from strips of a width along the monitor edges, or
+        /// with a button and modifiers held anywhere, once for each threshold of distance
+        Swipe(u16, u16, Option<u8>, Option<Mods>),
+        /// what a button pressed on the border of a node does, none removes the binding
+        #[struct_args_match(ND, "frame-button")]
+        FrameButton(u8, Mods, Frame),
+        /// what double clicking the border of a node does and how many ms the clicks can be apart
+        #[struct_args_match(ND, "frame-double-click")]
+        FrameDoubleClick(Frame, u32),
+        /// whether floating nodes stay above monocle and if monocle hides borders and gaps
+        Monocle(bool, bool, bool),
     }
 
-    pub struct Color(u32);
-    impl Arg for Color {
+    pub struct Field(ThemeField);
+    impl Arg for Field {
         fn parse_args(args: &mut Vec<String>) -> Result<Self> {
-            Ok(Self(parse_u32(
-                args.pop()
-                    .ok_or_else(|| Error::msg("mon: No argument provided"))?
-                    .as_str(),
-            )?))
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("field: No argument provided"))?
+                    .as_str()
+                {
+                    "gap" => ThemeField::Gap,
+                    "border-width" => ThemeField::BorderWidth,
+                    "margin-left" => ThemeField::Margin(Side_::Left),
+                    "margin-right" => ThemeField::Margin(Side_::Right),
+                    "margin-top" => ThemeField::Margin(Side_::Top),
+                    "margin-bottom" => ThemeField::Margin(Side_::Bottom),
+                    s => bail!("invalid theme field: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<gap|border-width|margin-left|margin-right|margin-top|margin-bottom>")
+        }
+    }
+
+    // "<width>x<height>"
+    pub struct GapAmount(Amount);
+    impl Arg for GapAmount {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            match Amount::parse_args(args)? {
+                Amount::Pixels(gap) if (0..=500).contains(&gap) => Ok(Self(Amount::Pixels(gap))),
+                Amount::Percent(gap) if (0.0..=50.0).contains(&gap) => {
+                    Ok(Self(Amount::Percent(gap)))
+                }
+                _ => bail!("gap: expected 0 to 500 pixels or 0 to 50%"),
+            }
+        }
+        fn usage() -> Vec<Usage> {
+            Amount::usage()
+        }
+    }
+
+    pub struct TileSize(u16, u16);
+    impl Arg for TileSize {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let arg = args
+                .pop()
+                .ok_or_else(|| Error::msg("size: No argument provided"))?;
+            let (width, height) = arg
+                .split_once('x')
+                .ok_or_else(|| Error::msg(format!("invalid size: {}", arg)))?;
+            Ok(Self(width.parse()?, height.parse()?))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<width>x<height>")
+        }
+    }
+
+    pub struct Override(Option<i16>);
+    impl Arg for Override {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let arg = args
+                .pop()
+                .ok_or_else(|| Error::msg("value: No argument provided"))?;
+            Ok(Self(if arg == "unset" {
+                None
+            } else {
+                Some(arg.parse()?)
+            }))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<value|unset>")
+        }
+    }
+
+    // modifier names joined by '+', like "mod4" or "ctrl+alt"
+    pub struct Mods(u16);
+    impl Arg for Mods {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let arg = args
+                .pop()
+                .ok_or_else(|| Error::msg("modifiers: No argument provided"))?;
+            let mut mods = 0;
+            for name in arg.split('+') {
+                mods |= match name {
+                    "shift" => 1,
+                    "ctrl" | "control" => 1 << 2,
+                    "alt" | "mod1" => 1 << 3,
+                    "mod2" => 1 << 4,
+                    "mod3" => 1 << 5,
+                    "super" | "mod4" => 1 << 6,
+                    "mod5" => 1 << 7,
+                    "none" => 0,
+                    s => bail!("invalid modifier: {}", s),
+                };
+            }
+            Ok(Self(mods))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<none|shift|ctrl|alt|super|mod1-5>[+...]")
+        }
+    }
+
+    pub struct Frame(Option<FrameAction>);
+    impl Arg for Frame {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            Ok(Self(Some(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("action: No argument provided"))?
+                    .as_str()
+                {
+                    "none" => return Ok(Self(None)),
+                    "close" => FrameAction::Close,
+                    "kill" => FrameAction::Kill,
+                    "hide" => FrameAction::Hide,
+                    "floating" => FrameAction::Floating,
+                    "fullscreen" => FrameAction::Fullscreen,
+                    "sticky" => FrameAction::Sticky,
+                    "raise" => FrameAction::Raise,
+                    "lower" => FrameAction::Lower,
+                    s => bail!("invalid frame action: {}", s),
+                },
+            )))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<none|close|kill|hide|floating|fullscreen|sticky|raise|lower>")
+        }
+    }
+
+    pub struct Indicator(FocusIndicator);
+    impl Arg for Indicator {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let style = args
+                .pop()
+                .ok_or_else(|| Error::msg("indicator: No argument provided"))?;
+            if style == "off" {
+                return Ok(Self(FocusIndicator::Off));
+            }
+            let size = args
+                .pop()
+                .ok_or_else(|| Error::msg("size: No argument provided"))?
+                .parse()?;
+            Ok(Self(match style.as_str() {
+                "edge" => FocusIndicator::Edge(size),
+                "corner" => FocusIndicator::Corner(size),
+                s => bail!("invalid focus indicator: {}", s),
+            }))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<off|edge <width>|corner <size>>")
+        }
+    }
+
+    pub struct Placement(FloatPlacement);
+    impl Arg for Placement {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let placement = args
+                .pop()
+                .ok_or_else(|| Error::msg("placement: No argument provided"))?;
+            Ok(Self(match placement.as_str() {
+                "center" => FloatPlacement::Center,
+                "cascade" => {
+                    let mut offset = || -> Result<i16> {
+                        Ok(args
+                            .pop()
+                            .ok_or_else(|| Error::msg("offset: No argument provided"))?
+                            .parse()?)
+                    };
+                    FloatPlacement::Cascade(offset()?, offset()?)
+                }
+                s => bail!("invalid float placement: {}", s),
+            }))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<center|cascade <dx> <dy>>")
+        }
+    }
+
+    pub struct Overflow(OverflowPolicy);
+    impl Arg for Overflow {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("policy: No argument provided"))?
+                    .as_str()
+                {
+                    "hide" => OverflowPolicy::Hide,
+                    "next-empty" => OverflowPolicy::NextEmpty,
+                    s => bail!("invalid overflow policy: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<hide|next-empty>")
+        }
+    }
+
+    pub struct Strategy(HideStrategy);
+    impl Arg for Strategy {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            Ok(Self(
+                match args
+                    .pop()
+                    .ok_or_else(|| Error::msg("strategy: No argument provided"))?
+                    .as_str()
+                {
+                    "unmap" => HideStrategy::Unmap,
+                    "offscreen" => HideStrategy::Offscreen,
+                    s => bail!("invalid hide strategy: {}", s),
+                },
+            ))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<unmap|offscreen>")
         }
     }
 
@@ -574,18 +2194,159 @@ mod config {
                 Self::BorderUnfocused(Color(color)) => {
                     stream.send_value(&ClientRequest::ConfigBorderUnfocused(color))
                 }
+                Self::BorderInactive(Color(color)) => {
+                    stream.send_value(&ClientRequest::ConfigBorderInactive(color))
+                }
                 Self::BorderWidth(width) => {
                     stream.send_value(&ClientRequest::ConfigBorderWidth(width))
                 }
-                Self::Gap(gap) => stream.send_value(&ClientRequest::ConfigGap(gap)),
+                Self::Gap(GapAmount(Amount::Pixels(gap))) => {
+                    stream.send_value(&ClientRequest::ConfigGap(gap as u16))
+                }
+                Self::Gap(GapAmount(Amount::Percent(gap))) => {
+                    stream.send_value(&ClientRequest::ConfigGapPercent(gap))
+                }
                 Self::Margin(Side(side), marg) => {
                     stream.send_value(&ClientRequest::ConfigMargin(side, marg))
                 }
+                Self::MarginDelta(Side(side), delta) => {
+                    stream.send_value(&ClientRequest::ConfigMarginDelta(side, delta))
+                }
+                Self::MarginDeltaAll(delta) => {
+                    stream.send_value(&ClientRequest::ConfigMarginDeltaAll(delta))
+                }
+                Self::TagSwitchMode(SwitchMode(mode)) => {
+                    stream.send_value(&ClientRequest::ConfigTagSwitchMode(mode))
+                }
+                Self::Animations(enabled, duration) => {
+                    stream.send_value(&ClientRequest::ConfigAnimations(enabled, duration))
+                }
+                Self::HideStrategy(Strategy(strategy)) => {
+                    stream.send_value(&ClientRequest::ConfigHideStrategy(strategy))
+                }
+                Self::Overflow(Unset(limit), Overflow(policy)) => {
+                    stream.send_value(&ClientRequest::ConfigOverflow(limit, policy))
+                }
+                Self::MinTileSize(Unset(size)) => stream.send_value(
+                    &ClientRequest::ConfigMinTileSize(size.map(|TileSize(w, h)| (w, h))),
+                ),
+                Self::UrgentTimeout(ms) => {
+                    stream.send_value(&ClientRequest::ConfigUrgentTimeout(ms))
+                }
+                Self::IdleTimeout(ms) => stream.send_value(&ClientRequest::ConfigIdleTimeout(ms)),
+                Self::PreselTimeout(ms) => {
+                    stream.send_value(&ClientRequest::ConfigPreselTimeout(ms))
+                }
+                Self::FocusSettle(ms) => stream.send_value(&ClientRequest::ConfigFocusSettle(ms)),
+                Self::CloseFade(ms) => stream.send_value(&ClientRequest::ConfigCloseFade(ms)),
+                Self::RememberFloating(enabled) => {
+                    stream.send_value(&ClientRequest::ConfigRememberFloating(enabled))
+                }
+                Self::FloatPlacement(Placement(placement)) => {
+                    stream.send_value(&ClientRequest::ConfigFloatPlacement(placement))
+                }
+                Self::MonitorTheme(name, Field(field), Override(value)) => {
+                    stream.send_value(&ClientRequest::ConfigMonitorTheme(name, field, value))
+                }
+                Self::SelectionFollow(follow) => {
+                    stream.send_value(&ClientRequest::ConfigSelectionFollow(follow))
+                }
+                Self::RefocusOffMonitors(enabled) => {
+                    stream.send_value(&ClientRequest::ConfigRefocusOffMonitors(enabled))
+                }
+                Self::FloatingShadowsOnly(enabled) => {
+                    stream.send_value(&ClientRequest::ConfigFloatingShadowsOnly(enabled))
+                }
+                Self::FocusFlash(ms, Color(color)) => {
+                    stream.send_value(&ClientRequest::ConfigFocusFlash(ms, color))
+                }
+                Self::VisualBell(ms, Color(color)) => {
+                    stream.send_value(&ClientRequest::ConfigVisualBell(ms, color))
+                }
+                Self::FocusIndicator(Indicator(style), color) => stream.send_value(
+                    &ClientRequest::ConfigFocusIndicator(style, color.map(|Color(color)| color)),
+                ),
+                Self::InsertAtPointer(enabled) => {
+                    stream.send_value(&ClientRequest::ConfigInsertAtPointer(enabled))
+                }
+                Self::GroupHide(enabled) => {
+                    stream.send_value(&ClientRequest::ConfigGroupHide(enabled))
+                }
+                Self::Tray(enabled, icon_size) => {
+                    stream.send_value(&ClientRequest::ConfigTray(enabled, icon_size))
+                }
+                Self::PanelLayer(above_fullscreen, click_through) => stream.send_value(
+                    &ClientRequest::ConfigPanelLayer(above_fullscreen, click_through),
+                ),
+                Self::PointerBindings(Mods(mods), move_button, resize_button) => {
+                    let bindings = PointerBindings {
+                        mods,
+                        move_button,
+                        resize_button,
+                    };
+                    if !bindings.is_valid() {
+                        bail!("pointer bindings need a modifier and two different buttons from 1 to 5")
+                    }
+                    stream.send_value(&ClientRequest::ConfigPointerBindings(bindings))
+                }
+                Self::Swipe(edge, threshold, button, mods) => {
+                    let swipe = Swipe_ {
+                        edge,
+                        binding: match (button, mods) {
+                            (Some(button), Some(Mods(mods))) => Some((button, mods)),
+                            (None, None) => None,
+                            _ => bail!("a swipe binding needs a button and modifiers"),
+                        },
+                        threshold,
+                    };
+                    if !swipe.is_valid() {
+                        bail!("swipes need a threshold and a binding with a modifier and a button from 1 to 5")
+                    }
+                    stream.send_value(&ClientRequest::ConfigSwipe(swipe))
+                }
+                Self::FrameButton(button, Mods(mods), Frame(action)) => {
+                    if !(1..=5).contains(&button) {
+                        bail!("frame buttons go from 1 to 5")
+                    }
+                    stream.send_value(&ClientRequest::BindFrameButton(button, mods, action))
+                }
+                Self::FrameDoubleClick(Frame(action), ms) => {
+                    stream.send_value(&ClientRequest::ConfigFrameDoubleClick(action, ms))
+                }
+                Self::Monocle(floating, borderless, gapless) => {
+                    stream.send_value(&ClientRequest::ConfigMonocle(floating, borderless, gapless))
+                }
             }
         }
     }
 }
 
+struct Match(MatchSpec);
+impl Arg for Match {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        let mut spec = MatchSpec::default();
+        while let Some(item) = args.pop() {
+            let field = match item.as_str() {
+                "class" => &mut spec.class,
+                "instance" | "inst" => &mut spec.instance,
+                "name" => &mut spec.name,
+                _ => {
+                    args.push(item);
+                    break;
+                }
+            };
+            *field = Some(
+                args.pop()
+                    .ok_or_else(|| Error::msg("match: No argument provided"))?,
+            );
+        }
+        Ok(Self(spec))
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("[class|instance|name <value>]...")
+    }
+}
+
 struct Rule(Rule_);
 impl Arg for Rule {
     fn parse_args(args: &mut Vec<String>) -> Result<Self> {
@@ -614,6 +2375,8 @@ impl Arg for Rule {
                         .ok_or_else(|| Error::msg("rule: No argument provided"))?
                         .parse()?,
                 )),
+                "geometry" => rule.geometry(GeometryArg::parse_args(args)?.0),
+                "relative" => rule.relative(),
                 "size" => rule.size((
                     args.pop()
                         .ok_or_else(|| Error::msg("rule: No argument provided"))?
@@ -622,6 +2385,18 @@ impl Arg for Rule {
                         .ok_or_else(|| Error::msg("rule: No argument provided"))?
                         .parse()?,
                 )),
+                "border-width" => rule.border_width(
+                    args.pop()
+                        .ok_or_else(|| Error::msg("rule: No argument provided"))?
+                        .parse()?,
+                ),
+                "border-color" => rule.border_color(parse_u32(
+                    &args
+                        .pop()
+                        .ok_or_else(|| Error::msg("rule: No argument provided"))?,
+                )?),
+                "force-fullscreen-layer" => rule.force_fullscreen_layer(),
+                "protected" => rule.protected(),
                 "temp" => rule.temp(),
                 _ => {
                     args.push(item);
@@ -631,52 +2406,354 @@ impl Arg for Rule {
         }
         Ok(Self(rule))
     }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<rule>...")
+    }
 }
 
 mod rule {
     use super::*;
     #[derive(Arg)]
     pub(super) enum Args {
+        /// add a rule for new windows
         Add(Rule),
+        /// always open windows with a class on a tag, --volatile creates a missing tag as volatile
+        /// for the first of them
+        Assign(#[struct_args_match("--volatile")] bool, String, Tag),
+        /// show which rules would match a window without applying them
+        Test(Window),
     }
 
     impl Args {
         pub(super) fn process(self, mut stream: ClientStream) -> Result<()> {
             match self {
                 Self::Add(Rule(rule)) => stream.send_value(&ClientRequest::AddRule(rule)),
+                Self::Assign(volatile, class, Tag(tag, _)) => {
+                    stream.send_value(&ClientRequest::AssignClass(class, tag, volatile))
+                }
+                Self::Test(Window(win)) => test(stream, win),
             }
         }
     }
+
+    fn test(mut stream: ClientStream, win: u32) -> Result<()> {
+        stream.send_value(&ClientRequest::RuleTest(win))?;
+        let (_, response) = stream.get_value()?;
+        if let CwmResponse::RuleTest(test) = response {
+            let show = |val: Option<String>| val.unwrap_or_else(|| "-".to_string());
+            println!("class\t{}", show(test.class));
+            println!("instance\t{}", show(test.instance));
+            println!("name\t{}", show(test.name));
+            println!("assigned\t{}", show(test.tag));
+            for (i, (rule, matched)) in test.rules.iter().enumerate() {
+                let matched = if *matched { "match" } else { "-" };
+                println!("{}\t{}\t{}", i, matched, rule.describe());
+            }
+        } else {
+            bail!("invalid response from server")
+        }
+        Ok(())
+    }
+}
+
+// translates a subset of bspc commands so sxhkd configs written for bspwm keep working
+mod bspc {
+    use super::*;
+
+    pub(super) struct Args(Vec<ClientRequest>);
+    impl Arg for Args {
+        fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+            let domain = args
+                .pop()
+                .ok_or_else(|| Error::msg("bspc: No domain provided"))?;
+            let requests = match domain.as_str() {
+                "node" => node(args)?,
+                "desktop" => desktop(args)?,
+                "config" => config(args)?,
+                "wm" => match args.pop().as_deref() {
+                    Some("-r") | Some("--restart") => vec![ClientRequest::Reload],
+                    arg => bail!("bspc wm: unsupported argument {:?}", arg),
+                },
+                "quit" => vec![ClientRequest::Quit],
+                domain => bail!("bspc: unsupported domain '{}'", domain),
+            };
+            args.clear();
+            Ok(Self(requests))
+        }
+        fn usage() -> Vec<Usage> {
+            Usage::arg("<node|desktop|config|wm|quit> <bspc args>...")
+        }
+    }
+
+    impl Args {
+        pub(super) fn process(self, mut stream: ClientStream) -> Result<()> {
+            for request in &self.0 {
+                stream.send_value(request)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn next(args: &mut Vec<String>, what: &str) -> Result<String> {
+        args.pop()
+            .ok_or_else(|| Error::msg(format!("bspc: No {} provided", what)))
+    }
+
+    // the next argument unless it is another command, for selectors and optional values
+    fn optional(args: &mut Vec<String>) -> Option<String> {
+        match args.last() {
+            Some(arg) if !arg.starts_with('-') => args.pop(),
+            _ => None,
+        }
+    }
+
+    fn node_sel(sel: Option<String>) -> Result<Option<u32>> {
+        match sel.as_deref() {
+            None | Some("focused") => Ok(None),
+            Some(sel) => parse_u32(sel)
+                .map(Some)
+                .map_err(|_| Error::msg(format!("bspc: unsupported node selector '{}'", sel))),
+        }
+    }
+
+    fn desktop_sel(sel: &str) -> Result<TagSelection> {
+        let mut parts = sel.split('.');
+        let tag = match parts.next().unwrap_or_default() {
+            "focused" => TagSelection::Focused(None),
+            "next" => TagSelection::Next(None),
+            "prev" => TagSelection::Prev(None),
+            "last" | "older" => TagSelection::Last(None),
+            sel => match sel.strip_prefix('^') {
+                Some(index) => TagSelection::Index(
+                    index
+                        .parse::<usize>()?
+                        .checked_sub(1)
+                        .ok_or_else(|| Error::msg("bspc: desktop indices start at 1"))?,
+                ),
+                None => TagSelection::Name(sel.to_string()),
+            },
+        };
+        // tags only exist on the focused monitor in cwm, so local changes nothing
+        if let Some(modifier) = parts.find(|x| *x != "local") {
+            bail!("bspc: unsupported desktop modifier '{}'", modifier)
+        }
+        Ok(tag)
+    }
+
+    fn dir(dir: &str) -> Result<Side_> {
+        Ok(match dir {
+            "west" => Side_::Left,
+            "east" => Side_::Right,
+            "north" => Side_::Top,
+            "south" => Side_::Bottom,
+            dir => bail!("bspc: unsupported direction '{}'", dir),
+        })
+    }
+
+    // a bare flag toggles, "=on" and "=off" set it
+    fn flag(flag: &str) -> Result<(&str, StateArg)> {
+        Ok(match flag.split_once('=') {
+            None => (flag, StateArg::Toggle),
+            Some((flag, "on")) => (flag, StateArg::On),
+            Some((flag, "off")) => (flag, StateArg::Off),
+            Some((_, value)) => bail!("bspc: unsupported flag value '{}'", value),
+        })
+    }
+
+    fn node(args: &mut Vec<String>) -> Result<Vec<ClientRequest>> {
+        let node = node_sel(optional(args))?;
+        let subtree = match node {
+            Some(win) => NodeSelector::Window(win),
+            None => NodeSelector::Focused,
+        };
+        let mut requests = Vec::new();
+        while let Some(command) = args.pop() {
+            match command.as_str() {
+                "-t" | "--state" => {
+                    let state = next(args, "state")?;
+                    let (state, toggle) = match state.strip_prefix('~') {
+                        Some(state) => (state, true),
+                        None => (state.as_str(), false),
+                    };
+                    let (on, off) = if toggle {
+                        (StateArg::Toggle, StateArg::Toggle)
+                    } else {
+                        (StateArg::On, StateArg::Off)
+                    };
+                    requests.push(match state {
+                        "tiled" | "pseudo_tiled" => ClientRequest::SetFloating(node, off),
+                        "floating" => ClientRequest::SetFloating(node, on),
+                        "fullscreen" => ClientRequest::SetFullscreen(node, on),
+                        state => bail!("bspc: unsupported state '{}'", state),
+                    })
+                }
+                "-g" | "--flag" => {
+                    let value = next(args, "flag")?;
+                    requests.push(match flag(&value)? {
+                        ("hidden", arg) => ClientRequest::SetHidden(node, arg),
+                        ("sticky", arg) => ClientRequest::SetSticky(node, arg),
+                        (flag, _) => bail!("bspc: unsupported flag '{}'", flag),
+                    })
+                }
+                "-l" | "--layer" => {
+                    let layer = match next(args, "layer")?.as_str() {
+                        "above" => StackLayer::Above,
+                        "normal" => StackLayer::Normal,
+                        "below" => StackLayer::Below,
+                        layer => bail!("bspc: unsupported layer '{}'", layer),
+                    };
+                    requests.push(ClientRequest::SetLayer(node, SetArg(layer, false)))
+                }
+                "-f" | "--focus" => requests.push(match optional(args).as_deref() {
+                    Some("next") => ClientRequest::CycleWindow(false, CycleFilter::All),
+                    Some("prev") => ClientRequest::CycleWindow(true, CycleFilter::All),
+                    Some(sel) => ClientRequest::SelectNeighbour(node, dir(sel)?),
+                    None => bail!("bspc: focusing a node needs a direction"),
+                }),
+                "-s" | "--swap" => {
+                    let side = dir(&next(args, "direction")?)?;
+                    requests.push(ClientRequest::MoveWindow(node, side, 0))
+                }
+                "-d" | "--to-desktop" => {
+                    let tag = desktop_sel(&next(args, "desktop")?)?;
+                    let follow = args.last().map(String::as_str) == Some("--follow");
+                    if follow {
+                        args.pop();
+                    }
+                    requests.push(ClientRequest::SetWindowTag(node, tag, false, follow))
+                }
+                "-c" | "--close" => requests.push(ClientRequest::CloseClient(node, false, false)),
+                "-k" | "--kill" => requests.push(ClientRequest::CloseClient(node, true, false)),
+                "-R" | "--rotate" => match next(args, "angle")?.as_str() {
+                    "90" => requests.push(ClientRequest::RotateNode(subtree.clone(), false)),
+                    "270" | "-90" => {
+                        requests.push(ClientRequest::RotateNode(subtree.clone(), true))
+                    }
+                    "180" => {
+                        requests.push(ClientRequest::RotateNode(subtree.clone(), false));
+                        requests.push(ClientRequest::RotateNode(subtree.clone(), false));
+                    }
+                    angle => bail!("bspc: unsupported angle '{}'", angle),
+                },
+                "-E" | "--equalize" => requests.push(ClientRequest::EqualizeNode(subtree.clone())),
+                command => bail!("bspc node: unsupported command '{}'", command),
+            }
+        }
+        Ok(requests)
+    }
+
+    fn desktop(args: &mut Vec<String>) -> Result<Vec<ClientRequest>> {
+        let tag = match optional(args) {
+            Some(sel) => desktop_sel(&sel)?,
+            None => TagSelection::Focused(None),
+        };
+        let mut requests = Vec::new();
+        while let Some(command) = args.pop() {
+            match command.as_str() {
+                "-f" | "--focus" => {
+                    let tag = match optional(args) {
+                        Some(sel) => desktop_sel(&sel)?,
+                        None => tag.clone(),
+                    };
+                    requests.push(ClientRequest::FocusTag(None, tag, false, None))
+                }
+                "-l" | "--layout" => {
+                    let arg = match next(args, "layout")?.as_str() {
+                        "monocle" => StateArg::On,
+                        "tiled" => StateArg::Off,
+                        "next" | "prev" => StateArg::Toggle,
+                        layout => bail!("bspc: unsupported layout '{}'", layout),
+                    };
+                    requests.push(ClientRequest::SetMonocle(tag.clone(), arg))
+                }
+                "-r" | "--remove" => requests.push(ClientRequest::RemoveTag(tag.clone())),
+                command => bail!("bspc desktop: unsupported command '{}'", command),
+            }
+        }
+        Ok(requests)
+    }
+
+    fn config(args: &mut Vec<String>) -> Result<Vec<ClientRequest>> {
+        let name = next(args, "setting")?;
+        let value = next(args, "value")?;
+        // bspwm colors have no alpha channel
+        let color = |value: &str| {
+            parse_u32(value).map(|color| {
+                if value.len() == 7 {
+                    color | 0xff000000
+                } else {
+                    color
+                }
+            })
+        };
+        Ok(vec![match name.as_str() {
+            "window_gap" => ClientRequest::ConfigGap(value.parse()?),
+            "border_width" => ClientRequest::ConfigBorderWidth(value.parse()?),
+            "focused_border_color" => ClientRequest::ConfigBorderFocused(color(&value)?),
+            "normal_border_color" => ClientRequest::ConfigBorderUnfocused(color(&value)?),
+            "top_padding" => ClientRequest::ConfigMargin(Side_::Top, value.parse()?),
+            "bottom_padding" => ClientRequest::ConfigMargin(Side_::Bottom, value.parse()?),
+            "left_padding" => ClientRequest::ConfigMargin(Side_::Left, value.parse()?),
+            "right_padding" => ClientRequest::ConfigMargin(Side_::Right, value.parse()?),
+            name => bail!("bspc config: unsupported setting '{}'", name),
+        }])
+    }
 }
 
 #[derive(Arg)]
 enum Opts {
+    /// operate on nodes
     Node(node::Args),
+    /// operate on tags
     Tag(tag::Args),
+    /// operate on monitors
     #[struct_args_match("mon")]
     Monitor(monitor::Args),
+    /// print updates as they happen
     #[struct_args_match("sub")]
     Subscribe(subscribe::Args),
+    /// print information about the window manager
     Query(query::Args),
+    /// run a command
     #[struct_args_match("cmd")]
     Command(command::Args),
+    /// change the config
     Config(config::Args),
+    /// add rules for new windows
     Rule(rule::Args),
+    /// translate a bspc command
+    Bspc(bspc::Args),
 }
 
 struct ClientStream {
     stream: Stream,
     fd: [PollFd; 1],
+    features: Vec<String>,
 }
 
 impl ClientStream {
     fn new() -> Result<Self> {
-        let socket = format!("/tmp/cwm-{}.sock", whoami::username());
-        let stream = Stream::new(UnixStream::connect(socket)?);
+        let stream = Stream::new(UnixStream::connect(socket_path())?);
         let fd = [PollFd::new(stream.as_raw_fd(), PollFlags::POLLIN)];
-        Ok(Self { stream, fd })
+        let mut stream = Self {
+            stream,
+            fd,
+            features: Vec::new(),
+        };
+        if !stream.stream.send(&Hello {
+            version: PROTOCOL_VERSION,
+        }) {
+            bail!("Could not send handshake to server")
+        }
+        match stream.recieve_value::<HelloReply>()?.1 {
+            HelloReply::Accepted { features, .. } => {
+                stream.features = features;
+                Ok(stream)
+            }
+            HelloReply::Rejected { reason, .. } => bail!("server rejected client: {}", reason),
+        }
     }
-    fn get_value(&mut self) -> Result<(bool, CwmResponse)> {
+    fn recieve_value<T: DeserializeOwned>(&mut self) -> Result<(bool, T)> {
         loop {
             poll(&mut self.fd, -1).ok();
             let info = self.stream.recieve();
@@ -687,7 +2764,15 @@ impl ClientStream {
             }
         }
     }
+    fn get_value(&mut self) -> Result<(bool, CwmResponse)> {
+        self.recieve_value()
+    }
     fn send_value(&mut self, val: &ClientRequest) -> Result<()> {
+        if let Some(feature) = val.feature() {
+            if !self.features.iter().any(|name| name == feature) {
+                bail!("the server does not support {}", feature)
+            }
+        }
         if self.stream.send(val) {
             Ok(())
         } else {
@@ -698,7 +2783,15 @@ impl ClientStream {
 
 fn main() -> Result<()> {
     SimpleLogger::init(LevelFilter::Error, Config::default()).unwrap();
-    let args = Opts::from_args()?;
+    if std::env::args().any(|x| x == "-h" || x == "--help") {
+        println!("usage: cwm-client <command>");
+        Opts::print_usage();
+        return Ok(());
+    }
+    let args = match Opts::from_args() {
+        Ok(args) => args,
+        Err(e) => bail!("{}\nsee cwm-client --help", e),
+    };
     let stream = ClientStream::new()?;
     match args {
         Opts::Node(args) => args.process(stream),
@@ -709,5 +2802,6 @@ fn main() -> Result<()> {
         Opts::Command(args) => args.process(stream),
         Opts::Config(args) => args.process(stream),
         Opts::Rule(args) => args.process(stream),
+        Opts::Bspc(args) => args.process(stream),
     }
 }
@@ -1,28 +1,15 @@
-use simplelog::*;
-use std::fs::File;
-use std::time::SystemTime;
+use log::LevelFilter;
+use std::path::PathBuf;
 
 fn main() {
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+    cwm::logging::init(LevelFilter::Info);
 
-    CombinedLogger::init(vec![
-        TermLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            TerminalMode::Mixed,
-            ColorChoice::Auto,
-        ),
-        WriteLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            File::create(format!("/tmp/cwm-{:X}.log", time)).unwrap(),
-        ),
-    ])
-    .unwrap();
-
-    cwm::run_wm();
+    // an in-place restart passes "--restore <state file>"
+    let mut args = std::env::args().skip(1);
+    let restore = match (args.next().as_deref(), args.next()) {
+        (Some("--restore"), Some(path)) => Some(PathBuf::from(path)),
+        _ => None,
+    };
+    cwm::run_wm(restore);
     print!("Done");
 }
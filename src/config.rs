@@ -1,11 +1,174 @@
-use crate::utils::mul_alpha;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::core::{Atom, Side};
+use crate::utils::{mul_alpha, Rect};
 
 pub const IGNORED_MODS: [u16; 2] = [0, (1 << 1)]; //normal mask, ignore caplock
 pub const IGNORED_MASK: u16 = !IGNORED_MODS[1];
+// the tiling area keeps at least this size however large the gap and margins get
+const MIN_TILING_SIZE: u16 = 32;
+
+// the modifiers held and buttons pressed to move or resize a node with the pointer
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct PointerBindings {
+    pub mods: u16,
+    pub move_button: u8,
+    pub resize_button: u8,
+}
+
+impl PointerBindings {
+    // without modifiers the drags would take every click from the focused client
+    pub fn is_valid(&self) -> bool {
+        self.mods & IGNORED_MASK != 0
+            && self.move_button != self.resize_button
+            && (1..=5).contains(&self.move_button)
+            && (1..=5).contains(&self.resize_button)
+    }
+
+    pub fn takes(&self, button: u8, mods: u16) -> bool {
+        mods == self.mods & IGNORED_MASK
+            && (button == self.move_button || button == self.resize_button)
+    }
+}
+
+impl Default for PointerBindings {
+    fn default() -> Self {
+        Self {
+            mods: 1 << 6, // mod4
+            move_button: 1,
+            resize_button: 3,
+        }
+    }
+}
+
+// switching tags by dragging sideways from an edge of a monitor or with a binding held, a drag
+// to the right goes to the previous tag
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Swipe {
+    pub edge: u16, // width of the strips along the left and right of each monitor, 0 for none
+    pub binding: Option<(u8, u16)>, // a button and modifiers that start a swipe anywhere
+    pub threshold: u16, // how far the pointer goes sideways for each tag
+}
+
+impl Swipe {
+    // like the pointer bindings, a binding without modifiers would take every click
+    pub fn is_valid(&self) -> bool {
+        self.threshold > 0
+            && match self.binding {
+                Some((button, mods)) => (1..=5).contains(&button) && mods & IGNORED_MASK != 0,
+                None => true,
+            }
+    }
+}
+
+impl Default for Swipe {
+    fn default() -> Self {
+        Self {
+            edge: 0,
+            binding: None,
+            threshold: 100,
+        }
+    }
+}
+
+// what a button pressed on the border of a client does to it
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum FrameAction {
+    Close,
+    Kill,
+    Hide,
+    Floating, // these toggle
+    Fullscreen,
+    Sticky,
+    Raise, // one stack layer up or down
+    Lower,
+}
+
+// what to do when the requested tag is already shown on another monitor
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum TagSwitchMode {
+    Swap,
+    Greedy,
+    Noop,
+}
+
+// how hidden clients and the clients on tags that aren't shown are hidden
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum HideStrategy {
+    Unmap,
+    Offscreen,
+}
+
+// what happens to a new tiled client when its tag already has the most allowed
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum OverflowPolicy {
+    Hide,
+    NextEmpty, // the next empty tag, or hidden if there is none
+}
+
+// an overlay that marks the focused client, for setups without borders
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum FocusIndicator {
+    Off,
+    Edge(u16),   // a frame of this width along the inside of the client
+    Corner(u16), // a square of this size in the top left corner
+}
+
+// where floating windows without a position of their own open
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum FloatPlacement {
+    Center,
+    Cascade(i16, i16), // offset from the last window placed on the tag
+}
+
+// a theme value that can be overridden per monitor
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum ThemeField {
+    Gap,
+    BorderWidth,
+    Margin(Side),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ThemeOverride {
+    pub gap: Option<u16>,
+    pub border_width: Option<u16>,
+    pub top_margin: Option<i16>,
+    pub bottom_margin: Option<i16>,
+    pub left_margin: Option<i16>,
+    pub right_margin: Option<i16>,
+}
+
+impl ThemeOverride {
+    // none removes the override
+    pub fn set(&mut self, field: ThemeField, value: Option<i16>) {
+        let unsigned = value.map(|value| value.max(0) as u16);
+        match field {
+            ThemeField::Gap => self.gap = unsigned,
+            ThemeField::BorderWidth => self.border_width = unsigned,
+            ThemeField::Margin(Side::Left) => self.left_margin = value,
+            ThemeField::Margin(Side::Right) => self.right_margin = value,
+            ThemeField::Margin(Side::Top) => self.top_margin = value,
+            ThemeField::Margin(Side::Bottom) => self.bottom_margin = value,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.gap.is_none()
+            && self.border_width.is_none()
+            && self.top_margin.is_none()
+            && self.bottom_margin.is_none()
+            && self.left_margin.is_none()
+            && self.right_margin.is_none()
+    }
+}
 
 pub struct Theme {
     pub border_width: u16,
     pub gap: u16,
+    // replaces gap with a percentage of the smaller side of each monitor
+    pub gap_percent: Option<f32>,
     pub top_margin: i16,
     pub bottom_margin: i16,
     pub left_margin: i16,
@@ -16,9 +179,66 @@ pub struct Theme {
     pub window_min_height: u16,
     pub border_color_focused: u32,
     pub border_color_unfocused: u32,
+    // the focused client of a tag on a monitor that doesn't have input focus
+    pub border_color_inactive: u32,
     pub selection_gap: u16,
     pub presel_color: u32,
     pub sel_color: u32,
+    pub tag_switch_mode: TagSwitchMode,
+    pub hide_strategy: HideStrategy,
+    pub tiled_limit: Option<usize>,
+    pub overflow_policy: OverflowPolicy,
+    pub monocle_floating: bool,
+    pub monocle_borderless: bool,
+    pub monocle_gapless: bool,
+    // tell compositors to only draw shadows for floating clients
+    pub floating_shadows_only: bool,
+    // hiding or showing a client does the same to the rest of its window group
+    pub group_hide: bool,
+    // new clients split the tiled client under the pointer instead of the focused one
+    pub insert_at_pointer: bool,
+    // the border of the focused client flashes this color after a tag switch, 0 ms disables it
+    pub focus_flash_color: u32,
+    pub focus_flash_ms: u32,
+    // the visual bell flashes the border of the window that rang it or the focused one
+    pub bell_flash_color: u32,
+    pub bell_flash_ms: u32,
+    pub focus_indicator: FocusIndicator,
+    pub focus_indicator_color: u32,
+    // tiled clients smaller than this or their own min size are collapsed, none disables it
+    pub min_tile_size: Option<(u16, u16)>,
+    // urgency of a client clears by itself after this long, 0 keeps it until it is focused
+    pub urgent_timeout_ms: u32,
+    // subscribers hear about idleness after this long without input or focus changes, 0 disables it
+    pub idle_timeout_ms: u32,
+    // a presel left alone this long is cancelled, 0 keeps it until it is used or cancelled
+    pub presel_timeout_ms: u32,
+    // focusing and the tag hooks wait until tag switches stop for this long, 0 doesn't wait
+    pub focus_settle_ms: u32,
+    // tiled clients wait this long to fill the space of a closed client, 0 fills it right away
+    pub close_fade_ms: u32,
+    // floating windows open where the last window of their class and instance was closed or tiled
+    pub remember_floating: bool,
+    pub float_placement: FloatPlacement,
+    // move the selection to the focused client instead of cancelling it on focus or tag changes
+    pub selection_follow: bool,
+    // focus leaves a monitor that goes off without being removed and comes back when it is on
+    pub refocus_off_monitors: bool,
+    pub pointer_bindings: PointerBindings,
+    // by button and modifiers, the move and resize bindings win over these
+    pub frame_buttons: HashMap<(u8, u16), FrameAction>,
+    // two presses of the first button without modifiers on the border of a client within
+    // double_click_ms
+    pub frame_double_click: Option<FrameAction>,
+    pub double_click_ms: u32,
+    pub swipe: Swipe,
+    pub panels_above_fullscreen: bool,
+    // input passes through desktop windows to the root window
+    pub desktop_click_through: bool,
+    // keyed by the atom of the monitor name, so overrides can be set before the monitor exists
+    pub monitors: HashMap<Atom, ThemeOverride>,
+    // the smaller side of every monitor, what gap_percent is taken of
+    pub monitor_sizes: HashMap<Atom, u16>,
 }
 
 impl Default for Theme {
@@ -26,6 +246,7 @@ impl Default for Theme {
         Self {
             border_width: 1,
             gap: 4,
+            gap_percent: None,
             top_margin: 4,
             left_margin: 4,
             right_margin: 4,
@@ -36,9 +257,205 @@ impl Default for Theme {
             window_min_height: 40,
             border_color_focused: mul_alpha(0xAAFF0000),
             border_color_unfocused: mul_alpha(0xAAFFFFFF),
+            border_color_inactive: mul_alpha(0xAAFFFFFF),
             selection_gap: 5,
             presel_color: mul_alpha(0x6600FF00),
             sel_color: mul_alpha(0x660000FF),
+            tag_switch_mode: TagSwitchMode::Swap,
+            hide_strategy: HideStrategy::Unmap,
+            tiled_limit: None,
+            overflow_policy: OverflowPolicy::Hide,
+            monocle_floating: true,
+            monocle_borderless: false,
+            monocle_gapless: false,
+            floating_shadows_only: false,
+            group_hide: false,
+            insert_at_pointer: false,
+            focus_flash_color: mul_alpha(0xFFFFAA00),
+            focus_flash_ms: 0,
+            bell_flash_color: mul_alpha(0xFFFF5500),
+            bell_flash_ms: 0,
+            focus_indicator: FocusIndicator::Off,
+            focus_indicator_color: mul_alpha(0xFFFFAA00),
+            min_tile_size: None,
+            urgent_timeout_ms: 0,
+            idle_timeout_ms: 0,
+            presel_timeout_ms: 0,
+            focus_settle_ms: 0,
+            close_fade_ms: 0,
+            remember_floating: false,
+            float_placement: FloatPlacement::Center,
+            selection_follow: false,
+            refocus_off_monitors: false,
+            pointer_bindings: PointerBindings::default(),
+            frame_buttons: HashMap::new(),
+            frame_double_click: None,
+            double_click_ms: 400,
+            swipe: Swipe::default(),
+            panels_above_fullscreen: false,
+            desktop_click_through: false,
+            monitors: HashMap::new(),
+            monitor_sizes: HashMap::new(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn margin_mut(&mut self, side: Side) -> &mut i16 {
+        match side {
+            Side::Left => &mut self.left_margin,
+            Side::Right => &mut self.right_margin,
+            Side::Top => &mut self.top_margin,
+            Side::Bottom => &mut self.bottom_margin,
+        }
+    }
+
+    pub fn set_monitor_override(&mut self, monitor: Atom, field: ThemeField, value: Option<i16>) {
+        let theme = self.monitors.entry(monitor).or_default();
+        theme.set(field, value);
+        if theme.is_empty() {
+            self.monitors.remove(&monitor);
+        }
+    }
+
+    fn monitor(&self, monitor: Option<Atom>) -> Option<&ThemeOverride> {
+        monitor.and_then(|monitor| self.monitors.get(&monitor))
+    }
+
+    pub fn gap(&self, monitor: Option<Atom>) -> u16 {
+        if let Some(gap) = self.monitor(monitor).and_then(|theme| theme.gap) {
+            return gap;
+        }
+        let size = monitor.and_then(|monitor| self.monitor_sizes.get(&monitor));
+        match (self.gap_percent, size) {
+            (Some(percent), Some(&size)) => (size as f32 * percent / 100.0).round() as u16,
+            _ => self.gap,
+        }
+    }
+
+    pub fn border_width(&self, monitor: Option<Atom>) -> u16 {
+        self.monitor(monitor)
+            .and_then(|theme| theme.border_width)
+            .unwrap_or(self.border_width)
+    }
+
+    // never negative, whatever was configured
+    pub fn margin(&self, monitor: Option<Atom>, side: Side) -> i16 {
+        let theme = self.monitor(monitor);
+        let margin = match side {
+            Side::Left => theme
+                .and_then(|theme| theme.left_margin)
+                .unwrap_or(self.left_margin),
+            Side::Right => theme
+                .and_then(|theme| theme.right_margin)
+                .unwrap_or(self.right_margin),
+            Side::Top => theme
+                .and_then(|theme| theme.top_margin)
+                .unwrap_or(self.top_margin),
+            Side::Bottom => theme
+                .and_then(|theme| theme.bottom_margin)
+                .unwrap_or(self.bottom_margin),
+        };
+        margin.max(0)
+    }
+
+    // shrinks the free area of a monitor by its gap and margins, down to MIN_TILING_SIZE
+    pub fn tiling_rect(&self, monitor: Option<Atom>, available: &Rect) -> Rect {
+        let gap = self.gap(monitor);
+        let margin = |side| self.margin(monitor, side) as u16;
+        // the offset and size along one axis
+        let shrink = |size: u16, before: u16, after: u16| {
+            let taken = gap
+                .saturating_mul(2)
+                .saturating_add(before)
+                .saturating_add(after);
+            let shrunk = size.saturating_sub(taken).max(MIN_TILING_SIZE.min(size));
+            (gap.saturating_add(before).min(size - shrunk) as i16, shrunk)
+        };
+        let (x, width) = shrink(available.width, margin(Side::Left), margin(Side::Right));
+        let (y, height) = shrink(available.height, margin(Side::Top), margin(Side::Bottom));
+        Rect::new(available.x + x, available.y + y, width, height)
+    }
+
+    // the inverse of tiling_rect, as long as it didn't have to keep the minimum size
+    pub fn available_rect(&self, monitor: Option<Atom>, tiling: &Rect) -> Rect {
+        let gap = self.gap(monitor) as i32;
+        let margin = |side| self.margin(monitor, side) as i32;
+        let grow = |pos: i16, size: u16, before: i32, after: i32| {
+            let pos = (pos as i32 - gap - before).max(i16::MIN as i32);
+            let size = (size as i32 + gap * 2 + before + after).min(u16::MAX as i32);
+            (pos as i16, size as u16)
+        };
+        let (x, width) = grow(
+            tiling.x,
+            tiling.width,
+            margin(Side::Left),
+            margin(Side::Right),
+        );
+        let (y, height) = grow(
+            tiling.y,
+            tiling.height,
+            margin(Side::Top),
+            margin(Side::Bottom),
+        );
+        Rect::new(x, y, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme(gap: u16, margin: i16) -> Theme {
+        let mut theme = Theme {
+            gap,
+            ..Theme::default()
+        };
+        for side in [Side::Left, Side::Right, Side::Top, Side::Bottom] {
+            *theme.margin_mut(side) = margin;
         }
+        theme
+    }
+
+    #[test]
+    fn tiling_rect_shrinks_by_gap_and_margins() {
+        let rect = theme(5, 10).tiling_rect(None, &Rect::new(0, 20, 1000, 500));
+        assert_eq!(rect, Rect::new(15, 35, 970, 470));
+    }
+
+    #[test]
+    fn tiling_rect_keeps_a_minimum_size() {
+        let available = Rect::new(100, 0, 800, 600);
+        for theme in [theme(0, i16::MAX), theme(u16::MAX, 0), theme(400, 400)] {
+            let rect = theme.tiling_rect(None, &available);
+            assert_eq!(
+                (rect.width, rect.height),
+                (MIN_TILING_SIZE, MIN_TILING_SIZE)
+            );
+            assert!(available.contains_rect(&rect));
+        }
+    }
+
+    #[test]
+    fn tiling_rect_of_a_tiny_area_fits_in_it() {
+        let available = Rect::new(0, 0, 10, 10);
+        let rect = theme(4, 4).tiling_rect(None, &available);
+        assert_eq!(rect, available);
+    }
+
+    #[test]
+    fn negative_margins_count_as_zero() {
+        let theme = theme(0, -50);
+        assert_eq!(theme.margin(None, Side::Left), 0);
+        let available = Rect::new(0, 0, 800, 600);
+        assert_eq!(theme.tiling_rect(None, &available), available);
+    }
+
+    #[test]
+    fn available_rect_undoes_tiling_rect() {
+        let theme = theme(6, 12);
+        let available = Rect::new(-1920, 0, 1920, 1080);
+        let tiling = theme.tiling_rect(None, &available);
+        assert_eq!(theme.available_rect(None, &tiling), available);
     }
 }
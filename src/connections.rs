@@ -1,29 +1,43 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::info;
 use nix::poll::{poll, PollFd, PollFlags};
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::prelude::*;
-use std::net::Shutdown;
+use std::collections::{HashMap, HashSet};
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use x11rb::connection::Connection;
 use x11rb::protocol::render::*;
 use x11rb::protocol::shape::{ConnectionExt, *};
 use x11rb::protocol::xproto::*;
-use x11rb::rust_connection::RustConnection;
 use x11rb::wrapper::ConnectionExt as _;
 use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT};
 
+use crate::animation::Animations;
+use crate::config::IGNORED_MASK;
+use crate::core::{Backend, Client, NodeContents, StackPos, Tag};
+use crate::display::Dpy;
+use crate::float_store::FloatStore;
 use crate::hooks::Hooks;
-use crate::tag::{NodeContents, Split, Tag};
+use crate::logging;
+use crate::metrics::Metrics;
+use crate::theme_file::ThemeWatch;
+use crate::timer::{Timeout, Timers};
 use crate::utils::{mul_alpha, Rect};
+use crate::x11::X11Client;
 use crate::{AtomCollection, WindowLocation, WindowManager};
 
-pub use crate::config::Theme;
-pub use crate::rules::Rule;
-pub use crate::tag::{Side, StackLayer};
+pub use crate::config::{
+    FloatPlacement, FocusIndicator, FrameAction, HideStrategy, OverflowPolicy, PointerBindings,
+    Swipe, TagSwitchMode, Theme, ThemeField,
+};
+pub use crate::core::{Rule, Side, Split, StackLayer, TreeNode};
+pub use crate::icon::Icon;
+pub use crate::protocol::{
+    features, socket_path, ClientRequest, Corner, CwmResponse, CycleFilter, Encoding, Geometry,
+    Hello, HelloReply, HiddenClient, HiddenSelection, Length, MatchSpec, Minimap, MinimapWindow,
+    MonitorLayers, MotionKind, NodeSelector, RuleTest, SetArg, StateArg, Stream, TagSelection,
+    TagState, TagTemplate, TemplateSpawn, WindowFlags, PROTOCOL_VERSION,
+};
 
 pub enum SelectionContent {
     Presel(Atom, usize, Presel),
@@ -34,55 +48,89 @@ pub enum SelectionContent {
 pub struct Selection {
     pub win: Window,
     pub sel: SelectionContent,
+    pub marks: Vec<Mark>,
+    pub focus: FocusOverlay,
+}
+
+// the overlay of the focus indicator and the client, rect and style it was last drawn for
+pub struct FocusOverlay {
+    overlay: Window,
+    shown: Option<(Window, Rect, FocusIndicator)>,
+}
+
+// a client in the multi selection, node commands without a target apply to all of them
+pub struct Mark {
+    pub client: Window,
+    overlay: Window,
+    rect: Option<Rect>,
+}
+
+// input passes through so the overlay never takes focus or clicks
+fn create_overlay(
+    dpy: &Dpy,
+    root: Window,
+    vis: &VisualConfig,
+    color: u32,
+    event_mask: EventMask,
+) -> Result<Window> {
+    let win = dpy.generate_id()?;
+    create_window(
+        dpy,
+        vis.depth(),
+        win,
+        root,
+        0,
+        0,
+        100,
+        100,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        vis.visualid(),
+        &CreateWindowAux::new()
+            .colormap(vis.colormap())
+            .background_pixel(color)
+            .border_pixel(0)
+            .event_mask(event_mask),
+    )?;
+    dpy.shape_rectangles(
+        SO::SET,
+        SK::INPUT,
+        ClipOrdering::UNSORTED,
+        win,
+        0,
+        0,
+        &[Rectangle {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        }],
+    )?;
+    Ok(win)
 }
 
 impl Selection {
-    fn new(dpy: &RustConnection, root: Window, vis: &VisualConfig) -> Result<Self> {
-        let win = dpy.generate_id()?;
-        create_window(
+    fn new(dpy: &Dpy, root: Window, vis: &VisualConfig) -> Result<Self> {
+        let win = create_overlay(
             dpy,
-            vis.depth(),
-            win,
             root,
-            0,
-            0,
-            100,
-            100,
-            0,
-            WindowClass::INPUT_OUTPUT,
-            vis.visualid(),
-            &CreateWindowAux::new()
-                .colormap(vis.colormap())
-                .background_pixel(mul_alpha(0x6600FF00))
-                .border_pixel(0)
-                .event_mask(EventMask::ENTER_WINDOW),
-        )?;
-        dpy.shape_rectangles(
-            SO::SET,
-            SK::INPUT,
-            ClipOrdering::UNSORTED,
-            win,
-            0,
-            0,
-            &[Rectangle {
-                x: 0,
-                y: 0,
-                width: 0,
-                height: 0,
-            }],
+            vis,
+            mul_alpha(0x6600FF00),
+            EventMask::ENTER_WINDOW,
         )?;
+        let focus = create_overlay(dpy, root, vis, mul_alpha(0xFFFFAA00), EventMask::NO_EVENT)?;
         Ok(Self {
             win,
             sel: SelectionContent::None,
+            marks: Vec::new(),
+            focus: FocusOverlay {
+                overlay: focus,
+                shown: None,
+            },
         })
     }
 
-    pub fn presel(
-        &mut self,
-        dpy: &RustConnection,
-        tag: Atom,
-        node: usize,
-    ) -> Result<Option<Presel>> {
+    pub fn presel(&mut self, dpy: &Dpy, tag: Atom, node: usize) -> Result<Option<Presel>> {
         Ok(match &self.sel {
             SelectionContent::Presel(t, n, presel) if *t == tag && *n == node => {
                 let presel = presel.clone();
@@ -94,6 +142,23 @@ impl Selection {
         })
     }
 
+    pub fn tag(&self) -> Option<Atom> {
+        match &self.sel {
+            SelectionContent::Presel(tag, ..) | SelectionContent::Node(tag, _) => Some(*tag),
+            SelectionContent::None => None,
+        }
+    }
+
+    // keeps the kind of selection and any presel settings
+    pub fn anchor(&mut self, tag_: Atom, node_: usize) {
+        if let SelectionContent::Presel(tag, node, ..) | SelectionContent::Node(tag, node) =
+            &mut self.sel
+        {
+            *tag = tag_;
+            *node = node_;
+        }
+    }
+
     pub fn sel(&mut self) -> Option<(Atom, usize)> {
         if let SelectionContent::Node(tag, node) = &self.sel {
             Some((*tag, *node))
@@ -102,17 +167,12 @@ impl Selection {
         }
     }
 
-    pub fn show(&self, dpy: &RustConnection) -> Result<()> {
+    pub fn show(&self, dpy: &Dpy) -> Result<()> {
         map_window(dpy, self.win)?;
         Ok(())
     }
 
-    pub fn hide(
-        &mut self,
-        dpy: &RustConnection,
-        tag_: Option<Atom>,
-        node_: Option<usize>,
-    ) -> Result<()> {
+    pub fn hide(&mut self, dpy: &Dpy, tag_: Option<Atom>, node_: Option<usize>) -> Result<()> {
         match &mut self.sel {
             SelectionContent::Presel(tag, node, ..) | SelectionContent::Node(tag, node) => {
                 if tag_.map(|x| *tag == x).unwrap_or(true)
@@ -146,10 +206,14 @@ impl Default for Presel {
 pub struct VisualConfig(Option<(u8, Visualid, Colormap, Pictformat)>);
 
 impl VisualConfig {
-    pub fn new(dpy: &RustConnection, root: Window, screen: usize) -> Result<Self> {
+    pub fn new(dpy: &Dpy, root: Window, screen: usize) -> Result<Self> {
         let info = query_pict_formats(dpy)?.reply()?;
         let formats: HashMap<_, _> = info.formats.iter().map(|x| (x.id, x)).collect();
-        for Pictdepth { depth, visuals } in &info.screens[screen].depths {
+        let depths = match info.screens.get(screen) {
+            Some(screen) => &screen.depths,
+            None => return Ok(Self(None)),
+        };
+        for Pictdepth { depth, visuals } in depths {
             for visual in visuals {
                 if let Some(format) = formats.get(&visual.format) {
                     if format.type_ == PictType::DIRECT && format.direct.alpha_mask == 0xFF {
@@ -178,7 +242,7 @@ impl VisualConfig {
 }
 
 pub struct Aux {
-    pub dpy: RustConnection,
+    pub dpy: Dpy,
     listener: UnixListener,
     streams: Vec<Stream>,
     poll_fds: Vec<PollFd>,
@@ -188,99 +252,79 @@ pub struct Aux {
     pub hooks: Hooks,
     pub atoms: AtomCollection,
     pub rules: Vec<Rule>,
+    pub class_tags: HashMap<String, (Atom, bool)>, // class -> tag, volatile, kept across reloads
+    pub templates: HashMap<String, TagTemplate>,
+    // class -> tag and split for the next window of the class, from spawned templates
+    pub class_pins: HashMap<String, (Atom, Option<(Side, f32)>)>,
     pub vis: VisualConfig,
     pub selection: Selection,
+    // never mapped, clients stack below it and panels above it when they cover fullscreen clients
+    pub panel_anchor: Window,
+    pub timers: Timers,
+    pub theme_watch: ThemeWatch,
+    pub animations: Animations,
+    pub metrics: Metrics,
+    pub last_activity: Instant,
+    pub float_store: FloatStore,
+    // monitors whose outputs are all off, their windows are moved without animating
+    pub sleeping: HashSet<Atom>,
+    // the button grabs are let go while a passthrough client is focused
+    pub grabs_released: bool,
+    // the state of managed windows that the layout core doesn't need
+    pub x11_clients: HashMap<Window, X11Client>,
 }
 
-pub struct Stream {
-    stream: UnixStream,
-    length: usize,
-    reading: bool,
-    data: Vec<u8>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum HiddenSelection {
-    All,
-    First,
-    Last,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum TagSelection {
-    Name(String),
-    Index(usize),
-    Focused(Option<u32>),
-    Next(Option<u32>),
-    Prev(Option<u32>),
-    Last(Option<u32>),
-    Id(u32),
+// a socket is stale if nothing answers a ping on it
+fn instance_running(socket: &str) -> bool {
+    let stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(_) => return false,
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_millis(100)));
+    let mut stream = Stream::new(stream);
+    if !stream.send(&Hello {
+        version: PROTOCOL_VERSION,
+    }) {
+        return false;
+    }
+    match stream.recieve_timeout(Duration::from_secs(1)) {
+        Some(HelloReply::Accepted { .. }) => {
+            stream.send(&ClientRequest::Ping);
+            matches!(
+                stream.recieve_timeout(Duration::from_secs(1)),
+                Some(CwmResponse::Pong)
+            )
+        }
+        // an instance with a different protocol version is still running
+        Some(HelloReply::Rejected { .. }) => true,
+        None => false,
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum ClientRequest {
-    MonitorFocus(Option<u32>),
+// what happens to a connection once its request has been handled
+enum Reply {
+    Keep(Option<CwmResponse>),
+    Close,
+    MonitorFocus(u32),
     TagState,
-    FocusedWindow(TagSelection),
-    FocusedTag(Option<u32>),
-    FocusedMonitor,
-    Quit,
-    Reload,
-    CloseClient(Option<u32>, bool),
-    SetLayer(Option<u32>, SetArg<StackLayer>),
-    SetFullscreen(Option<u32>, SetArg<bool>),
-    SetFloating(Option<u32>, SetArg<bool>),
-    SetSticky(Option<u32>, SetArg<bool>),
-    SetHidden(Option<u32>, SetArg<bool>),
-    SetMonocle(TagSelection, SetArg<bool>),
-    Show(TagSelection, HiddenSelection),
-    ResizeWindow(Option<u32>, Side, i16), // +grow, -shrink
-    MoveWindow(Option<u32>, Side, u16),   // floating move amnt, tiling swap neighbour
-    SelectNeighbour(Option<u32>, Side),   // select tiling neighbour
-    CycleWindow(bool),
-    FocusTag(Option<u32>, TagSelection, bool),
-    SetWindowTag(Option<u32>, TagSelection, bool),
-    TagName(TagSelection),
-    MonitorName(Option<u32>),
-    ConfigBorderFocused(u32),
-    ConfigBorderUnfocused(u32),
-    ConfigBorderWidth(u16),
-    ConfigGap(u16),
-    ConfigMargin(Side, i16),
-    AddRule(Rule),
-    AddTag(String),
-    RemoveTag(TagSelection),
-    Select(Option<u32>),
-    SelectDir(Side),
-    SelectParent,
-    PreselAmt(f32),
-    SelectionCancel,
-    Rotate(bool),
-    ViewLayers(TagSelection),
-    ViewStack(TagSelection),
-    ViewClients(TagSelection),
-    IgnoreSizeHints(Option<u32>),
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct TagState {
-    pub name: String,
-    pub focused: Option<u32>,
-    pub urgent: bool,
-    pub empty: bool,
+    Tree(Option<u32>),
+    Fullscreen,
+    Wait(MatchSpec, Option<Duration>),
+    Collapsed,
+    Idle,
+    Layout,
+    Presel,
+    Property(u32, Atom),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum CwmResponse {
-    MonitorFocusedClient(Option<String>),
-    TagState(Vec<TagState>, u32),
-    FocusedMonitor(u32),
-    FocusedTag(u32),
-    FocusedWindow(Option<u32>),
-    Name(String),
-    ViewLayers(Vec<Vec<usize>>),
-    ViewStack(Vec<usize>),
-    ViewClients(Vec<(usize, u32, Option<String>)>),
+impl Reply {
+    // a query the server can't answer closes the connection instead of leaving the client waiting
+    fn query(response: Option<CwmResponse>) -> Self {
+        match response {
+            Some(response) => Self::Keep(Some(response)),
+            None => Self::Close,
+        }
+    }
 }
 
 impl Drop for Aux {
@@ -289,35 +333,47 @@ impl Drop for Aux {
     }
 }
 
-impl Drop for Stream {
-    fn drop(&mut self) {
-        let _ = self.stream.shutdown(Shutdown::Both);
-    }
-}
-
-impl AsRawFd for Stream {
-    fn as_raw_fd(&self) -> i32 {
-        self.stream.as_raw_fd()
-    }
-}
-
 impl Aux {
-    pub(crate) fn new(dpy: RustConnection, root: u32, screen: usize) -> Result<Self> {
-        let socket = format!("/tmp/cwm-{}.sock", whoami::username());
-        let _ = std::fs::remove_file(&socket); // possibly use this to check if it is already running.
-        let listener = UnixListener::bind(&socket).unwrap();
+    // the display, listener, timer and theme watch fds come before the streams
+    const STREAM_FDS: usize = 4;
+
+    pub(crate) fn new(dpy: Dpy, root: u32, screen: usize, socket: String) -> Result<Self> {
+        if instance_running(&socket) {
+            bail!("another instance of cwm is already listening on {}", socket);
+        }
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket)?;
         listener
             .set_nonblocking(true)
             .expect("Couldn't set non blocking");
 
+        let timers = Timers::new()?;
+        let theme_watch = ThemeWatch::new()?;
         let poll_fds = vec![
-            PollFd::new(dpy.stream().as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(dpy.raw_fd(), PollFlags::POLLIN),
             PollFd::new(listener.as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(timers.as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(theme_watch.as_raw_fd(), PollFlags::POLLIN),
         ];
 
         let atoms = AtomCollection::new(&dpy)?.reply()?;
         let vis = VisualConfig::new(&dpy, root, screen)?;
         let selection = Selection::new(&dpy, root, &vis)?;
+        let panel_anchor = dpy.generate_id()?;
+        create_window(
+            &dpy,
+            0,
+            panel_anchor,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )?;
 
         dpy.change_property32(
             PropMode::APPEND,
@@ -327,6 +383,7 @@ impl Aux {
             &[
                 atoms._NET_WM_STATE,
                 atoms._NET_WM_STATE_FULLSCREEN,
+                atoms._NET_WM_STATE_HIDDEN,
                 atoms._NET_WM_STATE_DEMANDS_ATTENTION,
                 atoms._NET_ACTIVE_WINDOW,
             ],
@@ -343,13 +400,123 @@ impl Aux {
             hooks: Hooks::new(),
             atoms,
             rules: Vec::new(),
+            class_tags: HashMap::new(),
+            templates: HashMap::new(),
+            class_pins: HashMap::new(),
             vis,
             selection,
+            panel_anchor,
+            timers,
+            theme_watch,
+            animations: Animations::new(),
+            metrics: Metrics::default(),
+            last_activity: Instant::now(),
+            float_store: FloatStore::default(),
+            sleeping: HashSet::new(),
+            grabs_released: false,
+            x11_clients: HashMap::new(),
         })
     }
 
+    // streams with output left also wake the loop once their socket has room for it
     pub(crate) fn wait_for_updates(&mut self) {
+        for (poll_fd, stream) in self.poll_fds[Self::STREAM_FDS..]
+            .iter_mut()
+            .zip(&self.streams)
+        {
+            let events = if stream.pending() {
+                PollFlags::POLLIN | PollFlags::POLLOUT
+            } else {
+                PollFlags::POLLIN
+            };
+            *poll_fd = PollFd::new(stream.as_raw_fd(), events);
+        }
+        let len = self.poll_fds.len();
+        for fd in self.hooks.pending_fds() {
+            self.poll_fds.push(PollFd::new(fd, PollFlags::POLLOUT));
+        }
         poll(&mut self.poll_fds, -1).ok();
+        self.poll_fds.truncate(len);
+    }
+
+    // writes what the sockets take of every stream's queued output
+    pub fn flush_streams(&mut self) {
+        for stream in self.streams.iter_mut().filter(|stream| stream.pending()) {
+            stream.flush();
+        }
+        self.hooks.flush();
+    }
+
+    // input or a focus change, ends idleness and pushes the idle timeout back
+    pub fn activity(&mut self) -> Result<()> {
+        self.last_activity = Instant::now();
+        if self.hooks.is_idle() {
+            self.hooks.idle(false);
+            self.arm_idle()?;
+        }
+        Ok(())
+    }
+
+    pub fn arm_idle(&self) -> Result<()> {
+        match self.theme.idle_timeout_ms {
+            0 => self.timers.cancel(Timeout::Idle),
+            ms => self
+                .timers
+                .set(Timeout::Idle, Duration::from_millis(ms as u64)),
+        }
+    }
+
+    // the timer is only moved once it expires, so activity stays cheap
+    pub fn idle_expired(&mut self) -> Result<()> {
+        let timeout = Duration::from_millis(self.theme.idle_timeout_ms as u64);
+        if timeout.is_zero() {
+            return Ok(());
+        }
+        let idle_for = self.last_activity.elapsed();
+        if idle_for >= timeout {
+            self.hooks.idle(true);
+        } else {
+            self.timers.set(Timeout::Idle, timeout - idle_for)?;
+        }
+        Ok(())
+    }
+
+    // restarted whenever a presel is shown or changed
+    pub fn arm_presel(&self) -> Result<()> {
+        match (&self.selection.sel, self.theme.presel_timeout_ms) {
+            (SelectionContent::Presel(..), ms) if ms > 0 => self
+                .timers
+                .set(Timeout::Presel, Duration::from_millis(ms as u64)),
+            _ => self.timers.cancel(Timeout::Presel),
+        }
+    }
+
+    pub fn toggle_mark(&mut self, client: Window) -> Result<()> {
+        let marks = &mut self.selection.marks;
+        if let Some(idx) = marks.iter().position(|mark| mark.client == client) {
+            destroy_window(&self.dpy, marks.remove(idx).overlay)?;
+        } else {
+            let overlay = create_overlay(
+                &self.dpy,
+                self.root,
+                &self.vis,
+                self.theme.sel_color,
+                EventMask::NO_EVENT,
+            )?;
+            marks.push(Mark {
+                client,
+                overlay,
+                rect: None,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn clear_marks(&mut self) -> Result<()> {
+        for mark in self.selection.marks.drain(..) {
+            destroy_window(&self.dpy, mark.overlay)?;
+        }
+        Ok(())
     }
 
     pub fn resize_selection(&mut self, tag: &Tag) -> Result<()> {
@@ -396,67 +563,64 @@ impl Aux {
     }
 }
 
-impl Stream {
-    pub fn new(stream: UnixStream) -> Self {
-        Self {
-            stream,
-            length: 0,
-            reading: false,
-            data: Vec::new(),
-        }
+impl Backend for Aux {
+    fn theme(&self) -> &Theme {
+        &self.theme
     }
 
-    pub fn send<T: Serialize>(&mut self, item: &T) -> bool {
-        let data = bincode::serialize(item).unwrap();
-        match self
-            .stream
-            .write_all(bincode::serialize(&(data.len() as u32)).unwrap().as_slice())
-            .and(self.stream.write_all(data.as_slice()))
-        {
-            Ok(_) => true,
-            Err(e) => {
-                info!("{:?}", e);
-                false
-            }
-        }
+    fn configure_client(
+        &self,
+        win: Window,
+        from: Option<Rect>,
+        rect: &Rect,
+        border: u16,
+    ) -> Result<()> {
+        Aux::configure_client(self, win, from, rect, border)
     }
 
-    pub fn get_bytes(&mut self) -> bool {
-        let mut bytes = [0u8; 256];
-        match self.stream.read(&mut bytes) {
-            Ok(0) => true,
-            Ok(len) => {
-                self.data.extend(&bytes[..len]);
-                false
-            }
-            Err(e) => {
-                info!("{:?}", e);
-                e.kind() != std::io::ErrorKind::WouldBlock
-            }
-        }
+    fn stack_client(&self, client: &Client, rect: &Rect, border: u16, pos: StackPos) -> Result<()> {
+        let conf_aux = match pos {
+            StackPos::Below(sibling) => rect
+                .aux(border)
+                .sibling(sibling)
+                .stack_mode(StackMode::BELOW),
+            StackPos::Above(sibling) => rect
+                .aux(border)
+                .sibling(sibling)
+                .stack_mode(StackMode::ABOVE),
+            StackPos::UnderPanels => rect
+                .aux(border)
+                .sibling(self.panel_anchor)
+                .stack_mode(StackMode::BELOW),
+        };
+        configure_window(&self.dpy, client.win, &conf_aux)?;
+        client.set_shadow(self)?;
+        self.animations.forget(client.win);
+        Ok(())
     }
 
-    pub fn recieve<T: DeserializeOwned>(&mut self) -> (bool, Option<T>) {
-        let done = self.get_bytes();
-        if !self.reading && self.data.len() >= 4 {
-            self.length =
-                bincode::deserialize::<u32>(self.data.drain(..4).as_ref()).unwrap() as usize;
-            self.reading = true;
-        }
-        if self.reading && self.data.len() >= self.length {
-            self.reading = false;
-            (
-                done,
-                Some(bincode::deserialize(self.data.drain(..self.length).as_ref()).unwrap()),
-            )
-        } else {
-            (done, None)
-        }
+    fn take_presel(&mut self, tag: Atom, leaf: usize) -> Result<Option<(Side, f32)>> {
+        Ok(self
+            .selection
+            .presel(&self.dpy, tag, leaf)?
+            .map(|presel| (presel.side, presel.amt)))
+    }
+
+    fn resize_selection(&mut self, tag: &Tag) -> Result<()> {
+        Aux::resize_selection(self, tag)
+    }
+
+    fn relayout_done(&self, start: Instant) {
+        self.metrics.relayout(start);
+    }
+
+    fn sleeping(&self, monitor: Atom) -> bool {
+        self.sleeping.contains(&monitor)
     }
 }
 
 impl WindowManager {
-    fn get_client(&self, client: Option<u32>) -> Option<(u32, usize)> {
+    pub(crate) fn get_client(&self, client: Option<u32>) -> Option<(u32, usize)> {
         if let Some(client) = client {
             if let Some(WindowLocation::Client(tag, id)) = self.windows.get(&client) {
                 Some((*tag, *id))
@@ -472,6 +636,68 @@ impl WindowManager {
         }
     }
 
+    // the marked clients when no client is given, otherwise the given or focused client
+    fn get_clients(&self, client: Option<u32>) -> Vec<Window> {
+        match client {
+            None if !self.aux.selection.marks.is_empty() => self
+                .aux
+                .selection
+                .marks
+                .iter()
+                .map(|mark| mark.client)
+                .collect(),
+            _ => self
+                .get_client(client)
+                .map(|(tag, client)| self.tags.get(&tag).unwrap().client(client).win)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn get_node(&self, node: NodeSelector) -> Result<Option<(u32, usize)>> {
+        Ok(match node {
+            NodeSelector::Window(win) => self
+                .get_client(Some(win))
+                .map(|(tag, client)| (tag, self.tags.get(&tag).unwrap().client(client).node)),
+            NodeSelector::Focused => self
+                .get_client(None)
+                .map(|(tag, client)| (tag, self.tags.get(&tag).unwrap().client(client).node)),
+            NodeSelector::Index(tag, node) => self
+                .get_tag(tag)?
+                .filter(|tag| self.tags.get(tag).unwrap().valid_node(node))
+                .map(|tag| (tag, node)),
+            NodeSelector::Selection => match &self.aux.selection.sel {
+                SelectionContent::Node(tag, node) | SelectionContent::Presel(tag, node, ..) => {
+                    Some((*tag, *node))
+                }
+                SelectionContent::None => None,
+            },
+        })
+    }
+
+    // the x, y, width and height of the monitor geometry is relative to, by default the one
+    // showing the tag or the focused one
+    fn geometry_monitor(&self, tag: Atom, mon: Option<u32>) -> (i16, i16, u16, u16) {
+        let mon = mon
+            .filter(|mon| self.monitors.contains_key(mon))
+            .or(self.tags.get(&tag).unwrap().monitor)
+            .unwrap_or(self.focused_monitor);
+        let size = &self.monitors.get(&mon).unwrap().size;
+        (size.x, size.y, size.width, size.height)
+    }
+
+    fn update_inactive_borders(&self) -> Result<()> {
+        for mon in self.monitors.values() {
+            if mon.id != self.focused_monitor {
+                self.tags
+                    .get(&mon.focused_tag)
+                    .unwrap()
+                    .show_inactive(&self.aux)?;
+            }
+        }
+        Ok(())
+    }
+
     fn get_monitor(&self, mon: Option<u32>) -> Option<u32> {
         if let Some(mon) = mon {
             if self.monitors.contains_key(&mon) {
@@ -484,7 +710,7 @@ impl WindowManager {
         }
     }
 
-    fn get_tag(&self, tag: TagSelection) -> Result<Option<u32>> {
+    pub(crate) fn get_tag(&self, tag: TagSelection) -> Result<Option<u32>> {
         match tag {
             TagSelection::Index(idx) => Ok(self.tag_order.get(idx).copied()),
             TagSelection::Name(name) => {
@@ -536,96 +762,556 @@ impl WindowManager {
         }
     }
 
-    fn handle_request(
-        &mut self,
-        mut stream: Stream,
-        poll_fd: PollFd,
-        request: ClientRequest,
-    ) -> Result<()> {
-        info!("Request {:?}", request);
-        match request {
-            ClientRequest::MonitorFocus(mon) => {
-                if let Some(mon) = self.get_monitor(mon) {
-                    self.aux.hooks.add_monitor_focus(mon, stream)
+    // sends the layout of the tag shown on each subscriber's monitor whenever it changes
+    pub fn update_tree_hooks(&mut self) {
+        if self.aux.hooks.tree.is_empty() {
+            return;
+        }
+        let mut hooks = std::mem::take(&mut self.aux.hooks.tree);
+        hooks.retain_mut(|hook| {
+            let tree = self.get_monitor(hook.mon).map(|mon| {
+                let mon = self.monitors.get(&mon).unwrap();
+                self.tags.get(&mon.focused_tag).unwrap().tree()
+            });
+            match tree {
+                Some(tree) if hook.last.as_ref() != Some(&tree) => {
+                    let sent = hook.stream.send(&CwmResponse::Tree(tree.clone()));
+                    hook.last.replace(tree);
+                    sent
+                }
+                _ => true,
+            }
+        });
+        self.aux.hooks.tree = hooks;
+    }
+
+    // fullscreen clients on the tags shown on monitors, switching away from one counts as leaving
+    pub fn update_fullscreen_hooks(&mut self) {
+        if self.aux.hooks.fullscreen.0.is_empty() {
+            // nobody is listening, a new subscriber starts from scratch
+            self.aux.hooks.fullscreen.1.clear();
+            return;
+        }
+        let mut windows = HashMap::new();
+        for mon in self.monitors.values() {
+            let tag = self.tags.get(&mon.focused_tag).unwrap();
+            for client in tag.used_clients() {
+                let client = tag.client(client);
+                if client.flags.fullscreen && !client.flags.hidden {
+                    windows.insert(client.win, mon.id);
+                }
+            }
+        }
+        self.aux.hooks.fullscreen_update(windows);
+    }
+
+    pub fn presel_expired(&mut self) -> Result<()> {
+        if let SelectionContent::Presel(tag, node, _) = self.aux.selection.sel {
+            let win = self.tags.get(&tag).and_then(|tag| {
+                tag.get_node_client(node)
+                    .map(|client| tag.client(client).win)
+            });
+            self.aux.selection.hide(&self.aux.dpy, None, None)?;
+            self.aux.hooks.presel_expired(win);
+        }
+        Ok(())
+    }
+
+    // the geometry of every shown window on the tags shown on monitors, sent per monitor when it
+    // changes
+    pub fn update_layout_hooks(&mut self) {
+        if self.aux.hooks.layout.0.is_empty() {
+            self.aux.hooks.layout.1.clear();
+            return;
+        }
+        let mut layouts = HashMap::new();
+        for mon in self.monitors.values() {
+            let tag = self.tags.get(&mon.focused_tag).unwrap();
+            let layout = tag
+                .used_clients()
+                .into_iter()
+                .filter(|client| !tag.client(*client).flags.hidden)
+                .filter_map(|client| {
+                    tag.get_rect(&self.aux, client).map(|rect| {
+                        let win = tag.client(client).win;
+                        (win, (rect.x, rect.y, rect.width, rect.height))
+                    })
+                })
+                .collect();
+            layouts.insert(mon.id, layout);
+        }
+        self.aux.hooks.layout_update(layouts);
+    }
+
+    // hides the least recently focused tiled client of a shown tag while any tile is smaller
+    // than its min size, the focused client is never collapsed
+    pub fn collapse_small_tiles(&mut self) -> Result<()> {
+        let floor = match self.aux.theme.min_tile_size {
+            Some(floor) => floor,
+            None => return Ok(()),
+        };
+        let mut changed = false;
+        for mon in self.monitors.values() {
+            let tag = self.tags.get_mut(&mon.focused_tag).unwrap();
+            loop {
+                let overflowing = tag.overflowing(floor);
+                if overflowing.is_empty() {
+                    break;
+                }
+                // the least recently focused tile goes first, the focused one never does
+                let stack = tag.get_stack();
+                let victim = match stack.into_iter().rev().find(|&client| {
+                    Some(client) != tag.focused && !tag.client(client).flags.absent()
+                }) {
+                    Some(client) => client,
+                    None => break,
+                };
+                info!(
+                    "collapsing client {} on tag {}, overflowing nodes {:?}",
+                    victim, tag.name, overflowing
+                );
+                tag.set_hidden(&mut self.aux, victim, &SetArg(true, false))?;
+                self.aux.hooks.collapsed(mon.id, tag.client(victim).win);
+                changed = true;
+            }
+        }
+        if changed {
+            self.aux
+                .hooks
+                .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
+        }
+        Ok(())
+    }
+
+    // keeps each overlay over its client, marks of clients that are gone are dropped
+    pub fn update_marks(&mut self) -> Result<()> {
+        for mut mark in std::mem::take(&mut self.aux.selection.marks) {
+            let (tag, client) = match self.windows.get(&mark.client) {
+                Some(WindowLocation::Client(tag, client)) => (*tag, *client),
+                _ => {
+                    destroy_window(&self.aux.dpy, mark.overlay)?;
+                    continue;
+                }
+            };
+            let tag = self.tags.get(&tag).unwrap();
+            let rect = if tag.monitor.is_some() && !tag.client(client).flags.hidden {
+                tag.get_rect(&self.aux, client)
+            } else {
+                None
+            };
+            if rect != mark.rect {
+                match &rect {
+                    Some(rect) => {
+                        configure_window(
+                            &self.aux.dpy,
+                            mark.overlay,
+                            &rect
+                                .aux(self.aux.theme.selection_gap)
+                                .stack_mode(StackMode::ABOVE),
+                        )?;
+                        map_window(&self.aux.dpy, mark.overlay)?;
+                    }
+                    None => {
+                        unmap_window(&self.aux.dpy, mark.overlay)?;
+                    }
+                }
+                mark.rect = rect;
+            }
+            self.aux.selection.marks.push(mark);
+        }
+        Ok(())
+    }
+
+    // keeps the focus indicator over the focused client of the focused monitor
+    pub fn update_focus_indicator(&mut self) -> Result<()> {
+        let tag = self.tags.get(&self.focused_tag()).unwrap();
+        let target = match self.aux.theme.focus_indicator {
+            FocusIndicator::Off => None,
+            style => tag
+                .focused_client()
+                .filter(|client| !tag.client(*client).flags.hidden)
+                .and_then(|client| {
+                    tag.get_rect(&self.aux, client)
+                        .map(|rect| (tag.client(client).win, rect, style))
+                }),
+        };
+        let focus = &mut self.aux.selection.focus;
+        if target == focus.shown {
+            return Ok(());
+        }
+        match &target {
+            Some((_, rect, style)) => {
+                let (width, height, shape) = match *style {
+                    FocusIndicator::Edge(size) => {
+                        let size = size.min(rect.width / 2).min(rect.height / 2).max(1);
+                        let inner = (rect.width - size * 2, rect.height - size * 2);
+                        (
+                            rect.width,
+                            rect.height,
+                            vec![
+                                Rectangle {
+                                    x: 0,
+                                    y: 0,
+                                    width: rect.width,
+                                    height: size,
+                                },
+                                Rectangle {
+                                    x: 0,
+                                    y: (rect.height - size) as i16,
+                                    width: rect.width,
+                                    height: size,
+                                },
+                                Rectangle {
+                                    x: 0,
+                                    y: size as i16,
+                                    width: size,
+                                    height: inner.1,
+                                },
+                                Rectangle {
+                                    x: (rect.width - size) as i16,
+                                    y: size as i16,
+                                    width: size,
+                                    height: inner.1,
+                                },
+                            ],
+                        )
+                    }
+                    FocusIndicator::Corner(size) => {
+                        let size = size.min(rect.width).min(rect.height).max(1);
+                        (
+                            size,
+                            size,
+                            vec![Rectangle {
+                                x: 0,
+                                y: 0,
+                                width: size,
+                                height: size,
+                            }],
+                        )
+                    }
+                    FocusIndicator::Off => unreachable!(),
+                };
+                self.aux.dpy.shape_rectangles(
+                    SO::SET,
+                    SK::BOUNDING,
+                    ClipOrdering::UNSORTED,
+                    focus.overlay,
+                    0,
+                    0,
+                    &shape,
+                )?;
+                configure_window(
+                    &self.aux.dpy,
+                    focus.overlay,
+                    &ConfigureWindowAux::new()
+                        .x(rect.x as i32)
+                        .y(rect.y as i32)
+                        .width(width as u32)
+                        .height(height as u32)
+                        .stack_mode(StackMode::ABOVE),
+                )?;
+                map_window(&self.aux.dpy, focus.overlay)?;
+            }
+            None => {
+                unmap_window(&self.aux.dpy, focus.overlay)?;
+            }
+        }
+        focus.shown = target;
+        Ok(())
+    }
+
+    fn debug_dump(&self) -> (String, Vec<String>) {
+        let mut dump = String::new();
+        let mut errors = Vec::new();
+        let mut windows = self.windows.iter().collect::<Vec<_>>();
+        windows.sort_by_key(|x| x.0);
+        dump.push_str("windows:\n");
+        for (win, location) in windows {
+            dump.push_str(&format!("  {}: {:?}\n", win, location));
+            if let WindowLocation::Client(tag, client) = location {
+                match self.tags.get(tag) {
+                    Some(tag) if client < &tag.clients().len() => {
+                        if tag.client(*client).win != *win {
+                            errors.push(format!(
+                                "window {} maps to client {} of tag {} which has window {}",
+                                win,
+                                client,
+                                tag.name,
+                                tag.client(*client).win
+                            ));
+                        }
+                    }
+                    _ => errors.push(format!("window {} maps to a missing client", win)),
                 }
             }
-            ClientRequest::TagState => self.aux.hooks.add_monitor_tag(stream),
-            ClientRequest::CloseClient(client, kill) => {
+        }
+        for id in self.tag_order.iter() {
+            let tag = self.tags.get(id).unwrap();
+            dump.push_str(&tag.debug_dump());
+            errors.extend(tag.check_invariants());
+            for client in tag.used_clients() {
+                let win = tag.client(client).win;
+                match self.windows.get(&win) {
+                    Some(WindowLocation::Client(tag_, client_))
+                        if *tag_ == tag.id && *client_ == client => {}
+                    location => errors.push(format!(
+                        "client {} of tag {} has window {} which maps to {:?}",
+                        client, tag.name, win, location
+                    )),
+                }
+            }
+        }
+        (dump, errors)
+    }
+
+    // only tags whose tiling area actually changed are resized
+    fn update_margins(&mut self) -> Result<()> {
+        for mon in self.monitors.values() {
+            let tag = self.tags.get_mut(&mon.focused_tag).unwrap();
+            tag.set_tiling_size(&self.aux, mon.free_rect())?;
+        }
+        Ok(())
+    }
+
+    // for requests that don't come from a connection, like the ones made from the theme file
+    pub(crate) fn apply_request(&mut self, request: ClientRequest) -> Result<()> {
+        self.handle_request(request).map(|_| ())
+    }
+
+    fn handle_request(&mut self, request: ClientRequest) -> Result<Reply> {
+        info!("Request {:?}", request);
+        self.aux.metrics.request();
+        Ok(match request {
+            ClientRequest::MonitorFocus(mon) => match self.get_monitor(mon) {
+                Some(mon) => Reply::MonitorFocus(mon),
+                None => Reply::Close,
+            },
+            ClientRequest::TagState => Reply::TagState,
+            ClientRequest::Ping => Reply::Keep(Some(CwmResponse::Pong)),
+            ClientRequest::Metrics => Reply::Keep(Some(CwmResponse::Metrics(self.metrics()))),
+            ClientRequest::CloseClient(client, kill, force) => {
                 info!("Killing Client");
-                if let Some((tag, client)) = self.get_client(client) {
-                    self.tags
-                        .get(&tag)
-                        .unwrap()
-                        .client(client)
-                        .close(&self.aux, kill)?
+                let mut protected = Vec::new();
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        let client = self.tags.get(&tag).unwrap().client(client);
+                        if client.flags.protected && !force {
+                            protected.push(win.to_string());
+                        } else {
+                            client.close(&self.aux, kill)?
+                        }
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(Some(CwmResponse::Ack(if protected.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "protected, use --force to close: {}",
+                        protected.join(" ")
+                    ))
+                })))
             }
             ClientRequest::Quit => {
                 self.running = false;
                 info!("Exiting");
+                Reply::Close
+            }
+            ClientRequest::SetFloatingGeometry(client, geometry, mon) => {
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        let mon = self.geometry_monitor(tag, mon);
+                        let (x, y, width, height) = geometry.resolve(mon);
+                        self.tags.get_mut(&tag).unwrap().set_floating_geometry(
+                            &self.aux,
+                            client,
+                            &Rect::new(x, y, width, height),
+                        )?;
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigBorderInactive(color) => {
+                self.aux.theme.border_color_inactive = mul_alpha(color);
+                self.update_inactive_borders()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigRememberFloating(enabled) => {
+                self.aux.theme.remember_floating = enabled;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigPreselTimeout(ms) => {
+                self.aux.theme.presel_timeout_ms = ms;
+                self.aux.arm_presel()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigFocusSettle(ms) => {
+                self.aux.theme.focus_settle_ms = ms;
+                if ms == 0 {
+                    self.settle_tag_switch()?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigFloatPlacement(placement) => {
+                self.aux.theme.float_placement = placement;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigIdleTimeout(ms) => {
+                self.aux.theme.idle_timeout_ms = ms;
+                if ms == 0 {
+                    self.aux.hooks.idle(false);
+                }
+                self.aux.last_activity = Instant::now();
+                self.aux.arm_idle()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigFocusIndicator(style, color) => {
+                self.aux.theme.focus_indicator = style;
+                if let Some(color) = color {
+                    self.aux.theme.focus_indicator_color = mul_alpha(color);
+                }
+                let focus = &mut self.aux.selection.focus;
+                change_window_attributes(
+                    &self.aux.dpy,
+                    focus.overlay,
+                    &ChangeWindowAttributesAux::new()
+                        .background_pixel(self.aux.theme.focus_indicator_color),
+                )?;
+                clear_area(&self.aux.dpy, false, focus.overlay, 0, 0, 0, 0)?;
+                // redrawn by the main loop
+                focus.shown = None;
+                unmap_window(&self.aux.dpy, focus.overlay)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::GetFlags(client) => {
+                Reply::Keep(Some(CwmResponse::Flags(self.get_client(client).map(
+                    |(tag, client)| self.tags.get(&tag).unwrap().client(client).window_flags(),
+                ))))
+            }
+            ClientRequest::QueryGeometry(client, relative) => Reply::Keep(Some(
+                CwmResponse::Geometry(self.get_client(client).map(|(tag, client)| {
+                    let rect = self.tags.get(&tag).unwrap().get_client_rect(client);
+                    let (x, y, ..) = if relative {
+                        self.geometry_monitor(tag, None)
+                    } else {
+                        (0, 0, 0, 0)
+                    };
+                    (rect.x - x, rect.y - y, rect.width, rect.height)
+                })),
+            )),
+            ClientRequest::Restart => {
+                self.restarting = true;
+                Reply::Close
             }
             ClientRequest::Reload => {
                 for mon in self.monitors.values() {
-                    self.aux.hooks.mon_close(mon.id, mon.name.as_str());
+                    let tag = &self.tags.get(&mon.focused_tag).unwrap().name;
+                    self.aux.hooks.mon_close(mon, tag);
                 }
                 self.aux.hooks.config();
+                self.load_theme_file()?;
                 for mon in self.monitors.values() {
-                    self.aux.hooks.mon_open(mon.id, mon.name.as_str(), mon.bg);
+                    let tag = &self.tags.get(&mon.focused_tag).unwrap().name;
+                    self.aux.hooks.mon_open(mon, tag);
                 }
+                Reply::Keep(None)
             }
             ClientRequest::SetFullscreen(client, arg) => {
                 info!("Fullscreen {:?}", arg);
-                if let Some((tag, client)) = self.get_client(client) {
-                    self.tags
-                        .get_mut(&tag)
-                        .unwrap()
-                        .set_fullscreen(&self.aux, client, &arg)?
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags.get_mut(&tag).unwrap().set_fullscreen(
+                            &self.aux,
+                            client,
+                            &arg.into(),
+                        )?
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::SetLayer(client, arg) => {
                 info!("SetLayer {:?}", arg);
-                if let Some((tag, client)) = self.get_client(client) {
-                    self.tags
-                        .get_mut(&tag)
-                        .unwrap()
-                        .set_stack_layer(&self.aux, client, &arg)?
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags
+                            .get_mut(&tag)
+                            .unwrap()
+                            .set_stack_layer(&self.aux, client, &arg)?
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::SetFloating(client, arg) => {
                 info!("Floating {:?}", arg);
-                if let Some((tag, client)) = self.get_client(client) {
-                    self.tags
-                        .get_mut(&tag)
-                        .unwrap()
-                        .set_floating(&self.aux, client, &arg)?
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags.get_mut(&tag).unwrap().set_floating(
+                            &self.aux,
+                            client,
+                            &arg.into(),
+                        )?
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::SetAot(client, arg) => {
+                info!("Aot {:?}", arg);
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags
+                            .get_mut(&tag)
+                            .unwrap()
+                            .set_aot(&self.aux, client, &arg.into())?
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::SetPassthrough(client, arg) => {
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        let tag = self.tags.get_mut(&tag).unwrap();
+                        let flags = &mut tag.client_mut(client).flags;
+                        arg.apply(&mut flags.passthrough);
+                        let passthrough = flags.passthrough;
+                        if tag.focused == Some(client) {
+                            self.aux.update_passthrough(passthrough)?;
+                        }
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::SetProtected(client, arg) => {
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        let client = self.tags.get_mut(&tag).unwrap().client_mut(client);
+                        arg.apply(&mut client.flags.protected);
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::SetInhibitFullscreen(client, arg) => {
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        let client = self.tags.get_mut(&tag).unwrap().client_mut(client);
+                        arg.apply(&mut client.flags.inhibit_fullscreen);
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::SetSticky(client, arg) => {
                 info!("Sticky {:?}", arg);
-                if let Some((tag, client)) = self.get_client(client) {
-                    self.set_sticky(tag, client, &arg);
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.set_sticky(tag, client, &arg.into());
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::SetHidden(client, arg) => {
                 info!("Hidden {:?}", arg);
-                if let Some((tag, client)) = self.get_client(client) {
-                    self.tags
-                        .get_mut(&tag)
-                        .unwrap()
-                        .set_hidden(&mut self.aux, client, &arg)?
+                for win in self.get_clients(client) {
+                    self.set_window_hidden(win, &arg.into())?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::SetMonocle(tag, arg) => {
                 info!("Monocle {:?}", arg);
@@ -633,10 +1319,9 @@ impl WindowManager {
                     self.tags
                         .get_mut(&tag)
                         .unwrap()
-                        .set_monocle(&self.aux, &arg)?;
+                        .set_monocle(&self.aux, &arg.into())?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::Show(tag, selection) => {
                 info!("Show {:?}, {:?}", tag, selection);
@@ -646,55 +1331,76 @@ impl WindowManager {
                         .unwrap()
                         .show_clients(&mut self.aux, selection)?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::FocusedMonitor => {
-                stream.send(&CwmResponse::FocusedMonitor(self.focused_monitor));
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(Some(CwmResponse::FocusedMonitor(self.focused_monitor)))
             }
             ClientRequest::FocusedTag(mon) => {
-                if let Some(mon) = self.get_monitor(mon) {
-                    stream.send(&CwmResponse::FocusedTag(
-                        self.monitors.get(&mon).unwrap().focused_tag,
-                    ));
-                }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
-            }
-            ClientRequest::FocusedWindow(tag) => {
-                if let Some(tag) = self.get_tag(tag)? {
-                    let tag = self.tags.get(&tag).unwrap();
-                    stream.send(&CwmResponse::FocusedWindow(
-                        tag.focused_client().map(|x| tag.client(x).win),
-                    ));
-                    self.aux.streams.push(stream);
-                    self.aux.poll_fds.push(poll_fd);
-                }
+                Reply::query(self.get_monitor(mon).map(|mon| {
+                    CwmResponse::FocusedTag(self.monitors.get(&mon).unwrap().focused_tag)
+                }))
             }
-            ClientRequest::FocusTag(mon, tag, toggle) => {
+            ClientRequest::FocusedWindow(tag) => Reply::query(self.get_tag(tag)?.map(|tag| {
+                let tag = self.tags.get(&tag).unwrap();
+                CwmResponse::FocusedWindow(tag.focused_client().map(|x| tag.client(x).win))
+            })),
+            ClientRequest::FocusTag(mon, tag, toggle, mode) => {
                 if let (Some(mon), Some(tag)) = (self.get_monitor(mon), self.get_tag(tag)?) {
-                    self.switch_monitor_tag(mon, SetArg(tag, toggle))?;
+                    let mode = mode.unwrap_or(self.aux.theme.tag_switch_mode);
+                    if self.aux.theme.focus_settle_ms > 0 {
+                        self.switch_monitor_tag_settled(mon, SetArg(tag, toggle), mode)?;
+                    } else {
+                        self.switch_monitor_tag(mon, SetArg(tag, toggle), mode)?;
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
-            ClientRequest::SetWindowTag(client, tag, toggle) => {
-                if let Some(dest) = self.get_tag(tag)? {
-                    if let Some((tag, client)) = self.get_client(client) {
-                        self.move_client(tag, client, SetArg(dest, toggle))?;
+            ClientRequest::SetWindowTag(client, dest, toggle, follow) => {
+                let mut moved = None;
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        // last is the tag the client came from, falling back to the monitor's last tag
+                        let dest = match (&dest, self.client_prev_tag(tag, client)) {
+                            (TagSelection::Last(..), Some(prev)) => Some(prev),
+                            _ => self.get_tag(dest.clone())?,
+                        };
+                        if let Some(dest) = dest {
+                            self.move_client(tag, client, SetArg(dest, toggle))?;
+                            moved.replace(win);
+                        }
                     }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                if let Some(win) = moved.filter(|_| follow) {
+                    self.focus_window(win)?;
+                }
+                Reply::Keep(None)
             }
-            ClientRequest::CycleWindow(rev) => {
+            ClientRequest::CycleWindow(rev, filter) => {
                 let tag = self.focused_tag();
                 let tag = self.tags.get_mut(&tag).unwrap();
-                tag.cycle(&mut self.aux, rev)?;
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                tag.cycle(&mut self.aux, rev, filter)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::FocusNthRecent(tag, n, toggle) => {
+                if let Some(tag) = self.get_tag(tag)? {
+                    let focused = tag == self.focused_tag();
+                    let tag = self.tags.get(&tag).unwrap();
+                    let client = match tag.nth_recent_client(n) {
+                        // the previous focus is next in the history
+                        Some(client)
+                            if toggle && focused && tag.focused_client() == Some(client) =>
+                        {
+                            tag.nth_recent_client(1)
+                        }
+                        client => client,
+                    };
+                    if let Some(client) = client {
+                        let win = tag.client(client).win;
+                        self.focus_window(win)?;
+                    }
+                }
+                Reply::Keep(None)
             }
             ClientRequest::SelectNeighbour(client, side) => {
                 if let Some((tag, client)) = self.get_client(client) {
@@ -703,18 +1409,16 @@ impl WindowManager {
                         tag.focus_client(&mut self.aux, neighbour)?;
                     }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::MoveWindow(client, side, amt) => {
                 if let Some((tag, client)) = self.get_client(client) {
                     let tag = self.tags.get_mut(&tag).unwrap();
                     tag.move_side(&self.aux, client, side, amt)?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
-            ClientRequest::ResizeWindow(client, side, amt) => {
+            ClientRequest::ResizeWindow(client, side, amt, proportional) => {
                 if let Some((tag, client)) = self.get_client(client) {
                     let tag = self.tags.get_mut(&tag).unwrap();
                     let delta = side.parse_amt(amt);
@@ -724,11 +1428,51 @@ impl WindowManager {
                         delta,
                         side == Side::Left,
                         side == Side::Top,
+                        proportional,
                     )?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
+            }
+            ClientRequest::BeginMotion(client, side, kind) => {
+                self.motion_begin(client, side, kind)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::MotionStep(amt) => {
+                self.motion_step(amt)?;
+                Reply::Keep(None)
             }
+            ClientRequest::EndMotion => {
+                self.motion_end()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ResizeWindowPercent(client, side, percent, proportional) => {
+                if let Some((tag, client)) = self.get_client(client) {
+                    let tag = self.tags.get_mut(&tag).unwrap();
+                    let rect = tag.get_client_rect(client);
+                    let size = match side.get_split().0 {
+                        Split::Vertical => rect.width,
+                        Split::Horizontal => rect.height,
+                    };
+                    let delta = side.parse_amt((size as f32 * percent / 100.0).round() as i16);
+                    tag.resize_client(
+                        &mut self.aux,
+                        client,
+                        delta,
+                        side == Side::Left,
+                        side == Side::Top,
+                        proportional,
+                    )?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ClientIcon(win) => match self.windows.get(&win) {
+                Some(WindowLocation::Client(tag, client)) => {
+                    Reply::Keep(Some(CwmResponse::ClientIcon(
+                        self.tags.get(tag).unwrap().client(*client).icon.clone(),
+                    )))
+                }
+                _ => Reply::Close,
+            },
             ClientRequest::IgnoreSizeHints(client) => {
                 if let Some((tag, client)) = self.get_client(client) {
                     let mut tag = self.tags.get_mut(&tag).unwrap();
@@ -739,27 +1483,16 @@ impl WindowManager {
                         leaf.max_size = (std::u16::MAX, std::u16::MAX);
                     }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
-            }
-            ClientRequest::MonitorName(mon) => {
-                if let Some(mon) = self.get_monitor(mon) {
-                    stream.send(&CwmResponse::Name(
-                        self.monitors.get(&mon).unwrap().name.clone(),
-                    ));
-                }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
-            }
-            ClientRequest::TagName(tag) => {
-                if let Some(tag) = self.get_tag(tag)? {
-                    stream.send(&CwmResponse::Name(
-                        self.tags.get(&tag).unwrap().name.clone(),
-                    ));
-                }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
+            ClientRequest::MonitorName(mon) => Reply::query(
+                self.get_monitor(mon)
+                    .map(|mon| CwmResponse::Name(self.monitors.get(&mon).unwrap().name.clone())),
+            ),
+            ClientRequest::TagName(tag) => Reply::query(
+                self.get_tag(tag)?
+                    .map(|tag| CwmResponse::Name(self.tags.get(&tag).unwrap().name.clone())),
+            ),
             ClientRequest::ConfigBorderFocused(color) => {
                 self.aux.theme.border_color_focused = mul_alpha(color);
                 for mon in self.monitors.values() {
@@ -770,12 +1503,11 @@ impl WindowManager {
                             &self.aux.dpy,
                             client.win,
                             &ChangeWindowAttributesAux::new()
-                                .border_pixel(self.aux.theme.border_color_focused),
+                                .border_pixel(client.border_pixel(&self.aux, true)),
                         )?;
                     }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::ConfigBorderUnfocused(color) => {
                 self.aux.theme.border_color_unfocused = mul_alpha(color);
@@ -787,70 +1519,430 @@ impl WindowManager {
                                 &self.aux.dpy,
                                 client.win,
                                 &ChangeWindowAttributesAux::new()
-                                    .border_pixel(self.aux.theme.border_color_unfocused),
+                                    .border_pixel(client.border_pixel(&self.aux, false)),
                             )?;
                         }
                     }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                self.update_inactive_borders()?;
+                Reply::Keep(None)
             }
             ClientRequest::ConfigBorderWidth(width) => {
+                self.aux.theme.border_width = width;
                 for tag in self.tags.values_mut() {
-                    for client in tag.clients_mut() {
-                        if client.border_width == self.aux.theme.border_width {
-                            client.border_width = width;
-                        }
-                    }
+                    tag.update_border_widths(&self.aux);
                 }
-                self.aux.theme.border_width = width;
                 for mon in self.monitors.values() {
                     let tag = self.tags.get_mut(&mon.focused_tag).unwrap();
                     tag.resize_all(&self.aux, &mon.free_rect(), &mon.size)?;
                 }
 
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::ConfigGap(gap) => {
                 self.aux.theme.gap = gap;
-                for mon in self.monitors.values() {
+                self.aux.theme.gap_percent = None;
+                self.update_margins()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigGapPercent(percent) => {
+                self.aux.theme.gap_percent = Some(percent);
+                self.update_margins()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigMargin(side, marg) => {
+                *self.aux.theme.margin_mut(side) = marg.max(0);
+                self.update_margins()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigTagSwitchMode(mode) => {
+                self.aux.theme.tag_switch_mode = mode;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigAnimations(enabled, duration) => {
+                self.aux.animations.enabled = enabled;
+                self.aux.animations.duration = Duration::from_millis(duration as u64);
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigOverflow(limit, policy) => {
+                self.aux.theme.tiled_limit = limit;
+                self.aux.theme.overflow_policy = policy;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigHideStrategy(strategy) => {
+                self.aux.theme.hide_strategy = strategy;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigMarginDelta(side, delta) => {
+                let marg = self.aux.theme.margin_mut(side);
+                *marg = marg.saturating_add(delta).max(0);
+                self.update_margins()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigMarginDeltaAll(delta) => {
+                for side in [Side::Left, Side::Right, Side::Top, Side::Bottom] {
+                    let marg = self.aux.theme.margin_mut(side);
+                    *marg = marg.saturating_add(delta).max(0);
+                }
+                self.update_margins()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigMonitorTheme(name, field, value) => {
+                let mon = intern_atom(&self.aux.dpy, false, name.as_bytes())?
+                    .reply()?
+                    .atom;
+                self.aux.theme.set_monitor_override(mon, field, value);
+                for tag in self.tags.values_mut() {
+                    if tag.monitor == Some(mon) {
+                        tag.update_border_widths(&self.aux);
+                    }
+                }
+                if let Some(mon) = self.monitors.get(&mon) {
                     let tag = self.tags.get_mut(&mon.focused_tag).unwrap();
-                    tag.set_tiling_size(&self.aux, mon.free_rect())?;
+                    tag.resize_all(&self.aux, &mon.free_rect(), &mon.size)?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
-            ClientRequest::ConfigMargin(side, marg) => {
-                match side {
-                    Side::Left => self.aux.theme.left_margin = marg,
-                    Side::Right => self.aux.theme.right_margin = marg,
-                    Side::Top => self.aux.theme.top_margin = marg,
-                    Side::Bottom => self.aux.theme.bottom_margin = marg,
+            ClientRequest::Raise(client) | ClientRequest::Lower(client) => {
+                let raise = matches!(request, ClientRequest::Raise(_));
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags
+                            .get_mut(&tag)
+                            .unwrap()
+                            .restack(&self.aux, client, raise)?
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigPanelLayer(above_fullscreen, click_through) => {
+                self.aux.theme.panels_above_fullscreen = above_fullscreen;
+                self.aux.theme.desktop_click_through = click_through;
+                self.restack_panels()?;
+                self.update_desktop_windows()?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigPointerBindings(bindings) => {
+                if bindings.is_valid() {
+                    self.aux.theme.pointer_bindings = bindings;
+                    if !self.aux.grabs_released {
+                        self.aux.grab_buttons()?;
+                    }
+                } else {
+                    info!("Ignoring invalid pointer bindings {:?}", bindings);
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigSwipe(swipe) => {
+                if swipe.is_valid() {
+                    self.aux.theme.swipe = swipe;
+                    self.update_swipe_edges()?;
+                    if !self.aux.grabs_released {
+                        self.aux.grab_buttons()?;
+                    }
+                } else {
+                    info!("Ignoring invalid swipe config {:?}", swipe);
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::Pip(client, corner, scale) => {
+                self.pip(client, corner, scale)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigFrameDoubleClick(action, ms) => {
+                self.aux.theme.frame_double_click = action;
+                self.aux.theme.double_click_ms = ms;
+                Reply::Keep(None)
+            }
+            ClientRequest::BindFrameButton(button, mods, action) => {
+                let mods = mods & IGNORED_MASK;
+                let frame_buttons = &mut self.aux.theme.frame_buttons;
+                match action {
+                    Some(action) if (1..=5).contains(&button) => {
+                        frame_buttons.insert((button, mods), action);
+                    }
+                    Some(_) => info!("Ignoring frame binding for button {}", button),
+                    None => {
+                        frame_buttons.remove(&(button, mods));
+                    }
                 }
+                if !self.aux.grabs_released {
+                    self.aux.grab_buttons()?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::SetBorderWidth(client, width) => {
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags
+                            .get_mut(&tag)
+                            .unwrap()
+                            .set_border_width(&self.aux, client, width)?;
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::SetBorderColor(client, color) => {
+                for win in self.get_clients(client) {
+                    if let Some((tag, client)) = self.get_client(Some(win)) {
+                        self.tags.get_mut(&tag).unwrap().set_border_color(
+                            &self.aux,
+                            client,
+                            color.map(mul_alpha),
+                        )?;
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::RuleTest(win) => Reply::query(
+                self.test_rules(win)
+                    .map_err(|e| info!("rule test for {} failed: {:?}", win, e))
+                    .ok()
+                    .map(CwmResponse::RuleTest),
+            ),
+            ClientRequest::SelectToggle(client) => {
+                if let Some((tag, client)) = self.get_client(client) {
+                    let win = self.tags.get(&tag).unwrap().client(client).win;
+                    self.aux.toggle_mark(win)?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigSelectionFollow(follow) => {
+                self.aux.theme.selection_follow = follow;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigRefocusOffMonitors(enabled) => {
+                self.aux.theme.refocus_off_monitors = enabled;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigFloatingShadowsOnly(enabled) => {
+                self.aux.theme.floating_shadows_only = enabled;
+                for tag in self.tags.values() {
+                    for client in tag.clients() {
+                        if enabled {
+                            client.set_shadow(&self.aux)?;
+                        } else {
+                            client.clear_shadow(&self.aux)?;
+                        }
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigMonocle(floating, borderless, gapless) => {
+                self.aux.theme.monocle_floating = floating;
+                self.aux.theme.monocle_borderless = borderless;
+                self.aux.theme.monocle_gapless = gapless;
                 for mon in self.monitors.values() {
                     let tag = self.tags.get_mut(&mon.focused_tag).unwrap();
-                    tag.set_tiling_size(&self.aux, mon.free_rect())?;
+                    if tag.monocle() {
+                        tag.relayout_monocle(&self.aux)?;
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::AddRule(rule) => {
                 self.aux.rules.push(rule);
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
+            }
+            ClientRequest::AssignClass(class, tag, volatile) => {
+                // a name can be assigned before the tag exists
+                let tag = match tag {
+                    TagSelection::Name(name) => Some(
+                        intern_atom(&self.aux.dpy, false, name.as_ref())?
+                            .reply()?
+                            .atom,
+                    ),
+                    tag => self.get_tag(tag)?,
+                };
+                if let Some(tag) = tag {
+                    self.aux.class_tags.insert(class, (tag, volatile));
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ListAssignments => {
+                let mut assignments = Vec::new();
+                for (class, (tag, _)) in self.aux.class_tags.iter() {
+                    let name =
+                        String::from_utf8_lossy(&get_atom_name(&self.aux.dpy, *tag)?.reply()?.name)
+                            .into_owned();
+                    assignments.push((class.clone(), name));
+                }
+                assignments.sort();
+                Reply::Keep(Some(CwmResponse::Assignments(assignments)))
+            }
+            ClientRequest::AddTag(tag, volatile) => {
+                self.add_tag(tag, volatile)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::AddTemplate(template) => {
+                self.aux.templates.insert(template.name.clone(), template);
+                Reply::Keep(None)
+            }
+            ClientRequest::WaitForWindow(spec, timeout) => Reply::Wait(
+                spec,
+                (timeout > 0).then(|| Duration::from_millis(timeout.into())),
+            ),
+            ClientRequest::ViewHidden(tag) => Reply::query(
+                self.get_tag(tag)?
+                    .map(|tag| CwmResponse::Hidden(self.tags.get(&tag).unwrap().hidden_clients())),
+            ),
+            ClientRequest::ShowSpecific(win) => {
+                if let Some((tag, client)) = self.get_client(Some(win)) {
+                    if self.tags.get(&tag).unwrap().client(client).flags.hidden {
+                        self.set_window_hidden(win, &SetArg(false, false))?;
+                        self.tags
+                            .get_mut(&tag)
+                            .unwrap()
+                            .focus_client(&mut self.aux, client)?;
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::MoveGroupToTag(client, dest) => {
+                if let Some(dest) = self.get_tag(dest)? {
+                    let mut windows = Vec::new();
+                    for win in self.get_clients(client) {
+                        for win in self.group_windows(win) {
+                            if !windows.contains(&win) {
+                                windows.push(win);
+                            }
+                        }
+                    }
+                    for win in windows {
+                        if let Some((tag, client)) = self.get_client(Some(win)) {
+                            self.move_client(tag, client, SetArg(dest, false))?;
+                        }
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::CycleGroup(rev) => {
+                let tag = self.focused_tag();
+                let tag = self.tags.get_mut(&tag).unwrap();
+                tag.cycle_group(&mut self.aux, rev)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigGroupHide(enabled) => {
+                self.aux.theme.group_hide = enabled;
+                Reply::Keep(None)
+            }
+            ClientRequest::RotateNode(node, rev) => {
+                if let Some((tag, node)) = self.get_node(node)? {
+                    self.tags
+                        .get_mut(&tag)
+                        .unwrap()
+                        .rotate(&self.aux, node, rev)?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::EqualizeNode(node) => {
+                if let Some((tag, node)) = self.get_node(node)? {
+                    self.tags.get_mut(&tag).unwrap().equalize(&self.aux, node)?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::LockRatio(node, locked) => {
+                if let Some((tag, node)) = self.get_node(node)? {
+                    self.tags.get_mut(&tag).unwrap().lock_ratio(node, locked);
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::MoveSubtree(node, target) => {
+                if let (Some((tag, node)), Some((target_tag, target))) =
+                    (self.get_node(node)?, self.get_node(target)?)
+                {
+                    // subtrees only move within a tag
+                    if tag == target_tag {
+                        // node indices change, so the selection might point at a freed node
+                        self.aux.selection.hide(&self.aux.dpy, None, None)?;
+                        self.tags
+                            .get_mut(&tag)
+                            .unwrap()
+                            .move_subtree(&self.aux, node, target)?;
+                    }
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigTray(enabled, icon_size) => {
+                if enabled {
+                    self.tray_start(icon_size)?;
+                } else {
+                    self.tray_stop()?;
+                }
+                Reply::Keep(None)
+            }
+            ClientRequest::TrayWidth => {
+                Reply::Keep(Some(CwmResponse::TrayWidth(self.tray_width())))
+            }
+            ClientRequest::ConfigMinTileSize(size) => {
+                self.aux.theme.min_tile_size = size;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigFocusFlash(ms, color) => {
+                self.aux.theme.focus_flash_ms = ms;
+                self.aux.theme.focus_flash_color = mul_alpha(color);
+                Reply::Keep(None)
+            }
+            ClientRequest::SetFakeMonitors(rects) => {
+                self.set_fake_monitors(Some(rects))?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ClearFakeMonitors => {
+                self.set_fake_monitors(None)?;
+                Reply::Keep(None)
+            }
+            // only clients have their property changes selected
+            ClientRequest::WatchProperty(win, name) => match self.windows.get(&win) {
+                Some(WindowLocation::Client(..)) => {
+                    let atom = intern_atom(&self.aux.dpy, false, name.as_bytes())?
+                        .reply()?
+                        .atom;
+                    Reply::Property(win, atom)
+                }
+                _ => Reply::Close,
+            },
+            ClientRequest::ConfigCloseFade(ms) => {
+                self.aux.theme.close_fade_ms = ms;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigVisualBell(ms, color) => {
+                self.aux.theme.bell_flash_ms = ms;
+                self.aux.theme.bell_flash_color = mul_alpha(color);
+                Reply::Keep(None)
+            }
+            ClientRequest::VisualBell(client) => {
+                self.visual_bell(client)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::ConfigInsertAtPointer(enabled) => {
+                self.aux.theme.insert_at_pointer = enabled;
+                Reply::Keep(None)
+            }
+            ClientRequest::SpawnInPresel(command) => {
+                self.spawn_in_presel(command)?;
+                Reply::Keep(None)
             }
-            ClientRequest::AddTag(tag) => {
-                self.add_tag(tag)?;
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+            ClientRequest::CycleClass(rev) => {
+                self.cycle_class(rev)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::DropdownToggle(command, height) => {
+                self.dropdown_toggle(command, height)?;
+                Reply::Keep(None)
+            }
+            ClientRequest::AddTagFromTemplate(name) => {
+                if let Some(template) = self.aux.templates.get(&name).cloned() {
+                    self.add_tag_from_template(template)?;
+                } else {
+                    info!("no tag template named {}", name);
+                }
+                Reply::Keep(None)
             }
             ClientRequest::RemoveTag(tag) => {
                 if let Some(tag) = self.get_tag(tag)? {
                     self.remove_tag(tag)?;
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::Keep(None)
             }
             ClientRequest::Select(client) => {
                 if let Some((tag, client)) = self.get_client(client) {
@@ -860,6 +1952,7 @@ impl WindowManager {
                     self.aux.resize_selection(tag)?;
                     self.aux.selection.show(&self.aux.dpy)?;
                 }
+                Reply::Keep(None)
             }
             ClientRequest::SelectParent => {
                 if let Some((tag, node)) = match &mut self.aux.selection.sel {
@@ -878,53 +1971,58 @@ impl WindowManager {
                         self.aux.selection.show(&self.aux.dpy)?;
                     }
                 }
+                Reply::Keep(None)
             }
-            ClientRequest::SelectDir(side) => match &mut self.aux.selection.sel {
-                SelectionContent::Node(tag, node) => {
-                    let tag = *tag;
-                    let node_ = &self.tags.get(&tag).unwrap().node(*node);
-                    match &node_.info {
-                        NodeContents::Node(node_) => {
-                            if let Some(node_) = match (&node_.split, side) {
-                                (Split::Vertical, Side::Left) => Some(node_.first_child),
-                                (Split::Vertical, Side::Right) => Some(node_.second_child),
-                                (Split::Horizontal, Side::Top) => Some(node_.first_child),
-                                (Split::Horizontal, Side::Bottom) => Some(node_.second_child),
-                                _ => None,
-                            } {
-                                *node = node_;
+            ClientRequest::SelectDir(side) => {
+                match &mut self.aux.selection.sel {
+                    SelectionContent::Node(tag, node) => {
+                        let tag = *tag;
+                        let node_ = &self.tags.get(&tag).unwrap().node(*node);
+                        match &node_.info {
+                            NodeContents::Node(node_) => {
+                                if let Some(node_) = match (&node_.split, side) {
+                                    (Split::Vertical, Side::Left) => Some(node_.first_child),
+                                    (Split::Vertical, Side::Right) => Some(node_.second_child),
+                                    (Split::Horizontal, Side::Top) => Some(node_.first_child),
+                                    (Split::Horizontal, Side::Bottom) => Some(node_.second_child),
+                                    _ => None,
+                                } {
+                                    *node = node_;
+                                }
                             }
+                            NodeContents::Leaf(..) => {
+                                let node = *node;
+                                self.aux.selection.sel =
+                                    SelectionContent::Presel(tag, node, Presel { side, amt: 0.5 })
+                            }
+                            _ => (),
                         }
-                        NodeContents::Leaf(..) => {
-                            let node = *node;
-                            self.aux.selection.sel =
-                                SelectionContent::Presel(tag, node, Presel { side, amt: 0.5 })
-                        }
-                        _ => (),
-                    }
-                    let tag = self.tags.get(&tag).unwrap();
-                    self.aux.resize_selection(tag)?;
-                    self.aux.selection.show(&self.aux.dpy)?;
-                }
-                SelectionContent::Presel(tag, _, presel) => {
-                    presel.side = side;
-                    let tag = self.tags.get(tag).unwrap();
-                    self.aux.resize_selection(tag)?;
-                    self.aux.selection.show(&self.aux.dpy)?;
-                }
-                _ => {
-                    if let Some((tag, client)) = self.get_client(None) {
                         let tag = self.tags.get(&tag).unwrap();
-                        self.aux.selection.sel = SelectionContent::Presel(
-                            tag.id,
-                            tag.client(client).node,
-                            Presel { side, amt: 0.5 },
-                        );
                         self.aux.resize_selection(tag)?;
                         self.aux.selection.show(&self.aux.dpy)?;
                     }
+                    SelectionContent::Presel(tag, _, presel) => {
+                        presel.side = side;
+                        let tag = self.tags.get(tag).unwrap();
+                        self.aux.resize_selection(tag)?;
+                        self.aux.selection.show(&self.aux.dpy)?;
+                    }
+                    _ => {
+                        if let Some((tag, client)) = self.get_client(None) {
+                            let tag = self.tags.get(&tag).unwrap();
+                            self.aux.selection.sel = SelectionContent::Presel(
+                                tag.id,
+                                tag.client(client).node,
+                                Presel { side, amt: 0.5 },
+                            );
+                            self.aux.resize_selection(tag)?;
+                            self.aux.selection.show(&self.aux.dpy)?;
+                        }
+                    }
                 }
-            },
+                self.aux.arm_presel()?;
+                Reply::Keep(None)
+            }
             ClientRequest::PreselAmt(amt_) => {
                 if let Some(tag) = if let SelectionContent::Presel(tag, _, Presel { side, amt }) =
                     &mut self.aux.selection.sel
@@ -942,9 +2040,13 @@ impl WindowManager {
                 } {
                     self.aux.resize_selection(tag)?;
                 }
+                self.aux.arm_presel()?;
+                Reply::Keep(None)
             }
             ClientRequest::SelectionCancel => {
                 self.aux.selection.hide(&self.aux.dpy, None, None)?;
+                self.aux.clear_marks()?;
+                Reply::Keep(None)
             }
             ClientRequest::Rotate(rev) => {
                 if let SelectionContent::Node(tag, node) = &self.aux.selection.sel {
@@ -955,40 +2057,114 @@ impl WindowManager {
                 } else if let Some(tag) = self.get_tag(TagSelection::Focused(None))? {
                     self.tags.get_mut(&tag).unwrap().rotate(&self.aux, 0, rev)?;
                 }
+                Reply::Keep(None)
             }
             ClientRequest::ViewLayers(tag) => {
-                if let Some(tag) = self.get_tag(tag)? {
-                    stream.send(&CwmResponse::ViewLayers(
-                        self.tags.get_mut(&tag).unwrap().get_layers(),
-                    ));
-                }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                Reply::query(self.get_tag(tag)?.map(|tag| {
+                    CwmResponse::ViewLayers(self.tags.get_mut(&tag).unwrap().get_layers())
+                }))
             }
-            ClientRequest::ViewStack(tag) => {
-                if let Some(tag) = self.get_tag(tag)? {
-                    stream.send(&CwmResponse::ViewStack(
-                        self.tags.get_mut(&tag).unwrap().get_stack(),
-                    ));
-                }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+            ClientRequest::PanelAutoHide(win, enable) => {
+                self.panel_auto_hide(win, enable)?;
+                Reply::Keep(None)
             }
-            ClientRequest::ViewClients(tag) => {
-                if let Some(tag) = self.get_tag(tag)? {
-                    stream.send(&CwmResponse::ViewClients(
-                        self.tags.get_mut(&tag).unwrap().get_clients(),
-                    ));
+            ClientRequest::SetFocusLock(arg) => {
+                info!("Focus lock {:?}", arg);
+                arg.apply(&mut self.focus_lock);
+                Reply::Keep(None)
+            }
+            ClientRequest::ViewTree(tag) => Reply::query(
+                self.get_tag(tag)?
+                    .map(|tag| CwmResponse::Tree(self.tags.get(&tag).unwrap().tree())),
+            ),
+            ClientRequest::SubscribeTree(mon) => Reply::Tree(mon),
+            ClientRequest::RenderLayout(tag) => {
+                Reply::query(self.get_tag(tag)?.map(|tag| {
+                    CwmResponse::Minimap(self.tags.get(&tag).unwrap().minimap(&self.aux))
+                }))
+            }
+            ClientRequest::SubscribeFullscreen => Reply::Fullscreen,
+            ClientRequest::SubscribeCollapsed => Reply::Collapsed,
+            ClientRequest::SubscribeIdle => Reply::Idle,
+            ClientRequest::SubscribeLayout => Reply::Layout,
+            ClientRequest::SubscribePresel => Reply::Presel,
+            ClientRequest::ConfigUrgentTimeout(ms) => {
+                self.aux.theme.urgent_timeout_ms = ms;
+                Reply::Keep(None)
+            }
+            ClientRequest::ClearUrgent(tag) => {
+                let tags = match tag {
+                    Some(tag) => self.get_tag(tag)?.into_iter().collect(),
+                    None => self.tag_order.clone(),
+                };
+                let mut changed = false;
+                for tag in tags {
+                    let tag = self.tags.get_mut(&tag).unwrap();
+                    for client in tag.urgent_clients() {
+                        changed |= tag.clear_urgent(client);
+                    }
                 }
-                self.aux.streams.push(stream);
-                self.aux.poll_fds.push(poll_fd);
+                if changed {
+                    self.aux
+                        .hooks
+                        .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
+                }
+                Reply::Keep(None)
             }
-        }
-        Ok(())
+            ClientRequest::SetLogLevel(level) => {
+                info!("log level set to {}", level);
+                logging::set_level(level);
+                Reply::Keep(None)
+            }
+            ClientRequest::TailLog(n) => Reply::Keep(Some(CwmResponse::Log(logging::tail(n)))),
+            ClientRequest::DebugDump => {
+                let (dump, errors) = self.debug_dump();
+                Reply::Keep(Some(CwmResponse::DebugDump(dump, errors)))
+            }
+            ClientRequest::ViewMonitorLayers => {
+                let tree = query_tree(&self.aux.dpy, self.aux.root)?.reply()?;
+                let mut monitors = self
+                    .monitors
+                    .values()
+                    .map(|mon| {
+                        let tag = self.tags.get(&mon.focused_tag).unwrap();
+                        let layers: Vec<Vec<Window>> = tag
+                            .get_layers()
+                            .into_iter()
+                            .map(|layer| layer.into_iter().map(|i| tag.client(i).win).collect())
+                            .collect();
+                        // freed client slots keep the window of whatever used them last
+                        let wins = layers.iter().flatten().copied().collect::<HashSet<_>>();
+                        MonitorLayers {
+                            monitor: mon.name.clone(),
+                            tag: tag.name.clone(),
+                            layers,
+                            stacking: tree
+                                .children
+                                .iter()
+                                .filter(|win| wins.contains(win))
+                                .copied()
+                                .collect(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                monitors.sort_by(|a, b| a.monitor.cmp(&b.monitor));
+                Reply::Keep(Some(CwmResponse::MonitorLayers(monitors)))
+            }
+            ClientRequest::ViewStack(tag) => {
+                Reply::query(self.get_tag(tag)?.map(|tag| {
+                    CwmResponse::ViewStack(self.tags.get_mut(&tag).unwrap().get_stack())
+                }))
+            }
+            ClientRequest::ViewClients(tag) => Reply::query(self.get_tag(tag)?.map(|tag| {
+                CwmResponse::ViewClients(self.tags.get_mut(&tag).unwrap().get_clients())
+            })),
+        })
     }
 
     pub(crate) fn handle_connections(&mut self) -> Result<()> {
-        if let Ok((stream, _)) = self.aux.listener.accept() {
+        // every waiting connection is accepted so a burst of clients is handled in one go
+        while let Ok((stream, _)) = self.aux.listener.accept() {
             stream
                 .set_read_timeout(Some(Duration::from_nanos(100)))
                 .unwrap();
@@ -998,78 +2174,765 @@ impl WindowManager {
             self.aux
                 .poll_fds
                 .push(PollFd::new(stream.as_raw_fd(), PollFlags::POLLIN));
-            self.aux.streams.push(Stream::new(stream));
+            self.aux.streams.push(Stream::with_encoding(stream, None));
         }
+        // streams stay in the order they connected and every complete request of one is handled
+        // before the next, so requests are answered in the order they arrived and none is left
+        // in a buffer that poll won't wake up for
         for (mut stream, poll_fd) in self
             .aux
             .streams
             .drain(..)
-            .zip(self.aux.poll_fds.drain(2..))
+            .zip(self.aux.poll_fds.drain(Aux::STREAM_FDS..))
             .collect::<Vec<_>>()
         {
-            match stream.recieve() {
-                (false, None) => {
+            let mut done = stream.read_available();
+            if !stream.greeted {
+                match stream.next_message(done) {
+                    (false, None) => {
+                        self.aux.streams.push(stream);
+                        self.aux.poll_fds.push(poll_fd);
+                        continue;
+                    }
+                    (_, Some(hello)) if stream.greet(&hello) => (),
+                    _ => {
+                        self.aux.hooks.close(stream);
+                        continue;
+                    }
+                }
+            }
+            let request = loop {
+                match stream.next_message(done) {
+                    // a client that is done writing can still be waiting for the response
+                    (_, Some(request)) => match self.handle_request(request)? {
+                        Reply::Keep(Some(response)) if !stream.send(&response) => {
+                            done = true;
+                            break None;
+                        }
+                        Reply::Keep(_) => (),
+                        reply => break Some(reply),
+                    },
+                    (finished, _) => {
+                        done = finished;
+                        break None;
+                    }
+                }
+            };
+            match request {
+                None if !done => {
                     self.aux.streams.push(stream);
                     self.aux.poll_fds.push(poll_fd);
                 }
-                (false, Some(request)) => self.handle_request(stream, poll_fd, request)?,
-                _ => (),
+                None | Some(Reply::Keep(_)) | Some(Reply::Close) => self.aux.hooks.close(stream),
+                Some(Reply::MonitorFocus(mon)) => self.aux.hooks.add_monitor_focus(mon, stream),
+                Some(Reply::TagState) => self.aux.hooks.add_monitor_tag(stream),
+                Some(Reply::Tree(mon)) => self.aux.hooks.add_tree(mon, stream),
+                Some(Reply::Fullscreen) => self.aux.hooks.add_fullscreen(stream),
+                Some(Reply::Collapsed) => self.aux.hooks.add_collapsed(stream),
+                Some(Reply::Idle) => self.aux.hooks.add_idle(stream),
+                Some(Reply::Layout) => self.aux.hooks.add_layout(stream),
+                Some(Reply::Presel) => self.aux.hooks.add_presel(stream),
+                Some(Reply::Property(win, atom)) => self.aux.hooks.add_property(win, atom, stream),
+                Some(Reply::Wait(spec, timeout)) => {
+                    let id = self.aux.hooks.add_waiter(spec, stream);
+                    if let Some(timeout) = timeout {
+                        self.aux.timers.set(Timeout::WindowWait(id), timeout)?;
+                    }
+                }
             }
         }
         Ok(())
     }
 }
 
-impl TagState {
-    pub fn format(&self, curr_mon: u32, focused_mon: u32) -> String {
-        let prefix = match self {
-            Self { urgent: true, .. } => "!",
-            Self {
-                focused: Some(mon), ..
-            } if *mon == curr_mon && *mon == focused_mon => "#",
-            Self {
-                focused: Some(mon), ..
-            } if *mon == curr_mon => "+",
-            Self {
-                focused: Some(mon), ..
-            } if *mon == focused_mon => "%",
-            Self {
-                focused: Some(_), ..
-            } => "-",
-            Self { empty: false, .. } => ":",
-            _ => ".",
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn unanswerable_queries_close_the_connection() {
+        assert!(matches!(Reply::query(None), Reply::Close));
+        assert!(matches!(
+            Reply::query(Some(CwmResponse::FocusedMonitor(1))),
+            Reply::Keep(Some(CwmResponse::FocusedMonitor(1)))
+        ));
+    }
+
+    fn geometry(wm: &mut WindowManager, win: Window) -> (i16, i16, u16, u16) {
+        let (tag, client) = wm.get_client(Some(win)).unwrap();
+        let rect = wm.tags.get(&tag).unwrap().get_client_rect(client);
+        (rect.x, rect.y, rect.width, rect.height)
+    }
+
+    fn focused(wm: &WindowManager) -> Option<Window> {
+        let tag = wm.tags.get(&wm.focused_tag()).unwrap();
+        tag.focused_client().map(|client| tag.client(client).win)
+    }
+
+    #[test]
+    fn ping_answers_pong() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        assert!(matches!(
+            wm.handle_request(ClientRequest::Ping),
+            Ok(Reply::Keep(Some(CwmResponse::Pong)))
+        ));
+    }
+
+    #[test]
+    fn config_gap_retiles_and_configures_the_windows() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        mock::mock(&wm).take_requests();
+        wm.handle_request(ClientRequest::ConfigGap(20)).unwrap();
+        let margin = wm.aux.theme.margin(None, Side::Left);
+        assert_eq!(geometry(&mut wm, a).0, 20 + margin);
+        // a ConfigureWindow of the client
+        let configured = mock::mock(&wm)
+            .take_requests()
+            .iter()
+            .any(|request| request[0] == 12 && request[4..8] == a.to_ne_bytes());
+        assert!(configured);
+    }
+
+    #[test]
+    fn floating_a_window_gives_the_other_the_whole_area() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        let whole = geometry(&mut wm, a);
+        let b = mock::map(&mut wm, "b");
+        assert_ne!(geometry(&mut wm, a), whole);
+        wm.handle_request(ClientRequest::SetFloating(Some(b), StateArg::On))
+            .unwrap();
+        assert_eq!(geometry(&mut wm, a), whole);
+        wm.handle_request(ClientRequest::SetFloating(Some(b), StateArg::Toggle))
+            .unwrap();
+        assert_ne!(geometry(&mut wm, a), whole);
+    }
+
+    #[test]
+    fn focus_recent_toggles_back() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        let b = mock::map(&mut wm, "b");
+        assert_eq!(focused(&wm), Some(b));
+        let recent =
+            |n, toggle| ClientRequest::FocusNthRecent(TagSelection::Focused(None), n, toggle);
+        wm.handle_request(recent(1, false)).unwrap();
+        assert_eq!(focused(&wm), Some(a));
+        // a has focus already, the toggle goes back to b
+        wm.handle_request(recent(0, true)).unwrap();
+        assert_eq!(focused(&wm), Some(b));
+        wm.handle_request(recent(0, false)).unwrap();
+        assert_eq!(focused(&wm), Some(b));
+    }
+
+    #[test]
+    fn margin_deltas_stop_at_zero() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        wm.handle_request(ClientRequest::ConfigMarginDeltaAll(i16::MIN))
+            .unwrap();
+        assert_eq!(wm.aux.theme.margin(None, Side::Top), 0);
+        let gap = wm.aux.theme.gap as i16;
+        assert_eq!(geometry(&mut wm, a).0, gap);
+        for _ in 0..4 {
+            wm.handle_request(ClientRequest::ConfigMarginDeltaAll(i16::MAX))
+                .unwrap();
+        }
+        let (x, y, width, height) = geometry(&mut wm, a);
+        assert!(width > 0 && height > 0);
+        assert!(x >= 0 && y >= 0 && x + width as i16 <= 1000 && y + height as i16 <= 500);
+    }
+
+    #[test]
+    fn a_gap_percentage_is_taken_of_each_monitor() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500), (1000, 0, 2000, 1000)]);
+        let a = mock::map(&mut wm, "a");
+        wm.handle_request(ClientRequest::ConfigMarginDeltaAll(i16::MIN))
+            .unwrap();
+        wm.handle_request(ClientRequest::ConfigGapPercent(2.0))
+            .unwrap();
+        // the tiling area of each monitor starts one gap in from its corner
+        let corners = |wm: &WindowManager| {
+            let mut corners: Vec<_> = wm
+                .monitors
+                .values()
+                .map(|mon| {
+                    let tiling = &wm.tags.get(&mon.focused_tag).unwrap().tiling_size;
+                    (tiling.x - mon.size.x, tiling.y - mon.size.y)
+                })
+                .collect();
+            corners.sort_unstable();
+            corners
+        };
+        assert_eq!(corners(&wm), vec![(10, 10), (20, 20)]);
+        assert_eq!(geometry(&mut wm, a).0, 10);
+        mock::mock(&wm).set_monitors(&[(0, 0, 1000, 500), (1000, 0, 1000, 500)]);
+        wm.update_monitors().unwrap();
+        assert_eq!(corners(&wm), vec![(10, 10), (10, 10)]);
+        wm.handle_request(ClientRequest::ConfigGap(4)).unwrap();
+        assert_eq!(corners(&wm), vec![(4, 4), (4, 4)]);
+    }
+
+    #[test]
+    fn borders_follow_the_monitor_a_tag_is_shown_on() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500), (1000, 0, 1000, 500)]);
+        wm.handle_request(ClientRequest::ConfigMonitorTheme(
+            "fake-1".into(),
+            ThemeField::BorderWidth,
+            Some(5),
+        ))
+        .unwrap();
+        let other = *wm
+            .monitors
+            .keys()
+            .find(|&&mon| mon != wm.focused_monitor)
+            .unwrap();
+        let border = |wm: &mut WindowManager, win| {
+            let (tag, client) = wm.get_client(Some(win)).unwrap();
+            wm.tags.get(&tag).unwrap().client(client).border_width
         };
-        prefix.to_string() + self.name.as_str()
+        let a = mock::map(&mut wm, "a");
+        assert_eq!(border(&mut wm, a), wm.aux.theme.border_width);
+        // moved onto the tag shown on the other monitor
+        let dest = wm.monitors.get(&other).unwrap().focused_tag;
+        let (tag, client) = wm.get_client(Some(a)).unwrap();
+        wm.move_client(tag, client, SetArg(dest, false)).unwrap();
+        assert_eq!(border(&mut wm, a), 5);
+        // shown on the other monitor along with its tag
+        let b = mock::map(&mut wm, "b");
+        let (tag, _) = wm.get_client(Some(b)).unwrap();
+        let idx = wm.tag_order.iter().position(|&other| other == tag).unwrap();
+        wm.handle_request(ClientRequest::FocusTag(
+            Some(other),
+            TagSelection::Index(idx),
+            false,
+            None,
+        ))
+        .unwrap();
+        assert_eq!(wm.tags.get(&tag).unwrap().monitor, Some(other));
+        assert_eq!(border(&mut wm, b), 5);
     }
-}
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct SetArg<T: PartialEq + Clone>(pub T, pub bool);
-
-impl<T: PartialEq + Clone> SetArg<T> {
-    pub fn apply_arg(&self, arg: &mut T, last: T) -> bool {
-        if *arg != self.0 {
-            *arg = self.0.clone();
-            true
-        } else if self.1 && last != self.0 {
-            *arg = last;
-            true
-        } else {
-            false
+    #[test]
+    fn grabs_are_released_while_a_passthrough_client_is_focused() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        wm.handle_request(ClientRequest::SetPassthrough(Some(a), StateArg::On))
+            .unwrap();
+        assert!(wm.aux.grabs_released);
+        let b = mock::map(&mut wm, "b");
+        assert_eq!(focused(&wm), Some(b));
+        assert!(!wm.aux.grabs_released);
+        wm.focus_window(a).unwrap();
+        assert!(wm.aux.grabs_released);
+        wm.handle_request(ClientRequest::SetPassthrough(Some(a), StateArg::Off))
+            .unwrap();
+        assert!(!wm.aux.grabs_released);
+    }
+
+    #[test]
+    fn small_tiles_collapse_least_recently_focused_first() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        wm.handle_request(ClientRequest::ConfigMinTileSize(Some((300, 1))))
+            .unwrap();
+        let hidden = |wm: &mut WindowManager, win| {
+            let (tag, client) = wm.get_client(Some(win)).unwrap();
+            wm.tags.get(&tag).unwrap().client(client).flags.hidden
+        };
+        let a = mock::map(&mut wm, "a");
+        let b = mock::map(&mut wm, "b");
+        let c = mock::map(&mut wm, "c");
+        wm.focus_window(a).unwrap();
+        wm.collapse_small_tiles().unwrap();
+        assert_eq!(
+            [a, b, c].map(|win| hidden(&mut wm, win)),
+            [false, true, false]
+        );
+        let d = mock::map(&mut wm, "d");
+        wm.collapse_small_tiles().unwrap();
+        assert_eq!(
+            [a, b, c, d].map(|win| hidden(&mut wm, win)),
+            [false, true, true, false]
+        );
+        // the focused tile is kept even when it is too small on its own
+        wm.handle_request(ClientRequest::ConfigMinTileSize(Some((2000, 1))))
+            .unwrap();
+        wm.collapse_small_tiles().unwrap();
+        assert_eq!(
+            [a, b, c, d].map(|win| hidden(&mut wm, win)),
+            [true, true, true, false]
+        );
+        assert_eq!(focused(&wm), Some(d));
+    }
+
+    // what a request leaves the connection doing
+    #[derive(Debug, PartialEq)]
+    enum Expect {
+        Keep,      // open without an answer
+        Answer,    // open with an answer
+        Close,     // closed
+        Subscribe, // handed over to a subscription or a wait
+    }
+
+    fn expect(reply: &Reply) -> Expect {
+        match reply {
+            Reply::Keep(None) => Expect::Keep,
+            Reply::Keep(Some(_)) => Expect::Answer,
+            Reply::Close => Expect::Close,
+            _ => Expect::Subscribe,
         }
     }
-}
 
-impl SetArg<bool> {
-    pub fn apply(&self, arg: &mut bool) -> bool {
-        if *arg != self.0 {
-            *arg = self.0;
-            true
-        } else if self.1 {
-            *arg ^= true;
-            true
-        } else {
-            false
+    // bump with every new request, each one needs a row below
+    const REQUESTS: usize = 132;
+
+    // one row for every request, a and b are tiled on the focused tag with b focused
+    fn requests(a: u32, b: u32) -> Vec<(ClientRequest, Expect)> {
+        use ClientRequest::*;
+        use Expect::*;
+        let focused = || TagSelection::Focused(None);
+        let node = || NodeSelector::Window(a);
+        let bindings = PointerBindings {
+            mods: 8,
+            move_button: 1,
+            resize_button: 3,
+        };
+        let swipe = Swipe {
+            edge: 2,
+            binding: None,
+            threshold: 100,
+        };
+        let geometry = Geometry {
+            width: Length::Pixels(100),
+            height: Length::Percent(50.0),
+            x: Length::Pixels(10),
+            y: Length::Pixels(10),
+            relative: true,
+        };
+        let template = TagTemplate {
+            name: "web".into(),
+            monocle: true,
+            spawn: Vec::new(),
+        };
+        let spec = |class: &str| MatchSpec {
+            class: Some(class.into()),
+            instance: None,
+            name: None,
+        };
+        vec![
+            (MonitorFocus(None), Subscribe),
+            (TagState, Subscribe),
+            (FocusedWindow(focused()), Answer),
+            (FocusedTag(None), Answer),
+            (FocusedMonitor, Answer),
+            (Quit, Close),
+            (Reload, Keep),
+            (CloseClient(Some(a), false, false), Answer),
+            (SetLayer(Some(a), SetArg(StackLayer::Above, false)), Keep),
+            (SetFullscreen(Some(a), StateArg::Toggle), Keep),
+            (SetFloating(Some(a), StateArg::On), Keep),
+            (SetSticky(Some(a), StateArg::On), Keep),
+            (SetHidden(Some(a), StateArg::On), Keep),
+            (SetMonocle(focused(), StateArg::Toggle), Keep),
+            (Show(focused(), HiddenSelection::All), Keep),
+            (ResizeWindow(Some(a), Side::Right, 10, false), Keep),
+            (MoveWindow(Some(a), Side::Right, 10), Keep),
+            (SelectNeighbour(Some(a), Side::Right), Keep),
+            (CycleWindow(false, CycleFilter::All), Keep),
+            (FocusNthRecent(focused(), 1, true), Keep),
+            (FocusTag(None, TagSelection::Index(1), false, None), Keep),
+            (
+                SetWindowTag(Some(a), TagSelection::Index(1), false, false),
+                Keep,
+            ),
+            (TagName(focused()), Answer),
+            (MonitorName(None), Answer),
+            (ConfigBorderFocused(0xff0000), Keep),
+            (ConfigBorderUnfocused(0x00ff00), Keep),
+            (ConfigBorderWidth(2), Keep),
+            (ConfigGap(8), Keep),
+            (ConfigMargin(Side::Top, 20), Keep),
+            (ConfigTagSwitchMode(TagSwitchMode::Greedy), Keep),
+            (ConfigAnimations(false, 0), Keep),
+            (ConfigHideStrategy(HideStrategy::Offscreen), Keep),
+            (AddRule(Rule::new()), Keep),
+            (AddTag("new".into(), false), Keep),
+            (RemoveTag(TagSelection::Index(1)), Keep),
+            (Select(Some(a)), Keep),
+            (SelectDir(Side::Left), Keep),
+            (SelectParent, Keep),
+            (PreselAmt(0.3), Keep),
+            (SelectionCancel, Keep),
+            (Rotate(true), Keep),
+            (ViewLayers(focused()), Answer),
+            (ViewStack(focused()), Answer),
+            (ViewClients(focused()), Answer),
+            (IgnoreSizeHints(Some(a)), Keep),
+            (Ping, Answer),
+            (
+                ResizeWindowPercent(Some(a), Side::Bottom, 10.0, false),
+                Keep,
+            ),
+            (ClientIcon(a), Answer),
+            (
+                AssignClass("a".into(), TagSelection::Name("assigned".into()), false),
+                Keep,
+            ),
+            (ListAssignments, Answer),
+            (ConfigMonocle(true, true, true), Keep),
+            (ConfigMarginDelta(Side::Left, 5), Keep),
+            (ConfigMarginDeltaAll(-2), Keep),
+            (
+                ConfigMonitorTheme("fake-0".into(), ThemeField::Gap, Some(2)),
+                Keep,
+            ),
+            (ConfigFloatingShadowsOnly(true), Keep),
+            (ViewMonitorLayers, Answer),
+            (SetFocusLock(StateArg::Toggle), Keep),
+            (PanelAutoHide(a, true), Keep),
+            (ViewTree(focused()), Answer),
+            (SubscribeTree(None), Subscribe),
+            (DebugDump, Answer),
+            (SetLogLevel(log::LevelFilter::Info), Keep),
+            (TailLog(5), Answer),
+            (SubscribeFullscreen, Subscribe),
+            (ConfigSelectionFollow(true), Keep),
+            (SelectToggle(Some(a)), Keep),
+            (RuleTest(a), Answer),
+            (Raise(Some(a)), Keep),
+            (Lower(Some(a)), Keep),
+            (SetAot(Some(a), StateArg::On), Keep),
+            (ConfigPanelLayer(true, true), Keep),
+            (ConfigPointerBindings(bindings), Keep),
+            (SetBorderWidth(Some(a), Some(3)), Keep),
+            (SetBorderColor(Some(a), Some(0xffffff)), Keep),
+            (Metrics, Answer),
+            (ConfigOverflow(Some(4), OverflowPolicy::NextEmpty), Keep),
+            (AddTemplate(template), Keep),
+            (AddTagFromTemplate("web".into()), Keep),
+            (WaitForWindow(spec("a"), 0), Subscribe),
+            (ViewHidden(focused()), Answer),
+            (ShowSpecific(a), Keep),
+            (MoveGroupToTag(Some(a), TagSelection::Index(1)), Keep),
+            (CycleGroup(true), Keep),
+            (ConfigGroupHide(true), Keep),
+            (DropdownToggle("true".into(), 0.4), Keep),
+            (ConfigInsertAtPointer(true), Keep),
+            (ConfigFocusFlash(100, 0xffffff), Keep),
+            (RotateNode(NodeSelector::Focused, true), Keep),
+            (EqualizeNode(NodeSelector::Focused), Keep),
+            (MoveSubtree(node(), NodeSelector::Window(b)), Keep),
+            (ConfigTray(false, 16), Keep),
+            (TrayWidth, Answer),
+            (SetPassthrough(Some(a), StateArg::On), Keep),
+            (ConfigMinTileSize(Some((50, 50))), Keep),
+            (SubscribeCollapsed, Subscribe),
+            (ConfigUrgentTimeout(1000), Keep),
+            (ClearUrgent(None), Keep),
+            (Restart, Close),
+            (SetFloatingGeometry(Some(a), geometry, None), Keep),
+            (QueryGeometry(Some(a), true), Answer),
+            (ConfigBorderInactive(0x0000ff), Keep),
+            (ConfigFocusIndicator(FocusIndicator::Edge(2), None), Keep),
+            (SetProtected(Some(a), StateArg::On), Keep),
+            (ConfigIdleTimeout(0), Keep),
+            (SubscribeIdle, Subscribe),
+            (ConfigRememberFloating(true), Keep),
+            (LockRatio(NodeSelector::Focused, true), Keep),
+            (SubscribeLayout, Subscribe),
+            (ConfigFloatPlacement(FloatPlacement::Cascade(10, 10)), Keep),
+            (ConfigPreselTimeout(0), Keep),
+            (SubscribePresel, Subscribe),
+            (GetFlags(Some(a)), Answer),
+            (BindFrameButton(1, 8, Some(FrameAction::Raise)), Keep),
+            (BeginMotion(Some(a), Side::Right, MotionKind::Move), Keep),
+            (MotionStep(5), Keep),
+            (EndMotion, Keep),
+            (ConfigSwipe(swipe), Keep),
+            (ConfigFocusSettle(0), Keep),
+            (SpawnInPresel("true".into()), Keep),
+            (CycleClass(true), Keep),
+            (SetInhibitFullscreen(Some(a), StateArg::On), Keep),
+            (RenderLayout(focused()), Answer),
+            (
+                ConfigFrameDoubleClick(Some(FrameAction::Floating), 300),
+                Keep,
+            ),
+            (Pip(Some(a), Corner::BottomRight, 0.25), Keep),
+            (ConfigRefocusOffMonitors(true), Keep),
+            (VisualBell(Some(a)), Keep),
+            (ConfigVisualBell(100, 0xff0000), Keep),
+            (ConfigCloseFade(0), Keep),
+            (
+                SetFakeMonitors(vec![(0, 0, 500, 500), (500, 0, 500, 500)]),
+                Keep,
+            ),
+            (ClearFakeMonitors, Keep),
+            (WatchProperty(a, "WM_NAME".into()), Subscribe),
+            (ConfigGapPercent(2.0), Keep),
+        ]
+    }
+
+    #[test]
+    fn every_request_leaves_the_connection_as_expected() {
+        let rows = requests(0, 0);
+        let variants: HashSet<_> = rows
+            .iter()
+            .map(|(request, _)| std::mem::discriminant(request))
+            .collect();
+        assert_eq!(variants.len(), rows.len(), "a request has two rows");
+        assert_eq!(rows.len(), REQUESTS, "a request has no row");
+        let mut wrong = Vec::new();
+        for i in 0..REQUESTS {
+            let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+            let a = mock::map(&mut wm, "a");
+            let b = mock::map(&mut wm, "b");
+            let (request, expected) = requests(a, b).swap_remove(i);
+            let name = format!("{:?}", request);
+            match wm.handle_request(request) {
+                Ok(reply) if expect(&reply) == expected => (),
+                Ok(reply) => wrong.push(format!("{} left {:?}", name, expect(&reply))),
+                Err(e) => wrong.push(format!("{} failed: {}", name, e)),
+            }
+        }
+        assert!(wrong.is_empty(), "{:#?}", wrong);
+    }
+
+    #[test]
+    fn the_server_has_the_feature_of_every_request() {
+        let all: HashSet<_> = features::ALL.iter().copied().collect();
+        assert_eq!(all.len(), features::ALL.len(), "a feature is listed twice");
+        let mut used = HashSet::new();
+        for (request, _) in requests(0, 0) {
+            if let Some(feature) = request.feature() {
+                assert!(all.contains(feature), "{:?}", request);
+                used.insert(feature);
+            }
+        }
+        assert_eq!(used, all, "a feature no request needs");
+    }
+
+    #[test]
+    fn queries_about_nothing_close_the_connection() {
+        let missing = || TagSelection::Name("missing".into());
+        let rows = vec![
+            ClientRequest::FocusedWindow(missing()),
+            ClientRequest::TagName(missing()),
+            ClientRequest::ViewTree(missing()),
+            ClientRequest::ViewHidden(missing()),
+            ClientRequest::RenderLayout(missing()),
+            ClientRequest::ClientIcon(1),
+            ClientRequest::MonitorName(Some(1)),
+        ];
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        for request in rows {
+            let name = format!("{:?}", request);
+            let reply = wm.handle_request(request).unwrap();
+            assert_eq!(expect(&reply), Expect::Close, "{}", name);
         }
     }
+
+    fn monitor_layers(wm: &mut WindowManager) -> Vec<MonitorLayers> {
+        match wm.handle_request(ClientRequest::ViewMonitorLayers) {
+            Ok(Reply::Keep(Some(CwmResponse::MonitorLayers(monitors)))) => monitors,
+            _ => panic!("no monitor layers"),
+        }
+    }
+
+    // the x stacking order of a monitor's windows has to be the one its layers ask for
+    fn assert_stacked_by_layers(wm: &mut WindowManager, after: &str) {
+        for mon in monitor_layers(wm) {
+            let layered: Vec<_> = mon
+                .layers
+                .iter()
+                .flat_map(|layer| layer.iter().rev())
+                .copied()
+                .collect();
+            assert_eq!(mon.stacking, layered, "{} on {}", after, mon.monitor);
+        }
+    }
+
+    fn fullscreen(wm: &mut WindowManager, win: Window) -> bool {
+        let (tag, client) = wm.get_client(Some(win)).unwrap();
+        wm.tags.get(&tag).unwrap().client(client).flags.fullscreen
+    }
+
+    // a client that has greeted the wm, its requests are handled once the wm serves connections
+    fn client(socket: &str) -> Stream {
+        let stream = UnixStream::connect(socket).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let mut stream = Stream::new(stream);
+        stream.send(&Hello {
+            version: PROTOCOL_VERSION,
+        });
+        stream
+    }
+
+    fn response<T: serde::de::DeserializeOwned>(stream: &mut Stream) -> T {
+        stream.recieve_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    // serves connections the way the main loop does until the clients are done
+    fn serve_until(wm: &mut WindowManager, done: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done() {
+            assert!(Instant::now() < deadline, "clients were left waiting");
+            wm.handle_connections().unwrap();
+            wm.aux.flush_streams();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn dozens_of_clients_at_once_are_answered_in_order() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        let mon = wm.focused_monitor;
+        let socket = &wm.aux.socket.clone();
+        let finished = &std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            let clients: Vec<_> = (0..48)
+                .map(|_| {
+                    scope.spawn(move || {
+                        let mut stream = client(socket);
+                        for _ in 0..10 {
+                            stream.send(&ClientRequest::Ping);
+                            stream.send(&ClientRequest::FocusedMonitor);
+                            stream.send(&ClientRequest::GetFlags(Some(a)));
+                        }
+                        let accepted = matches!(response(&mut stream), HelloReply::Accepted { .. });
+                        let responses: Vec<CwmResponse> =
+                            (0..30).map(|_| response(&mut stream)).collect();
+                        finished.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                        (accepted, responses)
+                    })
+                })
+                .collect();
+            serve_until(&mut wm, || {
+                finished.load(std::sync::atomic::Ordering::SeqCst) == clients.len()
+            });
+            for client in clients {
+                let (accepted, responses) = client.join().unwrap();
+                assert!(accepted);
+                for round in responses.chunks(3) {
+                    match round {
+                        [CwmResponse::Pong, CwmResponse::FocusedMonitor(focused), CwmResponse::Flags(Some(_))]
+                            if *focused == mon => {}
+                        _ => panic!("out of order: {:?}", round),
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn a_subscriber_that_stops_reading_does_not_hold_up_the_wm() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let socket = &wm.aux.socket.clone();
+        let mut subscriber = client(socket);
+        subscriber.send(&ClientRequest::SubscribeCollapsed);
+        wm.handle_connections().unwrap();
+        // far more than fits in the socket, the wm used to wait on every message once it was full
+        let start = Instant::now();
+        for _ in 0..100_000 {
+            wm.aux.hooks.collapsed(1, 2);
+        }
+        wm.aux.flush_streams();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        let pinged = &std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                let mut stream = client(socket);
+                stream.send(&ClientRequest::Ping);
+                let _: HelloReply = response(&mut stream);
+                let pong: CwmResponse = response(&mut stream);
+                assert!(matches!(pong, CwmResponse::Pong));
+                pinged.store(true, std::sync::atomic::Ordering::SeqCst);
+            });
+            serve_until(&mut wm, || pinged.load(std::sync::atomic::Ordering::SeqCst));
+        });
+        // dropped once too much was waiting for it, after what already made it into the socket
+        let _: HelloReply = response(&mut subscriber);
+        let mut received = 0;
+        loop {
+            match subscriber.recieve::<CwmResponse>() {
+                (_, Some(CwmResponse::Collapsed(1, 2))) => received += 1,
+                (_, Some(response)) => panic!("unexpected {:?}", response),
+                (true, None) => break,
+                (false, None) => (),
+            }
+        }
+        assert!(received > 0 && received < 100_000, "{}", received);
+    }
+
+    // two monitors with two windows each, the left one is focused
+    fn two_monitors() -> (WindowManager, [Window; 4]) {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500), (1000, 0, 1000, 500)]);
+        let left = wm.focused_monitor;
+        let right = *wm.monitors.keys().find(|mon| **mon != left).unwrap();
+        let a1 = mock::map(&mut wm, "a1");
+        let a2 = mock::map(&mut wm, "a2");
+        wm.focused_monitor = right;
+        let b1 = mock::map(&mut wm, "b1");
+        let b2 = mock::map(&mut wm, "b2");
+        wm.focused_monitor = left;
+        (wm, [a1, a2, b1, b2])
+    }
+
+    #[test]
+    fn restacking_keeps_every_monitor_in_layer_order() {
+        let (mut wm, [a1, a2, b1, b2]) = two_monitors();
+        assert_stacked_by_layers(&mut wm, "mapping");
+        let steps = vec![
+            ClientRequest::SetFloating(Some(a1), StateArg::On),
+            ClientRequest::SetFullscreen(Some(b2), StateArg::On),
+            ClientRequest::SetLayer(Some(a2), SetArg(StackLayer::Above, false)),
+            ClientRequest::SetLayer(Some(b1), SetArg(StackLayer::Above, false)),
+            ClientRequest::SetFullscreen(Some(a2), StateArg::On),
+            ClientRequest::SetLayer(Some(b1), SetArg(StackLayer::Below, false)),
+            ClientRequest::SetFullscreen(Some(b2), StateArg::Off),
+            ClientRequest::SetFloating(Some(b2), StateArg::On),
+            ClientRequest::SetLayer(Some(a1), SetArg(StackLayer::Normal, false)),
+            ClientRequest::SetSticky(Some(a1), StateArg::On),
+            ClientRequest::FocusTag(None, TagSelection::Next(None), false, None),
+            ClientRequest::SetFloating(Some(a2), StateArg::On),
+            ClientRequest::FocusTag(None, TagSelection::Prev(None), false, None),
+            ClientRequest::SetHidden(Some(b1), StateArg::On),
+            ClientRequest::SetHidden(Some(b1), StateArg::Off),
+        ];
+        for step in steps {
+            let name = format!("{:?}", step);
+            wm.handle_request(step).unwrap();
+            assert_stacked_by_layers(&mut wm, &name);
+        }
+    }
+
+    #[test]
+    fn a_fullscreen_client_only_takes_the_layer_of_its_own_tag() {
+        let (mut wm, [a1, _, b1, b2]) = two_monitors();
+        wm.handle_request(ClientRequest::SetFullscreen(Some(a1), StateArg::On))
+            .unwrap();
+        let left = monitor_layers(&mut wm).remove(0);
+        wm.handle_request(ClientRequest::SetFullscreen(Some(b1), StateArg::On))
+            .unwrap();
+        assert!(fullscreen(&mut wm, a1));
+        wm.handle_request(ClientRequest::SetFullscreen(Some(b2), StateArg::On))
+            .unwrap();
+        // b2 takes the fullscreen layer of its tag from b1, a1 keeps its own
+        assert!(fullscreen(&mut wm, a1));
+        assert!(!fullscreen(&mut wm, b1));
+        wm.handle_request(ClientRequest::SetLayer(
+            Some(b1),
+            SetArg(StackLayer::Above, false),
+        ))
+        .unwrap();
+        let monitors = monitor_layers(&mut wm);
+        assert_eq!(monitors[0].layers, left.layers);
+        assert_eq!(monitors[0].stacking, left.stacking);
+        assert_stacked_by_layers(&mut wm, "moving b1 above");
+    }
 }
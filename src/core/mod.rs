@@ -0,0 +1,161 @@
+// the layout engine, tags and rules without anything tied to a display server
+use anyhow::Result;
+use std::time::Instant;
+
+use crate::config::Theme;
+use crate::utils::Rect;
+
+pub mod node;
+pub mod rules;
+pub mod tag;
+
+pub use node::{NodeContents, Side, Split, TreeNode};
+pub use rules::Rule;
+pub use tag::{Client, ClientArgs, ClientFlags, StackLayer, StackPos, Tag};
+
+// ids handed out by the backend, the same as x11 windows and atoms
+pub type Window = u32;
+pub type Atom = u32;
+
+// what the layout needs from the display server, the x11 one is implemented on Aux
+pub trait Backend {
+    fn theme(&self) -> &Theme;
+
+    // from is the geometry last applied to the window, if any
+    fn configure_client(
+        &self,
+        win: Window,
+        from: Option<Rect>,
+        rect: &Rect,
+        border: u16,
+    ) -> Result<()>;
+
+    // applies the geometry right away and restacks the window
+    fn stack_client(&self, client: &Client, rect: &Rect, border: u16, pos: StackPos) -> Result<()>;
+
+    // the preselection of a leaf is used up by the next client split from it
+    fn take_presel(&mut self, tag: Atom, leaf: usize) -> Result<Option<(Side, f32)>>;
+
+    // keeps the selection drawn over the tag's nodes
+    fn resize_selection(&mut self, tag: &Tag) -> Result<()>;
+
+    // start is when the relayout began
+    fn relayout_done(&self, start: Instant);
+
+    // whether every output of the monitor is off
+    fn sleeping(&self, monitor: Atom) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    // a display server that only remembers where windows were put
+    #[derive(Default)]
+    struct Headless {
+        theme: Theme,
+        windows: RefCell<HashMap<Window, Rect>>,
+    }
+
+    impl Backend for Headless {
+        fn theme(&self) -> &Theme {
+            &self.theme
+        }
+
+        fn configure_client(
+            &self,
+            win: Window,
+            _from: Option<Rect>,
+            rect: &Rect,
+            _border: u16,
+        ) -> Result<()> {
+            self.windows.borrow_mut().insert(win, rect.clone());
+            Ok(())
+        }
+
+        fn stack_client(
+            &self,
+            client: &Client,
+            rect: &Rect,
+            border: u16,
+            _: StackPos,
+        ) -> Result<()> {
+            self.configure_client(client.win, None, rect, border)
+        }
+
+        fn take_presel(&mut self, _: Atom, _: usize) -> Result<Option<(Side, f32)>> {
+            Ok(None)
+        }
+
+        fn resize_selection(&mut self, _: &Tag) -> Result<()> {
+            Ok(())
+        }
+
+        fn relayout_done(&self, _: Instant) {}
+
+        fn sleeping(&self, _: Atom) -> bool {
+            false
+        }
+    }
+
+    fn client(win: Window) -> Client {
+        Client {
+            name: None,
+            class: None,
+            instance: None,
+            border_width: 1,
+            border_override: None,
+            border_color: None,
+            layer: StackLayer::Normal,
+            last_layer: StackLayer::Normal,
+            node: 0,
+            stack_pos: 0,
+            layer_pos: (0, 0),
+            flags: ClientFlags {
+                urgent: false,
+                hidden: false,
+                floating: false,
+                fullscreen: false,
+                sticky: false,
+                psuedo_urgent: false,
+                aot: false,
+                passthrough: false,
+                protected: false,
+                inhibit_fullscreen: false,
+            },
+            win,
+            applied: RefCell::new(None),
+            transient_for: None,
+            group: None,
+            icon: None,
+            prev_tag: None,
+            force_fullscreen: false,
+            pip: None,
+        }
+    }
+
+    #[test]
+    fn tags_are_laid_out_without_a_display_server() {
+        let mut backend = Headless::default();
+        let mut tag = Tag::default();
+        let available = Rect::new(0, 0, 1000, 500);
+        tag.set_tiling_size(&backend, available.clone()).unwrap();
+        for win in [1, 2] {
+            let info = NodeContents::leaf(0, (1, 1), (u16::MAX, u16::MAX), Rect::default());
+            let client = tag
+                .add_client(&mut backend, client(win), None, info, true)
+                .unwrap();
+            tag.set_layer(&backend, client, true).unwrap();
+        }
+        let windows = backend.windows.borrow();
+        let (a, b) = (&windows[&1], &windows[&2]);
+        let tiling = backend.theme.tiling_rect(None, &available);
+        assert!(tiling.contains_rect(a) && tiling.contains_rect(b));
+        assert_eq!((a.y, a.height), (b.y, b.height));
+        assert!(a.x + (a.width as i16) < b.x);
+        assert_eq!(tag.get_stack(), vec![1, 0]);
+    }
+}
@@ -2,10 +2,12 @@ use anyhow::Result;
 use log::info;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::time::Instant;
 
-use super::{Client, Tag};
+use super::tag::{Client, Tag};
+use super::Backend;
+use crate::protocol::{Minimap, MinimapWindow};
 use crate::utils::{pop_set, three_mut, Rect};
-use crate::Aux;
 
 #[derive(PartialEq, Serialize, Deserialize, Debug, Copy, Clone)]
 pub enum Side {
@@ -40,7 +42,7 @@ impl Side {
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Split {
     Horizontal,
     Vertical,
@@ -52,6 +54,7 @@ pub struct NodeInfo {
     pub ratio: f32,
     pub first_child: usize,
     pub second_child: usize,
+    pub locked: bool, // the ratio is left alone by resizing and equalizing
 }
 
 impl NodeInfo {
@@ -110,6 +113,7 @@ impl NodeContents {
             ratio,
             first_child,
             second_child,
+            locked: false,
         })
     }
 }
@@ -122,11 +126,117 @@ pub struct Node {
     pub info: NodeContents,
 }
 
+// a snapshot of the layout of a tag, absent leaves are floating, fullscreen or hidden
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TreeNode {
+    Split {
+        node: usize,
+        split: Split,
+        ratio: f32,
+        locked: bool,
+        first: Box<TreeNode>,
+        second: Box<TreeNode>,
+    },
+    Leaf {
+        node: usize,
+        win: u32,
+        name: Option<String>,
+        focused: bool,
+        absent: bool,
+    },
+    Empty,
+}
+
 impl Tag {
+    pub fn tree(&self) -> TreeNode {
+        self.subtree(0)
+    }
+
+    fn subtree(&self, node: usize) -> TreeNode {
+        let node_ = &self.nodes[node];
+        match &node_.info {
+            NodeContents::Node(info) => TreeNode::Split {
+                node,
+                split: info.split.clone(),
+                ratio: info.ratio,
+                locked: info.locked,
+                first: Box::new(self.subtree(info.first_child)),
+                second: Box::new(self.subtree(info.second_child)),
+            },
+            NodeContents::Leaf(leaf) => {
+                let client = &self.clients[leaf.client];
+                TreeNode::Leaf {
+                    node,
+                    win: client.win,
+                    name: client.name.clone(),
+                    focused: self.focused_client() == Some(leaf.client),
+                    absent: node_.absent,
+                }
+            }
+            NodeContents::Empty => TreeNode::Empty,
+        }
+    }
+
+    pub fn minimap(&self, backend: &impl Backend) -> Minimap {
+        let focused = self.focused_client();
+        let windows = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.vec().into_iter().rev())
+            .filter(|client| !self.clients[*client].flags.hidden)
+            .filter_map(|client_| {
+                let client = &self.clients[client_];
+                self.get_rect(backend, client_).map(|rect| MinimapWindow {
+                    win: client.win,
+                    rect: (
+                        rect.x - self.size.x,
+                        rect.y - self.size.y,
+                        rect.width,
+                        rect.height,
+                    ),
+                    focused: focused == Some(client_),
+                    floating: client.flags.floating && !client.flags.fullscreen,
+                    urgent: client.flags.urgent || client.flags.psuedo_urgent,
+                })
+            })
+            .collect();
+        Minimap {
+            width: self.size.width,
+            height: self.size.height,
+            windows,
+        }
+    }
+
+    // false for indices that are out of range or were freed
+    pub fn valid_node(&self, node: usize) -> bool {
+        node < self.nodes.len()
+            && !self.free_nodes.contains(&node)
+            && !matches!(self.nodes[node].info, NodeContents::Empty)
+    }
+
+    fn in_subtree(&self, root: usize, node: usize) -> bool {
+        let mut node = Some(node);
+        while let Some(node_) = node {
+            if node_ == root {
+                return true;
+            }
+            node = self.nodes[node_].parent.map(|x| x.0);
+        }
+        false
+    }
+
     pub fn get_node_rect(&self, node: usize) -> &Rect {
         &self.nodes[node].rect
     }
 
+    pub fn get_client_rect(&self, client: usize) -> &Rect {
+        let client = &self.clients[client];
+        match &self.nodes[client.node].info {
+            NodeContents::Leaf(leaf) if client.flags.floating => &leaf.floating,
+            _ => &self.nodes[client.node].rect,
+        }
+    }
+
     pub fn get_node_client(&self, node: usize) -> Option<usize> {
         if let NodeContents::Leaf(leaf) = &self.nodes[node].info {
             Some(leaf.client)
@@ -135,14 +245,41 @@ impl Tag {
         }
     }
 
-    pub fn get_rect(&self, client: usize) -> Option<Rect> {
+    // where the client goes the next time it is floating, nothing is applied
+    pub fn set_floating_rect(&mut self, client: usize, rect: &Rect) {
+        let node = self.clients[client].node;
+        if let NodeContents::Leaf(leaf) = &mut self.nodes[node].info {
+            leaf.floating.copy(rect);
+        }
+    }
+
+    // only moves the window if it is floating and shown
+    pub fn set_floating_geometry(
+        &mut self,
+        backend: &impl Backend,
+        client: usize,
+        rect: &Rect,
+    ) -> Result<()> {
+        self.set_floating_rect(client, rect);
+        let flags = &self.clients[client].flags;
+        if flags.floating && !flags.fullscreen && !flags.hidden && self.monitor.is_some() {
+            self.apply_pos_size(backend, client, rect, true)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_rect(&self, backend: &impl Backend, client: usize) -> Option<Rect> {
         let client = &self.clients[client];
         if client.flags.fullscreen {
             Some(self.size.clone())
         } else {
             let node = &self.nodes[client.node];
             if !client.flags.floating {
-                Some(node.rect.clone())
+                if self.monocle {
+                    Some(self.monocle_rect(backend))
+                } else {
+                    Some(node.rect.clone())
+                }
             } else if let NodeContents::Leaf(leaf) = &node.info {
                 Some(leaf.floating.clone())
             } else {
@@ -153,7 +290,7 @@ impl Tag {
 
     fn resize_node(
         &mut self,
-        aux: &Aux,
+        backend: &impl Backend,
         node: usize,
         to_process: &mut Vec<usize>,
         force_process: bool,
@@ -164,12 +301,18 @@ impl Tag {
             None
         } {
             info!("{} {} {}", node, _child1, _child2);
+            let monitor = self.monitor;
+            let monocle = if self.monocle {
+                Some(self.monocle_rect(backend))
+            } else {
+                None
+            };
             let (node, child1, child2) =
                 three_mut(&mut self.nodes, (node, _child1, _child2)).unwrap();
             if let NodeContents::Node(info) = &node.info {
-                if self.monocle {
-                    child1.rect.copy(&self.tiling_size);
-                    child2.rect.copy(&self.tiling_size);
+                if let Some(rect) = monocle {
+                    child1.rect.copy(&rect);
+                    child2.rect.copy(&rect);
                     to_process.push(_child2);
                     to_process.push(_child1);
                 } else {
@@ -194,7 +337,7 @@ impl Tag {
                                 info.ratio,
                                 &mut child1.rect,
                                 &mut child2.rect,
-                                aux.theme.gap,
+                                backend.theme().gap(monitor),
                             );
                             to_process.push(_child2);
                             to_process.push(_child1);
@@ -218,32 +361,30 @@ impl Tag {
 
     pub fn split_leaf(
         &mut self,
-        aux: &mut Aux,
+        backend: &mut impl Backend,
         leaf_idx: usize,
         absent: bool,
         idx: usize,
         info: NodeContents,
+        presel: Option<(Side, f32)>,
     ) -> Result<()> {
-        let ((split, first), ratio) = aux
-            .selection
-            .presel(&aux.dpy, self.id, leaf_idx)?
-            .map_or_else(
-                || {
-                    let rect = self.nodes[leaf_idx].rect.clone();
+        let ((split, first), ratio) = presel.or_else(|| self.next_split.take()).map_or_else(
+            || {
+                let rect = self.nodes[leaf_idx].rect.clone();
+                (
                     (
-                        (
-                            if rect.width > rect.height {
-                                Split::Vertical
-                            } else {
-                                Split::Horizontal
-                            },
-                            false,
-                        ),
-                        0.5,
-                    )
-                },
-                |presel| (presel.side.get_split(), presel.amt),
-            );
+                        if rect.width > rect.height {
+                            Split::Vertical
+                        } else {
+                            Split::Horizontal
+                        },
+                        false,
+                    ),
+                    0.5,
+                )
+            },
+            |(side, amt)| (side.get_split(), amt),
+        );
         let (node1, node2, leaf_absent) = {
             let leaf = &self.nodes[leaf_idx];
             (
@@ -275,21 +416,22 @@ impl Tag {
             ratio,
             first_child: if first { second_child } else { first_child },
             second_child: if first { first_child } else { second_child },
+            locked: false,
         });
         self.clients[idx].node = second_child;
         // recompute child sizes of node
         if leaf_absent && !absent {
-            self.propagate_absent(aux, leaf_idx)?;
+            self.propagate_absent(backend, leaf_idx)?;
         } else if !(self.monocle || (leaf_absent && absent)) {
-            self.resize_node(aux, leaf_idx, &mut vec![], false);
+            self.resize_node(backend, leaf_idx, &mut vec![], false);
         }
         if !leaf_absent && idx2.is_some() {
-            self.apply_pos_size(aux, idx2.unwrap(), &self.nodes[first_child].rect, true)?;
+            self.apply_pos_size(backend, idx2.unwrap(), &self.nodes[first_child].rect, true)?;
         }
         Ok(())
     }
 
-    fn propagate_absent(&mut self, aux: &Aux, node: usize) -> Result<()> {
+    fn propagate_absent(&mut self, backend: &impl Backend, node: usize) -> Result<()> {
         let mut parent = Some(node);
         let mut prev_parent = node;
         while parent.is_some() {
@@ -322,10 +464,10 @@ impl Tag {
             let node_ = q.pop().unwrap();
             let node = &self.nodes[node_];
             match &node.info {
-                NodeContents::Node(_) => self.resize_node(aux, node_, &mut q, false),
+                NodeContents::Node(_) => self.resize_node(backend, node_, &mut q, false),
                 NodeContents::Leaf(leaf) => {
                     if !node.absent {
-                        self.apply_pos_size(aux, leaf.client, &node.rect, true)?
+                        self.apply_pos_size(backend, leaf.client, &node.rect, true)?
                     }
                 }
                 _ => (),
@@ -433,7 +575,7 @@ impl Tag {
         }
     }
 
-    pub fn rotate(&mut self, aux: &Aux, node: usize, rev: bool) -> Result<()> {
+    pub fn rotate(&mut self, backend: &impl Backend, node: usize, rev: bool) -> Result<()> {
         let mut q = vec![node];
         while !q.is_empty() {
             let node = q.pop().unwrap();
@@ -444,10 +586,104 @@ impl Tag {
                 self.rotate_nodes(node, first_child, second_child, rev);
             }
         }
-        self.resize_tiled(aux, node, None)?;
+        self.resize_tiled(backend, node, None)?;
         Ok(())
     }
 
+    // the shown tiled leaves smaller than the floor or the min size of their client
+    pub fn overflowing(&self, floor: (u16, u16)) -> Vec<usize> {
+        if self.monocle {
+            return Vec::new();
+        }
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| match &node.info {
+                NodeContents::Leaf(leaf) if !node.absent => {
+                    node.rect.width < floor.0.max(leaf.min_size.0)
+                        || node.rect.height < floor.1.max(leaf.min_size.1)
+                }
+                _ => false,
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    pub fn equalize(&mut self, backend: &impl Backend, node: usize) -> Result<()> {
+        let mut q = vec![node];
+        while let Some(node) = q.pop() {
+            if let NodeContents::Node(info) = &mut self.nodes[node].info {
+                if !info.locked {
+                    info.ratio = 0.5;
+                }
+                q.push(info.first_child);
+                q.push(info.second_child);
+            }
+        }
+        self.resize_tiled(backend, node, None)
+    }
+
+    pub fn lock_ratio(&mut self, node: usize, locked: bool) {
+        if let NodeContents::Node(info) = &mut self.nodes[node].info {
+            info.locked = locked;
+        }
+    }
+
+    // detaches the subtree at node and splits target with it, the sibling of node takes the
+    // place of their parent
+    pub fn move_subtree(
+        &mut self,
+        backend: &impl Backend,
+        node: usize,
+        target: usize,
+    ) -> Result<()> {
+        let (parent, first) = match self.nodes[node].parent {
+            Some(parent) if !self.in_subtree(node, target) => parent,
+            _ => return Ok(()),
+        };
+        let sibling = match &self.nodes[parent].info {
+            NodeContents::Node(info) => info.get_child(!first),
+            _ => return Ok(()),
+        };
+        let target = if target == sibling { parent } else { target };
+        self.replace_node(sibling, parent);
+        self.free_nodes.push(sibling);
+        let split = if self.nodes[target].rect.width > self.nodes[target].rect.height {
+            Split::Vertical
+        } else {
+            Split::Horizontal
+        };
+        let moved = self.add_node(Node {
+            parent: Some((target, true)),
+            rect: self.nodes[target].rect.clone(),
+            absent: false,
+            info: NodeContents::Empty,
+        });
+        self.replace_node(target, moved);
+        self.nodes[node].parent = Some((target, false));
+        self.nodes[target].info = NodeContents::node(split, 0.5, moved, node);
+        if let Some((grandparent, _)) = self.nodes[parent].parent {
+            self.propagate_absent(backend, grandparent)?;
+        }
+        self.propagate_absent(backend, target)?;
+        self.resize_tiled(backend, 0, None)
+    }
+
+    // moves the contents of from into to, keeping the parent of to
+    fn replace_node(&mut self, from: usize, to: usize) {
+        let info = std::mem::replace(&mut self.nodes[from].info, NodeContents::Empty);
+        match &info {
+            NodeContents::Leaf(leaf) => self.clients[leaf.client].node = to,
+            NodeContents::Node(info) => {
+                self.nodes[info.first_child].parent = Some((to, true));
+                self.nodes[info.second_child].parent = Some((to, false));
+            }
+            NodeContents::Empty => (),
+        }
+        self.nodes[to].absent = self.nodes[from].absent;
+        self.nodes[to].info = info;
+    }
+
     pub fn client_under_cursor(&self, root: usize, pos: &(i16, i16)) -> Option<usize> {
         #[inline]
         fn check_node(tag: &Tag, node_: usize, q: &mut Vec<usize>, pos: &(i16, i16)) {
@@ -474,7 +710,7 @@ impl Tag {
 
     pub fn move_client(
         &mut self,
-        aux: &Aux,
+        backend: &impl Backend,
         client_: usize,
         delta: (i16, i16),
         pos: &(i16, i16),
@@ -487,7 +723,7 @@ impl Tag {
                     leaf.floating.y += delta.1;
                 }
                 if let NodeContents::Leaf(leaf) = &self.nodes[client.node].info {
-                    self.apply_pos_size(aux, client_, &leaf.floating, true)?;
+                    self.apply_pos_size(backend, client_, &leaf.floating, true)?;
                 }
             } else if !self.nodes[client.node].rect.contains(pos) {
                 if let Some(other) = self.client_under_cursor(0, pos) {
@@ -498,15 +734,21 @@ impl Tag {
                     let info = self.nodes[node].info.clone();
                     self.nodes[node].info = self.nodes[other_node].info.clone();
                     self.nodes[other_node].info = info;
-                    self.apply_pos_size(aux, client_, &self.nodes[other_node].rect, true)?;
-                    self.apply_pos_size(aux, other, &self.nodes[node].rect, true)?;
+                    self.apply_pos_size(backend, client_, &self.nodes[other_node].rect, true)?;
+                    self.apply_pos_size(backend, other, &self.nodes[node].rect, true)?;
                 }
             }
         }
         Ok(())
     }
 
-    pub fn move_side(&mut self, aux: &Aux, client_: usize, side: Side, amount: u16) -> Result<()> {
+    pub fn move_side(
+        &mut self,
+        backend: &impl Backend,
+        client_: usize,
+        side: Side,
+        amount: u16,
+    ) -> Result<()> {
         info!("moving {:?}", side);
         let client = &self.clients[client_];
         if !client.flags.fullscreen {
@@ -517,7 +759,7 @@ impl Tag {
                     leaf.floating.y += delta.1;
                 }
                 if let NodeContents::Leaf(leaf) = &self.nodes[client.node].info {
-                    self.apply_pos_size(aux, client_, &leaf.floating, true)?;
+                    self.apply_pos_size(backend, client_, &leaf.floating, true)?;
                 }
             } else if let Some(other) = self.get_neighbour(client_, side) {
                 let other_node = self.clients[other].node;
@@ -527,8 +769,8 @@ impl Tag {
                 let info = self.nodes[node].info.clone();
                 self.nodes[node].info = self.nodes[other_node].info.clone();
                 self.nodes[other_node].info = info;
-                self.apply_pos_size(aux, client_, &self.nodes[other_node].rect, true)?;
-                self.apply_pos_size(aux, other, &self.nodes[node].rect, true)?;
+                self.apply_pos_size(backend, client_, &self.nodes[other_node].rect, true)?;
+                self.apply_pos_size(backend, other, &self.nodes[node].rect, true)?;
             }
         }
         Ok(())
@@ -579,11 +821,12 @@ impl Tag {
 
     pub fn resize_client(
         &mut self,
-        aux: &mut Aux,
+        backend: &mut impl Backend,
         client: usize,
         delta: (i16, i16),
         left: bool,
         top: bool,
+        proportional: bool,
     ) -> Result<()> {
         let (fullscreen, floating, node) = {
             let client = &self.clients[client];
@@ -632,19 +875,32 @@ impl Tag {
                     }
                 }
                 if let NodeContents::Leaf(leaf) = &self.nodes[node].info {
-                    self.apply_pos_size(aux, client, &leaf.floating, true)?;
+                    self.apply_pos_size(backend, client, &leaf.floating, true)?;
                 }
             } else {
                 let (parent_h, depth1) =
                     self.get_split_parent(node, if left { Side::Left } else { Side::Right });
                 let (parent_v, depth2) =
                     self.get_split_parent(node, if top { Side::Top } else { Side::Bottom });
+                if proportional {
+                    if let Some((parent, _)) = parent_h {
+                        let grow = if left { -delta.0 } else { delta.0 };
+                        self.keep_siblings(node, parent, Split::Vertical, grow);
+                    }
+                    if let Some((parent, _)) = parent_v {
+                        let grow = if top { -delta.1 } else { delta.1 };
+                        self.keep_siblings(node, parent, Split::Horizontal, grow);
+                    }
+                }
                 let mut q = vec![];
                 if let Some((parent_, _)) = parent_h {
                     let parent = &mut self.nodes[parent_];
-                    if let NodeContents::Node(node) = &mut parent.info {
-                        let diff = delta.0 as f32 / parent.rect.width as f32;
-                        node.ratio = (node.ratio + diff).min(Side::MAX).max(Side::MIN);
+                    match &mut parent.info {
+                        NodeContents::Node(node) if !node.locked => {
+                            let diff = delta.0 as f32 / parent.rect.width as f32;
+                            node.ratio = (node.ratio + diff).min(Side::MAX).max(Side::MIN);
+                        }
+                        _ => (),
                     }
                     if parent_v.is_none() || depth1 > depth2 {
                         q.push(parent_);
@@ -652,9 +908,12 @@ impl Tag {
                 }
                 if let Some((parent_, _)) = parent_v {
                     let parent = &mut self.nodes[parent_];
-                    if let NodeContents::Node(node) = &mut parent.info {
-                        let diff = delta.1 as f32 / parent.rect.height as f32;
-                        node.ratio = (node.ratio + diff).min(Side::MAX).max(Side::MIN);
+                    match &mut parent.info {
+                        NodeContents::Node(node) if !node.locked => {
+                            let diff = delta.1 as f32 / parent.rect.height as f32;
+                            node.ratio = (node.ratio + diff).min(Side::MAX).max(Side::MIN);
+                        }
+                        _ => (),
                     }
                     if q.is_empty() {
                         q.push(parent_)
@@ -664,22 +923,55 @@ impl Tag {
                     let node_ = q.pop().unwrap();
                     let node = &self.nodes[node_];
                     match &node.info {
-                        NodeContents::Node(_) => self.resize_node(aux, node_, &mut q, false),
+                        NodeContents::Node(_) => self.resize_node(backend, node_, &mut q, false),
                         NodeContents::Leaf(leaf) => {
                             if !node.absent {
-                                self.apply_pos_size(aux, leaf.client, &node.rect, true)?
+                                self.apply_pos_size(backend, leaf.client, &node.rect, true)?
                             }
                         }
                         _ => (),
                     }
                 }
-                aux.resize_selection(self)?;
+                backend.resize_selection(self)?;
             }
         }
         Ok(())
     }
 
-    pub fn set_absent(&mut self, aux: &Aux, client: usize, absent: bool) -> Result<()> {
+    // adjusts the splits between a node and the split that moves its edge so the siblings along
+    // the way keep their size, the node takes all of the growth and the other side of the edge
+    // shrinks as a whole
+    fn keep_siblings(&mut self, node: usize, edge: usize, split: Split, grow: i16) {
+        let mut current = node;
+        while let Some((parent, first)) = self.nodes[current].parent {
+            if parent == edge {
+                break;
+            }
+            let shared = match &self.nodes[parent].info {
+                NodeContents::Node(info) => !self.nodes[info.get_child(!first)].absent,
+                _ => false,
+            };
+            let parent_ = &mut self.nodes[parent];
+            let size = match split {
+                Split::Vertical => parent_.rect.width,
+                Split::Horizontal => parent_.rect.height,
+            } as f32;
+            if let NodeContents::Node(info) = &mut parent_.info {
+                if shared && !info.locked && info.split == split && size + grow as f32 > 0.0 {
+                    let first_size = info.ratio * size + if first { grow as f32 } else { 0.0 };
+                    info.ratio = (first_size / (size + grow as f32)).clamp(Side::MIN, Side::MAX);
+                }
+            }
+            current = parent;
+        }
+    }
+
+    pub fn set_absent(
+        &mut self,
+        backend: &impl Backend,
+        client: usize,
+        absent: bool,
+    ) -> Result<()> {
         if let Some(parent) = {
             let node = &mut self.nodes[self.clients[client].node];
             if node.absent != absent {
@@ -689,29 +981,30 @@ impl Tag {
                 None
             }
         } {
-            self.propagate_absent(aux, parent)?;
+            self.propagate_absent(backend, parent)?;
         }
         if !absent {
-            self.resize_tiled(aux, self.clients[client].node, None)?;
+            self.resize_tiled(backend, self.clients[client].node, None)?;
         }
         Ok(())
     }
 
-    pub fn set_tiling_size(&mut self, aux: &Aux, mut tiling_size: Rect) -> Result<()> {
-        tiling_size.x += aux.theme.gap as i16 + aux.theme.left_margin;
-        tiling_size.y += aux.theme.gap as i16 + aux.theme.top_margin;
-        tiling_size.width -=
-            (aux.theme.gap as i16 * 2 + aux.theme.right_margin + aux.theme.left_margin) as u16;
-        tiling_size.height -=
-            (aux.theme.gap as i16 * 2 + aux.theme.bottom_margin + aux.theme.top_margin) as u16;
+    pub fn set_tiling_size(&mut self, backend: &impl Backend, available: Rect) -> Result<()> {
+        let tiling_size = backend.theme().tiling_rect(self.monitor, &available);
         if tiling_size != self.tiling_size {
             self.tiling_size.copy(&tiling_size);
-            self.resize_tiled(aux, 0, Some(&tiling_size))?;
+            self.resize_tiled(backend, 0, Some(&tiling_size))?;
         }
         Ok(())
     }
 
-    pub fn resize_tiled(&mut self, aux: &Aux, node: usize, size: Option<&Rect>) -> Result<()> {
+    pub fn resize_tiled(
+        &mut self,
+        backend: &impl Backend,
+        node: usize,
+        size: Option<&Rect>,
+    ) -> Result<()> {
+        let start = Instant::now();
         if let Some(size) = size {
             self.nodes[node].rect.copy(size);
         }
@@ -720,26 +1013,28 @@ impl Tag {
             let node_ = q.pop().unwrap();
             let node = &self.nodes[node_];
             match &node.info {
-                NodeContents::Node(..) => self.resize_node(aux, node_, &mut q, false),
+                NodeContents::Node(..) => self.resize_node(backend, node_, &mut q, false),
                 NodeContents::Leaf(leaf) => {
                     if !node.absent {
-                        self.apply_pos_size(aux, leaf.client, &node.rect, true)?
+                        self.apply_pos_size(backend, leaf.client, &node.rect, true)?
                     }
                 }
                 _ => (),
             }
         }
+        backend.relayout_done(start);
         Ok(())
     }
 
-    pub fn resize_all(&mut self, aux: &Aux, available: &Rect, new_size: &Rect) -> Result<()> {
-        let mut tiling_size = &mut self.nodes[0].rect;
-        tiling_size.x = available.x + aux.theme.gap as i16 + aux.theme.left_margin;
-        tiling_size.y = available.y + aux.theme.gap as i16 + aux.theme.top_margin;
-        tiling_size.width = available.width
-            - (aux.theme.gap as i16 * 2 + aux.theme.right_margin + aux.theme.left_margin) as u16;
-        tiling_size.height = available.height
-            - (aux.theme.gap as i16 * 2 + aux.theme.bottom_margin + aux.theme.top_margin) as u16;
+    pub fn resize_all(
+        &mut self,
+        backend: &impl Backend,
+        available: &Rect,
+        new_size: &Rect,
+    ) -> Result<()> {
+        let start = Instant::now();
+        let tiling_size = &mut self.nodes[0].rect;
+        tiling_size.copy(&backend.theme().tiling_rect(self.monitor, available));
         if *tiling_size != self.tiling_size {
             self.tiling_size.copy(tiling_size)
         }
@@ -748,7 +1043,7 @@ impl Tag {
             let node_ = q.pop().unwrap();
             let node = &self.nodes[node_];
             match &node.info {
-                NodeContents::Node(..) => self.resize_node(aux, node_, &mut q, true),
+                NodeContents::Node(..) => self.resize_node(backend, node_, &mut q, true),
                 NodeContents::Leaf(leaf) => {
                     let leaf_client = leaf.client;
                     let floating = {
@@ -768,17 +1063,18 @@ impl Tag {
                     } else {
                         (&node.rect, true)
                     };
-                    self.apply_pos_size(aux, leaf_client, rect, border)?
+                    self.apply_pos_size(backend, leaf_client, rect, border)?
                 }
                 _ => (),
             }
         }
+        backend.relayout_done(start);
         Ok(())
     }
 
     pub fn add_client(
         &mut self,
-        aux: &mut Aux,
+        backend: &mut impl Backend,
         client: Client,
         parent: Option<usize>,
         mut info: NodeContents,
@@ -786,6 +1082,22 @@ impl Tag {
     ) -> Result<usize> {
         let absent = client.flags.absent();
         let hidden = client.flags.hidden;
+        // the leaf to split and its presel are found before anything changes, so failing here
+        // leaves the tag as it was
+        let leaf = match self.nodes[0].info {
+            NodeContents::Empty => None,
+            NodeContents::Leaf(..) => Some(0),
+            NodeContents::Node(..) => {
+                let leaf = parent
+                    .or_else(|| self.focus_stack.front().cloned())
+                    .unwrap_or_else(|| *self.hidden.back().unwrap());
+                Some(self.clients[leaf].node)
+            }
+        };
+        let presel = match leaf {
+            Some(leaf) => backend.take_presel(self.id, leaf)?,
+            None => None,
+        };
         let client = if let Some(idx) = pop_set(&mut self.free_clients) {
             self.clients[idx] = client;
             idx
@@ -798,34 +1110,30 @@ impl Tag {
             leaf.client = client;
         }
 
-        match self.nodes[0].info {
-            NodeContents::Empty => {
+        // once the client is in the tree it is kept track of like the rest, even when laying out
+        // the split fails
+        let res = match leaf {
+            None => {
                 self.nodes[0].info = info;
                 self.nodes[0].absent = absent;
                 self.clients[client].node = 0;
+                Ok(())
             }
-            NodeContents::Leaf(..) => {
-                self.split_leaf(aux, 0, absent, client, info)?;
-            }
-            NodeContents::Node(..) => {
-                let leaf = parent
-                    .or_else(|| self.focus_stack.front().cloned())
-                    .unwrap_or_else(|| *self.hidden.back().unwrap());
-                let leaf = self.clients[leaf].node;
-                self.split_leaf(aux, leaf, absent, client, info)?;
-            }
-        }
-        if !hidden {
+            Some(leaf) => self.split_leaf(backend, leaf, absent, client, info, presel),
+        };
+        if hidden {
+            self.hidden.push_back(client);
+        } else {
             self.clients[client].stack_pos = if focus {
                 self.focus_stack.push_front(client)
             } else {
                 self.focus_stack.push_back(client)
             };
         }
-        Ok(client)
+        res.map(|_| client)
     }
 
-    pub fn remove_node(&mut self, aux: &Aux, node: usize) -> Result<()> {
+    pub fn remove_node(&mut self, backend: &impl Backend, node: usize) -> Result<()> {
         let parent = self.nodes[node].parent;
         self.nodes[node].info = NodeContents::Empty;
         self.free_nodes.push(node);
@@ -864,8 +1172,8 @@ impl Tag {
                     self.nodes[second_child].parent = Some((parent_, false));
                 }
             }
-            self.resize_tiled(aux, parent_, None)?;
-            self.propagate_absent(aux, parent_)?;
+            self.resize_tiled(backend, parent_, None)?;
+            self.propagate_absent(backend, parent_)?;
         }
         Ok(())
     }
@@ -889,3 +1197,111 @@ impl Tag {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::core::Window;
+    use crate::mock;
+    use crate::utils::Rect;
+    use crate::{WindowLocation, WindowManager};
+
+    fn client(wm: &WindowManager, win: Window) -> Option<(u32, usize)> {
+        match wm.windows.get(&win) {
+            Some(WindowLocation::Client(tag, client)) => Some((*tag, *client)),
+            _ => None,
+        }
+    }
+
+    fn rect(wm: &WindowManager, win: Window) -> Rect {
+        let (tag, client) = client(wm, win).unwrap();
+        wm.tags.get(&tag).unwrap().get_client_rect(client).clone()
+    }
+
+    fn tiling(wm: &WindowManager) -> Rect {
+        wm.tags.get(&wm.focused_tag()).unwrap().tiling_size.clone()
+    }
+
+    fn overlap(a: &Rect, b: &Rect) -> bool {
+        a.x < b.x + b.width as i16
+            && b.x < a.x + a.width as i16
+            && a.y < b.y + b.height as i16
+            && b.y < a.y + a.height as i16
+    }
+
+    #[test]
+    fn first_window_fills_the_tiling_area() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        let tiling = tiling(&wm);
+        assert_eq!(
+            tiling,
+            wm.aux.theme.tiling_rect(None, &Rect::new(0, 0, 1000, 500))
+        );
+        assert_eq!(rect(&wm, a), tiling);
+    }
+
+    #[test]
+    fn second_window_splits_the_longer_side() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        let b = mock::map(&mut wm, "b");
+        let (a, b) = (rect(&wm, a), rect(&wm, b));
+        let gap = wm.aux.theme.gap;
+        assert_eq!((a.y, a.height), (b.y, b.height));
+        assert_eq!(a.width, b.width);
+        assert_eq!(a.x + (a.width + gap) as i16, b.x);
+        assert_eq!(a.width * 2 + gap, tiling(&wm).width);
+    }
+
+    #[test]
+    fn a_portrait_monitor_splits_top_and_bottom() {
+        let mut wm = mock::wm(&[(0, 0, 500, 1000)]);
+        let a = mock::map(&mut wm, "a");
+        let b = mock::map(&mut wm, "b");
+        let (a, b) = (rect(&wm, a), rect(&wm, b));
+        assert_eq!((a.x, a.width), (b.x, b.width));
+        assert!(a.y + (a.height as i16) < b.y);
+    }
+
+    #[test]
+    fn tiles_stay_inside_the_tiling_area_without_overlapping() {
+        let mut wm = mock::wm(&[(0, 0, 1920, 1080)]);
+        let wins: Vec<_> = (0..7)
+            .map(|n| mock::map(&mut wm, &format!("app{}", n)))
+            .collect();
+        let tiling = tiling(&wm);
+        let rects: Vec<_> = wins.iter().map(|win| rect(&wm, *win)).collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(tiling.contains_rect(a), "{:?} outside of {:?}", a, tiling);
+            for b in &rects[i + 1..] {
+                assert!(!overlap(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn closing_a_window_gives_its_space_back() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        let b = mock::map(&mut wm, "b");
+        wm.unmanage_window(b).unwrap();
+        assert!(client(&wm, b).is_none());
+        assert_eq!(rect(&wm, a), tiling(&wm));
+    }
+
+    #[test]
+    fn windows_go_to_the_focused_monitor() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500), (1000, 0, 800, 600)]);
+        let left = mock::map(&mut wm, "left");
+        let right_mon = *wm
+            .monitors
+            .keys()
+            .find(|mon| **mon != wm.focused_monitor)
+            .unwrap();
+        wm.focused_monitor = right_mon;
+        let right = mock::map(&mut wm, "right");
+        assert!(rect(&wm, left).x < 1000);
+        assert!(rect(&wm, right).x >= 1000);
+        assert_eq!(rect(&wm, right), tiling(&wm));
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use super::ClientArgs;
+use crate::protocol::Geometry;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Rule {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub name: Option<String>,
+    floating: Option<bool>,
+    size: Option<(u16, u16)>,
+    pos: Option<(i16, i16)>,
+    geometry: Option<Geometry>,
+    relative: bool, // pos and geometry are relative to the monitor of the tag the window opens on
+    border_width: Option<u16>,
+    border_color: Option<u32>,
+    force_fullscreen_layer: bool,
+    protected: bool,
+    temp: bool,
+}
+
+impl Rule {
+    pub fn new() -> Self {
+        Rule::default()
+    }
+    pub fn class(&mut self, class: String) {
+        self.class.replace(class);
+    }
+    pub fn instance(&mut self, instance: String) {
+        self.instance.replace(instance);
+    }
+    pub fn name(&mut self, name: String) {
+        self.name.replace(name);
+    }
+    pub fn floating(&mut self, floating: bool) {
+        self.floating.replace(floating);
+    }
+    pub fn size(&mut self, size: (u16, u16)) {
+        self.size.replace(size);
+    }
+    pub fn pos(&mut self, pos: (i16, i16)) {
+        self.pos.replace(pos);
+    }
+    pub fn geometry(&mut self, geometry: Geometry) {
+        self.geometry.replace(geometry);
+    }
+    pub fn relative(&mut self) {
+        self.relative = true;
+    }
+    pub fn border_width(&mut self, width: u16) {
+        self.border_width.replace(width);
+    }
+    pub fn border_color(&mut self, color: u32) {
+        self.border_color.replace(color);
+    }
+    pub fn force_fullscreen_layer(&mut self) {
+        self.force_fullscreen_layer = true;
+    }
+    pub fn protected(&mut self) {
+        self.protected = true;
+    }
+    pub fn temp(&mut self) {
+        self.temp = true;
+    }
+
+    // in the same words cwm-client rule add takes
+    pub fn describe(&self) -> String {
+        let mut words = Vec::new();
+        if let Some(class) = &self.class {
+            words.push(format!("class {}", class));
+        }
+        if let Some(instance) = &self.instance {
+            words.push(format!("instance {}", instance));
+        }
+        if let Some(name) = &self.name {
+            words.push(format!("name {}", name));
+        }
+        match self.floating {
+            Some(true) => words.push("floating".to_string()),
+            Some(false) => words.push("!floating".to_string()),
+            None => (),
+        }
+        if let Some((x, y)) = self.pos {
+            words.push(format!("pos {} {}", x, y));
+        }
+        if let Some((width, height)) = self.size {
+            words.push(format!("size {} {}", width, height));
+        }
+        if let Some(geometry) = self.geometry {
+            words.push(format!("geometry {}", geometry));
+        }
+        if self.relative {
+            words.push("relative".to_string());
+        }
+        if let Some(width) = self.border_width {
+            words.push(format!("border-width {}", width));
+        }
+        if let Some(color) = self.border_color {
+            words.push(format!("border-color {:#010x}", color));
+        }
+        if self.force_fullscreen_layer {
+            words.push("force-fullscreen-layer".to_string());
+        }
+        if self.protected {
+            words.push("protected".to_string());
+        }
+        if self.temp {
+            words.push("temp".to_string());
+        }
+        words.join(" ")
+    }
+
+    pub fn apply(&self, args: &mut ClientArgs) -> bool {
+        if let Some(floating) = self.floating {
+            args.flags.floating = floating;
+        }
+        if let Some(size) = self.size {
+            args.size = Some(size);
+        }
+        if let Some(pos) = self.pos {
+            args.pos.replace(pos);
+            args.pos_relative = self.relative;
+        }
+        if let Some(mut geometry) = self.geometry {
+            geometry.relative |= self.relative;
+            args.geometry.replace(geometry);
+        }
+        if let Some(width) = self.border_width {
+            args.border_width.replace(width);
+        }
+        if let Some(color) = self.border_color {
+            args.border_color.replace(color);
+        }
+        if self.force_fullscreen_layer {
+            args.force_fullscreen = true;
+        }
+        if self.protected {
+            args.flags.protected = true;
+        }
+        self.temp
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{Backend, Tag, Window};
+use crate::utils::Stack;
+
+pub enum Layer {
+    Single(Option<usize>),
+    Multi(Stack<usize>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StackLayer {
+    Below,
+    Normal,
+    Above,
+}
+
+impl Layer {
+    pub const COUNT: usize = 3;
+    pub const TILING: usize = 0;
+    pub const FLOATING: usize = 1;
+    pub const FULLSCREEN: usize = 2;
+    pub const SUBCOUNT: usize = 3;
+    // each kind is split into normal and always on top clients
+    pub const SUBLAYERS: usize = 2;
+    pub const TOTAL: usize = Self::COUNT * Self::SUBLAYERS * Self::SUBCOUNT;
+
+    // tiling, floating or fullscreen
+    pub fn kind(layer: usize) -> usize {
+        layer % (Self::COUNT * Self::SUBLAYERS) / Self::SUBLAYERS
+    }
+
+    fn front(&self) -> Option<usize> {
+        match self {
+            Layer::Single(layer) => *layer,
+            Layer::Multi(layer) => layer.front().cloned(),
+        }
+    }
+
+    fn back(&self) -> Option<usize> {
+        match self {
+            Layer::Single(layer) => *layer,
+            Layer::Multi(layer) => layer.back().cloned(),
+        }
+    }
+
+    fn push_front(&mut self, client: usize) -> (usize, Option<usize>) {
+        match self {
+            Layer::Single(layer) => (0, layer.replace(client)),
+            Layer::Multi(layer) => (layer.push_front(client), None),
+        }
+    }
+
+    fn push_back(&mut self, client: usize) -> (usize, Option<usize>) {
+        match self {
+            Layer::Single(layer) => (0, layer.replace(client)),
+            Layer::Multi(layer) => (layer.push_back(client), None),
+        }
+    }
+
+    pub fn remove(&mut self, layer_pos: usize) {
+        match self {
+            Layer::Single(layer) => *layer = None,
+            Layer::Multi(layer) => layer.remove_node(layer_pos),
+        }
+    }
+
+    pub fn vec(&self) -> Vec<usize> {
+        match self {
+            Layer::Single(layer) => {
+                if let Some(item) = layer {
+                    vec![*item]
+                } else {
+                    vec![]
+                }
+            }
+            Layer::Multi(layer) => layer.iter().cloned().collect(),
+        }
+    }
+}
+
+// where a client goes in the stacking order of its monitor
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StackPos {
+    Below(Window),
+    Above(Window),
+    UnderPanels, // directly below the panels
+}
+
+impl StackLayer {
+    fn get(&self) -> usize {
+        match self {
+            StackLayer::Below => 0,
+            StackLayer::Normal => Layer::COUNT * Layer::SUBLAYERS,
+            StackLayer::Above => Layer::COUNT * Layer::SUBLAYERS * 2,
+        }
+    }
+}
+
+impl Tag {
+    pub fn switch_layer(&mut self, backend: &impl Backend, idx: usize) -> Result<()> {
+        let client = &mut self.clients[idx];
+        let (prev_layer, layer_pos) = client.layer_pos;
+        self.layers[prev_layer].remove(layer_pos);
+        match (
+            Layer::kind(prev_layer) == Layer::TILING,
+            Layer::kind(client.flags.get_layer()) == Layer::TILING,
+        ) {
+            (false, true) => self.set_absent(backend, idx, false)?,
+            (true, false) => self.set_absent(backend, idx, true)?,
+            _ => (),
+        }
+
+        self.set_layer(backend, idx, true)?;
+        self.raise_transients(backend, idx)
+    }
+
+    // moves a client to the top or bottom of its layer, focus is left alone
+    pub fn restack(&mut self, backend: &impl Backend, idx: usize, raise: bool) -> Result<()> {
+        if self.clients[idx].flags.hidden {
+            return Ok(());
+        }
+        let (layer, layer_pos) = self.clients[idx].layer_pos;
+        self.layers[layer].remove(layer_pos);
+        self.set_layer(backend, idx, raise)?;
+        if raise {
+            self.raise_transients(backend, idx)?;
+        }
+        Ok(())
+    }
+
+    // dialogs are kept just above the window they belong to
+    fn raise_transients(&mut self, backend: &impl Backend, idx: usize) -> Result<()> {
+        for transient in self.transients(self.clients[idx].win) {
+            if !self.clients[transient].flags.hidden {
+                let (layer, layer_pos) = self.clients[transient].layer_pos;
+                self.layers[layer].remove(layer_pos);
+                self.set_layer(backend, transient, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_layer(&mut self, backend: &impl Backend, idx: usize, focus: bool) -> Result<()> {
+        let client = &self.clients[idx];
+        let layer = client.layer.get() + client.flags.get_layer();
+        let rect = self.get_rect(backend, idx).unwrap();
+        let tiled = !(client.flags.floating || client.flags.fullscreen);
+        // restacking doesn't move a held client
+        let rect = match &*client.applied.borrow() {
+            Some((applied, _)) if tiled && self.layout_held => applied.clone(),
+            _ => rect,
+        };
+        let border = if client.flags.fullscreen || (tiled && !self.tiled_border(backend)) {
+            0
+        } else {
+            client.border_width
+        };
+
+        // a focused monocle client goes above floating clients that aren't always on top
+        let bound = if focus && tiled && self.covers_floating(backend) {
+            layer - layer % Layer::SUBLAYERS + Layer::FLOATING * Layer::SUBLAYERS + 1
+        } else {
+            layer + if focus { 1 } else { 0 }
+        };
+        let pos = if let Some(sibling) = self.get_layer_bound_below(bound) {
+            StackPos::Below(sibling)
+        } else if let Some(sibling) = self.get_layer_bound_above(bound) {
+            StackPos::Above(sibling)
+        } else {
+            StackPos::UnderPanels
+        };
+        backend.stack_client(client, &rect, border, pos)?;
+        client.applied.replace(Some((rect, border)));
+        let client = &mut self.clients[idx];
+        let (layer_pos, old) = if focus {
+            self.layers[layer].push_front(idx)
+        } else {
+            self.layers[layer].push_back(idx)
+        };
+        client.layer_pos = (layer, layer_pos);
+        if let Some(idx) = old {
+            self.clients[idx].flags.fullscreen = false;
+            if !self.clients[idx].flags.floating {
+                self.set_absent(backend, idx, false)?
+            }
+            self.set_layer(backend, idx, true)?
+        }
+        Ok(())
+    }
+
+    fn get_layer_bound_below(&self, layer: usize) -> Option<Window> {
+        if layer > Layer::TOTAL {
+            None
+        } else {
+            self.layers[layer..]
+                .iter()
+                .filter_map(|x| x.back())
+                .map(|x| self.clients[x].win)
+                .next()
+        }
+    }
+
+    fn get_layer_bound_above(&self, layer: usize) -> Option<Window> {
+        if layer > Layer::TOTAL {
+            None
+        } else {
+            self.layers[..layer]
+                .iter()
+                .rev()
+                .filter_map(|x| x.front())
+                .map(|x| self.clients[x].win)
+                .next()
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use anyhow::Result;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+
+use super::node::{Node, NodeContents, Side};
+use super::rules::Rule;
+use super::{Atom, Backend, Window};
+use crate::icon::Icon;
+use crate::protocol::{Corner, Geometry, SetArg};
+use crate::utils::{Rect, Stack};
+
+mod layer;
+pub(crate) use layer::Layer;
+pub use layer::{StackLayer, StackPos};
+
+#[derive(Debug, Clone)]
+pub struct ClientFlags {
+    pub urgent: bool,
+    pub hidden: bool,
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub sticky: bool,
+    pub psuedo_urgent: bool,
+    pub aot: bool,
+    pub passthrough: bool, // the wm releases its grabs while the client is focused
+    pub protected: bool,   // closing or killing it needs force
+    // fullscreen requests of other clients only demand attention while the client is focused
+    pub inhibit_fullscreen: bool,
+}
+
+impl ClientFlags {
+    pub fn get_layer(&self) -> usize {
+        let kind = match self {
+            Self {
+                fullscreen: true, ..
+            } => Layer::FULLSCREEN,
+            Self { floating: true, .. } => Layer::FLOATING,
+            Self { .. } => Layer::TILING,
+        };
+        kind * Layer::SUBLAYERS + self.aot as usize
+    }
+
+    pub fn absent(&self) -> bool {
+        self.floating | self.fullscreen | self.hidden
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientArgs {
+    pub focus: bool,
+    pub flags: ClientFlags,
+    pub centered: bool,
+    pub managed: bool,
+    pub(crate) min_size: (u16, u16),
+    pub(crate) max_size: (u16, u16),
+    pub size: Option<(u16, u16)>,
+    pub pos: Option<(i16, i16)>,
+    pub border_width: Option<u16>,
+    pub border_color: Option<u32>,
+    pub split: Option<(Side, f32)>,
+    // video players and games that misuse their hints are put on the fullscreen layer
+    pub force_fullscreen: bool,
+    // resolved against the monitor the window opens on once the rules are applied
+    pub geometry: Option<Geometry>,
+    pub pos_relative: bool,
+    pub(crate) layer: StackLayer,
+    pub(crate) class: Option<String>,
+    pub(crate) instance: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) tag: Option<u32>,
+    pub(crate) parent: Option<usize>, // a leaf
+    pub(crate) transient_for: Option<Window>,
+    pub(crate) group: Option<Window>,
+    pub(crate) icon: Option<Icon>,
+}
+
+impl PartialEq<Rule> for ClientArgs {
+    fn eq(&self, other: &Rule) -> bool {
+        self.name
+            .as_ref()
+            .map(|x| other.name.as_ref().map(|y| x == y).unwrap_or(true))
+            .unwrap_or_else(|| other.name.is_none())
+            && self
+                .instance
+                .as_ref()
+                .map(|x| other.instance.as_ref().map(|y| x == y).unwrap_or(true))
+                .unwrap_or_else(|| other.instance.is_none())
+            && self
+                .class
+                .as_ref()
+                .map(|x| other.class.as_ref().map(|y| x == y).unwrap_or(true))
+                .unwrap_or_else(|| other.class.is_none())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub name: Option<String>,
+    pub(crate) class: Option<String>,
+    pub(crate) instance: Option<String>,
+    pub border_width: u16,
+    // set by rules or commands, the theme no longer applies to the client
+    pub border_override: Option<u16>,
+    pub border_color: Option<u32>,
+    pub layer: StackLayer,
+    pub(crate) last_layer: StackLayer,
+    pub node: usize,
+    pub stack_pos: usize,
+    pub layer_pos: (usize, usize),
+    pub flags: ClientFlags,
+    pub win: Window,
+    pub applied: RefCell<Option<(Rect, u16)>>, // the last geometry and border sent to the window
+    pub transient_for: Option<Window>,
+    pub group: Option<Window>, // the group leader from WM_HINTS
+    pub icon: Option<Icon>,
+    pub prev_tag: Option<Atom>, // the tag the client was last moved from
+    pub(crate) force_fullscreen: bool,
+    pub pip: Option<(Corner, f32)>, // the corner and scale it is kept at on its monitor
+}
+
+pub struct Tag {
+    pub id: Atom,
+    pub name: String,
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) clients: Vec<Client>,
+    pub(crate) free_nodes: Vec<usize>,
+    pub(crate) free_clients: HashSet<usize>,
+    pub(crate) focus_stack: Stack<usize>,
+    pub(crate) layers: [Layer; Layer::TOTAL],
+    pub size: Rect,
+    pub(crate) tiling_size: Rect,
+    pub(crate) focused: Option<usize>,
+    pub monitor: Option<Atom>,
+    pub(crate) urgent: HashSet<usize>,
+    pub(crate) psuedo_urgent: HashSet<usize>,
+    pub(crate) hidden: VecDeque<usize>,
+    pub(crate) monocle: bool,
+    pub(crate) temp: bool,
+    // removed once it is empty and not shown, after it has held a client
+    pub volatile: bool,
+    pub(crate) occupied: bool,
+    pub(crate) bg: Option<Window>,
+    pub next_split: Option<(Side, f32)>, // used instead of the default split by the next client
+    pub(crate) cascade: Option<(i16, i16)>, // where the last cascaded floating client was placed
+    // tiled clients stay where they are while a closed client fades out
+    pub layout_held: bool,
+}
+
+impl Tag {
+    pub fn get_layers(&self) -> Vec<Vec<usize>> {
+        self.layers.iter().map(|layer| layer.vec()).collect()
+    }
+    pub fn get_stack(&self) -> Vec<usize> {
+        self.focus_stack.iter().cloned().collect()
+    }
+    pub fn get_clients(&self) -> Vec<(usize, u32, Option<String>)> {
+        self.clients
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (i, x.win, x.name.clone()))
+            .collect()
+    }
+    pub fn empty(&self) -> bool {
+        self.clients.len() == self.free_clients.len()
+    }
+
+    pub fn monocle(&self) -> bool {
+        self.monocle
+    }
+
+    pub fn temp(&self) -> bool {
+        self.temp
+    }
+
+    pub fn urgent(&self) -> bool {
+        !(self.urgent.is_empty() && self.psuedo_urgent.is_empty())
+    }
+
+    pub fn hidden_count(&self) -> usize {
+        self.hidden.len()
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len() - self.free_clients.len()
+    }
+
+    // clients without a border width of their own take the one of the tag's monitor
+    pub fn update_border_widths(&mut self, backend: &impl Backend) {
+        let width = backend.theme().border_width(self.monitor);
+        for client in self.clients.iter_mut() {
+            client.border_width = client.border_override.unwrap_or(width);
+        }
+    }
+
+    pub fn focused_client(&self) -> Option<usize> {
+        self.focus_stack.front().copied()
+    }
+
+    pub fn nth_recent_client(&self, n: usize) -> Option<usize> {
+        self.focus_stack.iter().nth(n).copied()
+    }
+
+    pub fn client(&self, client: usize) -> &Client {
+        &self.clients[client]
+    }
+
+    pub fn client_mut(&mut self, client: usize) -> &mut Client {
+        &mut self.clients[client]
+    }
+
+    pub fn clients(&self) -> &[Client] {
+        self.clients.as_ref()
+    }
+
+    pub fn clients_mut(&mut self) -> &mut [Client] {
+        self.clients.as_mut()
+    }
+
+    pub fn node(&self, node: usize) -> &Node {
+        &self.nodes[node]
+    }
+
+    pub fn node_mut(&mut self, node: usize) -> &mut Node {
+        &mut self.nodes[node]
+    }
+
+    pub fn set_monocle(&mut self, backend: &impl Backend, arg: &SetArg<bool>) -> Result<()> {
+        if arg.apply(&mut self.monocle) {
+            self.relayout_monocle(backend)?;
+        }
+        Ok(())
+    }
+
+    // reapplies the tiled layout and restacks the focused client after monocle or its config changes
+    pub fn relayout_monocle(&mut self, backend: &impl Backend) -> Result<()> {
+        self.resize_tiled(backend, 0, None)?;
+        if let Some(client) = self.focused_client() {
+            let flags = &self.clients[client].flags;
+            if !(flags.floating || flags.fullscreen) {
+                self.switch_layer(backend, client)?;
+            }
+        }
+        Ok(())
+    }
+
+    // the rect tiled clients take in monocle, the whole free area when gaps are hidden
+    pub(crate) fn monocle_rect(&self, backend: &impl Backend) -> Rect {
+        if backend.theme().monocle_gapless {
+            backend
+                .theme()
+                .available_rect(self.monitor, &self.tiling_size)
+        } else {
+            self.tiling_size.clone()
+        }
+    }
+
+    pub(crate) fn tiled_border(&self, backend: &impl Backend) -> bool {
+        !(self.monocle && backend.theme().monocle_borderless)
+    }
+
+    // whether a tiled client should be stacked above floating clients
+    pub(crate) fn covers_floating(&self, backend: &impl Backend) -> bool {
+        self.monocle && !backend.theme().monocle_floating
+    }
+
+    // the clients that are dialogs of a window
+    pub fn transients(&self, win: Window) -> Vec<usize> {
+        (0..self.clients.len())
+            .filter(|i| !self.free_clients.contains(i))
+            .filter(|i| self.clients[*i].transient_for == Some(win) && self.clients[*i].win != win)
+            .collect()
+    }
+
+    pub fn apply_pos_size(
+        &self,
+        backend: &impl Backend,
+        client: usize,
+        size: &Rect,
+        border: bool,
+    ) -> Result<()> {
+        let client = &self.clients[client];
+        let tiled = !(client.flags.floating || client.flags.fullscreen);
+        // applied is left alone so releasing the hold moves the client
+        if tiled && self.layout_held {
+            return Ok(());
+        }
+        let monocle_rect;
+        let (size, border) = if self.monocle && tiled {
+            monocle_rect = self.monocle_rect(backend);
+            (&monocle_rect, border && self.tiled_border(backend))
+        } else {
+            (size, border)
+        };
+        let border = if border { client.border_width } else { 0 };
+        let mut applied = client.applied.borrow_mut();
+        if *applied == Some((size.clone(), border)) {
+            return Ok(());
+        }
+        let from = applied.replace((size.clone(), border)).map(|x| x.0);
+        // nothing is animated on a monitor that is off
+        let from = match self.monitor {
+            Some(mon) if backend.sleeping(mon) => None,
+            _ => from,
+        };
+        backend.configure_client(client.win, from, size, border)
+    }
+}
+
+impl Default for Tag {
+    fn default() -> Self {
+        Tag {
+            id: 0,
+            name: String::new(),
+            nodes: vec![Node {
+                absent: false,
+                info: NodeContents::empty(),
+                parent: None,
+                rect: Rect::new(0, 0, 1920, 1080),
+            }],
+            clients: Vec::new(),
+            free_nodes: Vec::new(),
+            free_clients: HashSet::new(),
+            focus_stack: Stack::default(),
+            // only one fullscreen client per layer
+            layers: std::array::from_fn(|layer| {
+                if Layer::kind(layer) == Layer::FULLSCREEN {
+                    Layer::Single(None)
+                } else {
+                    Layer::Multi(Stack::default())
+                }
+            }),
+            size: Rect::new(0, 0, 1920, 1080),
+            tiling_size: Rect::default(),
+            focused: None,
+            monitor: None,
+            urgent: HashSet::new(),
+            psuedo_urgent: HashSet::new(),
+            hidden: VecDeque::new(),
+            temp: false,
+            volatile: false,
+            occupied: false,
+            monocle: false,
+            bg: None,
+            next_split: None,
+            cascade: None,
+            layout_held: false,
+        }
+    }
+}
@@ -0,0 +1,739 @@
+// the connection used by the wm, either a real server or a mock that records requests
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::IoSlice;
+use std::os::unix::io::{AsRawFd, RawFd};
+use x11rb::connection::{
+    BufWithFds, Connection, DiscardMode, RawEventAndSeqNumber, ReplyOrError, RequestConnection,
+    RequestKind, SequenceNumber,
+};
+use x11rb::cookie::{Cookie, CookieWithFds, VoidCookie};
+use x11rb::errors::{ConnectionError, ParseError, ReplyOrIdError};
+use x11rb::protocol::randr;
+use x11rb::protocol::xproto::{BackingStore, ConfigWindow, ImageOrder, Screen, Setup, StackMode};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::utils::RawFdContainer;
+use x11rb::x11_utils::{ExtInfoProvider, ExtensionInformation, TryParse, TryParseFd, X11Error};
+
+pub enum Dpy {
+    Rust(Box<RustConnection>),
+    Mock(Box<MockConnection>),
+}
+
+impl Dpy {
+    // the fd to poll for events, the mock has none so poll ignores it
+    pub fn raw_fd(&self) -> RawFd {
+        match self {
+            Self::Rust(dpy) => dpy.stream().as_raw_fd(),
+            Self::Mock(_) => -1,
+        }
+    }
+
+    pub fn mock(&self) -> Option<&MockConnection> {
+        match self {
+            Self::Mock(mock) => Some(mock),
+            Self::Rust(_) => None,
+        }
+    }
+
+    fn send(
+        &self,
+        bufs: &[IoSlice<'_>],
+        fds: Vec<RawFdContainer>,
+        kind: RequestKind,
+    ) -> Result<SequenceNumber, ConnectionError> {
+        // the inner cookie only borrows the inner connection, forgetting it keeps the reply
+        Ok(match self {
+            Self::Rust(dpy) => match kind {
+                RequestKind::IsVoid => {
+                    let cookie = dpy.send_request_without_reply(bufs, fds)?;
+                    let seq = cookie.sequence_number();
+                    std::mem::forget(cookie);
+                    seq
+                }
+                RequestKind::HasResponse => {
+                    let cookie = dpy.send_request_with_reply::<Ignored>(bufs, fds)?;
+                    let seq = cookie.sequence_number();
+                    std::mem::forget(cookie);
+                    seq
+                }
+            },
+            Self::Mock(mock) => mock.record(bufs, kind)?,
+        })
+    }
+}
+
+// stands in for the reply type when forwarding a request, replies are parsed by the outer cookie
+struct Ignored;
+
+impl TryParse for Ignored {
+    fn try_parse(value: &[u8]) -> Result<(Self, &[u8]), ParseError> {
+        Ok((Self, value))
+    }
+}
+
+impl RequestConnection for Dpy {
+    type Buf = Vec<u8>;
+
+    fn send_request_with_reply<R>(
+        &self,
+        bufs: &[IoSlice<'_>],
+        fds: Vec<RawFdContainer>,
+    ) -> Result<Cookie<'_, Self, R>, ConnectionError>
+    where
+        R: TryParse,
+    {
+        let seq = self.send(bufs, fds, RequestKind::HasResponse)?;
+        Ok(Cookie::new(self, seq))
+    }
+
+    fn send_request_with_reply_with_fds<R>(
+        &self,
+        bufs: &[IoSlice<'_>],
+        fds: Vec<RawFdContainer>,
+    ) -> Result<CookieWithFds<'_, Self, R>, ConnectionError>
+    where
+        R: TryParseFd,
+    {
+        let seq = self.send(bufs, fds, RequestKind::HasResponse)?;
+        Ok(CookieWithFds::new(self, seq))
+    }
+
+    fn send_request_without_reply(
+        &self,
+        bufs: &[IoSlice<'_>],
+        fds: Vec<RawFdContainer>,
+    ) -> Result<VoidCookie<'_, Self>, ConnectionError> {
+        let seq = self.send(bufs, fds, RequestKind::IsVoid)?;
+        Ok(VoidCookie::new(self, seq))
+    }
+
+    fn discard_reply(&self, sequence: SequenceNumber, kind: RequestKind, mode: DiscardMode) {
+        match self {
+            Self::Rust(dpy) => dpy.discard_reply(sequence, kind, mode),
+            Self::Mock(mock) => mock.discard(sequence),
+        }
+    }
+
+    fn prefetch_extension_information(
+        &self,
+        extension_name: &'static str,
+    ) -> Result<(), ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.prefetch_extension_information(extension_name),
+            Self::Mock(_) => Ok(()),
+        }
+    }
+
+    fn extension_information(
+        &self,
+        extension_name: &'static str,
+    ) -> Result<Option<ExtensionInformation>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.extension_information(extension_name),
+            Self::Mock(mock) => Ok(Some(mock.extension(extension_name))),
+        }
+    }
+
+    fn wait_for_reply_or_raw_error(
+        &self,
+        sequence: SequenceNumber,
+    ) -> Result<ReplyOrError<Self::Buf>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.wait_for_reply_or_raw_error(sequence),
+            Self::Mock(mock) => mock.reply(sequence),
+        }
+    }
+
+    fn wait_for_reply(
+        &self,
+        sequence: SequenceNumber,
+    ) -> Result<Option<Self::Buf>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.wait_for_reply(sequence),
+            Self::Mock(mock) => match mock.reply(sequence)? {
+                ReplyOrError::Reply(reply) => Ok(Some(reply)),
+                ReplyOrError::Error(_) => Ok(None),
+            },
+        }
+    }
+
+    fn wait_for_reply_with_fds_raw(
+        &self,
+        sequence: SequenceNumber,
+    ) -> Result<ReplyOrError<BufWithFds<Self::Buf>, Self::Buf>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.wait_for_reply_with_fds_raw(sequence),
+            Self::Mock(mock) => Ok(match mock.reply(sequence)? {
+                ReplyOrError::Reply(reply) => ReplyOrError::Reply((reply, Vec::new())),
+                ReplyOrError::Error(error) => ReplyOrError::Error(error),
+            }),
+        }
+    }
+
+    fn check_for_raw_error(
+        &self,
+        sequence: SequenceNumber,
+    ) -> Result<Option<Self::Buf>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.check_for_raw_error(sequence),
+            Self::Mock(_) => Ok(None),
+        }
+    }
+
+    fn prefetch_maximum_request_bytes(&self) {
+        if let Self::Rust(dpy) = self {
+            dpy.prefetch_maximum_request_bytes()
+        }
+    }
+
+    fn maximum_request_bytes(&self) -> usize {
+        match self {
+            Self::Rust(dpy) => dpy.maximum_request_bytes(),
+            Self::Mock(mock) => usize::from(mock.setup.maximum_request_length) * 4,
+        }
+    }
+
+    fn parse_error(&self, error: &[u8]) -> Result<X11Error, ParseError> {
+        match self {
+            Self::Rust(dpy) => dpy.parse_error(error),
+            Self::Mock(_) => X11Error::try_parse(error, &NoExtensions),
+        }
+    }
+
+    fn parse_event(&self, event: &[u8]) -> Result<Event, ParseError> {
+        match self {
+            Self::Rust(dpy) => dpy.parse_event(event),
+            Self::Mock(_) => Event::parse(event, &NoExtensions),
+        }
+    }
+}
+
+impl Connection for Dpy {
+    fn wait_for_raw_event_with_sequence(
+        &self,
+    ) -> Result<RawEventAndSeqNumber<Self::Buf>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.wait_for_raw_event_with_sequence(),
+            // nothing else could ever queue an event, so waiting would block forever
+            Self::Mock(mock) => mock.event().ok_or(ConnectionError::UnknownError),
+        }
+    }
+
+    fn poll_for_raw_event_with_sequence(
+        &self,
+    ) -> Result<Option<RawEventAndSeqNumber<Self::Buf>>, ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.poll_for_raw_event_with_sequence(),
+            Self::Mock(mock) => Ok(mock.event()),
+        }
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        match self {
+            Self::Rust(dpy) => dpy.flush(),
+            Self::Mock(_) => Ok(()),
+        }
+    }
+
+    fn setup(&self) -> &Setup {
+        match self {
+            Self::Rust(dpy) => dpy.setup(),
+            Self::Mock(mock) => &mock.setup,
+        }
+    }
+
+    fn generate_id(&self) -> Result<u32, ReplyOrIdError> {
+        match self {
+            Self::Rust(dpy) => dpy.generate_id(),
+            Self::Mock(mock) => {
+                let id = mock.next_id.get();
+                mock.next_id.set(id + 1);
+                Ok(id)
+            }
+        }
+    }
+}
+
+struct NoExtensions;
+
+impl ExtInfoProvider for NoExtensions {
+    fn get_from_major_opcode(&self, _: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+
+    fn get_from_event_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+
+    fn get_from_error_code(&self, _: u8) -> Option<(&str, ExtensionInformation)> {
+        None
+    }
+}
+
+// a single screen server that records requests instead of sending them and answers the ones
+// with a reply itself. every extension is present, atoms are interned consistently, properties
+// come from what was set with set_property, the geometry of windows and the stacking order of the
+// children of the root follow the requests, randr reports the monitors set with set_monitors and
+// requests about a destroyed window fail with BadWindow. anything else gets a reply of zeros, which
+// parses as empty for every reply type.
+// replies queued with push_reply go to the next requests waited on before any of that
+pub struct MockConnection {
+    setup: Setup,
+    sequence: Cell<SequenceNumber>,
+    next_id: Cell<u32>,
+    requests: RefCell<Vec<Vec<u8>>>,
+    replies: RefCell<VecDeque<Vec<u8>>>,
+    pending: RefCell<HashMap<SequenceNumber, ReplyOrError<Vec<u8>>>>,
+    events: RefCell<VecDeque<Vec<u8>>>,
+    atoms: RefCell<HashMap<Vec<u8>, u32>>,
+    extensions: RefCell<HashMap<&'static str, ExtensionInformation>>,
+    properties: RefCell<HashMap<(u32, u32), Property>>,
+    destroyed: RefCell<HashSet<u32>>,
+    monitors: RefCell<Vec<(i16, i16, u16, u16)>>,
+    stack: RefCell<Vec<u32>>, // children of the root from the bottom up
+    destroy_at: Cell<Option<(u32, SequenceNumber)>>,
+    fail_at: Cell<Option<SequenceNumber>>,
+    geometry: RefCell<HashMap<u32, Geometry>>,
+}
+
+// the type, format and value of a window's property
+type Property = (u32, u8, Vec<u8>);
+
+// x, y, width, height and border width
+type Geometry = (i16, i16, u16, u16, u16);
+
+// core opcodes the mock answers with more than zeros or keeps track of
+const CREATE_WINDOW: u8 = 1;
+const GET_WINDOW_ATTRIBUTES: u8 = 3;
+const DESTROY_WINDOW: u8 = 4;
+const CONFIGURE_WINDOW: u8 = 12;
+const GET_GEOMETRY: u8 = 14;
+const QUERY_TREE: u8 = 15;
+const INTERN_ATOM: u8 = 16;
+const GET_ATOM_NAME: u8 = 17;
+const GET_PROPERTY: u8 = 20;
+// randr minor opcode
+const GET_MONITORS: u8 = 42;
+const BAD_WINDOW: u8 = 3;
+const BAD_ATOM: u8 = 5;
+const BAD_ALLOC: u8 = 11;
+
+fn u32_at(request: &[u8], offset: usize) -> u32 {
+    request
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .unwrap_or(0)
+}
+
+fn u16_at(request: &[u8], offset: usize) -> u16 {
+    request
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+        .unwrap_or(0)
+}
+
+// the fixed part of every reply type fits, lists in it are all empty
+fn zero_reply(seq: SequenceNumber, extra: usize) -> Vec<u8> {
+    let mut reply = vec![0; 256 + extra];
+    reply[0] = 1;
+    reply[2..4].copy_from_slice(&(seq as u16).to_ne_bytes());
+    reply
+}
+
+fn error(seq: SequenceNumber, code: u8, value: u32, opcode: u8) -> Vec<u8> {
+    let mut error = vec![0; 32];
+    error[1] = code;
+    error[2..4].copy_from_slice(&(seq as u16).to_ne_bytes());
+    error[4..8].copy_from_slice(&value.to_ne_bytes());
+    error[10] = opcode;
+    error
+}
+
+impl MockConnection {
+    pub const ROOT: u32 = 1;
+    const ID_BASE: u32 = 0x0020_0000;
+    // past the predefined atoms
+    const FIRST_ATOM: u32 = 0x100;
+
+    pub fn new(width: u16, height: u16) -> Self {
+        let screen = Screen {
+            root: Self::ROOT,
+            default_colormap: 0,
+            white_pixel: 0xFFFFFF,
+            black_pixel: 0,
+            current_input_masks: 0,
+            width_in_pixels: width,
+            height_in_pixels: height,
+            width_in_millimeters: 0,
+            height_in_millimeters: 0,
+            min_installed_maps: 1,
+            max_installed_maps: 1,
+            root_visual: 0,
+            backing_stores: BackingStore::NOT_USEFUL,
+            save_unders: false,
+            root_depth: 24,
+            allowed_depths: Vec::new(),
+        };
+        Self {
+            setup: Setup {
+                status: 1,
+                protocol_major_version: 11,
+                protocol_minor_version: 0,
+                length: 0,
+                release_number: 0,
+                resource_id_base: Self::ID_BASE,
+                resource_id_mask: 0x001F_FFFF,
+                motion_buffer_size: 0,
+                maximum_request_length: u16::MAX,
+                image_byte_order: ImageOrder::LSB_FIRST,
+                bitmap_format_bit_order: ImageOrder::LSB_FIRST,
+                bitmap_format_scanline_unit: 32,
+                bitmap_format_scanline_pad: 32,
+                min_keycode: 8,
+                max_keycode: 255,
+                vendor: b"cwm mock".to_vec(),
+                pixmap_formats: Vec::new(),
+                roots: vec![screen],
+            },
+            sequence: Cell::new(0),
+            next_id: Cell::new(Self::ID_BASE),
+            requests: RefCell::new(Vec::new()),
+            replies: RefCell::new(VecDeque::new()),
+            pending: RefCell::new(HashMap::new()),
+            events: RefCell::new(VecDeque::new()),
+            atoms: RefCell::new(HashMap::new()),
+            extensions: RefCell::new(HashMap::new()),
+            properties: RefCell::new(HashMap::new()),
+            destroyed: RefCell::new(HashSet::new()),
+            monitors: RefCell::new(Vec::new()),
+            stack: RefCell::new(Vec::new()),
+            destroy_at: Cell::new(None),
+            fail_at: Cell::new(None),
+            geometry: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn record(
+        &self,
+        bufs: &[IoSlice<'_>],
+        kind: RequestKind,
+    ) -> Result<SequenceNumber, ConnectionError> {
+        let request: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        let seq = self.sequence.get() + 1;
+        if let Some((win, _)) = self.destroy_at.get().filter(|&(_, at)| seq > at) {
+            self.destroyed.borrow_mut().insert(win);
+            self.destroy_at.set(None);
+        }
+        let fail = matches!(self.fail_at.get(), Some(at) if seq > at);
+        if fail {
+            self.fail_at.set(None);
+            if kind == RequestKind::IsVoid {
+                return Err(ConnectionError::UnknownError);
+            }
+        }
+        self.sequence.set(seq);
+        if kind == RequestKind::HasResponse {
+            let reply = match fail {
+                true => ReplyOrError::Error(error(seq, BAD_ALLOC, 0, request[0])),
+                false => self.answer(seq, &request),
+            };
+            self.pending.borrow_mut().insert(seq, reply);
+        }
+        self.track(&request);
+        self.requests.borrow_mut().push(request);
+        Ok(seq)
+    }
+
+    fn track(&self, request: &[u8]) {
+        let win = u32_at(request, 4);
+        let mut stack = self.stack.borrow_mut();
+        match request.first().copied().unwrap_or(0) {
+            CREATE_WINDOW => {
+                let geometry = (
+                    u16_at(request, 12) as i16,
+                    u16_at(request, 14) as i16,
+                    u16_at(request, 16),
+                    u16_at(request, 18),
+                    u16_at(request, 20),
+                );
+                self.geometry.borrow_mut().insert(win, geometry);
+                if u32_at(request, 8) == Self::ROOT {
+                    stack.push(win);
+                }
+            }
+            DESTROY_WINDOW => {
+                stack.retain(|other| *other != win);
+                self.geometry.borrow_mut().remove(&win);
+            }
+            CONFIGURE_WINDOW => {
+                // the values follow the mask in the order of its bits
+                let mask = u16_at(request, 8);
+                let value = |bit: ConfigWindow| {
+                    let bit = u16::from(bit);
+                    (mask & bit != 0).then(|| {
+                        let before = (mask & (bit - 1)).count_ones() as usize;
+                        u32_at(request, 12 + before * 4)
+                    })
+                };
+                {
+                    let mut geometry = self.geometry.borrow_mut();
+                    let geometry = geometry.entry(win).or_default();
+                    if let Some(x) = value(ConfigWindow::X) {
+                        geometry.0 = x as i16;
+                    }
+                    if let Some(y) = value(ConfigWindow::Y) {
+                        geometry.1 = y as i16;
+                    }
+                    if let Some(width) = value(ConfigWindow::WIDTH) {
+                        geometry.2 = width as u16;
+                    }
+                    if let Some(height) = value(ConfigWindow::HEIGHT) {
+                        geometry.3 = height as u16;
+                    }
+                    if let Some(border) = value(ConfigWindow::BORDER_WIDTH) {
+                        geometry.4 = border as u16;
+                    }
+                }
+                let mode = match value(ConfigWindow::STACK_MODE) {
+                    Some(mode) if stack.contains(&win) => StackMode::from(mode as u8),
+                    _ => return,
+                };
+                let sibling = value(ConfigWindow::SIBLING);
+                if matches!(sibling, Some(sibling) if !stack.contains(&sibling)) {
+                    return;
+                }
+                stack.retain(|other| *other != win);
+                let at = match (mode, sibling) {
+                    (StackMode::ABOVE, None) => stack.len(),
+                    (StackMode::BELOW, None) => 0,
+                    (StackMode::ABOVE, Some(sibling)) => {
+                        stack.iter().position(|other| *other == sibling).unwrap() + 1
+                    }
+                    (StackMode::BELOW, Some(sibling)) => {
+                        stack.iter().position(|other| *other == sibling).unwrap()
+                    }
+                    // the modes that depend on overlapping windows are left alone
+                    _ => stack.len(),
+                };
+                stack.insert(at, win);
+            }
+            _ => (),
+        }
+    }
+
+    fn answer(&self, seq: SequenceNumber, request: &[u8]) -> ReplyOrError<Vec<u8>> {
+        let opcode = request.first().copied().unwrap_or(0);
+        // every core request with a window has it first, extensions have their own layouts
+        let win = u32_at(request, 4);
+        if opcode < 128 && self.destroyed.borrow().contains(&win) {
+            return ReplyOrError::Error(error(seq, BAD_WINDOW, win, opcode));
+        }
+        match opcode {
+            GET_WINDOW_ATTRIBUTES => {
+                let mut reply = zero_reply(seq, 0);
+                // a mapped window with no override redirect
+                reply[26] = 2;
+                ReplyOrError::Reply(reply)
+            }
+            INTERN_ATOM => {
+                let len = u16_at(request, 4) as usize;
+                let name = request.get(8..8 + len).unwrap_or_default();
+                let mut reply = zero_reply(seq, 0);
+                reply[8..12].copy_from_slice(&self.intern(name).to_ne_bytes());
+                ReplyOrError::Reply(reply)
+            }
+            GET_GEOMETRY => {
+                let (x, y, width, height, border) = self.geometry(win).unwrap_or_default();
+                let mut reply = zero_reply(seq, 0);
+                reply[1] = 24;
+                reply[8..12].copy_from_slice(&Self::ROOT.to_ne_bytes());
+                reply[12..14].copy_from_slice(&x.to_ne_bytes());
+                reply[14..16].copy_from_slice(&y.to_ne_bytes());
+                reply[16..18].copy_from_slice(&width.to_ne_bytes());
+                reply[18..20].copy_from_slice(&height.to_ne_bytes());
+                reply[20..22].copy_from_slice(&border.to_ne_bytes());
+                ReplyOrError::Reply(reply)
+            }
+            QUERY_TREE if win == Self::ROOT => {
+                let stack = self.stack.borrow();
+                let mut reply = zero_reply(seq, stack.len() * 4);
+                reply[4..8].copy_from_slice(&(stack.len() as u32).to_ne_bytes());
+                reply[8..12].copy_from_slice(&Self::ROOT.to_ne_bytes());
+                reply[16..18].copy_from_slice(&(stack.len() as u16).to_ne_bytes());
+                for (i, child) in stack.iter().enumerate() {
+                    reply[32 + i * 4..36 + i * 4].copy_from_slice(&child.to_ne_bytes());
+                }
+                ReplyOrError::Reply(reply)
+            }
+            GET_ATOM_NAME => match self.atom_name(win) {
+                Some(name) => {
+                    let mut reply = zero_reply(seq, name.len());
+                    reply[4..8].copy_from_slice(&((name.len() as u32).div_ceil(4)).to_ne_bytes());
+                    reply[8..10].copy_from_slice(&(name.len() as u16).to_ne_bytes());
+                    reply[32..32 + name.len()].copy_from_slice(&name);
+                    ReplyOrError::Reply(reply)
+                }
+                None => ReplyOrError::Error(error(seq, BAD_ATOM, win, opcode)),
+            },
+            GET_PROPERTY => {
+                let prop = self
+                    .properties
+                    .borrow()
+                    .get(&(win, u32_at(request, 8)))
+                    .cloned();
+                let mut reply = zero_reply(seq, prop.as_ref().map_or(0, |prop| prop.2.len()));
+                if let Some((type_, format, value)) = prop {
+                    reply[1] = format;
+                    reply[4..8].copy_from_slice(&((value.len() as u32).div_ceil(4)).to_ne_bytes());
+                    reply[8..12].copy_from_slice(&type_.to_ne_bytes());
+                    let len = value.len() as u32 / (format as u32 / 8).max(1);
+                    reply[16..20].copy_from_slice(&len.to_ne_bytes());
+                    reply[32..32 + value.len()].copy_from_slice(&value);
+                }
+                ReplyOrError::Reply(reply)
+            }
+            _ if Some(opcode) == self.major_opcode(randr::X11_EXTENSION_NAME)
+                && request.get(1) == Some(&GET_MONITORS) =>
+            {
+                ReplyOrError::Reply(self.monitors_reply(seq))
+            }
+            _ => ReplyOrError::Reply(zero_reply(seq, 0)),
+        }
+    }
+
+    fn major_opcode(&self, name: &str) -> Option<u8> {
+        self.extensions
+            .borrow()
+            .get(name)
+            .map(|info| info.major_opcode)
+    }
+
+    // monitors without outputs, named fake-0, fake-1 and so on
+    fn monitors_reply(&self, seq: SequenceNumber) -> Vec<u8> {
+        let monitors = self.monitors.borrow();
+        let mut reply = zero_reply(seq, 24 * monitors.len());
+        reply[4..8].copy_from_slice(&(6 * monitors.len() as u32).to_ne_bytes());
+        reply[12..16].copy_from_slice(&(monitors.len() as u32).to_ne_bytes());
+        for (i, &(x, y, width, height)) in monitors.iter().enumerate() {
+            let info = &mut reply[32 + 24 * i..56 + 24 * i];
+            let name = self.intern(format!("fake-{}", i).as_bytes());
+            info[0..4].copy_from_slice(&name.to_ne_bytes());
+            info[4] = (i == 0) as u8;
+            info[8..10].copy_from_slice(&x.to_ne_bytes());
+            info[10..12].copy_from_slice(&y.to_ne_bytes());
+            info[12..14].copy_from_slice(&width.to_ne_bytes());
+            info[14..16].copy_from_slice(&height.to_ne_bytes());
+        }
+        reply
+    }
+
+    fn reply(&self, seq: SequenceNumber) -> Result<ReplyOrError<Vec<u8>>, ConnectionError> {
+        let pending = self.pending.borrow_mut().remove(&seq);
+        match self.replies.borrow_mut().pop_front() {
+            Some(reply) => Ok(ReplyOrError::Reply(reply)),
+            None => pending.ok_or(ConnectionError::UnknownError),
+        }
+    }
+
+    fn discard(&self, seq: SequenceNumber) {
+        self.pending.borrow_mut().remove(&seq);
+    }
+
+    fn extension(&self, name: &'static str) -> ExtensionInformation {
+        let mut extensions = self.extensions.borrow_mut();
+        let n = extensions.len() as u8;
+        *extensions.entry(name).or_insert(ExtensionInformation {
+            major_opcode: 128 + n,
+            first_event: 64 + n * 16,
+            first_error: 128 + n * 16,
+        })
+    }
+
+    fn event(&self) -> Option<RawEventAndSeqNumber<Vec<u8>>> {
+        let event = self.events.borrow_mut().pop_front()?;
+        Some((event, self.sequence.get()))
+    }
+
+    // the same atom the wm gets for the name
+    pub fn intern(&self, name: &[u8]) -> u32 {
+        let mut atoms = self.atoms.borrow_mut();
+        let next = Self::FIRST_ATOM + atoms.len() as u32;
+        *atoms.entry(name.to_vec()).or_insert(next)
+    }
+
+    fn atom_name(&self, atom: u32) -> Option<Vec<u8>> {
+        self.atoms
+            .borrow()
+            .iter()
+            .find(|(_, other)| **other == atom)
+            .map(|(name, _)| name.clone())
+    }
+
+    // format is 8, 16 or 32 and value has the bytes of the elements in native order
+    pub fn set_property(&self, win: u32, property: u32, type_: u32, format: u8, value: Vec<u8>) {
+        self.properties
+            .borrow_mut()
+            .insert((win, property), (type_, format, value));
+    }
+
+    pub fn set_text_property(&self, win: u32, property: &str, type_: &str, value: &[u8]) {
+        let property = self.intern(property.as_bytes());
+        let type_ = self.intern(type_.as_bytes());
+        self.set_property(win, property, type_, 8, value.to_vec());
+    }
+
+    // x, y, width and height of each monitor randr reports, the first one is primary
+    pub fn set_monitors(&self, monitors: &[(i16, i16, u16, u16)]) {
+        *self.monitors.borrow_mut() = monitors.to_vec();
+    }
+
+    // a window a client made, on top of the other children of the root
+    pub fn create_window(&self, win: u32) {
+        self.stack.borrow_mut().push(win);
+    }
+
+    pub fn set_geometry(&self, win: u32, x: i16, y: i16, width: u16, height: u16) {
+        self.geometry
+            .borrow_mut()
+            .insert(win, (x, y, width, height, 0));
+    }
+
+    pub fn geometry(&self, win: u32) -> Option<Geometry> {
+        self.geometry.borrow().get(&win).copied()
+    }
+
+    // every request about the window fails from now on
+    pub fn destroy_window(&self, win: u32) {
+        self.destroyed.borrow_mut().insert(win);
+        self.stack.borrow_mut().retain(|other| *other != win);
+    }
+
+    // like destroy_window once this many more requests were sent, to fail whatever the wm is
+    // doing with the window part way through
+    pub fn destroy_window_after(&self, win: u32, requests: usize) {
+        let at = self.sequence.get() + requests as SequenceNumber;
+        self.destroy_at.set(Some((win, at)));
+    }
+
+    // the first request after this many more requests fails, with BadAlloc if it has a reply and
+    // without being sent if it doesn't. the windows involved stay as they are
+    pub fn fail_after(&self, requests: usize) {
+        self.fail_at
+            .set(Some(self.sequence.get() + requests as SequenceNumber));
+    }
+
+    // the raw bytes of every request sent since the last call, the first byte is the opcode
+    pub fn take_requests(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.requests.borrow_mut())
+    }
+
+    pub fn push_reply(&self, reply: Vec<u8>) {
+        self.replies.borrow_mut().push_back(reply);
+    }
+
+    pub fn push_event(&self, event: impl Into<[u8; 32]>) {
+        self.events.borrow_mut().push_back(event.into().to_vec());
+    }
+}
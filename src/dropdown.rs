@@ -0,0 +1,121 @@
+use anyhow::Result;
+use std::time::{Duration, Instant};
+use x11rb::protocol::xproto::*;
+
+use crate::connections::SetArg;
+use crate::core::ClientArgs;
+use crate::hooks;
+use crate::utils::Rect;
+use crate::{WindowLocation, WindowManager};
+
+// how long a spawned command has to map its window before toggling spawns it again
+const SPAWN_GRACE: Duration = Duration::from_secs(5);
+
+// a quake style window for a command, found again by the command that started it
+#[derive(Default)]
+pub struct Dropdown {
+    win: Option<Window>,
+    spawned: Option<(u32, Instant)>, // waiting for the window of this pid
+    height: f32,
+}
+
+impl Dropdown {
+    fn pending(&self) -> bool {
+        self.win.is_none() && self.spawned.is_some()
+    }
+}
+
+impl WindowManager {
+    // hides the window if it is shown on the focused tag, otherwise brings it there or spawns it
+    pub fn dropdown_toggle(&mut self, command: String, height: f32) -> Result<()> {
+        let focused_tag = self.focused_tag();
+        let dropdown = self.dropdowns.entry(command.clone()).or_default();
+        dropdown.height = height.clamp(0.1, 1.0);
+        let current = dropdown.win;
+        match current.and_then(|win| self.windows.get(&win).copied().map(|x| (win, x))) {
+            Some((win, WindowLocation::Client(tag, client))) => {
+                if tag == focused_tag && !self.tags.get(&tag).unwrap().client(client).flags.hidden {
+                    self.tags.get_mut(&tag).unwrap().set_hidden(
+                        &mut self.aux,
+                        client,
+                        &SetArg(true, false),
+                    )?;
+                    self.aux
+                        .hooks
+                        .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
+                } else {
+                    self.dropdown_show(win)?;
+                }
+            }
+            _ => {
+                let dropdown = self.dropdowns.get_mut(&command).unwrap();
+                // the window of the last spawn might still be on its way
+                if dropdown.pending()
+                    && matches!(dropdown.spawned, Some((_, at)) if at.elapsed() < SPAWN_GRACE)
+                {
+                    return Ok(());
+                }
+                dropdown.win = None;
+                // exec so the pid is the one the window reports
+                dropdown.spawned =
+                    hooks::spawn(&format!("exec {}", command)).map(|pid| (pid, Instant::now()));
+            }
+        }
+        Ok(())
+    }
+
+    // claims a new window for a dropdown waiting on the process that owns it
+    pub fn dropdown_claim(&mut self, win: Window, args: &mut ClientArgs) -> Result<bool> {
+        if !self.dropdowns.values().any(Dropdown::pending) {
+            return Ok(false);
+        }
+        match self.window_pid(win)?.and_then(|pid| {
+            self.dropdowns.values_mut().find(|dropdown| {
+                dropdown.pending() && matches!(dropdown.spawned, Some((x, _)) if x == pid)
+            })
+        }) {
+            Some(dropdown) => {
+                dropdown.win = Some(win);
+                dropdown.spawned = None;
+                args.flags.floating = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // floating across the top of the free area of the focused monitor, focused and raised
+    pub fn dropdown_show(&mut self, win: Window) -> Result<()> {
+        let height = match self.dropdowns.values().find(|x| x.win == Some(win)) {
+            Some(dropdown) => dropdown.height,
+            None => return Ok(()),
+        };
+        let (tag, client) = match self.windows.get(&win) {
+            Some(WindowLocation::Client(tag, client)) => (*tag, *client),
+            _ => return Ok(()),
+        };
+        let focused_tag = self.focused_tag();
+        let client = self.move_client(tag, client, SetArg(focused_tag, false))?;
+        let free = self
+            .monitors
+            .get(&self.focused_monitor)
+            .unwrap()
+            .free_rect();
+        let rect = Rect::new(
+            free.x,
+            free.y,
+            free.width,
+            (free.height as f32 * height) as u16,
+        );
+        let tag = self.tags.get_mut(&focused_tag).unwrap();
+        tag.set_floating_rect(client, &rect);
+        tag.client_mut(client).flags.floating = true;
+        tag.set_hidden(&mut self.aux, client, &SetArg(false, false))?;
+        tag.switch_layer(&self.aux, client)?;
+        tag.focus_client(&mut self.aux, client)?;
+        self.aux
+            .hooks
+            .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
+        Ok(())
+    }
+}
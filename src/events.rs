@@ -1,17 +1,18 @@
 use anyhow::Result;
 use log::info;
 use x11rb::{
-    protocol::{randr::*, xproto::*, Event},
+    protocol::{randr::*, xkb, xproto::*, Event},
     CURRENT_TIME, NONE,
 };
 
 use super::config::IGNORED_MASK;
 use super::connections::SetArg;
-use super::tag::NodeContents;
+use super::core::NodeContents;
 use super::{WindowLocation, WindowManager};
 
 pub(crate) struct EventHandler {
     drag: DragState,
+    last_click: Option<(Window, Timestamp)>, // the last press of the first button on a border
 }
 
 // for window things
@@ -24,22 +25,36 @@ impl EventHandler {
     pub fn new() -> Self {
         Self {
             drag: DragState::default(),
+            last_click: None,
         }
     }
 
     pub fn handle_event(&mut self, wm: &mut WindowManager, e: Event) -> Result<()> {
+        if matches!(
+            e,
+            Event::ButtonPress(_) | Event::MotionNotify(_) | Event::EnterNotify(_)
+        ) {
+            wm.aux.activity()?;
+        }
         match e {
             Event::ButtonPress(ev) => self.handle_button_press(wm, ev),
             Event::MotionNotify(ev) => self.handle_motion_notify(wm, ev),
             Event::ButtonRelease(ev) => self.handle_button_release(wm, ev),
             Event::DestroyNotify(ev) => self.handle_destroy_notify(wm, ev),
             Event::EnterNotify(ev) => self.handle_enter_notify(wm, ev),
+            Event::LeaveNotify(ev) => self.handle_leave_notify(wm, ev),
             Event::MapRequest(ev) => self.handle_map_request(wm, ev),
             Event::ClientMessage(ev) => self.handle_client_message(wm, ev),
             Event::ConfigureRequest(ev) => self.handle_configure_request(wm, ev),
             Event::PropertyNotify(ev) => self.handle_property_notify(wm, ev),
             Event::UnmapNotify(ev) => self.handle_unmap_notify(wm, ev),
             Event::RandrScreenChangeNotify(ev) => self.handle_randr_norify(wm, ev),
+            Event::ConfigureNotify(ev) if ev.window == wm.aux.root => {
+                self.handle_root_configure(wm, ev)
+            }
+            Event::RandrNotify(ev) => self.handle_randr_output(wm, ev),
+            Event::XkbBellNotify(ev) => self.handle_bell(wm, ev),
+            Event::SelectionClear(ev) => self.handle_selection_clear(wm, ev),
             _e => {
                 //info!("Unhandled Event: {:?}", _e);
                 Ok(())
@@ -57,15 +72,56 @@ impl EventHandler {
         Ok(())
     }
 
+    // adding or deleting a randr monitor only sends a configure notify for the root
+    fn handle_root_configure(
+        &mut self,
+        wm: &mut WindowManager,
+        e: ConfigureNotifyEvent,
+    ) -> Result<()> {
+        info!("Root configured {:?}", e);
+        wm.update_monitors()
+    }
+
+    // outputs turning off or on don't always change the screen
+    fn handle_randr_output(&mut self, wm: &mut WindowManager, e: NotifyEvent) -> Result<()> {
+        if e.sub_code == Notify::OUTPUT_CHANGE || e.sub_code == Notify::OUTPUT_PROPERTY {
+            wm.update_monitor_power()?;
+        }
+        Ok(())
+    }
+
+    // bells rung without a managed window flash the focused one
+    fn handle_bell(&mut self, wm: &mut WindowManager, e: xkb::BellNotifyEvent) -> Result<()> {
+        let win = Some(e.window).filter(|win| wm.windows.contains_key(win));
+        wm.visual_bell(win)
+    }
+
+    // another tray took over the system tray selection
+    fn handle_selection_clear(
+        &mut self,
+        wm: &mut WindowManager,
+        e: SelectionClearEvent,
+    ) -> Result<()> {
+        if wm.tray_owns(e.owner) {
+            wm.tray_stop()?;
+        }
+        Ok(())
+    }
+
     fn handle_enter_notify(&mut self, wm: &mut WindowManager, e: EnterNotifyEvent) -> Result<()> {
         info!("Handling Enter {}({})", e.event, e.child);
         match wm.windows.get(&e.event).copied() {
+            Some(WindowLocation::PanelEdge(panel)) => wm.panel_reveal(panel)?,
+            Some(WindowLocation::Panel(..)) => wm.panel_entered(e.event)?,
+            _ if wm.focus_lock => (),
             Some(WindowLocation::Client(tag, client)) => {
                 if let Some(mon) = wm.tags.get(&tag).unwrap().monitor {
                     wm.set_focus(mon)?;
                 }
                 let tag = wm.tags.get_mut(&tag).unwrap();
-                if tag.client(client).ignore_unmaps == 0 {
+                let win = tag.client(client).win;
+                let x11 = wm.aux.x11_clients.get(&win);
+                if x11.is_none_or(|x11| x11.ignore_unmaps == 0) {
                     tag.focus_client(&mut wm.aux, client)?;
                 }
             }
@@ -76,6 +132,14 @@ impl EventHandler {
         }
         Ok(())
     }
+    fn handle_leave_notify(&mut self, wm: &mut WindowManager, e: LeaveNotifyEvent) -> Result<()> {
+        if e.detail != NotifyDetail::INFERIOR {
+            if let Some(WindowLocation::Panel(..)) = wm.windows.get(&e.event) {
+                wm.panel_left(e.event)?;
+            }
+        }
+        Ok(())
+    }
     fn handle_map_request(&mut self, wm: &mut WindowManager, e: MapRequestEvent) -> Result<()> {
         info!("Handling Map Request {:?}", e);
         match wm.windows.get(&e.window) {
@@ -83,7 +147,7 @@ impl EventHandler {
                 // wants to be mapped
                 Ok(())
             }
-            None => wm.manage_window(wm.focused_monitor, e.window),
+            None => wm.manage_requested(wm.focused_monitor, e.window),
             _ => Ok(()),
         }
     }
@@ -93,6 +157,7 @@ impl EventHandler {
         e: DestroyNotifyEvent,
     ) -> Result<()> {
         info!("Handling Destroy Notify {}, {}", e.event, e.window);
+        wm.manage_cancel(e.window)?;
         wm.unmanage_window(e.window)?;
         Ok(())
     }
@@ -100,13 +165,18 @@ impl EventHandler {
         info!("Handling Unmap Notify {}, {}", e.event, e.window);
         info!("{:?}", e);
         let mut unmap = true;
-        if let Some(WindowLocation::Client(tag, client)) = wm.windows.get(&e.window) {
-            let client = wm.tags.get_mut(tag).unwrap().client_mut(*client);
-            if client.ignore_unmaps != 0 {
-                info!("ignore unmap {}", client.ignore_unmaps);
-                client.ignore_unmaps -= 1;
-                unmap = false;
+        match wm.windows.get(&e.window) {
+            Some(WindowLocation::Client(..)) => {
+                if let Some(x11) = wm.aux.x11_clients.get_mut(&e.window) {
+                    if x11.ignore_unmaps != 0 {
+                        info!("ignore unmap {}", x11.ignore_unmaps);
+                        x11.ignore_unmaps -= 1;
+                        unmap = false;
+                    }
+                }
             }
+            Some(WindowLocation::TrayIcon) => unmap = !wm.tray_ignore_unmap(e.window),
+            _ => (),
         }
         if unmap {
             wm.unmanage_window(e.window)?;
@@ -121,7 +191,7 @@ impl EventHandler {
         let atom = get_atom_name(&wm.aux.dpy, e.atom).unwrap().reply().unwrap();
         info!(
             "Handling Property Notify. Property {}, {}",
-            String::from_utf8(atom.name).unwrap(),
+            String::from_utf8_lossy(&atom.name),
             e.window
         );
         match wm.windows.get(&e.window).copied() {
@@ -129,6 +199,7 @@ impl EventHandler {
             Some(WindowLocation::Panel(mon)) => wm.panel_property_changed(e.window, mon, e.atom)?,
             _ => (),
         }
+        wm.property_changed(&e, &String::from_utf8_lossy(&atom.name))?;
         Ok(())
     }
     fn handle_client_message(
@@ -139,7 +210,7 @@ impl EventHandler {
         let name = get_atom_name(&wm.aux.dpy, e.type_)?.reply()?;
         info!(
             "Handling Client Message {}, {}, {:?}",
-            String::from_utf8(name.name)?,
+            String::from_utf8_lossy(&name.name),
             e.window,
             e.data.as_data32(),
         );
@@ -155,6 +226,18 @@ impl EventHandler {
                 wm.client_state(tag, client, data[1], data[0]);
                 wm.client_state(tag, client, data[1], data[0]);
             }
+        } else if e.type_ == wm.aux.atoms.WM_CHANGE_STATE {
+            // iconic state, there is no way for a client to ask to be shown again
+            if e.data.as_data32()[0] == 3 {
+                if let Some(WindowLocation::Client(..)) = wm.windows.get(&e.window) {
+                    wm.set_window_hidden(e.window, &SetArg(true, false))?;
+                    wm.aux
+                        .hooks
+                        .tag_update(&wm.tags, &wm.tag_order, wm.focused_monitor);
+                }
+            }
+        } else if e.type_ == wm.aux.atoms._NET_SYSTEM_TRAY_OPCODE {
+            wm.tray_message(&e)?;
         } else if e.type_ == wm.aux.atoms._NET_ACTIVE_WINDOW {
             if let Some(WindowLocation::Client(tag, client)) = wm.windows.get(&e.window).copied() {
                 wm.client_state(tag, client, wm.aux.atoms._NET_WM_STATE_DEMANDS_ATTENTION, 1);
@@ -171,6 +254,8 @@ impl EventHandler {
         match wm.windows.get(&e.window) {
             Some(WindowLocation::Client(..)) => (),
             Some(WindowLocation::Monitor(..)) => (),
+            // sized by the tray
+            Some(WindowLocation::TrayIcon) => (),
             _ => {
                 configure_window(
                     &wm.aux.dpy,
@@ -181,11 +266,81 @@ impl EventHandler {
         }
         Ok(())
     }
+    // true if the press was the second on the same border soon enough after the first
+    fn double_click(
+        &mut self,
+        wm: &mut WindowManager,
+        win: Window,
+        e: &ButtonPressEvent,
+    ) -> Result<bool> {
+        let action = match wm.aux.theme.frame_double_click {
+            Some(action) => action,
+            None => return Ok(false),
+        };
+        let (tag, client) = match wm.frame_client(win, e.root_x, e.root_y)? {
+            Some(client) => client,
+            None => {
+                self.last_click = None;
+                return Ok(false);
+            }
+        };
+        match self.last_click.take() {
+            Some((last, time))
+                if last == win && e.time.wrapping_sub(time) <= wm.aux.theme.double_click_ms =>
+            {
+                wm.frame_action(tag, client, action)?;
+                Ok(true)
+            }
+            _ => {
+                self.last_click = Some((win, e.time));
+                Ok(false)
+            }
+        }
+    }
+
     fn handle_button_press(&mut self, wm: &mut WindowManager, e: ButtonPressEvent) -> Result<()> {
         let win = e.child;
         info!("Handling Button Press {}", win);
         let mods = e.state & IGNORED_MASK;
-        if mods == 0 && e.detail == 1 {
+        let swipe_mon = match wm.windows.get(&e.event) {
+            Some(WindowLocation::SwipeEdge(mon)) => Some(*mon),
+            _ if wm.aux.theme.swipe.binding == Some((e.detail, mods)) => {
+                Some(wm.monitor_at((e.root_x, e.root_y)))
+            }
+            _ => None,
+        };
+        if let Some(mon) = swipe_mon.filter(|_| self.drag.button == 0) {
+            info!("Swipe ({})", e.detail);
+            self.drag.swipe = Some((e.detail, mon, e.root_x));
+            grab_pointer(
+                &wm.aux.dpy,
+                false,
+                wm.aux.root,
+                u32::from(
+                    EventMask::BUTTON_RELEASE
+                        | EventMask::POINTER_MOTION
+                        | EventMask::POINTER_MOTION_HINT,
+                ) as u16,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                wm.aux.root,
+                NONE,
+                CURRENT_TIME,
+            )?;
+            return Ok(());
+        }
+        let frame_action = wm.aux.theme.frame_buttons.get(&(e.detail, mods)).copied();
+        if let Some(action) = frame_action.filter(|_| {
+            self.drag.button == 0 && !wm.aux.theme.pointer_bindings.takes(e.detail, mods)
+        }) {
+            // the grab is synchronous, the click goes on to the client unless it hit the border
+            let allow = if wm.frame_button(win, e.root_x, e.root_y, action)? {
+                Allow::ASYNC_POINTER
+            } else {
+                Allow::REPLAY_POINTER
+            };
+            allow_events(&wm.aux.dpy, allow, CURRENT_TIME)?;
+        } else if mods == 0 && e.detail == 1 {
             if self.drag.button == 0 {
                 if let Some(WindowLocation::Client(tag, client)) = wm.windows.get(&win) {
                     info!("Raising Client");
@@ -195,20 +350,27 @@ impl EventHandler {
                         .switch_layer(&wm.aux, *client)?;
                 }
             }
-            allow_events(&wm.aux.dpy, Allow::REPLAY_POINTER, CURRENT_TIME)?;
+            let allow = if self.double_click(wm, win, &e)? {
+                Allow::ASYNC_POINTER
+            } else {
+                Allow::REPLAY_POINTER
+            };
+            allow_events(&wm.aux.dpy, allow, CURRENT_TIME)?;
         } else if self.drag.button == 0 {
             if let Some(WindowLocation::Client(tag, client)) = wm.windows.get(&win) {
+                let bindings = wm.aux.theme.pointer_bindings;
+                self.drag.resize = e.detail == bindings.resize_button;
                 self.drag.button = match e.detail {
-                    1 => 1,
-                    3 => {
-                        if let Some(rect) = wm.tags.get(tag).unwrap().get_rect(*client) {
+                    button if button == bindings.move_button => button,
+                    button if button == bindings.resize_button => {
+                        if let Some(rect) = wm.tags.get(tag).unwrap().get_rect(&wm.aux, *client) {
                             let center = (
                                 rect.x + (rect.width / 2) as i16,
                                 rect.y + (rect.height / 2) as i16,
                             );
                             self.drag.left = center.0 > e.root_x;
                             self.drag.top = center.1 > e.root_y;
-                            3
+                            button
                         } else {
                             0
                         }
@@ -245,11 +407,21 @@ impl EventHandler {
         _e: MotionNotifyEvent,
     ) -> Result<()> {
         info!("Handling Motion");
+        if let Some((button, mon, start)) = self.drag.swipe {
+            let x = query_pointer(&wm.aux.dpy, wm.aux.root)?.reply()?.root_x;
+            // a long enough swipe keeps switching tags
+            if (x - start).unsigned_abs() >= wm.aux.theme.swipe.threshold {
+                self.drag.swipe = Some((button, mon, x));
+                wm.swipe(mon, x - start)?;
+            }
+            return Ok(());
+        }
         let tag = wm.focused_tag();
         let tag = wm.tags.get_mut(&tag).unwrap();
         let poin = query_pointer(&wm.aux.dpy, wm.aux.root)?.reply()?;
-        match self.drag.button {
-            1 => {
+        match (self.drag.button, self.drag.resize) {
+            (0, _) => (),
+            (_, false) => {
                 let pos = (poin.root_x, poin.root_y);
                 if !wm
                     .monitors
@@ -297,7 +469,7 @@ impl EventHandler {
                     )?
                 }
             }
-            3 => tag.resize_client(
+            (_, true) => tag.resize_client(
                 &mut wm.aux,
                 self.drag.win,
                 (
@@ -306,8 +478,8 @@ impl EventHandler {
                 ),
                 self.drag.left,
                 self.drag.top,
+                false,
             )?,
-            _ => (),
         }
         self.drag.prev = (poin.root_x, poin.root_y);
         Ok(())
@@ -321,6 +493,9 @@ impl EventHandler {
         if e.detail == self.drag.button {
             self.drag.button = 0;
             ungrab_pointer(&wm.aux.dpy, CURRENT_TIME)?;
+        } else if matches!(self.drag.swipe, Some((button, ..)) if button == e.detail) {
+            self.drag.swipe = None;
+            ungrab_pointer(&wm.aux.dpy, CURRENT_TIME)?;
         }
         Ok(())
     }
@@ -329,8 +504,10 @@ impl EventHandler {
 #[derive(Default)]
 pub(crate) struct DragState {
     button: u8,
+    resize: bool,
     win: usize,
     prev: (i16, i16),
     left: bool,
     top: bool,
+    swipe: Option<(u8, u32, i16)>, // button, monitor, x the last tag switch happened at
 }
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use log::info;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env::var;
+use std::path::PathBuf;
+
+use crate::utils::Rect;
+
+fn store_path() -> Option<PathBuf> {
+    var("XDG_STATE_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".local/state"))
+        })
+        .map(|dir| dir.join("cwm").join("floating.json"))
+}
+
+// x, y, width and height by class and instance
+type Entries = HashMap<String, (i16, i16, u16, u16)>;
+
+fn key(class: &str, instance: Option<&str>) -> String {
+    format!("{}\n{}", class, instance.unwrap_or_default())
+}
+
+// the last floating geometry of each class and instance, relative to the monitor and with the
+// border, read the first time it is used and written back on every change
+#[derive(Default)]
+pub struct FloatStore {
+    entries: RefCell<Option<Entries>>,
+}
+
+impl FloatStore {
+    fn with<T>(&self, f: impl FnOnce(&mut Entries) -> T) -> T {
+        let mut entries = self.entries.borrow_mut();
+        let entries = entries.get_or_insert_with(|| {
+            store_path()
+                .and_then(|path| std::fs::read(path).ok())
+                .and_then(|data| serde_json::from_slice(&data).ok())
+                .unwrap_or_default()
+        });
+        f(entries)
+    }
+
+    pub fn get(&self, class: &str, instance: Option<&str>, mon: &Rect) -> Option<Rect> {
+        self.with(|entries| {
+            entries
+                .get(&key(class, instance))
+                .map(|(x, y, width, height)| Rect::new(mon.x + x, mon.y + y, *width, *height))
+        })
+    }
+
+    pub fn remember(&self, class: &str, instance: Option<&str>, rect: &Rect, mon: &Rect) {
+        let entry = (rect.x - mon.x, rect.y - mon.y, rect.width, rect.height);
+        let changed =
+            self.with(|entries| entries.insert(key(class, instance), entry) != Some(entry));
+        if changed {
+            if let Err(e) = self.save() {
+                info!("saving floating geometry failed: {:?}", e);
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = store_path() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            let data = self.with(|entries| serde_json::to_vec(entries))?;
+            std::fs::write(path, data)?;
+        }
+        Ok(())
+    }
+}
@@ -2,82 +2,201 @@ use log::info;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::env::var;
-use std::process::{Command, Stdio};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
 
 use super::Tag;
-use crate::connections::{CwmResponse, Stream, TagState};
+use crate::connections::{socket_path, CwmResponse, MatchSpec, Stream, TagState, TreeNode};
+use crate::monitor::Monitor;
+use crate::utils::Rect;
+use x11rb::protocol::xproto::{Atom, Window};
+
+pub struct TreeHook {
+    pub stream: Stream,
+    pub mon: Option<u32>,
+    pub last: Option<TreeNode>,
+}
 
 #[derive(Default)]
 pub struct Hooks {
     monitor_focused: HashMap<u32, (Vec<RefCell<Stream>>, Option<String>)>,
     pub monitor_tags: (Vec<RefCell<Stream>>, Vec<(TagState, u32)>, u32),
+    pub tree: Vec<TreeHook>,
+    pub fullscreen: (Vec<Stream>, HashMap<Window, Atom>), // fullscreen window -> monitor
+    collapsed: Vec<Stream>,
+    idle: (Vec<Stream>, bool),
+    pub layout: (Vec<Stream>, HashMap<Atom, Layout>),
+    presel: Vec<Stream>,
+    properties: Vec<(Window, Atom, Stream)>,
+    waiters: Vec<(u32, Stream, MatchSpec)>,
+    next_waiter: u32,
+    // answered streams kept until their output is written
+    closing: Vec<Stream>,
+    // tag switches are being coalesced, the tag hooks wait for the last one
+    pub settling: bool,
     script_config: Option<String>,
     script_mon_open: Option<String>,
     script_mon_close: Option<String>,
+    script_client_managed: Option<String>,
+    script_client_unmanaged: Option<String>,
+    script_tag_switched: Option<String>,
+    script_wm_exit: Option<String>,
+}
+
+// the shown windows of a monitor with their geometry
+pub type Layout = Vec<(Window, (i16, i16, u16, u16))>;
+
+// started in the background and waited on once they exit so they don't linger as zombies
+static CHILDREN: Mutex<Vec<Child>> = Mutex::new(Vec::new());
+
+// every script gets CWM_SOCKET, the rest depends on the hook
+type Env = Vec<(&'static str, String)>;
+
+fn monitor_env(mon: &Monitor, tag: &str) -> Env {
+    let Rect {
+        x,
+        y,
+        width,
+        height,
+    } = mon.size;
+    vec![
+        ("CWM_MONITOR", mon.id.to_string()),
+        ("CWM_MONITOR_NAME", mon.name.clone()),
+        (
+            "CWM_MONITOR_GEOMETRY",
+            format!("{}x{}+{}+{}", width, height, x, y),
+        ),
+        ("CWM_MONITOR_BG", mon.bg.to_string()),
+        ("CWM_TAG", tag.to_string()),
+    ]
+}
+
+fn run(script: &Option<String>, args: &[String], env: Env, wait: bool) {
+    if let Some(script) = script {
+        let mut command = Command::new(script);
+        command
+            .args(args)
+            .env("CWM_SOCKET", socket_path())
+            .envs(env)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let res = if wait {
+            command.status().map(|_| ())
+        } else {
+            command.spawn().map(keep)
+        };
+        if let Err(e) = res {
+            info!("hook {} failed: {:?}", script, e);
+        }
+    }
+}
+
+// a shell command started in the background, like a hook, returns the pid of the shell
+pub fn spawn(command: &str) -> Option<u32> {
+    let res = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("CWM_SOCKET", socket_path())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match res {
+        Ok(child) => {
+            let pid = child.id();
+            keep(child);
+            Some(pid)
+        }
+        Err(e) => {
+            info!("spawning {} failed: {:?}", command, e);
+            None
+        }
+    }
+}
+
+fn keep(child: Child) {
+    CHILDREN.lock().unwrap().push(child);
+}
+
+// collects the children that exited since the last call
+pub fn reap() {
+    CHILDREN
+        .lock()
+        .unwrap()
+        .retain_mut(|child| matches!(child.try_wait(), Ok(None)));
 }
 
 impl Hooks {
     pub fn new() -> Self {
-        let mut script_config = None;
-        let mut script_mon_open = None;
-        let mut script_mon_close = None;
-        if let Ok(path) = var("HOME") {
-            let config = path.clone() + "/.config/cwm/cwmrc";
-            let mon_open = path.clone() + "/.config/cwm/mon_open";
-            let mon_close = path + "/.config/cwm/mon_close";
-            if std::path::Path::new(&config).exists() {
-                script_config.replace(config);
-            }
-            if std::path::Path::new(&mon_open).exists() {
-                script_mon_open.replace(mon_open);
-            }
-            if std::path::Path::new(&mon_close).exists() {
-                script_mon_close.replace(mon_close);
-            }
-        }
+        let dir = var("HOME").ok().map(|path| path + "/.config/cwm/");
+        let script = |name: &str| {
+            dir.as_ref()
+                .map(|dir| dir.clone() + name)
+                .filter(|path| std::path::Path::new(path).exists())
+        };
         Self {
-            script_config,
-            script_mon_open,
-            script_mon_close,
+            script_config: script("cwmrc"),
+            script_mon_open: script("mon_open"),
+            script_mon_close: script("mon_close"),
+            script_client_managed: script("client_managed"),
+            script_client_unmanaged: script("client_unmanaged"),
+            script_tag_switched: script("tag_switched"),
+            script_wm_exit: script("wm_exit"),
             ..Self::default()
         }
     }
 
     pub fn config(&self) {
-        if let Some(script) = &self.script_config {
-            Command::new(script)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .output()
-                .unwrap();
-        }
+        run(&self.script_config, &[], Vec::new(), true);
     }
 
-    pub fn mon_open(&mut self, mon: u32, name: &str, bg: u32) {
-        self.monitor_focused.insert(mon, (Vec::new(), None));
-        if let Some(script) = &self.script_mon_open {
-            Command::new(script)
-                .arg(mon.to_string())
-                .arg(name)
-                .arg(bg.to_string())
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()
-                .unwrap();
-        }
+    pub fn mon_open(&mut self, mon: &Monitor, tag: &str) {
+        self.monitor_focused.insert(mon.id, (Vec::new(), None));
+        let args = [mon.id.to_string(), mon.name.clone(), mon.bg.to_string()];
+        run(&self.script_mon_open, &args, monitor_env(mon, tag), false);
     }
 
-    pub fn mon_close(&mut self, mon: u32, name: &str) {
-        self.monitor_focused.remove(&mon);
-        if let Some(script) = &self.script_mon_close {
-            Command::new(script)
-                .arg(mon.to_string())
-                .arg(name)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .spawn()
-                .unwrap();
+    pub fn mon_close(&mut self, mon: &Monitor, tag: &str) {
+        self.monitor_focused.remove(&mon.id);
+        let args = [mon.id.to_string(), mon.name.clone()];
+        run(&self.script_mon_close, &args, monitor_env(mon, tag), false);
+    }
+
+    pub fn client_managed(&self, win: Window, class: Option<&str>, tag: &str) {
+        let env = vec![
+            ("CWM_WINDOW", win.to_string()),
+            ("CWM_CLASS", class.unwrap_or_default().to_string()),
+            ("CWM_TAG", tag.to_string()),
+        ];
+        run(&self.script_client_managed, &[win.to_string()], env, false);
+    }
+
+    pub fn client_unmanaged(&self, win: Window, tag: &str) {
+        let env = vec![
+            ("CWM_WINDOW", win.to_string()),
+            ("CWM_TAG", tag.to_string()),
+        ];
+        run(
+            &self.script_client_unmanaged,
+            &[win.to_string()],
+            env,
+            false,
+        );
+    }
+
+    pub fn tag_switched(&self, mon: &Monitor, tag: &str, prev: &str) {
+        if self.settling {
+            return;
         }
+        let mut env = monitor_env(mon, tag);
+        env.push(("CWM_PREV_TAG", prev.to_string()));
+        let args = [tag.to_string(), prev.to_string()];
+        run(&self.script_tag_switched, &args, env, false);
+    }
+
+    // the wm no longer answers requests by the time this runs
+    pub fn wm_exit(&self) {
+        run(&self.script_wm_exit, &[], Vec::new(), false);
     }
 
     pub fn monitor_focus(&mut self, id: u32, focused: Option<String>) {
@@ -109,6 +228,213 @@ impl Hooks {
         }
     }
 
+    pub fn add_tree(&mut self, mon: Option<u32>, stream: Stream) {
+        self.tree.push(TreeHook {
+            stream,
+            mon,
+            last: None,
+        })
+    }
+
+    pub fn add_fullscreen(&mut self, mut stream: Stream) {
+        if self
+            .fullscreen
+            .1
+            .iter()
+            .all(|(win, mon)| stream.send(&CwmResponse::Fullscreen(*mon, *win, true)))
+        {
+            self.fullscreen.0.push(stream)
+        }
+    }
+
+    pub fn add_collapsed(&mut self, stream: Stream) {
+        self.collapsed.push(stream)
+    }
+
+    pub fn collapsed(&mut self, mon: Atom, win: Window) {
+        let message = CwmResponse::Collapsed(mon, win);
+        self.collapsed.retain_mut(|hook| hook.send(&message));
+    }
+
+    pub fn add_presel(&mut self, stream: Stream) {
+        self.presel.push(stream)
+    }
+
+    pub fn presel_expired(&mut self, win: Option<Window>) {
+        let message = CwmResponse::PreselExpired(win);
+        self.presel.retain_mut(|hook| hook.send(&message));
+    }
+
+    pub fn add_property(&mut self, win: Window, atom: Atom, stream: Stream) {
+        self.properties.push((win, atom, stream))
+    }
+
+    pub fn watches_property(&self, win: Window, atom: Atom) -> bool {
+        self.properties
+            .iter()
+            .any(|(other, other_atom, _)| *other == win && *other_atom == atom)
+    }
+
+    pub fn property_changed(&mut self, win: Window, atom: Atom, name: &str, value: Option<String>) {
+        let message = CwmResponse::Property(win, name.to_string(), value);
+        self.properties.retain_mut(|(other, other_atom, hook)| {
+            *other != win || *other_atom != atom || hook.send(&message)
+        });
+    }
+
+    // watchers of a window that is no longer managed are closed
+    pub fn forget_properties(&mut self, win: Window) {
+        self.properties.retain(|(other, ..)| *other != win);
+    }
+
+    pub fn add_idle(&mut self, mut stream: Stream) {
+        if stream.send(&CwmResponse::Idle(self.idle.1)) {
+            self.idle.0.push(stream)
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle.1
+    }
+
+    pub fn idle(&mut self, idle: bool) {
+        if self.idle.1 != idle {
+            self.idle.1 = idle;
+            let message = CwmResponse::Idle(idle);
+            self.idle.0.retain_mut(|hook| hook.send(&message));
+        }
+    }
+
+    pub fn add_layout(&mut self, mut stream: Stream) {
+        if self
+            .layout
+            .1
+            .iter()
+            .all(|(mon, layout)| stream.send(&CwmResponse::Layout(*mon, layout.clone())))
+        {
+            self.layout.0.push(stream)
+        }
+    }
+
+    // monitors that went away are sent without windows
+    pub fn layout_update(&mut self, layouts: HashMap<Atom, Layout>) {
+        let mut messages = Vec::new();
+        for mon in self.layout.1.keys() {
+            if !layouts.contains_key(mon) {
+                messages.push(CwmResponse::Layout(*mon, Vec::new()));
+            }
+        }
+        for (mon, layout) in layouts.iter() {
+            if self.layout.1.get(mon) != Some(layout) {
+                messages.push(CwmResponse::Layout(*mon, layout.clone()));
+            }
+        }
+        self.layout.1 = layouts;
+        if !messages.is_empty() {
+            self.layout
+                .0
+                .retain_mut(|hook| messages.iter().all(|message| hook.send(message)));
+        }
+    }
+
+    // the id names the waiter's timeout
+    pub fn add_waiter(&mut self, spec: MatchSpec, stream: Stream) -> u32 {
+        let id = self.next_waiter;
+        self.next_waiter = self.next_waiter.wrapping_add(1);
+        self.waiters.push((id, stream, spec));
+        id
+    }
+
+    pub fn wait_expired(&mut self, id: u32) {
+        if let Some(idx) = self.waiters.iter().position(|x| x.0 == id) {
+            let (_, mut stream, _) = self.waiters.remove(idx);
+            stream.send(&CwmResponse::Window(None));
+            self.close(stream);
+        }
+    }
+
+    // answers and drops every waiter the window matches, returning their ids
+    pub fn window_managed(
+        &mut self,
+        win: Window,
+        class: Option<&str>,
+        instance: Option<&str>,
+        name: Option<&str>,
+    ) -> Vec<u32> {
+        let (answered, waiting): (Vec<_>, _) = std::mem::take(&mut self.waiters)
+            .into_iter()
+            .partition(|(_, _, spec)| spec.matches(class, instance, name));
+        self.waiters = waiting;
+        answered
+            .into_iter()
+            .map(|(id, mut stream, _)| {
+                stream.send(&CwmResponse::Window(Some(win)));
+                self.close(stream);
+                id
+            })
+            .collect()
+    }
+
+    // the stream is dropped once the rest of its output is written
+    pub fn close(&mut self, stream: Stream) {
+        if stream.pending() {
+            self.closing.push(stream);
+        }
+    }
+
+    fn streams_mut(&mut self) -> impl Iterator<Item = &mut Stream> {
+        self.monitor_focused
+            .values_mut()
+            .flat_map(|(hooks, _)| hooks.iter_mut().map(RefCell::get_mut))
+            .chain(self.monitor_tags.0.iter_mut().map(RefCell::get_mut))
+            .chain(self.tree.iter_mut().map(|hook| &mut hook.stream))
+            .chain(self.fullscreen.0.iter_mut())
+            .chain(self.collapsed.iter_mut())
+            .chain(self.idle.0.iter_mut())
+            .chain(self.layout.0.iter_mut())
+            .chain(self.presel.iter_mut())
+            .chain(self.properties.iter_mut().map(|(_, _, stream)| stream))
+            .chain(self.waiters.iter_mut().map(|(_, stream, _)| stream))
+            .chain(self.closing.iter_mut())
+    }
+
+    // writes what the sockets take of the queued output, a subscriber whose stream broke is
+    // dropped by the next message sent to it
+    pub fn flush(&mut self) {
+        for stream in self.streams_mut().filter(|stream| stream.pending()) {
+            stream.flush();
+        }
+        self.closing.retain(|stream| stream.pending());
+    }
+
+    // the streams with output waiting for room in their socket
+    pub fn pending_fds(&mut self) -> Vec<RawFd> {
+        self.streams_mut()
+            .filter(|stream| stream.pending())
+            .map(|stream| stream.as_raw_fd())
+            .collect()
+    }
+
+    pub fn fullscreen_update(&mut self, windows: HashMap<Window, Atom>) {
+        let mut messages = Vec::new();
+        for (win, mon) in &self.fullscreen.1 {
+            if windows.get(win) != Some(mon) {
+                messages.push(CwmResponse::Fullscreen(*mon, *win, false));
+            }
+        }
+        for (win, mon) in &windows {
+            if self.fullscreen.1.get(win) != Some(mon) {
+                messages.push(CwmResponse::Fullscreen(*mon, *win, true));
+            }
+        }
+        self.fullscreen.1 = windows;
+        if !messages.is_empty() {
+            self.fullscreen
+                .0
+                .retain_mut(|hook| messages.iter().all(|message| hook.send(message)));
+        }
+    }
+
     pub fn update_tag(&mut self, tag: &Tag) {
         #[inline]
         fn val_changed<T: PartialEq>(val: &mut T, new: T) -> bool {
@@ -124,6 +450,8 @@ impl Hooks {
                 || val_changed(&mut state.focused, tag.monitor)
                 || val_changed(&mut state.urgent, tag.urgent())
                 || val_changed(&mut state.empty, tag.empty())
+                || val_changed(&mut state.hidden, tag.hidden_count())
+                || val_changed(&mut state.client_count, tag.client_count())
             {
                 let message = CwmResponse::TagState(
                     self.monitor_tags.1.iter().map(|x| x.0.clone()).collect(),
@@ -137,6 +465,9 @@ impl Hooks {
     }
 
     pub fn tag_update(&mut self, tags: &HashMap<u32, Tag>, order: &[u32], focused_mon: u32) {
+        if self.settling {
+            return;
+        }
         #[inline]
         fn val_changed<T: PartialEq>(val: &mut T, new: T) -> bool {
             if *val != new {
@@ -163,10 +494,13 @@ impl Hooks {
             .map(|id| tags.get(id).unwrap())
             .zip(self.monitor_tags.1.iter_mut())
         {
+            changed |= val_changed(&mut state.id, tag.id);
             changed |= val_changed(&mut state.name, tag.name.clone());
             changed |= val_changed(&mut state.focused, tag.monitor);
             changed |= val_changed(&mut state.urgent, tag.urgent());
             changed |= val_changed(&mut state.empty, tag.empty());
+            changed |= val_changed(&mut state.hidden, tag.hidden_count());
+            changed |= val_changed(&mut state.client_count, tag.client_count());
         }
         if changed {
             let message = CwmResponse::TagState(
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// icons are scaled to this width and height
+pub const ICON_SIZE: u16 = 32;
+
+// rgba, row major
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Icon {
+    pub width: u16,
+    pub height: u16,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Debug for Icon {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Icon({}x{})", self.width, self.height)
+    }
+}
+
+impl Icon {
+    // nearest neighbour scale of argb pixels to ICON_SIZE
+    pub fn scaled(width: usize, height: usize, argb: &[u32]) -> Self {
+        let size = ICON_SIZE as usize;
+        let mut data = Vec::with_capacity(size * size * 4);
+        for y in 0..size {
+            for x in 0..size {
+                let pixel = argb[y * height / size * width + x * width / size];
+                data.extend(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+                data.push((pixel >> 24) as u8);
+            }
+        }
+        Self {
+            width: ICON_SIZE,
+            height: ICON_SIZE,
+            data,
+        }
+    }
+
+    // _NET_WM_ICON is a list of width, height and then width * height argb pixels
+    pub fn from_net_wm_icon(values: &[u32]) -> Option<Self> {
+        let mut icons = Vec::new();
+        let mut rest = values;
+        while rest.len() >= 2 {
+            let (width, height) = (rest[0] as usize, rest[1] as usize);
+            let len = width * height;
+            if len == 0 || rest.len() < len + 2 {
+                break;
+            }
+            icons.push((width, height, &rest[2..len + 2]));
+            rest = &rest[len + 2..];
+        }
+        // the smallest icon that doesn't need to be scaled up, otherwise the largest
+        let size = ICON_SIZE as usize;
+        icons
+            .iter()
+            .filter(|x| x.0 >= size && x.1 >= size)
+            .min_by_key(|x| x.0 * x.1)
+            .or_else(|| icons.iter().max_by_key(|x| x.0 * x.1))
+            .map(|(width, height, argb)| Self::scaled(*width, *height, argb))
+    }
+}
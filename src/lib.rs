@@ -1,32 +1,61 @@
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use x11rb::{
     atom_manager,
     connection::Connection,
-    protocol::{randr::*, xproto::*},
+    protocol::{randr::*, xkb, xproto::*},
     rust_connection::RustConnection,
     NONE,
 };
 
 use log::info;
 mod config;
+pub mod core;
 pub mod utils;
+mod x11;
 use config::IGNORED_MODS;
 mod monitor;
 use monitor::Monitor;
 mod tag;
-use tag::Tag;
+use crate::core::Tag;
 mod events;
 use events::EventHandler;
 pub mod connections;
-use connections::Aux;
+pub mod display;
+pub mod protocol;
+use connections::{socket_path, Aux};
+use display::Dpy;
 mod hooks;
 use hooks::Hooks;
-mod rules;
+mod timer;
+use timer::Timeout;
+mod animation;
+mod dropdown;
+mod float_store;
+use dropdown::Dropdown;
+mod tray;
+use tray::Tray;
+mod restart;
+use restart::{RestartState, RestoredClient};
+mod icon;
+pub mod logging;
+mod metrics;
+#[cfg(test)]
+mod mock;
+mod motion;
+use motion::Motion;
+mod pip;
+mod presel_spawn;
+mod property_watch;
+use presel_spawn::PreselPin;
+pub mod testing;
+mod theme_file;
 
 atom_manager! {
     pub AtomCollection: AtomCollectionCookie {
         _NET_WM_NAME,
+        _NET_WM_ICON,
         _NET_WM_WINDOW_TYPE,
         _NET_WM_WINDOW_TYPE_DOCK,
         _NET_WM_WINDOW_TYPE_TOOLBAR,
@@ -39,7 +68,9 @@ atom_manager! {
         _NET_WM_STATE,
         _NET_WM_STATE_FULLSCREEN,
         _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_HIDDEN,
         _NET_WM_DESKTOP,
+        _NET_WM_PID,
         _NET_WM_STATE_DEMANDS_ATTENTION,
         _NET_ACTIVE_WINDOW,
         _NET_SUPPORTED,
@@ -47,16 +78,27 @@ atom_manager! {
         WM_STATE,
         WM_PROTOCOLS,
         WM_DELETE_WINDOW,
+        WM_CHANGE_STATE,
         UTF8_STRING,
+        COMPOUND_TEXT,
+        _COMPTON_SHADOW,
+        _PICOM_SHADOW,
+        _NET_SYSTEM_TRAY_OPCODE,
+        _NET_SYSTEM_TRAY_ORIENTATION,
+        _XEMBED,
+        MANAGER,
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 enum WindowLocation {
     Client(Atom, usize),
     Panel(Atom),
+    PanelEdge(Window), // the panel it reveals
+    SwipeEdge(Atom),
     DesktopWindow(Atom),
     Monitor(Atom),
+    TrayIcon,
     _Unmanaged,
 }
 
@@ -73,9 +115,41 @@ pub struct WindowManager {
     windows: HashMap<Window, WindowLocation>,
     running: bool,
     supporting: bool,
+    // ignore focus changes from the pointer entering windows
+    focus_lock: bool,
+    dropdowns: HashMap<String, Dropdown>, // keyed by command
+    tray: Option<Tray>,
+    restarting: bool,
+    restoring: HashMap<Window, RestoredClient>, // windows being managed again after a restart
+    pending_manage: HashSet<Window>, // mapped windows that are checked once more after managing
+    motion: Option<Motion>,
+    swipe_edges: Vec<Window>,
+    settling: Option<(Atom, Atom)>, // monitor and the tag it showed before a run of switches
+    off_focus: Option<Atom>,        // the monitor focus was moved off of when it went off
+    fake_monitors: Option<Vec<(i16, i16, u16, u16)>>, // used instead of the randr monitors
+    presel_pins: Vec<PreselPin>,
 }
 
 impl WindowManager {
+    fn handle_timeouts(&mut self) -> Result<()> {
+        for timeout in self.aux.timers.expired()? {
+            match timeout {
+                Timeout::AnimationFrame => self.aux.animation_frame()?,
+                Timeout::PanelHide(win) => self.panel_hide(win)?,
+                Timeout::WindowWait(id) => self.aux.hooks.wait_expired(id),
+                Timeout::FocusFlash(win) => self.end_focus_flash(win)?,
+                Timeout::Urgent(win) => self.urgent_expired(win),
+                Timeout::Idle => self.aux.idle_expired()?,
+                Timeout::Presel => self.presel_expired()?,
+                Timeout::Manage(win) => self.manage_settled(win)?,
+                Timeout::Motion => self.motion_frame()?,
+                Timeout::TagSettle => self.settle_tag_switch()?,
+                Timeout::CloseFade(tag) => self.release_layout(tag)?,
+            }
+        }
+        Ok(())
+    }
+
     fn unmanage_window(&mut self, win: Window) -> Result<()> {
         if let Some(location) = self.windows.remove(&win) {
             //info!("unmanage window, {} {:?}", win, location);
@@ -83,6 +157,7 @@ impl WindowManager {
                 WindowLocation::Client(tag, client) => self.unmanage_client(tag, client)?,
                 WindowLocation::DesktopWindow(mon) => self.desktop_window_unregister(mon, win),
                 WindowLocation::Panel(mon) => self.panel_unregister(mon, win)?,
+                WindowLocation::TrayIcon => self.tray_remove(win)?,
                 _ => (),
             }
         }
@@ -102,52 +177,32 @@ impl WindowManager {
             ),
         )?;
         ungrab_key(&dpy, 0, root, ModMask::ANY)?;
-        ungrab_button(&dpy, ButtonIndex::ANY, root, ModMask::ANY)?;
-        let event_mask: u16 = u32::from(EventMask::BUTTON_PRESS) as u16;
-        for &_m in &IGNORED_MODS {
-            grab_button(
-                &dpy,
-                false,
-                root,
-                event_mask,
-                GrabMode::ASYNC,
-                GrabMode::ASYNC,
-                root,
-                NONE,
-                ButtonIndex::M3,
-                u16::from(ModMask::M4) | _m,
-            )?;
-            grab_button(
-                &dpy,
-                false,
-                root,
-                event_mask,
-                GrabMode::ASYNC,
-                GrabMode::ASYNC,
-                root,
-                NONE,
-                ButtonIndex::M1,
-                u16::from(ModMask::M4) | _m,
-            )?;
-            grab_button(
+        select_input(
+            &dpy,
+            root,
+            NotifyMask::SCREEN_CHANGE | NotifyMask::OUTPUT_CHANGE | NotifyMask::OUTPUT_PROPERTY,
+        )?;
+        // bells arrive as xkb events, without the extension only the request flashes
+        if matches!(xkb::use_extension(&dpy, 1, 0)?.reply(), Ok(reply) if reply.supported) {
+            xkb::select_events(
                 &dpy,
-                false,
-                root,
-                event_mask,
-                GrabMode::SYNC,
-                GrabMode::ASYNC,
-                root,
-                NONE,
-                ButtonIndex::M1,
-                _m,
+                xkb::ID::USE_CORE_KBD.into(),
+                0u16,
+                xkb::EventType::BELL_NOTIFY,
+                0u16,
+                0u16,
+                &xkb::SelectEventsAux::default(),
             )?;
         }
-
-        select_input(&dpy, root, NotifyMask::SCREEN_CHANGE)?;
         dpy.flush()?;
+        Self::with_dpy(Dpy::Rust(Box::new(dpy)), pref_screen, socket_path())
+    }
 
+    // everything after connecting, the display can be the mock
+    pub(crate) fn with_dpy(dpy: Dpy, screen: usize, socket: String) -> Result<Self> {
+        let root = dpy.setup().roots[screen].root;
         let wm = Self {
-            aux: Aux::new(dpy, root, pref_screen)?,
+            aux: Aux::new(dpy, root, screen, socket)?,
             monitors: HashMap::new(),
             tags: HashMap::new(),
             free_tags: HashSet::new(),
@@ -159,13 +214,118 @@ impl WindowManager {
             windows: HashMap::new(),
             running: true,
             supporting: false,
+            focus_lock: false,
+            dropdowns: HashMap::new(),
+            tray: None,
+            restarting: false,
+            restoring: HashMap::new(),
+            pending_manage: HashSet::new(),
+            motion: None,
+            swipe_edges: Vec::new(),
+            settling: None,
+            off_focus: None,
+            fake_monitors: None,
+            presel_pins: Vec::new(),
         };
+        wm.aux.grab_buttons()?;
 
         Ok(wm)
     }
 }
 
-pub fn run_wm() {
+impl Aux {
+    // drags and swipes use the configured bindings, a plain left click is grabbed to raise
+    // clients, frame buttons are grabbed synchronously so clicks inside the client can be
+    // replayed to it
+    pub fn grab_buttons(&self) -> Result<()> {
+        let dpy = &self.dpy;
+        let root = self.root;
+        let bindings = self.theme.pointer_bindings;
+        ungrab_button(dpy, ButtonIndex::ANY, root, ModMask::ANY)?;
+        let event_mask: u16 = u32::from(EventMask::BUTTON_PRESS) as u16;
+        for &_m in &IGNORED_MODS {
+            for &button in &[bindings.resize_button, bindings.move_button] {
+                grab_button(
+                    dpy,
+                    false,
+                    root,
+                    event_mask,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                    root,
+                    NONE,
+                    ButtonIndex::from(button),
+                    bindings.mods | _m,
+                )?;
+            }
+            grab_button(
+                dpy,
+                false,
+                root,
+                event_mask,
+                GrabMode::SYNC,
+                GrabMode::ASYNC,
+                root,
+                NONE,
+                ButtonIndex::M1,
+                _m,
+            )?;
+            for &(button, mods) in self.theme.frame_buttons.keys() {
+                if bindings.takes(button, mods) || (button, mods) == (1, 0) {
+                    continue;
+                }
+                grab_button(
+                    dpy,
+                    false,
+                    root,
+                    event_mask,
+                    GrabMode::SYNC,
+                    GrabMode::ASYNC,
+                    root,
+                    NONE,
+                    ButtonIndex::from(button),
+                    mods | _m,
+                )?;
+            }
+            if let Some((button, mods)) = self.theme.swipe.binding {
+                if !bindings.takes(button, mods) {
+                    grab_button(
+                        dpy,
+                        false,
+                        root,
+                        event_mask,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                        root,
+                        NONE,
+                        ButtonIndex::from(button),
+                        mods | _m,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the grabs are released only while a passthrough client is focused, called whenever the
+    // focus or the passthrough flag of a client changes. the wm grabs no keys, key bindings are
+    // left to a hotkey daemon, so the buttons are the only grabs to release
+    pub fn update_passthrough(&mut self, passthrough: bool) -> Result<()> {
+        if passthrough != self.grabs_released {
+            self.grabs_released = passthrough;
+            if passthrough {
+                info!("releasing grabs");
+                ungrab_button(&self.dpy, ButtonIndex::ANY, self.root, ModMask::ANY)?;
+            } else {
+                self.grab_buttons()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// restore is the state file left by an in-place restart
+pub fn run_wm(restore: Option<PathBuf>) {
     info!("CWM Starting");
     let mut wm = match WindowManager::new() {
         Ok(wm) => wm,
@@ -174,12 +334,32 @@ pub fn run_wm() {
             return
         }
     };
+    let state = restore.and_then(|path| match RestartState::load(&path) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            info!("Error: {:?}", e);
+            None
+        }
+    });
+    if let Some(state) = &state {
+        if let Err(e) = wm.restore_tags(state) {
+            info!("Error: {:?}", e);
+        }
+    }
     let mut event_handler = EventHandler::new();
     wm.aux.hooks.config();
+    if let Err(e) = wm.load_theme_file() {
+        info!("Error: {:?}", e);
+    }
     if let Err(e) = wm.update_monitors() {
         info!("Error: {:?}", e);
         return
     }
+    if let Some(state) = state {
+        if let Err(e) = wm.restore(state) {
+            info!("Error: {:?}", e);
+        }
+    }
 
     while wm.running {
         wm.aux.wait_for_updates();
@@ -188,17 +368,53 @@ pub fn run_wm() {
             info!("Error: {:?}", e);
             None
         }) {
+            wm.aux.metrics.event();
             let _ = event_handler.handle_event(&mut wm, event);
         }
 
+        if let Err(e) = wm.handle_timeouts() {
+            info!("Error: {:?}", e);
+            break
+        }
         if let Err(e) = wm.handle_connections() {
             info!("Error: {:?}", e);
-            return
+            break
+        }
+        if let Err(e) = wm.update_theme_file() {
+            info!("Error: {:?}", e);
+        }
+        if wm.restarting {
+            wm.restarting = false;
+            if let Err(e) = wm.restart() {
+                info!("Error: {:?}", e);
+            }
+        }
+        wm.update_tree_hooks();
+        wm.update_fullscreen_hooks();
+        wm.update_layout_hooks();
+        hooks::reap();
+        if let Err(e) = wm.update_marks() {
+            info!("Error: {:?}", e);
+            break
+        }
+        if let Err(e) = wm.update_focus_indicator() {
+            info!("Error: {:?}", e);
+            break
+        }
+        if let Err(e) = wm.collapse_small_tiles() {
+            info!("Error: {:?}", e);
+            break
         }
+        if let Err(e) = wm.remove_volatile_tags() {
+            info!("Error: {:?}", e);
+            break
+        }
+        wm.aux.flush_streams();
         if let Err(e) = wm.aux.dpy.flush() {
             info!("Error: {:?}", e);
-            return
+            break
         }
     }
+    wm.aux.hooks.wm_exit();
     info!("CWM Stopping");
 }
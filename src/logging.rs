@@ -0,0 +1,141 @@
+use log::{error, LevelFilter};
+use simplelog::*;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// the log is rotated once it grows past this size, keeping this many old files
+const MAX_SIZE: u64 = 4 * 1024 * 1024;
+const KEEP: usize = 3;
+// lines kept in memory for TailLog
+const RECENT_LINES: usize = 1000;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+// $XDG_STATE_HOME/cwm, falling back to ~/.local/state/cwm and then /tmp
+pub fn log_dir() -> PathBuf {
+    std::env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(|_| PathBuf::from("/tmp"))
+        .join("cwm")
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    line: String,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> Self {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .ok();
+        let size = file
+            .as_ref()
+            .and_then(|file| file.metadata().ok())
+            .map_or(0, |meta| meta.len());
+        Self {
+            path,
+            file,
+            size,
+            line: String::new(),
+        }
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) {
+        self.file.take();
+        for n in (1..KEEP).rev() {
+            let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+        let _ = fs::rename(&self.path, self.rotated(1));
+        *self = Self::open(self.path.clone());
+    }
+
+    fn remember(&mut self, buf: &[u8]) {
+        self.line.push_str(&String::from_utf8_lossy(buf));
+        while let Some(end) = self.line.find('\n') {
+            let line = self.line.drain(..=end).collect::<String>();
+            // a panic while the lock was held must not stop the panic hook from logging
+            let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+            if recent.len() == RECENT_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(line.trim_end().to_string());
+        }
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.remember(buf);
+        if let Some(file) = &mut self.file {
+            // written straight to the file so nothing is lost if the wm crashes
+            file.write_all(buf)?;
+            self.size += buf.len() as u64;
+        }
+        // only rotate between lines
+        if self.size > MAX_SIZE && buf.ends_with(b"\n") {
+            self.rotate();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// loggers accept everything, the level is controlled with log::set_max_level
+pub fn init(level: LevelFilter) {
+    let dir = log_dir();
+    let _ = fs::create_dir_all(&dir);
+    CombinedLogger::init(vec![
+        TermLogger::new(
+            LevelFilter::Trace,
+            Config::default(),
+            TerminalMode::Mixed,
+            ColorChoice::Auto,
+        ),
+        WriteLogger::new(
+            LevelFilter::Trace,
+            Config::default(),
+            LogFile::open(dir.join("cwm.log")),
+        ),
+    ])
+    .unwrap();
+    log::set_max_level(level);
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        error!("{}", info);
+        hook(info)
+    }));
+}
+
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+pub fn tail(n: usize) -> Vec<String> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent
+        .iter()
+        .skip(recent.len().saturating_sub(n))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,103 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::protocol::MetricsReport;
+use crate::WindowManager;
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+// counts things and how many happened in the last full second
+#[derive(Default)]
+struct Rate {
+    total: Cell<u64>,
+    window_start: Cell<Option<Instant>>,
+    window_count: Cell<u64>,
+    last: Cell<f32>,
+}
+
+impl Rate {
+    fn tick(&self, now: Instant) {
+        self.total.set(self.total.get() + 1);
+        self.roll(now);
+        self.window_count.set(self.window_count.get() + 1);
+    }
+
+    fn roll(&self, now: Instant) {
+        let start = *self.window_start.get().get_or_insert(now);
+        let elapsed = now - start;
+        if elapsed >= WINDOW {
+            // an idle gap longer than a window means nothing happened in the last one
+            let rate = if elapsed >= WINDOW * 2 {
+                0.0
+            } else {
+                self.window_count.get() as f32 / elapsed.as_secs_f32()
+            };
+            self.last.set(rate);
+            self.window_count.set(0);
+            self.window_start.set(Some(now));
+        } else {
+            self.window_start.set(Some(start));
+        }
+    }
+
+    fn per_second(&self, now: Instant) -> f32 {
+        self.roll(now);
+        self.last.get()
+    }
+}
+
+// cells so tag code can record through a shared Aux
+#[derive(Default)]
+pub struct Metrics {
+    events: Rate,
+    requests: Rate,
+    relayouts: Cell<u64>,
+    relayout_total: Cell<Duration>,
+    relayout_max: Cell<Duration>,
+}
+
+impl Metrics {
+    pub fn event(&self) {
+        self.events.tick(Instant::now());
+    }
+
+    pub fn request(&self) {
+        self.requests.tick(Instant::now());
+    }
+
+    pub fn relayout(&self, start: Instant) {
+        let took = start.elapsed();
+        self.relayouts.set(self.relayouts.get() + 1);
+        self.relayout_total.set(self.relayout_total.get() + took);
+        self.relayout_max.set(self.relayout_max.get().max(took));
+    }
+}
+
+impl WindowManager {
+    pub fn metrics(&self) -> MetricsReport {
+        let metrics = &self.aux.metrics;
+        let now = Instant::now();
+        let relayouts = metrics.relayouts.get();
+        let clients = self
+            .tag_order
+            .iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .map(|tag| (tag.name.clone(), tag.used_clients().len()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        MetricsReport {
+            clients,
+            events: metrics.events.total.get(),
+            events_per_sec: metrics.events.per_second(now),
+            requests: metrics.requests.total.get(),
+            requests_per_sec: metrics.requests.per_second(now),
+            relayouts,
+            relayout_avg_us: if relayouts == 0 {
+                0
+            } else {
+                (metrics.relayout_total.get().as_micros() / relayouts as u128) as u64
+            },
+            relayout_max_us: metrics.relayout_max.get().as_micros() as u64,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// a wm running on the mock display for unit tests
+use std::sync::atomic::{AtomicUsize, Ordering};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::Window;
+
+use crate::display::{Dpy, MockConnection};
+use crate::hooks::Hooks;
+use crate::WindowManager;
+
+static NEXT_SOCKET: AtomicUsize = AtomicUsize::new(0);
+
+// monitors are x, y, width and height, the first one is focused
+pub fn wm(monitors: &[(i16, i16, u16, u16)]) -> WindowManager {
+    let socket = std::env::temp_dir().join(format!(
+        "cwm-unit-{}-{}.sock",
+        std::process::id(),
+        NEXT_SOCKET.fetch_add(1, Ordering::Relaxed)
+    ));
+    let width = monitors
+        .iter()
+        .map(|mon| mon.0 as u16 + mon.2)
+        .max()
+        .unwrap_or(0);
+    let height = monitors
+        .iter()
+        .map(|mon| mon.1 as u16 + mon.3)
+        .max()
+        .unwrap_or(0);
+    let mock = MockConnection::new(width, height);
+    mock.set_monitors(monitors);
+    let dpy = Dpy::Mock(Box::new(mock));
+    let mut wm = WindowManager::with_dpy(dpy, 0, socket.to_string_lossy().into_owned()).unwrap();
+    // the hook scripts of whoever runs the tests stay out of it
+    wm.aux.hooks = Hooks::default();
+    wm.update_monitors().unwrap();
+    wm
+}
+
+pub fn mock(wm: &WindowManager) -> &MockConnection {
+    wm.aux.dpy.mock().unwrap()
+}
+
+// a new application window, managed the way a map request would
+pub fn map(wm: &mut WindowManager, class: &str) -> Window {
+    let win = wm.aux.dpy.generate_id().unwrap();
+    let class = format!("{0}\0{0}\0", class);
+    mock(wm).create_window(win);
+    mock(wm).set_text_property(win, "WM_CLASS", "STRING", class.as_bytes());
+    let mon = wm.focused_monitor;
+    wm.manage_window(mon, win).unwrap();
+    win
+}
@@ -1,11 +1,25 @@
 use anyhow::Result;
+use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
 use x11rb::protocol::xproto::*;
+use x11rb::NONE;
 
 use super::{WindowLocation, WindowManager};
+use crate::Aux;
 
 #[derive(Debug)]
 pub struct DesktopWindow {}
 
+// an empty input shape lets clicks through, no mask restores the default
+fn set_click_through(aux: &Aux, win: Window) -> Result<()> {
+    if aux.theme.desktop_click_through {
+        aux.dpy
+            .shape_rectangles(SO::SET, SK::INPUT, ClipOrdering::UNSORTED, win, 0, 0, &[])?;
+    } else {
+        aux.dpy.shape_mask(SO::SET, SK::INPUT, win, 0, 0, NONE)?;
+    }
+    Ok(())
+}
+
 impl WindowManager {
     pub fn desktop_window_register(&mut self, mon: Atom, win: Window) -> Result<()> {
         self.monitors
@@ -13,11 +27,21 @@ impl WindowManager {
             .unwrap()
             .desktop_windows
             .insert(win, DesktopWindow {});
+        set_click_through(&self.aux, win)?;
         map_window(&self.aux.dpy, win)?;
         self.windows.insert(win, WindowLocation::DesktopWindow(mon));
         Ok(())
     }
 
+    pub fn update_desktop_windows(&self) -> Result<()> {
+        for mon in self.monitors.values() {
+            for win in mon.desktop_windows.keys() {
+                set_click_through(&self.aux, *win)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn desktop_window_unregister(&mut self, mon: Atom, win: Window) {
         self.monitors
             .get_mut(&mon)
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use log::info;
+use x11rb::protocol::randr::MonitorInfo;
+use x11rb::protocol::xproto::*;
+
+use crate::WindowManager;
+
+impl WindowManager {
+    // none goes back to the monitors randr reports
+    pub fn set_fake_monitors(&mut self, rects: Option<Vec<(i16, i16, u16, u16)>>) -> Result<()> {
+        match &rects {
+            Some(rects)
+                if rects.is_empty() || rects.iter().any(|rect| rect.2 == 0 || rect.3 == 0) =>
+            {
+                info!("ignoring invalid fake monitors {:?}", rects);
+                return Ok(());
+            }
+            Some(rects) => info!("faking monitors {:?}", rects),
+            None => info!("using randr monitors"),
+        }
+        self.fake_monitors = rects;
+        self.update_monitors()
+    }
+
+    // named FAKE-<n> in the order given, the first one is primary
+    pub(super) fn fake_monitor_info(&self) -> Result<Option<Vec<MonitorInfo>>> {
+        let rects = match &self.fake_monitors {
+            Some(rects) => rects,
+            None => return Ok(None),
+        };
+        let mut monitors = Vec::new();
+        for (i, &(x, y, width, height)) in rects.iter().enumerate() {
+            let name = format!("FAKE-{}", i);
+            monitors.push(MonitorInfo {
+                name: intern_atom(&self.aux.dpy, false, name.as_bytes())?
+                    .reply()?
+                    .atom,
+                primary: i == 0,
+                automatic: false,
+                x,
+                y,
+                width,
+                height,
+                width_in_millimeters: 0,
+                height_in_millimeters: 0,
+                outputs: Vec::new(),
+            });
+        }
+        Ok(Some(monitors))
+    }
+}
@@ -1,16 +1,25 @@
-use super::{tag::ClientArgs, WindowLocation, WindowManager};
+use super::{core::ClientArgs, WindowLocation, WindowManager};
+use crate::config::TagSwitchMode;
 use crate::connections::{Aux, SetArg};
+use crate::timer::Timeout;
 use crate::utils::{pop_set_ord, Rect};
 use anyhow::Result;
 use log::info;
 use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 use x11rb::connection::Connection;
 use x11rb::protocol::{randr::*, xproto::*};
 use x11rb::wrapper::ConnectionExt as _;
 use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT};
 
+// how long a newly managed window has to be destroyed in before it is checked on
+const MANAGE_CHECK: Duration = Duration::from_secs(1);
+
 mod desktop_window;
+mod fake;
 mod panel;
+mod power;
+mod swipe;
 use desktop_window::DesktopWindow;
 use panel::Panel;
 
@@ -25,11 +34,12 @@ pub struct Monitor {
     pub sticky: HashSet<usize>,
     pub size: Rect,
     pub bg: Window,
+    pub outputs: Vec<Output>,
 }
 
 #[derive(Debug)]
 pub(crate) enum ProcessWindow {
-    Client(ClientArgs),
+    Client(Box<ClientArgs>),
     Panel,
     Desktop,
 }
@@ -63,6 +73,11 @@ impl ProcessWindow {
     }
 }
 
+// mirrored outputs show the same part of the screen, outputs that only overlap are distinct
+fn mirrored(a: &MonitorInfo, b: &MonitorInfo) -> bool {
+    (a.x, a.y, a.width, a.height) == (b.x, b.y, b.width, b.height)
+}
+
 impl WindowManager {
     pub fn manage_window(&mut self, mon: Atom, win: Window) -> Result<()> {
         let type_cookie = get_property(
@@ -73,9 +88,8 @@ impl WindowManager {
             AtomEnum::ATOM,
             0,
             2048,
-        )
-        .unwrap();
-        let mut args = ProcessWindow::Client(ClientArgs::new(&self.aux));
+        )?;
+        let mut args = ProcessWindow::Client(Box::new(ClientArgs::new(&self.aux)));
         if let Ok(states) = type_cookie.reply() {
             if let Some(states) = states.value32() {
                 for state in states {
@@ -87,7 +101,15 @@ impl WindowManager {
         match args {
             ProcessWindow::Client(mut args) => {
                 self.process_args(win, &mut args)?;
-                self.manage_client(win, args)?;
+                if let Some(client) = self.restoring.get(&win) {
+                    args.restore(client);
+                }
+                self.presel_claim(win, &mut args)?;
+                let dropdown = self.dropdown_claim(win, &mut args)?;
+                self.manage_client(win, *args)?;
+                if dropdown {
+                    self.dropdown_show(win)?;
+                }
             }
             ProcessWindow::Desktop => self.desktop_window_register(mon, win)?,
             ProcessWindow::Panel => self.panel_register(mon, win)?,
@@ -95,6 +117,55 @@ impl WindowManager {
         Ok(())
     }
 
+    // a manage that fails part way through, usually because the window was destroyed meanwhile,
+    // drops what was already added again. a window destroyed right after is checked on later
+    pub fn manage_requested(&mut self, mon: Atom, win: Window) -> Result<()> {
+        let res = self.manage_window(mon, win);
+        if res.is_err() {
+            info!("managing window {} failed", win);
+            // the client is added to its tag before the window is tracked
+            if let Some((tag, client)) = self.untracked_client(win) {
+                self.windows
+                    .insert(win, WindowLocation::Client(tag, client));
+            }
+            self.unmanage_window(win)?;
+        } else {
+            self.pending_manage.insert(win);
+            self.aux.timers.set(Timeout::Manage(win), MANAGE_CHECK)?;
+        }
+        res
+    }
+
+    fn untracked_client(&self, win: Window) -> Option<(Atom, usize)> {
+        if self.windows.contains_key(&win) {
+            return None;
+        }
+        self.tags.values().find_map(|tag| {
+            tag.used_clients()
+                .into_iter()
+                .find(|client| tag.client(*client).win == win)
+                .map(|client| (tag.id, client))
+        })
+    }
+
+    // the destroy notify cleans up after the window itself
+    pub fn manage_cancel(&mut self, win: Window) -> Result<()> {
+        if self.pending_manage.remove(&win) {
+            self.aux.timers.cancel(Timeout::Manage(win))?;
+        }
+        Ok(())
+    }
+
+    pub fn manage_settled(&mut self, win: Window) -> Result<()> {
+        if self.pending_manage.remove(&win)
+            && get_window_attributes(&self.aux.dpy, win)?.reply().is_err()
+        {
+            info!("window {} went away while being managed", win);
+            self.unmanage_window(win)?;
+        }
+        Ok(())
+    }
+
     // maybe make sure that focused tag isn't currently viewed (that would break things)
     pub fn add_monitor(&mut self, tag: Option<Atom>, monitor: MonitorInfo) -> Result<Atom> {
         let id = monitor.name;
@@ -102,7 +173,8 @@ impl WindowManager {
             self.focused_monitor = id;
             self.prev_monitor = id;
         }
-        let name = String::from_utf8(get_atom_name(&self.aux.dpy, id)?.reply()?.name).unwrap();
+        let name =
+            String::from_utf8_lossy(&get_atom_name(&self.aux.dpy, id)?.reply()?.name).into_owned();
         let bg = self.aux.dpy.generate_id()?;
         let monitor = Monitor {
             id,
@@ -114,14 +186,19 @@ impl WindowManager {
             desktop_windows: HashMap::new(),
             sticky: HashSet::new(),
             bg,
+            outputs: monitor.outputs,
         };
         info!(" monitor: {:?}", monitor);
+        self.aux
+            .theme
+            .monitor_sizes
+            .insert(id, monitor.size.width.min(monitor.size.height));
         let tag = tag
             .or_else(|| pop_set_ord(&mut self.free_tags, &self.tag_order))
             .map_or_else(|| self.temp_tag(), Ok)?;
         self.monitors.insert(id, monitor);
         // self.focused_monitor = id;
-        self.set_monitor_tag(id, tag)?;
+        self.set_monitor_tag(id, tag, TagSwitchMode::Swap)?;
         let monitor = self.monitors.get_mut(&id).unwrap();
         monitor.prev_tag = tag;
         let aux = CreateWindowAux::new().event_mask(EventMask::ENTER_WINDOW);
@@ -163,35 +240,113 @@ impl WindowManager {
         }
         map_window(&self.aux.dpy, bg)?;
         self.windows.insert(bg, WindowLocation::Monitor(id));
-        self.aux.hooks.mon_open(id, &monitor.name, bg);
+        let tag = &self.tags.get(&monitor.focused_tag).unwrap().name;
+        self.aux.hooks.mon_open(monitor, tag);
         Ok(id)
     }
 
-    pub fn switch_monitor_tag(&mut self, mon: Atom, tag: SetArg<Atom>) -> Result<()> {
+    pub fn switch_monitor_tag(
+        &mut self,
+        mon: Atom,
+        tag: SetArg<Atom>,
+        mode: TagSwitchMode,
+    ) -> Result<()> {
+        let switched = self.show_monitor_tag(mon, tag, mode)?;
+        self.focus_monitor_tag(mon, switched)
+    }
+
+    // rapid switches only show the tags, focusing and the tag hooks wait for the last one
+    pub fn switch_monitor_tag_settled(
+        &mut self,
+        mon: Atom,
+        tag: SetArg<Atom>,
+        mode: TagSwitchMode,
+    ) -> Result<()> {
+        if !matches!(self.settling, Some((settling, _)) if settling == mon) {
+            self.settle_tag_switch()?;
+            if let Some(from) = self.monitors.get(&mon).map(|x| x.focused_tag) {
+                self.settling = Some((mon, from));
+                self.aux.hooks.settling = true;
+            }
+        }
+        self.show_monitor_tag(mon, tag, mode)?;
+        let settle = Duration::from_millis(self.aux.theme.focus_settle_ms.into());
+        self.aux.timers.set(Timeout::TagSettle, settle)
+    }
+
+    pub fn settle_tag_switch(&mut self) -> Result<()> {
+        if let Some((mon, from)) = self.settling.take() {
+            self.aux.timers.cancel(Timeout::TagSettle)?;
+            self.aux.hooks.settling = false;
+            if let Some(monitor) = self.monitors.get(&mon) {
+                let tag = monitor.focused_tag;
+                if tag != from && self.tags.contains_key(&from) {
+                    self.aux.hooks.tag_switched(
+                        monitor,
+                        &self.tags.get(&tag).unwrap().name,
+                        &self.tags.get(&from).unwrap().name,
+                    );
+                }
+                self.focus_monitor_tag(mon, tag != from)?;
+            }
+            self.aux
+                .hooks
+                .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
+        }
+        Ok(())
+    }
+
+    // true if the monitor shows another tag now
+    fn show_monitor_tag(
+        &mut self,
+        mon: Atom,
+        tag: SetArg<Atom>,
+        mode: TagSwitchMode,
+    ) -> Result<bool> {
         if let Some((mut focused_tag, prev_tag)) =
             self.monitors.get(&mon).map(|x| (x.focused_tag, x.prev_tag))
         {
             if tag.apply_arg(&mut focused_tag, prev_tag) {
-                self.set_monitor_tag(mon, focused_tag)?;
-                self.aux.selection.hide(
-                    &self.aux.dpy,
-                    self.monitors.get(&mon).map(|x| x.prev_tag),
-                    None,
-                )?;
+                self.set_monitor_tag(mon, focused_tag, mode)?;
+                return Ok(true);
             }
         }
+        Ok(false)
+    }
+
+    fn focus_monitor_tag(&mut self, mon: Atom, switched: bool) -> Result<()> {
         if self.focused_monitor == mon {
             let tag = self.tags.get_mut(&self.focused_tag()).unwrap();
             tag.set_focus(&mut self.aux)?;
+            if switched {
+                self.flash_focus()?;
+            }
+        } else if let Some(mon) = self.monitors.get(&mon) {
+            self.tags
+                .get(&mon.focused_tag)
+                .unwrap()
+                .show_inactive(&self.aux)?;
         }
         Ok(())
     }
 
-    pub fn set_monitor_tag(&mut self, mon: Atom, tag: Atom) -> Result<()> {
+    pub fn set_monitor_tag(&mut self, mon: Atom, tag: Atom, mode: TagSwitchMode) -> Result<()> {
         let old_tag = self.monitors.get(&mon).unwrap().focused_tag;
         if old_tag == tag {
             return Ok(());
         }
+        if let Some(old_mon) = self.tags.get(&tag).unwrap().monitor {
+            match mode {
+                TagSwitchMode::Swap => (),
+                TagSwitchMode::Greedy => {
+                    // hand the other monitor a free tag so no swap happens below
+                    let free = pop_set_ord(&mut self.free_tags, &self.tag_order)
+                        .map_or_else(|| self.temp_tag(), Ok)?;
+                    self.set_monitor_tag(old_mon, free, TagSwitchMode::Swap)?;
+                }
+                TagSwitchMode::Noop => return self.set_focus(old_mon),
+            }
+        }
         let old_valid = if let Some(tag) = self.tags.get_mut(&old_tag) {
             tag.hide(&mut self.aux)?;
             true
@@ -241,6 +396,15 @@ impl WindowManager {
                 *client = self.move_client(old_tag, *client, SetArg(tag, false))?
             }
             self.monitors.get_mut(&mon).unwrap().sticky.extend(sticky);
+            self.tags
+                .get(&tag)
+                .unwrap()
+                .follow_selection(&mut self.aux, old_tag)?;
+            self.aux.hooks.tag_switched(
+                self.monitors.get(&mon).unwrap(),
+                &self.tags.get(&tag).unwrap().name,
+                &self.tags.get(&old_tag).unwrap().name,
+            );
         }
         self.aux
             .hooks
@@ -251,20 +415,28 @@ impl WindowManager {
     pub fn remove_monitor(&mut self, mon: Atom) -> Result<()> {
         if let Some(mon) = self.monitors.remove(&mon) {
             info!("removing mon {} {}", mon.name, mon.id);
+            self.aux.sleeping.remove(&mon.id);
+            self.aux.theme.monitor_sizes.remove(&mon.id);
+            if self.off_focus == Some(mon.id) {
+                self.off_focus = None;
+            }
             self.windows.remove(&mon.bg);
             destroy_window(&self.aux.dpy, mon.bg)?;
             let tag = self.tags.get_mut(&mon.focused_tag).unwrap();
-            for client in mon.sticky {
-                tag.client_mut(client).flags.sticky = false;
+            for client in &mon.sticky {
+                tag.client_mut(*client).flags.sticky = false;
             }
             self.tags
                 .get_mut(&mon.focused_tag)
                 .unwrap()
                 .hide(&mut self.aux)?;
             self.free_tags.insert(mon.focused_tag);
-            self.aux.hooks.mon_close(mon.id, &mon.name);
+            let tag = &self.tags.get(&mon.focused_tag).unwrap().name;
+            self.aux.hooks.mon_close(&mon, tag);
         }
-        if self.tags.len() > self.monitors.len() {
+        // when every monitor is replaced the clients of a removed tag have nowhere to go,
+        // the free tags left over are taken by the new monitors instead
+        if !self.monitors.is_empty() && self.tags.len() > self.monitors.len() {
             if let Some(tag) = self
                 .temp_tags
                 .iter()
@@ -280,50 +452,101 @@ impl WindowManager {
 
     pub fn update_monitor(&mut self, info: MonitorInfo) -> Result<()> {
         let mon = self.monitors.get_mut(&info.name).unwrap();
-        mon.size = Rect::new(info.x, info.y, info.width, info.height);
+        let old = std::mem::replace(
+            &mut mon.size,
+            Rect::new(info.x, info.y, info.width, info.height),
+        );
+        mon.outputs = info.outputs;
+        self.aux
+            .theme
+            .monitor_sizes
+            .insert(mon.id, mon.size.width.min(mon.size.height));
         configure_window(&self.aux.dpy, mon.bg, &mon.size.aux(0))?;
         self.tags.get_mut(&mon.focused_tag).unwrap().resize_all(
             &self.aux,
             &mon.free_rect(),
             &mon.size,
-        )
+        )?;
+        self.panels_follow_monitor(info.name, &old)?;
+        self.pip_reanchor(info.name)
     }
 
     pub fn update_monitors(&mut self) -> Result<()> {
-        let monitors = get_monitors(&self.aux.dpy, self.aux.root, true)?.reply()?;
-        let mut new_mons = Vec::new();
-        let mut keep_monitors = HashSet::new();
-        for mon in monitors.monitors.into_iter() {
-            if self.monitors.contains_key(&mon.name) {
-                keep_monitors.insert(mon.name);
-                self.update_monitor(mon)?;
-            } else {
-                new_mons.push(mon);
+        let monitors = match self.fake_monitor_info()? {
+            Some(monitors) => monitors,
+            None => {
+                get_monitors(&self.aux.dpy, self.aux.root, true)?
+                    .reply()?
+                    .monitors
+            }
+        };
+        // with every output off there is nowhere to move the tags to
+        if monitors.is_empty() && !self.monitors.is_empty() {
+            info!("no monitors reported, keeping the current ones");
+            return Ok(());
+        }
+        // mirrored outputs cover the same area and only one monitor is kept for each area,
+        // the focused one, then any that already exists, then the primary one
+        let mut shown: Vec<MonitorInfo> = Vec::new();
+        for mon in monitors.into_iter() {
+            match shown.iter_mut().find(|other| mirrored(other, &mon)) {
+                Some(other) => {
+                    let exists = self.monitors.contains_key(&mon.name);
+                    let replace = if mon.name == self.focused_monitor {
+                        exists
+                    } else if self.monitors.contains_key(&other.name) {
+                        false
+                    } else {
+                        exists || mon.primary
+                    };
+                    info!(
+                        "monitor {} mirrors {}",
+                        if replace { other.name } else { mon.name },
+                        if replace { mon.name } else { other.name }
+                    );
+                    if replace {
+                        *other = mon;
+                    }
+                }
+                None => shown.push(mon),
             }
         }
+        let keep: HashSet<_> = shown.iter().map(|mon| mon.name).collect();
         let remove: Vec<_> = self
             .monitors
             .keys()
-            .filter(|x| !keep_monitors.contains(x))
+            .filter(|x| !keep.contains(x))
             .cloned()
             .collect();
+        // focus moves off a monitor before it goes away
+        if remove.contains(&self.focused_monitor) {
+            if let Some(mon) = keep
+                .iter()
+                .filter(|mon| self.monitors.contains_key(mon))
+                .min()
+                .copied()
+            {
+                self.set_focus(mon)?;
+            }
+        }
         for mon in remove {
             self.remove_monitor(mon)?;
         }
-        for mon in new_mons {
-            let size = Rect::new(mon.x, mon.y, mon.width, mon.height);
-            let mut keep = true;
-            for other in self.monitors.values() {
-                if other.size == size {
-                    keep = false;
-                    break;
-                }
-            }
-            if keep {
+        for mon in shown {
+            if self.monitors.contains_key(&mon.name) {
+                self.update_monitor(mon)?;
+            } else {
                 self.add_monitor(None, mon)?;
             }
         }
-        Ok(())
+        // no monitor that was kept existed before
+        if !self.monitors.contains_key(&self.focused_monitor) {
+            if let Some(mon) = self.monitors.keys().min().copied() {
+                self.set_focus(mon)?;
+            }
+        }
+        self.update_monitor_power()?;
+        self.update_swipe_edges()
     }
 
     pub fn set_sticky(&mut self, tag: Atom, client: usize, arg: &SetArg<bool>) {
@@ -366,3 +589,80 @@ impl WindowManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::Window;
+
+    use crate::display::MockConnection;
+    use crate::mock;
+    use crate::WindowManager;
+
+    fn tiled(wm: &WindowManager) -> usize {
+        wm.tags.get(&wm.focused_tag()).unwrap().client_count()
+    }
+
+    // a manage is cut short after every number of requests it sends, each time the window has
+    // to be either kept or gone again once the manage has failed or the check after it has run,
+    // and the window that was there already has to be as it was. returns how many manages failed
+    fn cut_short(cut: impl Fn(&MockConnection, Window, usize)) -> usize {
+        let total = {
+            let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+            mock::map(&mut wm, "a");
+            mock::mock(&wm).take_requests();
+            mock::map(&mut wm, "b");
+            mock::mock(&wm).take_requests().len()
+        };
+        let mut failed = 0;
+        for requests in 0..total {
+            let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+            let a = mock::map(&mut wm, "a");
+            let (tag, client) = wm.get_client(Some(a)).unwrap();
+            let whole = wm.tags.get(&tag).unwrap().get_client_rect(client).clone();
+            let win = wm.aux.dpy.generate_id().unwrap();
+            let mock = mock::mock(&wm);
+            mock.set_text_property(win, "WM_CLASS", "STRING", b"b\0b\0");
+            cut(mock, win, requests);
+            let mon = wm.focused_monitor;
+            let managed = wm.manage_requested(mon, win).is_ok();
+            let at = format!("after {} requests", requests);
+            if !managed {
+                failed += 1;
+                assert!(!wm.windows.contains_key(&win), "{}", at);
+            }
+            wm.manage_settled(win).unwrap();
+            if managed && wm.windows.contains_key(&win) {
+                assert_eq!(tiled(&wm), 2, "{}", at);
+                continue;
+            }
+            assert_eq!(tiled(&wm), 1, "{}", at);
+            let (tag, client) = wm.get_client(Some(a)).unwrap();
+            let tag = wm.tags.get(&tag).unwrap();
+            assert_eq!(tag.get_client_rect(client), &whole, "{}", at);
+            assert_eq!(tag.focused_client(), Some(client), "{}", at);
+        }
+        failed
+    }
+
+    #[test]
+    fn a_window_destroyed_while_being_managed_leaves_nothing_behind() {
+        let failed = cut_short(|mock, win, requests| mock.destroy_window_after(win, requests));
+        assert!(failed > 0);
+    }
+
+    #[test]
+    fn a_manage_that_fails_leaves_nothing_behind() {
+        let failed = cut_short(|mock, _, requests| mock.fail_after(requests));
+        assert!(failed > 0);
+    }
+
+    #[test]
+    fn a_window_that_stays_is_kept_after_the_check() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let a = mock::map(&mut wm, "a");
+        wm.manage_settled(a).unwrap();
+        assert!(wm.get_client(Some(a)).is_some());
+        assert_eq!(tiled(&wm), 1);
+    }
+}
@@ -1,15 +1,34 @@
 use anyhow::Result;
 use log::info;
+use std::time::Duration;
+use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
+use x11rb::COPY_FROM_PARENT;
 
 use super::Monitor;
+use crate::core::Side;
+use crate::timer::Timeout;
 use crate::utils::Rect;
 use crate::{Aux, WindowLocation, WindowManager};
 
+const OFFSCREEN: i32 = -0x4000;
+// how long the pointer has to be away from a revealed panel before it is hidden again
+const HIDE_DELAY: Duration = Duration::from_millis(800);
+// thickness of the window at the monitor edge that reveals the panel
+const EDGE_SIZE: u16 = 2;
+
 #[derive(Debug)]
 pub struct Panel {
     win: Window,
     wm_strut: WMStrut,
+    auto_hide: Option<AutoHide>,
+}
+
+#[derive(Debug)]
+struct AutoHide {
+    edge: Window,
+    home: Rect,
+    hidden: bool,
 }
 
 #[derive(PartialEq, Default, Debug)]
@@ -23,7 +42,11 @@ struct WMStrut {
 impl Panel {
     fn new(aux: &Aux, win: Window) -> Result<Self> {
         let wm_strut = WMStrut::new(aux, win)?;
-        Ok(Self { win, wm_strut })
+        Ok(Self {
+            win,
+            wm_strut,
+            auto_hide: None,
+        })
     }
 
     fn update_reserved_space(&mut self, aux: &Aux) -> Result<bool> {
@@ -37,6 +60,23 @@ impl Panel {
     }
 }
 
+// just above the background, or above every client
+fn stack_panel(aux: &Aux, mon: &Monitor, win: Window) -> Result<()> {
+    let sibling = if aux.theme.panels_above_fullscreen {
+        aux.panel_anchor
+    } else {
+        mon.bg
+    };
+    configure_window(
+        &aux.dpy,
+        win,
+        &ConfigureWindowAux::new()
+            .sibling(sibling)
+            .stack_mode(StackMode::ABOVE),
+    )?;
+    Ok(())
+}
+
 impl WindowManager {
     pub fn panel_changed(&mut self, mon: Atom) -> Result<()> {
         let mon = self.monitors.get(&mon).unwrap();
@@ -64,13 +104,7 @@ impl WindowManager {
                 win,
                 &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
             )?;
-            configure_window(
-                &self.aux.dpy,
-                win,
-                &ConfigureWindowAux::new()
-                    .sibling(mon.bg)
-                    .stack_mode(StackMode::ABOVE),
-            )?;
+            stack_panel(&self.aux, mon, win)?;
             map_window(&self.aux.dpy, win)?;
         }
         self.panel_changed(mon)?;
@@ -78,13 +112,24 @@ impl WindowManager {
         Ok(())
     }
 
+    pub fn restack_panels(&self) -> Result<()> {
+        for mon in self.monitors.values() {
+            for win in mon.panels.keys() {
+                stack_panel(&self.aux, mon, *win)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn panel_unregister(&mut self, mon: Atom, win: Window) -> Result<()> {
-        if self
+        if let Some(panel) = self
             .monitors
             .get_mut(&mon)
             .and_then(|mon| mon.panels.remove(&win))
-            .is_some()
         {
+            if let Some(auto_hide) = panel.auto_hide {
+                self.remove_panel_edge(win, auto_hide.edge)?;
+            }
             change_window_attributes(
                 &self.aux.dpy,
                 win,
@@ -95,6 +140,175 @@ impl WindowManager {
         Ok(())
     }
 
+    fn remove_panel_edge(&mut self, win: Window, edge: Window) -> Result<()> {
+        self.aux.timers.cancel(Timeout::PanelHide(win))?;
+        self.windows.remove(&edge);
+        destroy_window(&self.aux.dpy, edge)?;
+        Ok(())
+    }
+
+    pub fn panel_auto_hide(&mut self, win: Window, enable: bool) -> Result<()> {
+        let mon = if let Some(WindowLocation::Panel(mon)) = self.windows.get(&win) {
+            *mon
+        } else {
+            return Ok(());
+        };
+        let monitor = self.monitors.get_mut(&mon).unwrap();
+        let panel = monitor.panels.get_mut(&win).unwrap();
+        match (enable, panel.auto_hide.take()) {
+            (true, None) => {
+                info!("panel {} auto hide enabled", win);
+                let home: Rect = get_geometry(&self.aux.dpy, win)?.reply()?.into();
+                let edge_rect = edge_rect(&monitor.size, &home);
+                let edge = self.aux.dpy.generate_id()?;
+                create_window(
+                    &self.aux.dpy,
+                    0,
+                    edge,
+                    self.aux.root,
+                    edge_rect.x,
+                    edge_rect.y,
+                    edge_rect.width,
+                    edge_rect.height,
+                    0,
+                    WindowClass::INPUT_ONLY,
+                    COPY_FROM_PARENT,
+                    &CreateWindowAux::new()
+                        .override_redirect(1)
+                        .event_mask(EventMask::ENTER_WINDOW),
+                )?;
+                map_window(&self.aux.dpy, edge)?;
+                change_window_attributes(
+                    &self.aux.dpy,
+                    win,
+                    &ChangeWindowAttributesAux::new().event_mask(
+                        EventMask::PROPERTY_CHANGE
+                            | EventMask::ENTER_WINDOW
+                            | EventMask::LEAVE_WINDOW,
+                    ),
+                )?;
+                panel.auto_hide.replace(AutoHide {
+                    edge,
+                    home,
+                    hidden: false,
+                });
+                self.windows.insert(edge, WindowLocation::PanelEdge(win));
+                self.panel_changed(mon)?;
+                self.panel_hide(win)?;
+            }
+            (false, Some(auto_hide)) => {
+                info!("panel {} auto hide disabled", win);
+                configure_window(
+                    &self.aux.dpy,
+                    win,
+                    &ConfigureWindowAux::new()
+                        .x(auto_hide.home.x as i32)
+                        .y(auto_hide.home.y as i32),
+                )?;
+                change_window_attributes(
+                    &self.aux.dpy,
+                    win,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                )?;
+                self.remove_panel_edge(win, auto_hide.edge)?;
+                self.panel_changed(mon)?;
+            }
+            (_, auto_hide) => panel.auto_hide = auto_hide,
+        }
+        Ok(())
+    }
+
+    // a hidden panel keeps its place on a monitor that moved, a shown one is wherever its client
+    // put it. either way the edge that reveals it goes along the monitor again
+    pub fn panels_follow_monitor(&mut self, mon: Atom, old: &Rect) -> Result<()> {
+        let monitor = self.monitors.get_mut(&mon).unwrap();
+        if monitor.size == *old {
+            return Ok(());
+        }
+        for (win, panel) in monitor.panels.iter_mut() {
+            if let Some(auto_hide) = &mut panel.auto_hide {
+                if auto_hide.hidden {
+                    auto_hide.home.x += monitor.size.x - old.x;
+                    auto_hide.home.y += monitor.size.y - old.y;
+                    auto_hide.home.width = auto_hide.home.width.min(monitor.size.width);
+                    auto_hide.home.height = auto_hide.home.height.min(monitor.size.height);
+                    monitor.size.clamp(&mut auto_hide.home);
+                } else {
+                    auto_hide.home = get_geometry(&self.aux.dpy, *win)?.reply()?.into();
+                }
+                let edge = edge_rect(&monitor.size, &auto_hide.home);
+                configure_window(&self.aux.dpy, auto_hide.edge, &edge.aux(0))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn auto_hide_mut(&mut self, win: Window) -> Option<(&Aux, &mut AutoHide)> {
+        if let Some(WindowLocation::Panel(mon)) = self.windows.get(&win) {
+            let aux = &self.aux;
+            self.monitors
+                .get_mut(mon)
+                .and_then(|mon| mon.panels.get_mut(&win))
+                .and_then(|panel| panel.auto_hide.as_mut())
+                .map(|auto_hide| (aux, auto_hide))
+        } else {
+            None
+        }
+    }
+
+    pub fn panel_hide(&mut self, win: Window) -> Result<()> {
+        if let Some((aux, auto_hide)) = self.auto_hide_mut(win) {
+            let dpy = &aux.dpy;
+            if !auto_hide.hidden {
+                auto_hide.hidden = true;
+                configure_window(
+                    dpy,
+                    win,
+                    &ConfigureWindowAux::new().x(OFFSCREEN).y(OFFSCREEN),
+                )?;
+                // clients raised since the last reveal may cover the edge
+                configure_window(
+                    dpy,
+                    auto_hide.edge,
+                    &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn panel_reveal(&mut self, win: Window) -> Result<()> {
+        if let Some((aux, auto_hide)) = self.auto_hide_mut(win) {
+            if auto_hide.hidden {
+                auto_hide.hidden = false;
+                configure_window(
+                    &aux.dpy,
+                    win,
+                    &ConfigureWindowAux::new()
+                        .x(auto_hide.home.x as i32)
+                        .y(auto_hide.home.y as i32)
+                        .stack_mode(StackMode::ABOVE),
+                )?;
+            }
+            aux.timers.set(Timeout::PanelHide(win), HIDE_DELAY)?;
+        }
+        Ok(())
+    }
+
+    pub fn panel_entered(&mut self, win: Window) -> Result<()> {
+        if self.auto_hide_mut(win).is_some() {
+            self.aux.timers.cancel(Timeout::PanelHide(win))?;
+        }
+        Ok(())
+    }
+
+    pub fn panel_left(&mut self, win: Window) -> Result<()> {
+        if matches!(self.auto_hide_mut(win), Some((_, auto_hide)) if !auto_hide.hidden) {
+            self.aux.timers.set(Timeout::PanelHide(win), HIDE_DELAY)?;
+        }
+        Ok(())
+    }
+
     pub fn panel_property_changed(&mut self, win: Window, mon: Atom, atom: Atom) -> Result<()> {
         info!("property changed");
         if atom == self.aux.atoms._NET_WM_STRUT || atom == self.aux.atoms._NET_WM_STRUT_PARTIAL {
@@ -107,10 +321,46 @@ impl WindowManager {
         Ok(())
     }
 }
+// a thin strip along the monitor edge closest to the panel
+fn edge_rect(mon: &Rect, panel: &Rect) -> Rect {
+    let left = panel.x - mon.x;
+    let right = (mon.x + mon.width as i16) - (panel.x + panel.width as i16);
+    let top = panel.y - mon.y;
+    let bottom = (mon.y + mon.height as i16) - (panel.y + panel.height as i16);
+    let side = [
+        (left, Side::Left),
+        (right, Side::Right),
+        (top, Side::Top),
+        (bottom, Side::Bottom),
+    ]
+    .iter()
+    .min_by_key(|x| x.0)
+    .unwrap()
+    .1;
+    match side {
+        Side::Left => Rect::new(mon.x, mon.y, EDGE_SIZE, mon.height),
+        Side::Right => Rect::new(
+            mon.x + (mon.width - EDGE_SIZE) as i16,
+            mon.y,
+            EDGE_SIZE,
+            mon.height,
+        ),
+        Side::Top => Rect::new(mon.x, mon.y, mon.width, EDGE_SIZE),
+        Side::Bottom => Rect::new(
+            mon.x,
+            mon.y + (mon.height - EDGE_SIZE) as i16,
+            mon.width,
+            EDGE_SIZE,
+        ),
+    }
+}
+
 impl Monitor {
+    // auto hidden panels don't reserve space
     fn panel_reserved_space(&self) -> WMStrut {
         self.panels
             .values()
+            .filter(|panel| panel.auto_hide.is_none())
             .fold(WMStrut::default(), |x, y| x.max(&y.wm_strut))
     }
 
@@ -175,3 +425,62 @@ impl WMStrut {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::Window;
+
+    use crate::mock;
+    use crate::{WindowLocation, WindowManager};
+
+    // a panel along the top of the focused monitor with auto hide on
+    fn auto_hidden_panel(wm: &mut WindowManager) -> (Window, Window) {
+        let win = wm.aux.dpy.generate_id().unwrap();
+        mock::mock(wm).set_geometry(win, 100, 0, 600, 20);
+        let mon = wm.focused_monitor;
+        wm.panel_register(mon, win).unwrap();
+        wm.panel_auto_hide(win, true).unwrap();
+        let edge = wm
+            .windows
+            .iter()
+            .find(|(_, location)| matches!(location, WindowLocation::PanelEdge(panel) if *panel == win))
+            .map(|(edge, _)| *edge)
+            .unwrap();
+        (win, edge)
+    }
+
+    fn rect(wm: &WindowManager, win: Window) -> (i16, i16, u16, u16) {
+        let (x, y, width, height, _) = mock::mock(wm).geometry(win).unwrap();
+        (x, y, width, height)
+    }
+
+    #[test]
+    fn the_edge_of_a_hidden_panel_follows_its_monitor() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let (win, edge) = auto_hidden_panel(&mut wm);
+        assert_eq!(rect(&wm, edge), (0, 0, 1000, 2));
+        mock::mock(&wm).set_monitors(&[(200, 100, 800, 600)]);
+        wm.update_monitors().unwrap();
+        assert_eq!(rect(&wm, edge), (200, 100, 800, 2));
+        // revealed where it was on the monitor
+        wm.panel_reveal(win).unwrap();
+        assert_eq!(mock::mock(&wm).geometry(win).unwrap().0, 300);
+        assert_eq!(mock::mock(&wm).geometry(win).unwrap().1, 100);
+    }
+
+    #[test]
+    fn the_edge_of_a_shown_panel_goes_where_the_panel_is() {
+        let mut wm = mock::wm(&[(0, 0, 1000, 500)]);
+        let (win, edge) = auto_hidden_panel(&mut wm);
+        wm.panel_reveal(win).unwrap();
+        // the panel moves itself to the bottom of the new monitor
+        mock::mock(&wm).set_geometry(win, 300, 680, 600, 20);
+        mock::mock(&wm).set_monitors(&[(0, 0, 1200, 700)]);
+        wm.update_monitors().unwrap();
+        assert_eq!(rect(&wm, edge), (0, 698, 1200, 2));
+        wm.panel_hide(win).unwrap();
+        wm.panel_reveal(win).unwrap();
+        assert_eq!(mock::mock(&wm).geometry(win).unwrap().1, 680);
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use log::info;
+use x11rb::protocol::randr::*;
+use x11rb::protocol::xproto::Atom;
+use x11rb::{CURRENT_TIME, NONE};
+
+use crate::WindowManager;
+
+impl WindowManager {
+    // a monitor is off while none of its outputs is connected and driven by a crtc, which happens
+    // when it blanks or is unplugged without the screen being reconfigured
+    pub fn update_monitor_power(&mut self) -> Result<()> {
+        let mut changed = Vec::new();
+        for mon in self.monitors.values() {
+            let mut off = !mon.outputs.is_empty();
+            for output in &mon.outputs {
+                if let Ok(info) = get_output_info(&self.aux.dpy, *output, CURRENT_TIME)?.reply() {
+                    if info.connection == Connection::CONNECTED && info.crtc != NONE {
+                        off = false;
+                    }
+                }
+            }
+            if off != self.aux.sleeping.contains(&mon.id) {
+                changed.push((mon.id, off));
+            }
+        }
+        for (mon, off) in changed {
+            if off {
+                self.monitor_off(mon)?;
+            } else {
+                self.monitor_on(mon)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn monitor_off(&mut self, mon: Atom) -> Result<()> {
+        info!("monitor {} is off", mon);
+        self.aux.sleeping.insert(mon);
+        if self.aux.theme.refocus_off_monitors && mon == self.focused_monitor {
+            let sleeping = &self.aux.sleeping;
+            if let Some(other) = self
+                .monitors
+                .keys()
+                .filter(|other| !sleeping.contains(other))
+                .min()
+                .copied()
+            {
+                self.set_focus(other)?;
+                self.off_focus = Some(mon);
+            }
+        }
+        Ok(())
+    }
+
+    // focus goes back to the monitor if it was moved away when it went off
+    fn monitor_on(&mut self, mon: Atom) -> Result<()> {
+        info!("monitor {} is on", mon);
+        self.aux.sleeping.remove(&mon);
+        if self.off_focus == Some(mon) {
+            self.off_focus = None;
+            self.set_focus(mon)?;
+        }
+        Ok(())
+    }
+}
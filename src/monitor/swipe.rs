@@ -0,0 +1,76 @@
+use anyhow::Result;
+use log::info;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::COPY_FROM_PARENT;
+
+use crate::connections::{SetArg, TagSelection};
+use crate::{WindowLocation, WindowManager};
+
+impl WindowManager {
+    // the strips are made again for every monitor when the monitors or the width change
+    pub fn update_swipe_edges(&mut self) -> Result<()> {
+        for edge in self.swipe_edges.drain(..) {
+            self.windows.remove(&edge);
+            destroy_window(&self.aux.dpy, edge)?;
+        }
+        let width = self.aux.theme.swipe.edge;
+        if width == 0 {
+            return Ok(());
+        }
+        for mon in self.monitors.values() {
+            let width = width.min(mon.size.width / 2);
+            for x in [mon.size.x, mon.size.x + (mon.size.width - width) as i16] {
+                let edge = self.aux.dpy.generate_id()?;
+                create_window(
+                    &self.aux.dpy,
+                    0,
+                    edge,
+                    self.aux.root,
+                    x,
+                    mon.size.y,
+                    width,
+                    mon.size.height,
+                    0,
+                    WindowClass::INPUT_ONLY,
+                    COPY_FROM_PARENT,
+                    &CreateWindowAux::new()
+                        .override_redirect(1)
+                        .event_mask(EventMask::BUTTON_PRESS),
+                )?;
+                map_window(&self.aux.dpy, edge)?;
+                self.windows.insert(edge, WindowLocation::SwipeEdge(mon.id));
+                self.swipe_edges.push(edge);
+            }
+        }
+        Ok(())
+    }
+
+    // the monitor the pointer is on, for swipes started with the binding
+    pub fn monitor_at(&self, pos: (i16, i16)) -> Atom {
+        self.monitors
+            .values()
+            .find(|mon| mon.size.contains(&pos))
+            .map(|mon| mon.id)
+            .unwrap_or(self.focused_monitor)
+    }
+
+    // distance is how far the pointer went to the right
+    pub fn swipe(&mut self, mon: Atom, distance: i16) -> Result<()> {
+        let tag = if distance > 0 {
+            TagSelection::Prev(Some(mon))
+        } else {
+            TagSelection::Next(Some(mon))
+        };
+        if let Some(tag) = self.get_tag(tag)? {
+            info!("swiped to tag {} on {}", tag, mon);
+            let mode = self.aux.theme.tag_switch_mode;
+            if self.aux.theme.focus_settle_ms > 0 {
+                self.switch_monitor_tag_settled(mon, SetArg(tag, false), mode)?;
+            } else {
+                self.switch_monitor_tag(mon, SetArg(tag, false), mode)?;
+            }
+        }
+        Ok(())
+    }
+}
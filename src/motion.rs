@@ -0,0 +1,98 @@
+use anyhow::Result;
+use log::info;
+use std::time::Duration;
+use x11rb::protocol::xproto::Window;
+
+use crate::connections::{MotionKind, Side};
+use crate::timer::Timeout;
+use crate::WindowManager;
+
+// steps that arrive within a frame are applied as one move or resize
+const FRAME: Duration = Duration::from_millis(16);
+
+// a move or resize of one window that keeps going until the client ends it
+pub struct Motion {
+    win: Window,
+    side: Side,
+    kind: MotionKind,
+    pending: Option<i32>, // summed steps, some while the frame timer is armed
+}
+
+impl WindowManager {
+    // a new motion ends the one before it
+    pub fn motion_begin(
+        &mut self,
+        client: Option<u32>,
+        side: Side,
+        kind: MotionKind,
+    ) -> Result<()> {
+        self.motion_end()?;
+        if let Some((tag, client)) = self.get_client(client) {
+            let win = self.tags.get(&tag).unwrap().client(client).win;
+            info!("motion {:?} {:?} on {}", kind, side, win);
+            self.motion = Some(Motion {
+                win,
+                side,
+                kind,
+                pending: None,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn motion_step(&mut self, amt: i16) -> Result<()> {
+        if let Some(motion) = &mut self.motion {
+            match &mut motion.pending {
+                Some(pending) => *pending += amt as i32,
+                None => {
+                    motion.pending = Some(amt as i32);
+                    self.aux.timers.set(Timeout::Motion, FRAME)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn motion_frame(&mut self) -> Result<()> {
+        let (win, side, kind, amt) = match &mut self.motion {
+            Some(motion) => match motion.pending.take() {
+                Some(amt) => (motion.win, motion.side, motion.kind, amt),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        let (tag, client) = match self.get_client(Some(win)) {
+            Some(client) => client,
+            // the window went away, the rest of the steps have nothing to move
+            None => {
+                self.motion = None;
+                return Ok(());
+            }
+        };
+        let amt = amt.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        let tag = self.tags.get_mut(&tag).unwrap();
+        match kind {
+            MotionKind::Move if amt > 0 => tag.move_side(&self.aux, client, side, amt as u16)?,
+            MotionKind::Move => (),
+            MotionKind::Resize(proportional) => tag.resize_client(
+                &mut self.aux,
+                client,
+                side.parse_amt(amt),
+                side == Side::Left,
+                side == Side::Top,
+                proportional,
+            )?,
+        }
+        Ok(())
+    }
+
+    // whatever is left over is applied right away
+    pub fn motion_end(&mut self) -> Result<()> {
+        if self.motion.is_some() {
+            self.aux.timers.cancel(Timeout::Motion)?;
+            self.motion_frame()?;
+            self.motion = None;
+        }
+        Ok(())
+    }
+}
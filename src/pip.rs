@@ -0,0 +1,80 @@
+use anyhow::Result;
+use log::info;
+use x11rb::protocol::xproto::Atom;
+
+use crate::connections::{Corner, SetArg};
+use crate::utils::Rect;
+use crate::WindowManager;
+
+impl WindowManager {
+    // floating, sticky and anchored to a corner of its monitor, scale is a fraction of the
+    // monitor width and the window keeps its aspect ratio
+    pub fn pip(&mut self, client: Option<u32>, corner: Corner, scale: f32) -> Result<()> {
+        let (tag, client) = match self.get_client(client) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        // only clients on a shown tag can be sticky
+        if self.tags.get(&tag).unwrap().monitor.is_none() {
+            return Ok(());
+        }
+        let scale = scale.clamp(0.05, 1.0);
+        let tag_ = self.tags.get_mut(&tag).unwrap();
+        info!(
+            "pip {} at {:?} ({})",
+            tag_.client(client).win,
+            corner,
+            scale
+        );
+        tag_.set_floating(&self.aux, client, &SetArg(true, false))?;
+        tag_.client_mut(client).pip = Some((corner, scale));
+        self.set_sticky(tag, client, &SetArg(true, false));
+        self.pip_anchor(tag, client)
+    }
+
+    // a client that stopped being floating or sticky isn't anchored anymore
+    fn pip_anchor(&mut self, tag: Atom, client: usize) -> Result<()> {
+        let tag = self.tags.get_mut(&tag).unwrap();
+        let monitors = &self.monitors;
+        let mon = match tag.monitor.and_then(|mon| monitors.get(&mon)) {
+            Some(mon) => mon,
+            None => return Ok(()),
+        };
+        let client_ = tag.client_mut(client);
+        let (corner, scale) = match client_.pip {
+            Some(pip) if client_.flags.floating && client_.flags.sticky => pip,
+            _ => {
+                client_.pip = None;
+                return Ok(());
+            }
+        };
+        let area = self.aux.theme.tiling_rect(Some(mon.id), &mon.free_rect());
+        let current = tag.get_client_rect(client);
+        let width = (area.width as f32 * scale) as u16;
+        let height = if current.width == 0 {
+            (area.height as f32 * scale) as u16
+        } else {
+            (width as f32 * current.height as f32 / current.width as f32) as u16
+        }
+        .min(area.height);
+        let x = match corner {
+            Corner::TopLeft | Corner::BottomLeft => area.x,
+            Corner::TopRight | Corner::BottomRight => area.x + (area.width - width) as i16,
+        };
+        let y = match corner {
+            Corner::TopLeft | Corner::TopRight => area.y,
+            Corner::BottomLeft | Corner::BottomRight => area.y + (area.height - height) as i16,
+        };
+        tag.set_floating_geometry(&self.aux, client, &Rect::new(x, y, width, height))
+    }
+
+    // pip clients stick to the tag shown on the monitor, so they are all in its sticky set
+    pub fn pip_reanchor(&mut self, mon: Atom) -> Result<()> {
+        let mon = self.monitors.get(&mon).unwrap();
+        let tag = mon.focused_tag;
+        for client in mon.sticky.iter().copied().collect::<Vec<_>>() {
+            self.pip_anchor(tag, client)?;
+        }
+        Ok(())
+    }
+}
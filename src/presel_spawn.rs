@@ -0,0 +1,97 @@
+use anyhow::Result;
+use log::info;
+use std::time::{Duration, Instant};
+use x11rb::protocol::xproto::*;
+
+use crate::connections::{SelectionContent, Side};
+use crate::core::ClientArgs;
+use crate::hooks;
+use crate::{WindowLocation, WindowManager};
+
+// how long a spawned command has to map its window before the presel is given up
+const PIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// a presel taken out of the selection for the first window of a spawned process
+pub struct PreselPin {
+    pid: u32,
+    tag: Atom,
+    parent: Option<Window>, // the window of the preselected node
+    split: (Side, f32),
+    at: Instant,
+}
+
+impl WindowManager {
+    // without a presel the command is only spawned
+    pub fn spawn_in_presel(&mut self, command: String) -> Result<()> {
+        let presel = match &self.aux.selection.sel {
+            SelectionContent::Presel(tag, node, presel) => {
+                Some((*tag, *node, (presel.side, presel.amt)))
+            }
+            _ => None,
+        };
+        // exec so the pid is the one the window reports
+        let pid = hooks::spawn(&format!("exec {}", command));
+        if let (Some((tag, node, split)), Some(pid)) = (presel, pid) {
+            info!(
+                "pinning the presel of node {} on {} to pid {}",
+                node, tag, pid
+            );
+            let parent = self.tags.get(&tag).and_then(|tag| {
+                tag.get_node_client(node)
+                    .map(|client| tag.client(client).win)
+            });
+            self.aux
+                .selection
+                .hide(&self.aux.dpy, Some(tag), Some(node))?;
+            self.presel_pins
+                .retain(|pin| pin.at.elapsed() < PIN_TIMEOUT);
+            self.presel_pins.push(PreselPin {
+                pid,
+                tag,
+                parent,
+                split,
+                at: Instant::now(),
+            });
+        }
+        Ok(())
+    }
+
+    // a window of a process with a pinned presel goes where the presel was
+    pub fn presel_claim(&mut self, win: Window, args: &mut ClientArgs) -> Result<()> {
+        self.presel_pins
+            .retain(|pin| pin.at.elapsed() < PIN_TIMEOUT);
+        if self.presel_pins.is_empty() {
+            return Ok(());
+        }
+        let pin = match self
+            .window_pid(win)?
+            .and_then(|pid| self.presel_pins.iter().position(|pin| pin.pid == pid))
+        {
+            Some(idx) => self.presel_pins.remove(idx),
+            None => return Ok(()),
+        };
+        if self.tags.contains_key(&pin.tag) {
+            let parent = match pin.parent.and_then(|win| self.windows.get(&win)) {
+                Some(WindowLocation::Client(tag, client)) if *tag == pin.tag => Some(*client),
+                _ => None,
+            };
+            args.pin(pin.tag, parent, pin.split);
+        }
+        Ok(())
+    }
+
+    pub fn window_pid(&self, win: Window) -> Result<Option<u32>> {
+        Ok(get_property(
+            &self.aux.dpy,
+            false,
+            win,
+            self.aux.atoms._NET_WM_PID,
+            AtomEnum::CARDINAL,
+            0,
+            1,
+        )?
+        .reply()
+        .ok()
+        .and_then(|reply| reply.value32().and_then(|mut pid| pid.next())))
+    }
+}
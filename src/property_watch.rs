@@ -0,0 +1,51 @@
+use anyhow::Result;
+use x11rb::protocol::xproto::*;
+
+use crate::x11::decode_text;
+use crate::WindowManager;
+
+impl WindowManager {
+    pub fn property_changed(&mut self, e: &PropertyNotifyEvent, name: &str) -> Result<()> {
+        if !self.aux.hooks.watches_property(e.window, e.atom) {
+            return Ok(());
+        }
+        let value = match e.state {
+            Property::DELETE => None,
+            _ => self.property_text(e.window, e.atom)?,
+        };
+        self.aux
+            .hooks
+            .property_changed(e.window, e.atom, name, value);
+        Ok(())
+    }
+
+    // 8 bit properties as text, atoms by name and anything else as space separated numbers
+    fn property_text(&self, win: Window, atom: Atom) -> Result<Option<String>> {
+        let prop =
+            get_property(&self.aux.dpy, false, win, atom, AtomEnum::ANY, 0, u32::MAX)?.reply()?;
+        if prop.type_ == x11rb::NONE {
+            return Ok(None);
+        }
+        if prop.format == 8 {
+            return Ok(Some(decode_text(&self.aux.atoms, &prop)));
+        }
+        let values: Vec<u32> = match prop.value32() {
+            Some(values) => values.collect(),
+            None => prop
+                .value16()
+                .map(|values| values.map(u32::from).collect())
+                .unwrap_or_default(),
+        };
+        let words = if prop.type_ == u32::from(AtomEnum::ATOM) {
+            let mut names = Vec::new();
+            for atom in values {
+                let name = get_atom_name(&self.aux.dpy, atom)?.reply()?.name;
+                names.push(String::from_utf8_lossy(&name).into_owned());
+            }
+            names
+        } else {
+            values.iter().map(|value| value.to_string()).collect()
+        };
+        Ok(Some(words.join(" ")))
+    }
+}
@@ -0,0 +1,1102 @@
+// the ipc protocol between the wm and its clients, independent of the display server
+use log::{info, LevelFilter};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+use std::io::prelude::*;
+use std::io::ErrorKind;
+use std::net::Shutdown;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::config::{
+    FloatPlacement, FocusIndicator, FrameAction, HideStrategy, OverflowPolicy, PointerBindings,
+    Swipe, TagSwitchMode, ThemeField,
+};
+use crate::core::{Rule, Side, StackLayer, TreeNode};
+use crate::icon::Icon;
+
+pub struct Stream {
+    stream: UnixStream,
+    length: usize,
+    reading: bool,
+    data: Vec<u8>,
+    out: Vec<u8>, // written once the socket has room for it
+    pub(crate) greeted: bool,
+    encoding: Option<Encoding>,
+}
+
+// json is newline delimited so it can be used from scripts, the encoding of a
+// connection accepted by the server is detected from the first byte it sends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Bincode,
+    Json,
+}
+
+// bumped for every incompatible change to the messages once a version is released, a change
+// is incompatible when a message an older peer can already send or receive is encoded
+// differently (in either encoding) or means something else:
+// - adding, removing, reordering or retyping fields of an existing variant or struct,
+//   including the types they carry such as Rule, TagState and TreeNode
+// - removing, renaming or reordering variants, or inserting one anywhere but the end
+// appending a variant to the end of an enum is compatible, an older peer never sends it and
+// drops the connection when it receives one, nothing it already understood changes
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// a client that stopped reading is dropped once this much output is waiting for it
+const MAX_PENDING: usize = 1 << 20;
+
+// the optional features of the server by name, appending a request does not bump the protocol
+// version so a client checks that the server has the feature of a request before sending it
+pub mod features {
+    pub const ANIMATIONS: &str = "animations";
+    pub const TAG_SWITCH_MODE: &str = "tag-switch-mode";
+    pub const HIDE_STRATEGY: &str = "hide-strategy";
+    pub const PERCENT_AMOUNTS: &str = "percent-amounts";
+    pub const ICONS: &str = "icons";
+    pub const CLASS_ASSIGNMENTS: &str = "class-assignments";
+    pub const MONOCLE_OPTIONS: &str = "monocle-options";
+    pub const MARGIN_DELTA: &str = "margin-deltas";
+    pub const MONITOR_THEMES: &str = "monitor-themes";
+    pub const SHADOW_HINTS: &str = "shadow-hints";
+    pub const MONITOR_LAYERS: &str = "monitor-layers";
+    pub const FOCUS_LOCK: &str = "focus-lock";
+    pub const PANEL_AUTO_HIDE: &str = "panel-auto-hide";
+    pub const TREE: &str = "layout-tree";
+    pub const DEBUG: &str = "debugging";
+    pub const FULLSCREEN_EVENTS: &str = "fullscreen-events";
+    pub const MULTI_SELECTION: &str = "multi-selection";
+    pub const RULE_TEST: &str = "rule-test";
+    pub const RAISE_LOWER: &str = "raise-and-lower";
+    pub const ALWAYS_ON_TOP: &str = "always-on-top";
+    pub const PANEL_LAYER: &str = "panel-layer";
+    pub const POINTER_BINDINGS: &str = "pointer-bindings";
+    pub const BORDER_OVERRIDES: &str = "border-overrides";
+    pub const OVERFLOW: &str = "tiled-overflow";
+    pub const TEMPLATES: &str = "tag-templates";
+    pub const WAIT_FOR_WINDOW: &str = "wait-for-window";
+    pub const HIDDEN_LIST: &str = "hidden-list";
+    pub const WINDOW_GROUPS: &str = "window-groups";
+    pub const DROPDOWN: &str = "dropdown";
+    pub const INSERT_AT_POINTER: &str = "insert-at-pointer";
+    pub const FOCUS_FLASH: &str = "focus-flash";
+    pub const NODE_IDS: &str = "node-ids";
+    pub const TRAY: &str = "tray";
+    pub const PASSTHROUGH: &str = "passthrough";
+    pub const COLLAPSE: &str = "collapse";
+    pub const URGENT_TIMEOUT: &str = "urgent-timeout";
+    pub const RESTART: &str = "restart";
+    pub const GEOMETRY: &str = "geometry";
+    pub const INACTIVE_BORDER: &str = "inactive-border";
+    pub const FOCUS_INDICATOR: &str = "focus-indicator";
+    pub const PROTECTED: &str = "protected-clients";
+    pub const IDLE: &str = "idle";
+    pub const REMEMBER_FLOATING: &str = "remember-floating";
+    pub const LOCK_RATIO: &str = "lock-ratio";
+    pub const LAYOUT_EVENTS: &str = "layout-events";
+    pub const FLOAT_PLACEMENT: &str = "float-placement";
+    pub const PRESEL_TIMEOUT: &str = "presel-timeout";
+    pub const FLAGS: &str = "flags";
+    pub const FRAME_BUTTONS: &str = "frame-buttons";
+    pub const MOTION: &str = "motion";
+    pub const SWIPE: &str = "swipe";
+    pub const FOCUS_SETTLE: &str = "focus-settle";
+    pub const SPAWN_IN_PRESEL: &str = "spawn-in-presel";
+    pub const CYCLE_CLASS: &str = "cycle-class";
+    pub const INHIBIT_FULLSCREEN: &str = "inhibit-fullscreen";
+    pub const MINIMAP: &str = "minimap";
+    pub const PIP: &str = "picture-in-picture";
+    pub const REFOCUS_OFF_MONITORS: &str = "refocus-off-monitors";
+    pub const VISUAL_BELL: &str = "visual-bell";
+    pub const CLOSE_FADE: &str = "close-fade";
+    pub const FAKE_MONITORS: &str = "fake-monitors";
+    pub const WATCH_PROPERTY: &str = "watch-property";
+
+    pub const ALL: &[&str] = &[
+        ANIMATIONS,
+        TAG_SWITCH_MODE,
+        HIDE_STRATEGY,
+        PERCENT_AMOUNTS,
+        ICONS,
+        CLASS_ASSIGNMENTS,
+        MONOCLE_OPTIONS,
+        MARGIN_DELTA,
+        MONITOR_THEMES,
+        SHADOW_HINTS,
+        MONITOR_LAYERS,
+        FOCUS_LOCK,
+        PANEL_AUTO_HIDE,
+        TREE,
+        DEBUG,
+        FULLSCREEN_EVENTS,
+        MULTI_SELECTION,
+        RULE_TEST,
+        RAISE_LOWER,
+        ALWAYS_ON_TOP,
+        PANEL_LAYER,
+        POINTER_BINDINGS,
+        BORDER_OVERRIDES,
+        OVERFLOW,
+        TEMPLATES,
+        WAIT_FOR_WINDOW,
+        HIDDEN_LIST,
+        WINDOW_GROUPS,
+        DROPDOWN,
+        INSERT_AT_POINTER,
+        FOCUS_FLASH,
+        NODE_IDS,
+        TRAY,
+        PASSTHROUGH,
+        COLLAPSE,
+        URGENT_TIMEOUT,
+        RESTART,
+        GEOMETRY,
+        INACTIVE_BORDER,
+        FOCUS_INDICATOR,
+        PROTECTED,
+        IDLE,
+        REMEMBER_FLOATING,
+        LOCK_RATIO,
+        LAYOUT_EVENTS,
+        FLOAT_PLACEMENT,
+        PRESEL_TIMEOUT,
+        FLAGS,
+        FRAME_BUTTONS,
+        MOTION,
+        SWIPE,
+        FOCUS_SETTLE,
+        SPAWN_IN_PRESEL,
+        CYCLE_CLASS,
+        INHIBIT_FULLSCREEN,
+        MINIMAP,
+        PIP,
+        REFOCUS_OFF_MONITORS,
+        VISUAL_BELL,
+        CLOSE_FADE,
+        FAKE_MONITORS,
+        WATCH_PROPERTY,
+    ];
+}
+
+// the first message on every connection, Hello and Rejected must stay stable across versions,
+// Accepted only goes to clients of the same version
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Hello {
+    pub version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum HelloReply {
+    Accepted { version: u32, features: Vec<String> },
+    Rejected { version: u32, reason: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum HiddenSelection {
+    All,
+    First,
+    Last,
+    Nth(usize),  // in the order of ViewHidden, from 0
+    Window(u32), // ignored unless the window is hidden on the tag
+}
+
+// which clients of the focused tag a cycle goes through, the rest keep their place
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CycleFilter {
+    All,
+    Floating,
+    Tiled,
+    Class, // the class of the focused client
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TagSelection {
+    Name(String),
+    Index(usize),
+    Focused(Option<u32>),
+    Next(Option<u32>),
+    Prev(Option<u32>),
+    Last(Option<u32>),
+    Id(u32),
+}
+
+// a node of the layout tree, the index is the one shown in the tree of the tag
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NodeSelector {
+    Window(u32),
+    Index(TagSelection, usize),
+    Focused,
+    Selection,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ClientRequest {
+    MonitorFocus(Option<u32>),
+    TagState,
+    FocusedWindow(TagSelection),
+    FocusedTag(Option<u32>),
+    FocusedMonitor,
+    Quit,
+    Reload,
+    CloseClient(Option<u32>, bool, bool), // kill, force closes protected clients too
+    SetLayer(Option<u32>, SetArg<StackLayer>),
+    SetFullscreen(Option<u32>, StateArg),
+    SetFloating(Option<u32>, StateArg),
+    SetSticky(Option<u32>, StateArg),
+    SetHidden(Option<u32>, StateArg),
+    SetMonocle(TagSelection, StateArg),
+    Show(TagSelection, HiddenSelection),
+    ResizeWindow(Option<u32>, Side, i16, bool), // +grow, -shrink, proportional
+    MoveWindow(Option<u32>, Side, u16),         // floating move amnt, tiling swap neighbour
+    SelectNeighbour(Option<u32>, Side),         // select tiling neighbour
+    CycleWindow(bool, CycleFilter),
+    FocusNthRecent(TagSelection, usize, bool), // toggle back if the node is already focused
+    FocusTag(Option<u32>, TagSelection, bool, Option<TagSwitchMode>),
+    SetWindowTag(Option<u32>, TagSelection, bool, bool), // toggle, follow
+    TagName(TagSelection),
+    MonitorName(Option<u32>),
+    ConfigBorderFocused(u32),
+    ConfigBorderUnfocused(u32),
+    ConfigBorderWidth(u16),
+    ConfigGap(u16),
+    ConfigMargin(Side, i16),
+    ConfigTagSwitchMode(TagSwitchMode),
+    ConfigAnimations(bool, u16), // enabled, duration in ms
+    ConfigHideStrategy(HideStrategy),
+    AddRule(Rule),
+    AddTag(String, bool), // volatile
+    RemoveTag(TagSelection),
+    Select(Option<u32>),
+    SelectDir(Side),
+    SelectParent,
+    PreselAmt(f32),
+    SelectionCancel,
+    Rotate(bool),
+    ViewLayers(TagSelection),
+    ViewStack(TagSelection),
+    ViewClients(TagSelection),
+    IgnoreSizeHints(Option<u32>),
+    Ping,
+    ResizeWindowPercent(Option<u32>, Side, f32, bool), // percent of the current size, proportional
+    ClientIcon(u32),
+    AssignClass(String, TagSelection, bool), // create the tag as volatile when it is missing
+    ListAssignments,
+    ConfigMonocle(bool, bool, bool), // floating visible, borderless, gapless
+    ConfigMarginDelta(Side, i16),
+    ConfigMarginDeltaAll(i16),
+    ConfigMonitorTheme(String, ThemeField, Option<i16>), // none removes the override
+    ConfigFloatingShadowsOnly(bool),
+    ViewMonitorLayers,
+    SetFocusLock(StateArg),
+    PanelAutoHide(u32, bool),
+    ViewTree(TagSelection),
+    SubscribeTree(Option<u32>), // the tag shown on a monitor
+    DebugDump,
+    SetLogLevel(LevelFilter),
+    TailLog(usize),
+    SubscribeFullscreen,
+    ConfigSelectionFollow(bool),
+    SelectToggle(Option<u32>),
+    RuleTest(u32),
+    Raise(Option<u32>),
+    Lower(Option<u32>),
+    SetAot(Option<u32>, StateArg),
+    ConfigPanelLayer(bool, bool), // panels above fullscreen, desktop click through
+    ConfigPointerBindings(PointerBindings),
+    SetBorderWidth(Option<u32>, Option<u16>), // none goes back to the theme
+    SetBorderColor(Option<u32>, Option<u32>),
+    Metrics,
+    ConfigOverflow(Option<usize>, OverflowPolicy), // none removes the limit
+    AddTemplate(TagTemplate),
+    AddTagFromTemplate(String),
+    WaitForWindow(MatchSpec, u32), // timeout in ms, 0 waits forever
+    ViewHidden(TagSelection),
+    ShowSpecific(u32), // unhides and focuses a hidden window
+    MoveGroupToTag(Option<u32>, TagSelection), // every window sharing the node's group leader
+    CycleGroup(bool),
+    ConfigGroupHide(bool),
+    DropdownToggle(String, f32), // command, fraction of the monitor height
+    ConfigInsertAtPointer(bool),
+    ConfigFocusFlash(u32, u32), // ms, color
+    RotateNode(NodeSelector, bool),
+    EqualizeNode(NodeSelector),
+    MoveSubtree(NodeSelector, NodeSelector), // subtree, node it is split off of
+    ConfigTray(bool, u16),                   // enabled, icon size
+    TrayWidth,
+    SetPassthrough(Option<u32>, StateArg),
+    ConfigMinTileSize(Option<(u16, u16)>), // none stops collapsing tiled clients
+    SubscribeCollapsed,
+    ConfigUrgentTimeout(u32), // ms, 0 keeps urgency until the client is focused
+    ClearUrgent(Option<TagSelection>), // none clears every tag
+    Restart,                  // exec the wm again, keeping the windows and tags
+    SetFloatingGeometry(Option<u32>, Geometry, Option<u32>), // monitor, none for the node's own
+    QueryGeometry(Option<u32>, bool), // relative to the monitor
+    ConfigBorderInactive(u32), // the focused client of a monitor without input focus
+    ConfigFocusIndicator(FocusIndicator, Option<u32>), // none keeps the color
+    SetProtected(Option<u32>, StateArg),
+    ConfigIdleTimeout(u32), // ms, 0 disables idle detection
+    SubscribeIdle,
+    ConfigRememberFloating(bool),
+    LockRatio(NodeSelector, bool), // leaves that are not splits are ignored
+    SubscribeLayout,
+    ConfigFloatPlacement(FloatPlacement),
+    ConfigPreselTimeout(u32), // ms, 0 keeps a presel until it is used or cancelled
+    SubscribePresel,
+    GetFlags(Option<u32>),
+    BindFrameButton(u8, u16, Option<FrameAction>), // button, modifiers, none removes the binding
+    BeginMotion(Option<u32>, Side, MotionKind),
+    MotionStep(i16), // an amount for the move or resize of the motion
+    EndMotion,
+    ConfigSwipe(Swipe),
+    ConfigFocusSettle(u32), // ms, 0 focuses right after every tag switch
+    SpawnInPresel(String),  // the first window of the command fills the current presel
+    CycleClass(bool),       // across every tag
+    SetInhibitFullscreen(Option<u32>, StateArg),
+    RenderLayout(TagSelection),
+    ConfigFrameDoubleClick(Option<FrameAction>, u32), // none disables it, ms
+    Pip(Option<u32>, Corner, f32),                    // scale is a fraction of the monitor width
+    ConfigRefocusOffMonitors(bool),
+    VisualBell(Option<u32>),
+    ConfigVisualBell(u32, u32), // ms, color
+    ConfigCloseFade(u32),       // ms
+    SetFakeMonitors(Vec<(i16, i16, u16, u16)>),
+    ClearFakeMonitors,
+    WatchProperty(u32, String), // window, atom name
+    ConfigGapPercent(f32),      // of the smaller side of each monitor
+}
+
+impl ClientRequest {
+    // the feature the server needs to handle the request, none for the ones every server has
+    pub fn feature(&self) -> Option<&'static str> {
+        match self {
+            Self::ConfigAnimations(..) => Some(features::ANIMATIONS),
+            Self::ConfigTagSwitchMode(_) => Some(features::TAG_SWITCH_MODE),
+            Self::ConfigHideStrategy(_) => Some(features::HIDE_STRATEGY),
+            Self::ResizeWindowPercent(..) | Self::ConfigGapPercent(_) => {
+                Some(features::PERCENT_AMOUNTS)
+            }
+            Self::ClientIcon(_) => Some(features::ICONS),
+            Self::AssignClass(..) | Self::ListAssignments => Some(features::CLASS_ASSIGNMENTS),
+            Self::ConfigMonocle(..) => Some(features::MONOCLE_OPTIONS),
+            Self::ConfigMarginDelta(..) | Self::ConfigMarginDeltaAll(_) => {
+                Some(features::MARGIN_DELTA)
+            }
+            Self::ConfigMonitorTheme(..) => Some(features::MONITOR_THEMES),
+            Self::ConfigFloatingShadowsOnly(_) => Some(features::SHADOW_HINTS),
+            Self::ViewMonitorLayers => Some(features::MONITOR_LAYERS),
+            Self::SetFocusLock(_) => Some(features::FOCUS_LOCK),
+            Self::PanelAutoHide(..) => Some(features::PANEL_AUTO_HIDE),
+            Self::ViewTree(_) | Self::SubscribeTree(_) => Some(features::TREE),
+            Self::DebugDump | Self::SetLogLevel(_) | Self::TailLog(_) | Self::Metrics => {
+                Some(features::DEBUG)
+            }
+            Self::SubscribeFullscreen => Some(features::FULLSCREEN_EVENTS),
+            Self::ConfigSelectionFollow(_) | Self::SelectToggle(_) => {
+                Some(features::MULTI_SELECTION)
+            }
+            Self::RuleTest(_) => Some(features::RULE_TEST),
+            Self::Raise(_) | Self::Lower(_) => Some(features::RAISE_LOWER),
+            Self::SetAot(..) => Some(features::ALWAYS_ON_TOP),
+            Self::ConfigPanelLayer(..) => Some(features::PANEL_LAYER),
+            Self::ConfigPointerBindings(_) => Some(features::POINTER_BINDINGS),
+            Self::SetBorderWidth(..) | Self::SetBorderColor(..) => Some(features::BORDER_OVERRIDES),
+            Self::ConfigOverflow(..) => Some(features::OVERFLOW),
+            Self::AddTemplate(_) | Self::AddTagFromTemplate(_) => Some(features::TEMPLATES),
+            Self::WaitForWindow(..) => Some(features::WAIT_FOR_WINDOW),
+            Self::ViewHidden(_)
+            | Self::ShowSpecific(_)
+            | Self::Show(_, HiddenSelection::Nth(_))
+            | Self::Show(_, HiddenSelection::Window(_)) => Some(features::HIDDEN_LIST),
+            Self::MoveGroupToTag(..) | Self::CycleGroup(_) | Self::ConfigGroupHide(_) => {
+                Some(features::WINDOW_GROUPS)
+            }
+            Self::DropdownToggle(..) => Some(features::DROPDOWN),
+            Self::ConfigInsertAtPointer(_) => Some(features::INSERT_AT_POINTER),
+            Self::ConfigFocusFlash(..) => Some(features::FOCUS_FLASH),
+            Self::RotateNode(..) | Self::EqualizeNode(_) | Self::MoveSubtree(..) => {
+                Some(features::NODE_IDS)
+            }
+            Self::ConfigTray(..) | Self::TrayWidth => Some(features::TRAY),
+            Self::SetPassthrough(..) => Some(features::PASSTHROUGH),
+            Self::ConfigMinTileSize(_) | Self::SubscribeCollapsed => Some(features::COLLAPSE),
+            Self::ConfigUrgentTimeout(_) | Self::ClearUrgent(_) => Some(features::URGENT_TIMEOUT),
+            Self::Restart => Some(features::RESTART),
+            Self::SetFloatingGeometry(..) | Self::QueryGeometry(..) => Some(features::GEOMETRY),
+            Self::ConfigBorderInactive(_) => Some(features::INACTIVE_BORDER),
+            Self::ConfigFocusIndicator(..) => Some(features::FOCUS_INDICATOR),
+            Self::SetProtected(..) => Some(features::PROTECTED),
+            Self::ConfigIdleTimeout(_) | Self::SubscribeIdle => Some(features::IDLE),
+            Self::ConfigRememberFloating(_) => Some(features::REMEMBER_FLOATING),
+            Self::LockRatio(..) => Some(features::LOCK_RATIO),
+            Self::SubscribeLayout => Some(features::LAYOUT_EVENTS),
+            Self::ConfigFloatPlacement(_) => Some(features::FLOAT_PLACEMENT),
+            Self::ConfigPreselTimeout(_) | Self::SubscribePresel => Some(features::PRESEL_TIMEOUT),
+            Self::GetFlags(_) => Some(features::FLAGS),
+            Self::BindFrameButton(..) | Self::ConfigFrameDoubleClick(..) => {
+                Some(features::FRAME_BUTTONS)
+            }
+            Self::BeginMotion(..) | Self::MotionStep(_) | Self::EndMotion => Some(features::MOTION),
+            Self::ConfigSwipe(_) => Some(features::SWIPE),
+            Self::ConfigFocusSettle(_) => Some(features::FOCUS_SETTLE),
+            Self::SpawnInPresel(_) => Some(features::SPAWN_IN_PRESEL),
+            Self::CycleClass(_) => Some(features::CYCLE_CLASS),
+            Self::SetInhibitFullscreen(..) => Some(features::INHIBIT_FULLSCREEN),
+            Self::RenderLayout(_) => Some(features::MINIMAP),
+            Self::Pip(..) => Some(features::PIP),
+            Self::ConfigRefocusOffMonitors(_) => Some(features::REFOCUS_OFF_MONITORS),
+            Self::VisualBell(_) | Self::ConfigVisualBell(..) => Some(features::VISUAL_BELL),
+            Self::ConfigCloseFade(_) => Some(features::CLOSE_FADE),
+            Self::SetFakeMonitors(_) | Self::ClearFakeMonitors => Some(features::FAKE_MONITORS),
+            Self::WatchProperty(..) => Some(features::WATCH_PROPERTY),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// a move or resize driven by a held key, its steps are applied together once a frame
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MotionKind {
+    Move,
+    Resize(bool), // proportional
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TagState {
+    pub id: u32, // for TagSelection::Id, stays the same when tags are reordered
+    pub name: String,
+    pub focused: Option<u32>,
+    pub urgent: bool,
+    pub empty: bool,
+    pub hidden: usize,
+    pub client_count: usize, // hidden clients included
+}
+
+// the flags of a client that scripts branch on
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WindowFlags {
+    pub floating: bool,
+    pub fullscreen: bool,
+    pub sticky: bool,
+    pub hidden: bool,
+    pub urgent: bool,
+    pub layer: StackLayer,
+}
+
+// the shown windows of a tag from the bottom of the stack to the top, relative to the area of
+// the tag, for drawing it small
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Minimap {
+    pub width: u16,
+    pub height: u16,
+    pub windows: Vec<MinimapWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MinimapWindow {
+    pub win: u32,
+    pub rect: (i16, i16, u16, u16), // with the border
+    pub focused: bool,
+    pub floating: bool,
+    pub urgent: bool,
+}
+
+// a hidden client in the order show first/last picks from, the geometry is where it was last
+// placed and is none if it was hidden before ever being laid out
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HiddenClient {
+    pub win: u32,
+    pub class: Option<String>,
+    pub name: Option<String>,
+    pub geometry: Option<(i16, i16, u16, u16)>,
+}
+
+// the layers of the tag shown on a monitor next to the order the x server actually stacks them in
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MonitorLayers {
+    pub monitor: String,
+    pub tag: String,
+    pub layers: Vec<Vec<u32>>, // windows, topmost first within each layer
+    pub stacking: Vec<u32>,    // windows, bottom to top
+}
+
+// the properties rules match on for a window and every rule in order with whether it matches,
+// all matching rules are applied and temp rules are removed once they match
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RuleTest {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub name: Option<String>,
+    pub tag: Option<String>, // from a class assignment
+    pub rules: Vec<(Rule, bool)>,
+}
+
+// unset fields match anything
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MatchSpec {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub name: Option<String>,
+}
+
+impl MatchSpec {
+    pub fn matches(&self, class: Option<&str>, instance: Option<&str>, name: Option<&str>) -> bool {
+        let field = |spec: &Option<String>, value: Option<&str>| {
+            spec.as_deref().map(|x| value == Some(x)).unwrap_or(true)
+        };
+        field(&self.class, class) && field(&self.instance, instance) && field(&self.name, name)
+    }
+}
+
+// pixels or a percentage of the size of a monitor
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(i32),
+    Percent(f32),
+}
+
+impl Length {
+    fn resolve(&self, size: u16) -> i32 {
+        match self {
+            Self::Pixels(px) => *px,
+            Self::Percent(pc) => (size as f32 * pc / 100.0).round() as i32,
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pixels(px) => write!(f, "{}", px),
+            Self::Percent(pc) => write!(f, "{}%", pc),
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s.strip_suffix('%') {
+            Some(pc) => Self::Percent(pc.parse()?),
+            None => Self::Pixels(s.parse()?),
+        })
+    }
+}
+
+// "WxH+X+Y" like x11 geometry strings, where any part can be a percentage of the monitor. The
+// position is relative to the monitor when relative is set or when it is a percentage
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub width: Length,
+    pub height: Length,
+    pub x: Length,
+    pub y: Length,
+    pub relative: bool,
+}
+
+impl Geometry {
+    // mon is the x, y, width and height of the monitor
+    pub fn resolve(&self, mon: (i16, i16, u16, u16)) -> (i16, i16, u16, u16) {
+        let offset = |len: &Length, origin: i16| match len {
+            Length::Pixels(_) if !self.relative => 0,
+            _ => origin as i32,
+        };
+        (
+            (offset(&self.x, mon.0) + self.x.resolve(mon.2)) as i16,
+            (offset(&self.y, mon.1) + self.y.resolve(mon.3)) as i16,
+            self.width.resolve(mon.2).max(1) as u16,
+            self.height.resolve(mon.3).max(1) as u16,
+        )
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = |len: &Length| match len {
+            Length::Pixels(px) if *px < 0 => "",
+            Length::Percent(pc) if *pc < 0.0 => "",
+            _ => "+",
+        };
+        write!(
+            f,
+            "{}x{}{}{}{}{}",
+            self.width,
+            self.height,
+            sign(&self.x),
+            self.x,
+            sign(&self.y),
+            self.y
+        )
+    }
+}
+
+impl FromStr for Geometry {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let error = || anyhow::Error::msg(format!("geometry: expected WxH+X+Y, got '{}'", s));
+        // the sign that starts an offset is kept as part of it
+        let sign = |s: &str| {
+            s.char_indices()
+                .skip(1)
+                .find(|(_, c)| *c == '+' || *c == '-')
+                .map(|(i, _)| i)
+        };
+        let (width, rest) = s.split_once('x').ok_or_else(error)?;
+        let (height, pos) = rest.split_at(sign(rest).ok_or_else(error)?);
+        let (x, y) = pos.split_at(sign(pos).ok_or_else(error)?);
+        let offset = |s: &str| s.strip_prefix('+').unwrap_or(s).parse::<Length>();
+        Ok(Self {
+            width: width.parse()?,
+            height: height.parse()?,
+            x: offset(x)?,
+            y: offset(y)?,
+            relative: false,
+        })
+    }
+}
+
+// a tag that can be created in one go, each spawned command's first window of its class goes
+// to the tag and splits the focused node on a side with a ratio like presel-amt if given
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagTemplate {
+    pub name: String,
+    pub monocle: bool,
+    pub spawn: Vec<TemplateSpawn>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TemplateSpawn {
+    pub class: String,
+    pub command: String,
+    pub split: Option<(Side, f32)>,
+}
+
+// clients per tag, totals since startup and rates over the last second
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetricsReport {
+    pub clients: Vec<(String, usize)>,
+    pub events: u64,
+    pub events_per_sec: f32,
+    pub requests: u64,
+    pub requests_per_sec: f32,
+    pub relayouts: u64,
+    pub relayout_avg_us: u64,
+    pub relayout_max_us: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum CwmResponse {
+    MonitorFocusedClient(Option<String>),
+    TagState(Vec<TagState>, u32),
+    FocusedMonitor(u32),
+    FocusedTag(u32),
+    FocusedWindow(Option<u32>),
+    Name(String),
+    ViewLayers(Vec<Vec<usize>>),
+    ViewStack(Vec<usize>),
+    ViewClients(Vec<(usize, u32, Option<String>)>),
+    Pong,
+    ClientIcon(Option<Icon>),
+    Assignments(Vec<(String, String)>), // class, tag name
+    MonitorLayers(Vec<MonitorLayers>),
+    Tree(TreeNode),
+    DebugDump(String, Vec<String>), // state, broken invariants
+    Log(Vec<String>),
+    Fullscreen(u32, u32, bool), // monitor, window, entered
+    RuleTest(RuleTest),
+    Metrics(MetricsReport),
+    Window(Option<u32>), // none if the wait timed out
+    Hidden(Vec<HiddenClient>),
+    TrayWidth(u16),      // 0 without icons
+    Collapsed(u32, u32), // monitor, window hidden for being too small to tile
+    Geometry(Option<(i16, i16, u16, u16)>),
+    Ack(Result<(), String>),
+    Idle(bool), // true when going idle, false on the first activity after
+    Layout(u32, Vec<(u32, (i16, i16, u16, u16))>), // monitor, shown windows with their border
+    PreselExpired(Option<u32>),
+    Flags(Option<WindowFlags>), // none if there is no such client // the window the presel was on
+    Minimap(Minimap),
+    Property(u32, String, Option<String>), // window, atom, none once the property is deleted
+}
+
+// CWM_SOCKET overrides the default, which is per user and per display
+pub fn socket_path() -> String {
+    if let Ok(path) = std::env::var("CWM_SOCKET") {
+        return path;
+    }
+    let display = std::env::var("DISPLAY").unwrap_or_default();
+    // "host:1.0" -> "1", the screen doesn't matter as there is one wm per display
+    match display.rsplit(':').next().and_then(|x| x.split('.').next()) {
+        Some(num) if display.contains(':') && !num.is_empty() => {
+            format!("/tmp/cwm-{}-{}.sock", whoami::username(), num)
+        }
+        _ => format!("/tmp/cwm-{}.sock", whoami::username()),
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> i32 {
+        self.stream.as_raw_fd()
+    }
+}
+
+impl Stream {
+    pub fn new(stream: UnixStream) -> Self {
+        Self::with_encoding(stream, Some(Encoding::Bincode))
+    }
+
+    pub fn with_encoding(stream: UnixStream, encoding: Option<Encoding>) -> Self {
+        Self {
+            stream,
+            length: 0,
+            reading: false,
+            data: Vec::new(),
+            out: Vec::new(),
+            greeted: false,
+            encoding,
+        }
+    }
+
+    // queues the message behind any earlier output and writes as much as the socket takes, false
+    // if the stream is broken or the other end stopped reading
+    pub fn send<T: Serialize>(&mut self, item: &T) -> bool {
+        let message = match self.encoding {
+            Some(Encoding::Json) => {
+                let mut data = serde_json::to_vec(item).unwrap();
+                data.push(b'\n');
+                data
+            }
+            _ => {
+                let data = bincode::serialize(item).unwrap();
+                let mut message = bincode::serialize(&(data.len() as u32)).unwrap();
+                message.extend(data);
+                message
+            }
+        };
+        if self.out.len() + message.len() > MAX_PENDING {
+            info!(
+                "dropping a client with {} bytes left unread",
+                self.out.len()
+            );
+            return false;
+        }
+        self.out.extend(message);
+        self.flush()
+    }
+
+    // writes queued output until the socket is full, blocking streams write all of it. the
+    // output of a broken stream is thrown away so it isn't polled for room that never comes
+    pub fn flush(&mut self) -> bool {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => {
+                    self.out.clear();
+                    return false;
+                }
+                Ok(len) => {
+                    self.out.drain(..len);
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    info!("{:?}", e);
+                    self.out.clear();
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // output is waiting for room in the socket
+    pub fn pending(&self) -> bool {
+        !self.out.is_empty()
+    }
+
+    pub fn get_bytes(&mut self) -> bool {
+        let mut bytes = [0u8; 256];
+        match self.stream.read(&mut bytes) {
+            Ok(0) => true,
+            Ok(len) => {
+                self.data.extend(&bytes[..len]);
+                false
+            }
+            Err(e) => {
+                info!("{:?}", e);
+                e.kind() != std::io::ErrorKind::WouldBlock
+            }
+        }
+    }
+
+    // reads until the socket is empty, true if the other end is gone
+    pub(crate) fn read_available(&mut self) -> bool {
+        loop {
+            let len = self.data.len();
+            let done = self.get_bytes();
+            if done || self.data.len() == len {
+                return done;
+            }
+        }
+    }
+
+    pub fn recieve<T: DeserializeOwned>(&mut self) -> (bool, Option<T>) {
+        let done = self.get_bytes();
+        self.next_message(done)
+    }
+
+    // the next complete message of what has already been read
+    pub(crate) fn next_message<T: DeserializeOwned>(&mut self, done: bool) -> (bool, Option<T>) {
+        if self.encoding.is_none() {
+            match self.data.first() {
+                Some(b'{') | Some(b'[') | Some(b'"') => self.encoding = Some(Encoding::Json),
+                Some(_) => self.encoding = Some(Encoding::Bincode),
+                None => return (done, None),
+            }
+        }
+        if self.encoding == Some(Encoding::Json) {
+            return match self.data.iter().position(|x| *x == b'\n') {
+                Some(end) => {
+                    let line = self.data.drain(..=end).collect::<Vec<_>>();
+                    match serde_json::from_slice(&line) {
+                        Ok(val) => (done, Some(val)),
+                        Err(e) => {
+                            info!("invalid message: {:?}", e);
+                            (true, None)
+                        }
+                    }
+                }
+                None => (done, None),
+            };
+        }
+        if !self.reading && self.data.len() >= 4 {
+            self.length =
+                bincode::deserialize::<u32>(self.data.drain(..4).as_ref()).unwrap() as usize;
+            self.reading = true;
+        }
+        if self.reading && self.data.len() >= self.length {
+            self.reading = false;
+            match bincode::deserialize(self.data.drain(..self.length).as_ref()) {
+                Ok(val) => (done, Some(val)),
+                Err(e) => {
+                    info!("invalid message: {:?}", e);
+                    (true, None)
+                }
+            }
+        } else {
+            (done, None)
+        }
+    }
+
+    pub fn recieve_timeout<T: DeserializeOwned>(&mut self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            match self.recieve() {
+                (_, Some(val)) => return Some(val),
+                (true, None) => return None,
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // returns false if the connection should be closed
+    pub(crate) fn greet(&mut self, hello: &Hello) -> bool {
+        if hello.version == PROTOCOL_VERSION {
+            self.greeted = true;
+            self.send(&HelloReply::Accepted {
+                version: PROTOCOL_VERSION,
+                features: features::ALL
+                    .iter()
+                    .map(|feature| feature.to_string())
+                    .collect(),
+            })
+        } else {
+            info!("rejected client with protocol version {}", hello.version);
+            self.send(&HelloReply::Rejected {
+                version: PROTOCOL_VERSION,
+                reason: format!(
+                    "client protocol version {} does not match server version {}",
+                    hello.version, PROTOCOL_VERSION
+                ),
+            });
+            false
+        }
+    }
+}
+
+impl TagState {
+    pub fn format(&self, curr_mon: u32, focused_mon: u32) -> String {
+        let prefix = match self {
+            Self { urgent: true, .. } => "!",
+            Self {
+                focused: Some(mon), ..
+            } if *mon == curr_mon && *mon == focused_mon => "#",
+            Self {
+                focused: Some(mon), ..
+            } if *mon == curr_mon => "+",
+            Self {
+                focused: Some(mon), ..
+            } if *mon == focused_mon => "%",
+            Self {
+                focused: Some(_), ..
+            } => "-",
+            Self { empty: false, .. } => ":",
+            _ => ".",
+        };
+        prefix.to_string() + self.name.as_str()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetArg<T: PartialEq + Clone>(pub T, pub bool);
+
+impl<T: PartialEq + Clone> SetArg<T> {
+    pub fn apply_arg(&self, arg: &mut T, last: T) -> bool {
+        if *arg != self.0 {
+            *arg = self.0.clone();
+            true
+        } else if self.1 && last != self.0 {
+            *arg = last;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// the state a flag is set to, toggle flips it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StateArg {
+    On,
+    Off,
+    Toggle,
+}
+
+impl StateArg {
+    pub fn apply(self, arg: &mut bool) -> bool {
+        SetArg::from(self).apply(arg)
+    }
+}
+
+impl From<StateArg> for SetArg<bool> {
+    fn from(state: StateArg) -> Self {
+        match state {
+            StateArg::On => SetArg(true, false),
+            StateArg::Off => SetArg(false, false),
+            StateArg::Toggle => SetArg(true, true),
+        }
+    }
+}
+
+impl FromStr for StateArg {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "on" => Self::On,
+            "off" => Self::Off,
+            "toggle" => Self::Toggle,
+            s => anyhow::bail!("state: expected on, off or toggle, got '{}'", s),
+        })
+    }
+}
+
+impl SetArg<bool> {
+    pub fn apply(&self, arg: &mut bool) -> bool {
+        if *arg != self.0 {
+            *arg = self.0;
+            true
+        } else if self.1 {
+            *arg ^= true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the server's end is non blocking like the streams of the wm
+    fn pair() -> (Stream, Stream) {
+        let (server, client) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        (Stream::new(server), Stream::new(client))
+    }
+
+    fn log(i: usize) -> CwmResponse {
+        CwmResponse::Log(vec![format!("{:08}", i); 1024])
+    }
+
+    #[test]
+    fn output_waits_for_room_instead_of_blocking() {
+        let (mut server, mut client) = pair();
+        let mut sent = 0;
+        while !server.pending() {
+            assert!(server.send(&log(sent)));
+            sent += 1;
+        }
+        // queued behind the message that didn't fit
+        assert!(server.send(&log(sent)));
+        sent += 1;
+        let mut received = 0;
+        while received < sent {
+            server.flush();
+            match client.recieve::<CwmResponse>() {
+                (_, Some(CwmResponse::Log(lines))) => {
+                    assert_eq!(lines, vec![format!("{:08}", received); 1024]);
+                    received += 1;
+                }
+                (_, Some(response)) => panic!("unexpected {:?}", response),
+                (false, None) => (),
+                (true, None) => panic!("closed after {} of {} messages", received, sent),
+            }
+        }
+        assert!(!server.pending());
+    }
+
+    #[test]
+    fn a_client_that_stops_reading_is_dropped() {
+        let (mut server, _client) = pair();
+        let message = bincode::serialize(&log(0)).unwrap().len() + 4;
+        let mut sent = 0;
+        while server.send(&log(sent)) {
+            sent += 1;
+        }
+        assert!(server.out.len() <= MAX_PENDING);
+        assert!(server.out.len() + message > MAX_PENDING);
+    }
+
+    #[test]
+    fn a_broken_stream_gives_up_its_output() {
+        let (mut server, client) = pair();
+        let mut sent = 0;
+        while !server.pending() {
+            server.send(&log(sent));
+            sent += 1;
+        }
+        drop(client);
+        assert!(!server.flush());
+        assert!(!server.pending());
+        assert!(!server.send(&CwmResponse::Pong));
+    }
+}
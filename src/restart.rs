@@ -0,0 +1,197 @@
+use anyhow::{Error, Result};
+use log::info;
+use nix::unistd::{execvp, getpid};
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+
+use crate::config::TagSwitchMode;
+use crate::connections::SetArg;
+use crate::{WindowLocation, WindowManager};
+
+// what an in-place restart hands to the next process, the windows stay mapped through the exec
+// and are managed again with the tags and flags they had
+#[derive(Serialize, Deserialize, Default)]
+pub struct RestartState {
+    tags: Vec<RestoredTag>,      // in order, temp tags are left out
+    monitors: Vec<(Atom, Atom)>, // monitor, shown tag
+    focused_monitor: Atom,
+    clients: Vec<RestoredClient>, // least recently focused first
+    others: Vec<(Window, Atom)>,  // panels and desktop windows with their monitor
+}
+
+#[derive(Serialize, Deserialize)]
+struct RestoredTag {
+    name: String,
+    monocle: bool,
+    volatile: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RestoredClient {
+    pub win: Window,
+    pub tag: Atom,
+    pub floating: bool,
+    pub hidden: bool,
+    pub fullscreen: bool,
+    pub sticky: bool,
+    pub aot: bool,
+    pub passthrough: bool,
+    pub protected: bool,
+    pub inhibit_fullscreen: bool,
+    pub border_width: Option<u16>,
+    pub border_color: Option<u32>,
+    pub rect: (i16, i16, u16, u16), // floating geometry without the border
+}
+
+impl RestartState {
+    pub fn load(path: &Path) -> Result<Self> {
+        let state = serde_json::from_slice(&std::fs::read(path)?)?;
+        let _ = std::fs::remove_file(path);
+        Ok(state)
+    }
+}
+
+fn state_path() -> PathBuf {
+    std::env::temp_dir().join(format!("cwm-restart-{}.json", getpid()))
+}
+
+impl WindowManager {
+    fn restart_state(&self) -> RestartState {
+        let mut state = RestartState {
+            focused_monitor: self.focused_monitor,
+            ..RestartState::default()
+        };
+        let focused_tag = self.focused_tag();
+        // the focused tag goes last so its focused client ends up focused
+        let mut tags: Vec<_> = self
+            .tag_order
+            .iter()
+            .filter(|tag| **tag != focused_tag)
+            .collect();
+        tags.push(&focused_tag);
+        for id in tags {
+            let tag = self.tags.get(id).unwrap();
+            for client_ in tag.get_stack().into_iter().rev() {
+                let client = tag.client(client_);
+                let rect = tag.get_client_rect(client_);
+                let border = client.border_width as i16;
+                state.clients.push(RestoredClient {
+                    win: client.win,
+                    tag: tag.id,
+                    floating: client.flags.floating,
+                    hidden: client.flags.hidden,
+                    fullscreen: client.flags.fullscreen,
+                    sticky: client.flags.sticky,
+                    aot: client.flags.aot,
+                    passthrough: client.flags.passthrough,
+                    protected: client.flags.protected,
+                    inhibit_fullscreen: client.flags.inhibit_fullscreen,
+                    border_width: client.border_override,
+                    border_color: client.border_color,
+                    rect: (
+                        rect.x + border,
+                        rect.y + border,
+                        rect.width.saturating_sub(2 * border as u16),
+                        rect.height.saturating_sub(2 * border as u16),
+                    ),
+                });
+            }
+        }
+        for id in self.tag_order.iter() {
+            let tag = self.tags.get(id).unwrap();
+            if !tag.temp() {
+                state.tags.push(RestoredTag {
+                    name: tag.name.clone(),
+                    monocle: tag.monocle(),
+                    volatile: tag.volatile,
+                });
+            }
+        }
+        for mon in self.monitors.values() {
+            state.monitors.push((mon.id, mon.focused_tag));
+        }
+        for (win, location) in self.windows.iter() {
+            match location {
+                WindowLocation::Panel(mon) | WindowLocation::DesktopWindow(mon) => {
+                    state.others.push((*win, *mon))
+                }
+                _ => (),
+            }
+        }
+        state
+    }
+
+    // only returns if the exec fails
+    pub fn restart(&mut self) -> Result<()> {
+        let path = state_path();
+        std::fs::write(&path, serde_json::to_vec(&self.restart_state())?)?;
+        // the icons are docked again when the config starts the next tray
+        self.tray_stop()?;
+        self.aux.dpy.flush()?;
+        info!("restarting from {}", path.display());
+        // the binary at the path cwm was started from, so an upgraded binary is picked up
+        let exe = std::env::args_os()
+            .next()
+            .ok_or_else(|| Error::msg("no argv[0] to restart from"))?;
+        let args = [
+            CString::new(exe.as_bytes())?,
+            CString::new("--restore")?,
+            CString::new(path.as_os_str().as_bytes())?,
+        ];
+        let err = execvp(&args[0], &args).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        Err(err.into())
+    }
+
+    // the tags exist before the config runs and before monitors pick theirs
+    pub fn restore_tags(&mut self, state: &RestartState) -> Result<()> {
+        for tag in state.tags.iter() {
+            self.add_tag(tag.name.as_str(), tag.volatile)?;
+            let id = intern_atom(&self.aux.dpy, false, tag.name.as_bytes())?
+                .reply()?
+                .atom;
+            self.tags
+                .get_mut(&id)
+                .unwrap()
+                .set_monocle(&self.aux, &SetArg(tag.monocle, false))?;
+        }
+        Ok(())
+    }
+
+    pub fn restore(&mut self, state: RestartState) -> Result<()> {
+        for (mon, tag) in state.monitors {
+            if self.monitors.contains_key(&mon) && self.tags.contains_key(&tag) {
+                self.switch_monitor_tag(mon, SetArg(tag, false), TagSwitchMode::Swap)?;
+            }
+        }
+        for (win, mon) in state.others {
+            if get_window_attributes(&self.aux.dpy, win)?.reply().is_ok()
+                && self.monitors.contains_key(&mon)
+            {
+                let _ = self.manage_requested(mon, win);
+            }
+        }
+        for client in state.clients {
+            let win = client.win;
+            if self.windows.contains_key(&win)
+                || get_window_attributes(&self.aux.dpy, win)?.reply().is_err()
+            {
+                continue;
+            }
+            self.restoring.insert(win, client);
+            let _ = self.manage_requested(self.focused_monitor, win);
+            self.restoring.remove(&win);
+        }
+        if self.monitors.contains_key(&state.focused_monitor) {
+            self.set_focus(state.focused_monitor)?;
+        }
+        self.aux
+            .hooks
+            .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
+        Ok(())
+    }
+}
@@ -1,86 +1,21 @@
 use anyhow::Result;
 use log::info;
-use x11rb::{
-    connection::Connection, properties::*, protocol::xproto::*, wrapper::ConnectionExt as _,
-    CURRENT_TIME, NONE,
-};
+use std::cell::RefCell;
+use std::time::Duration;
+use x11rb::{properties::*, protocol::xproto::*, wrapper::ConnectionExt as _, CURRENT_TIME, NONE};
 
-use super::{node::NodeContents, Layer, StackLayer, Tag};
+use crate::config::{FloatPlacement, FrameAction, HideStrategy, OverflowPolicy, TagSwitchMode};
 use crate::connections::{Aux, SetArg};
-use crate::rules::Rule;
-use crate::utils::Rect;
+use crate::core::{Client, ClientArgs, ClientFlags, NodeContents, Side, StackLayer, Tag};
+use crate::icon::Icon;
+use crate::protocol::{CycleFilter, HiddenClient, RuleTest, WindowFlags};
+use crate::restart::RestoredClient;
+use crate::timer::Timeout;
+use crate::utils::{latin1_or_utf8, mul_alpha, Rect};
+use crate::x11::{decode_text, X11Client};
 use crate::{WindowLocation, WindowManager};
 
-#[derive(Debug, Clone)]
-pub struct ClientFlags {
-    pub urgent: bool,
-    pub hidden: bool,
-    pub floating: bool,
-    pub fullscreen: bool,
-    pub sticky: bool,
-    pub psuedo_urgent: bool,
-}
-
-impl ClientFlags {
-    pub fn get_layer(&self) -> usize {
-        match self {
-            Self {
-                fullscreen: true, ..
-            } => Layer::FULLSCREEN,
-            Self { floating: true, .. } => Layer::FLOATING,
-            Self { .. } => Layer::TILING,
-        }
-    }
-
-    pub fn absent(&self) -> bool {
-        self.floating | self.fullscreen | self.hidden
-    }
-}
-
-// maybe replace with a bit field?
-#[derive(Default, Debug, Clone)]
-pub struct ClientProtocols {
-    delete: bool,
-}
-
-#[derive(Debug)]
-pub struct ClientArgs {
-    pub focus: bool,
-    pub flags: ClientFlags,
-    pub centered: bool,
-    pub managed: bool,
-    min_size: (u16, u16),
-    max_size: (u16, u16),
-    pub size: Option<(u16, u16)>,
-    pub pos: Option<(i16, i16)>,
-    layer: StackLayer,
-    class: Option<String>,
-    instance: Option<String>,
-    name: Option<String>,
-    net_name: bool,
-    tag: Option<u32>,
-    parent: Option<usize>, // a leaf
-    protocols: ClientProtocols,
-}
-
-impl PartialEq<Rule> for ClientArgs {
-    fn eq(&self, other: &Rule) -> bool {
-        self.name
-            .as_ref()
-            .map(|x| other.name.as_ref().map(|y| x == y).unwrap_or(true))
-            .unwrap_or_else(|| other.name.is_none())
-            && self
-                .instance
-                .as_ref()
-                .map(|x| other.instance.as_ref().map(|y| x == y).unwrap_or(true))
-                .unwrap_or_else(|| other.instance.is_none())
-            && self
-                .class
-                .as_ref()
-                .map(|x| other.class.as_ref().map(|y| x == y).unwrap_or(true))
-                .unwrap_or_else(|| other.class.is_none())
-    }
-}
+const OFFSCREEN: i32 = -0x4000;
 
 impl ClientArgs {
     pub fn new(aux: &Aux) -> Self {
@@ -93,6 +28,10 @@ impl ClientArgs {
                 sticky: false,
                 hidden: false,
                 psuedo_urgent: false,
+                aot: false,
+                passthrough: false,
+                protected: false,
+                inhibit_fullscreen: false,
             },
             centered: false,
             managed: true,
@@ -100,14 +39,21 @@ impl ClientArgs {
             size: None,
             max_size: (std::u16::MAX, std::u16::MAX),
             pos: None,
+            border_width: None,
+            border_color: None,
+            split: None,
+            force_fullscreen: false,
+            geometry: None,
+            pos_relative: false,
             class: None,
             name: None,
-            net_name: false,
             instance: None,
             layer: StackLayer::Normal,
             parent: None,
+            transient_for: None,
+            group: None,
             tag: None,
-            protocols: ClientProtocols::default(),
+            icon: None,
         }
     }
 
@@ -120,7 +66,12 @@ impl ClientArgs {
     }
 
     fn process_hints(&mut self, hints: WmHints) {
-        self.flags.urgent = hints.urgent
+        self.flags.urgent = hints.urgent;
+        self.group = hints.window_group.filter(|group| *group != NONE);
+        // windows that ask to start iconic start hidden
+        if let Some(WmHintsState::Iconic) = hints.initial_state {
+            self.flags.hidden = true;
+        }
     }
 
     fn prcoess_size_hints(&mut self, size_hints: WmSizeHints) {
@@ -137,56 +88,67 @@ impl ClientArgs {
     }
 
     fn process_class(&mut self, class: WmClass) {
-        self.class
-            .replace(String::from_utf8(class.class().to_vec()).unwrap());
-        self.instance
-            .replace(String::from_utf8(class.instance().to_vec()).unwrap());
+        self.class.replace(latin1_or_utf8(class.class()));
+        self.instance.replace(latin1_or_utf8(class.instance()));
     }
 
-    fn process_name(&mut self, name: GetPropertyReply, net: bool) {
+    // whether the window had a name
+    fn process_name(&mut self, aux: &Aux, name: GetPropertyReply) -> bool {
         if name.length > 0 {
-            self.name.replace(String::from_utf8(name.value).unwrap());
-            self.net_name = net;
+            self.name.replace(decode_text(&aux.atoms, &name));
         }
+        name.length > 0
     }
 
     fn process_transient(&mut self, transient: GetPropertyReply) {
         if let Some(mut transient) = transient.value32() {
-            if transient
-                .next()
-                .map_or(false, |transient| transient != NONE)
-            {
+            if let Some(parent) = transient.next().filter(|parent| *parent != NONE) {
                 self.flags.floating = true;
+                self.transient_for = Some(parent);
             }
         }
     }
 
-    fn process_protocol(&mut self, aux: &Aux, protocol: Atom) {
-        if protocol == aux.atoms.WM_DELETE_WINDOW {
-            self.protocols.delete = true;
-        }
+    // splits a leaf of the tag like a presel, the rules can still move it
+    pub fn pin(&mut self, tag: Atom, parent: Option<usize>, split: (Side, f32)) {
+        self.tag = Some(tag);
+        self.parent = parent;
+        self.split = Some(split);
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Client {
-    pub name: Option<String>,
-    net_name: bool,
-    class: Option<String>,
-    instance: Option<String>,
-    pub border_width: u16,
-    pub layer: StackLayer,
-    last_layer: StackLayer,
-    pub node: usize,
-    pub stack_pos: usize,
-    pub layer_pos: (usize, usize),
-    pub flags: ClientFlags,
-    pub win: Window,
-    protocols: ClientProtocols,
-    pub ignore_unmaps: usize,
+    // overrides everything read from the window with what it had before a restart
+    pub fn restore(&mut self, client: &RestoredClient) {
+        self.tag = Some(client.tag);
+        self.flags.floating = client.floating;
+        self.flags.hidden = client.hidden;
+        self.flags.fullscreen = client.fullscreen;
+        self.flags.sticky = client.sticky;
+        self.flags.aot = client.aot;
+        self.flags.passthrough = client.passthrough;
+        self.flags.protected = client.protected;
+        self.flags.inhibit_fullscreen = client.inhibit_fullscreen;
+        self.border_width = client.border_width;
+        self.border_color = client.border_color;
+        let (x, y, width, height) = client.rect;
+        self.pos = Some((x, y));
+        self.size = Some((width, height));
+        self.centered = false;
+    }
 }
 
 impl Client {
+    pub fn border_pixel(&self, aux: &Aux, focused: bool) -> u32 {
+        self.border_color.unwrap_or(if focused {
+            aux.theme.border_color_focused
+        } else {
+            aux.theme.border_color_unfocused
+        })
+    }
+
+    pub fn inactive_border_pixel(&self, aux: &Aux) -> u32 {
+        self.border_color.unwrap_or(aux.theme.border_color_inactive)
+    }
+
     pub fn send_message(&self, aux: &Aux, msg: Atom, val: Atom) -> Result<()> {
         let event = ClientMessageEvent {
             response_type: CLIENT_MESSAGE_EVENT,
@@ -201,7 +163,11 @@ impl Client {
     }
 
     pub fn close(&self, aux: &Aux, kill: bool) -> Result<()> {
-        if self.protocols.delete && !kill {
+        let delete = aux
+            .x11_clients
+            .get(&self.win)
+            .is_some_and(|client| client.protocols.delete);
+        if delete && !kill {
             self.send_message(aux, aux.atoms.WM_PROTOCOLS, aux.atoms.WM_DELETE_WINDOW)?;
         } else {
             kill_client(&aux.dpy, self.win)?;
@@ -221,9 +187,90 @@ impl Client {
         Ok(())
     }
 
+    // only the hidden state is kept up to date, states set by the client are left alone
+    pub fn set_net_hidden(&self, aux: &Aux, hidden: bool) -> Result<()> {
+        let hidden_state = aux.atoms._NET_WM_STATE_HIDDEN;
+        let mut states: Vec<Atom> = get_property(
+            &aux.dpy,
+            false,
+            self.win,
+            aux.atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            0,
+            2048,
+        )?
+        .reply()
+        .ok()
+        .and_then(|reply| {
+            reply
+                .value32()
+                .map(|states| states.filter(|state| *state != hidden_state).collect())
+        })
+        .unwrap_or_default();
+        if hidden {
+            states.push(hidden_state);
+        }
+        aux.dpy.change_property32(
+            PropMode::REPLACE,
+            self.win,
+            aux.atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            &states,
+        )?;
+        Ok(())
+    }
+
+    // sets the compton/picom shadow hint, hints set by the client are left alone when disabled
+    pub fn set_shadow(&self, aux: &Aux) -> Result<()> {
+        if !aux.theme.floating_shadows_only {
+            return Ok(());
+        }
+        let shadow = self.flags.floating && !self.flags.fullscreen;
+        for atom in [aux.atoms._COMPTON_SHADOW, aux.atoms._PICOM_SHADOW] {
+            aux.dpy.change_property32(
+                PropMode::REPLACE,
+                self.win,
+                atom,
+                AtomEnum::CARDINAL,
+                &[shadow as u32],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn clear_shadow(&self, aux: &Aux) -> Result<()> {
+        for atom in [aux.atoms._COMPTON_SHADOW, aux.atoms._PICOM_SHADOW] {
+            delete_property(&aux.dpy, self.win, atom)?;
+        }
+        Ok(())
+    }
+
+    pub fn window_flags(&self) -> WindowFlags {
+        WindowFlags {
+            floating: self.flags.floating,
+            fullscreen: self.flags.fullscreen,
+            sticky: self.flags.sticky,
+            hidden: self.flags.hidden,
+            urgent: self.flags.urgent || self.flags.psuedo_urgent,
+            layer: self.layer,
+        }
+    }
+
+    pub fn hidden_info(&self) -> HiddenClient {
+        HiddenClient {
+            win: self.win,
+            class: self.class.clone(),
+            name: self.name.clone(),
+            geometry: self
+                .applied
+                .borrow()
+                .as_ref()
+                .map(|(rect, _)| (rect.x, rect.y, rect.width, rect.height)),
+        }
+    }
+
     pub fn hide(&mut self, aux: &mut Aux, tag: Atom) -> Result<()> {
         info!("hiding window {}", self.win);
-        unmap_window(&aux.dpy, self.win)?;
         aux.dpy.change_property32(
             PropMode::REPLACE,
             self.win,
@@ -231,10 +278,45 @@ impl Client {
             aux.atoms.WM_STATE,
             &[3, NONE],
         )?;
-        self.ignore_unmaps += 1;
+        match aux.theme.hide_strategy {
+            // the window stays mapped, so there is no unmap to ignore
+            HideStrategy::Offscreen => self.move_offscreen(aux)?,
+            HideStrategy::Unmap => {
+                unmap_window(&aux.dpy, self.win)?;
+                aux.x11_clients.entry(self.win).or_default().ignore_unmaps += 1;
+            }
+        }
         aux.selection.hide(&aux.dpy, Some(tag), Some(self.node))?;
         Ok(())
     }
+
+    // hides a client whose tag is no longer shown
+    pub fn park(&mut self, aux: &mut Aux, tag: Atom) -> Result<()> {
+        match aux.theme.hide_strategy {
+            HideStrategy::Unmap => self.hide(aux, tag),
+            HideStrategy::Offscreen => {
+                self.move_offscreen(aux)?;
+                aux.selection.hide(&aux.dpy, Some(tag), Some(self.node))?;
+                Ok(())
+            }
+        }
+    }
+
+    fn move_offscreen(&self, aux: &mut Aux) -> Result<()> {
+        configure_window(
+            &aux.dpy,
+            self.win,
+            &ConfigureWindowAux::new().x(OFFSCREEN).y(OFFSCREEN),
+        )?;
+        // the next layout has to move it back
+        aux.animations.forget(self.win);
+        self.applied.replace(None);
+        // unlike an unmapped window it would keep the input focus
+        if get_input_focus(&aux.dpy)?.reply()?.focus == self.win {
+            set_input_focus(&aux.dpy, InputFocus::POINTER_ROOT, aux.root, CURRENT_TIME)?;
+        }
+        Ok(())
+    }
 }
 
 impl Tag {
@@ -247,12 +329,14 @@ impl Tag {
             return Ok(());
         }
         info!("tag {} set focus {}", self.name, _client);
-        if let Some(client) = self.focused {
+        aux.activity()?;
+        // without a focused client the front of the stack might still show the inactive color
+        if let Some(client) = self.focused.or_else(|| self.focus_stack.front().copied()) {
             let client = &self.clients[client];
             change_window_attributes(
                 &aux.dpy,
                 client.win,
-                &ChangeWindowAttributesAux::new().border_pixel(aux.theme.border_color_unfocused),
+                &ChangeWindowAttributesAux::new().border_pixel(client.border_pixel(aux, false)),
             )?;
         }
         let client = &mut self.clients[_client];
@@ -260,11 +344,12 @@ impl Tag {
         self.focused.replace(_client);
         client.stack_pos = self.focus_stack.push_front(_client);
         set_input_focus(&aux.dpy, InputFocus::PARENT, client.win, CURRENT_TIME)?;
+        aux.update_passthrough(client.flags.passthrough)?;
         // focused window callback
         change_window_attributes(
             &aux.dpy,
             client.win,
-            &ChangeWindowAttributesAux::new().border_pixel(aux.theme.border_color_focused),
+            &ChangeWindowAttributesAux::new().border_pixel(client.border_pixel(aux, true)),
         )?;
         client.flags.psuedo_urgent = false;
         let name = client.name.clone();
@@ -272,39 +357,138 @@ impl Tag {
             aux.hooks.update_tag(self);
         }
         self.set_active_window(name, &mut aux.hooks);
+        self.follow_selection(aux, self.id)?;
         Ok(())
     }
 
-    pub fn apply_pos_size(
-        &self,
-        aux: &Aux,
-        client: usize,
-        size: &Rect,
-        border: bool,
-    ) -> Result<()> {
-        let client = &self.clients[client];
-        let conf_aux = size.aux(if border { client.border_width } else { 0 });
-        configure_window(&aux.dpy, client.win, &conf_aux)?;
+    pub fn set_fullscreen(&mut self, aux: &Aux, client: usize, arg: &SetArg<bool>) -> Result<()> {
+        if arg.apply(&mut self.clients[client].flags.fullscreen) {
+            self.switch_layer(aux, client)?;
+        }
         Ok(())
     }
 
-    pub fn set_fullscreen(&mut self, aux: &Aux, client: usize, arg: &SetArg<bool>) -> Result<()> {
-        if arg.apply(&mut self.clients[client].flags.fullscreen) {
+    pub fn set_aot(&mut self, aux: &Aux, client: usize, arg: &SetArg<bool>) -> Result<()> {
+        if arg.apply(&mut self.clients[client].flags.aot) {
             self.switch_layer(aux, client)?;
         }
         Ok(())
     }
 
+    pub fn set_border_width(&mut self, aux: &Aux, client: usize, width: Option<u16>) -> Result<()> {
+        let theme_width = aux.theme.border_width(self.monitor);
+        let tiled_border = self.tiled_border(aux);
+        let client_ = &mut self.clients[client];
+        client_.border_override = width;
+        client_.border_width = width.unwrap_or(theme_width);
+        let tiled = !(client_.flags.floating || client_.flags.fullscreen);
+        let border = !(client_.flags.fullscreen || (tiled && !tiled_border));
+        if self.monitor.is_some() && !client_.flags.hidden {
+            if let Some(rect) = self.get_rect(aux, client) {
+                self.apply_pos_size(aux, client, &rect, border)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_border_color(&mut self, aux: &Aux, client: usize, color: Option<u32>) -> Result<()> {
+        let client_ = &mut self.clients[client];
+        client_.border_color = color;
+        let focused = self.focused == Some(client);
+        let client = &self.clients[client];
+        change_window_attributes(
+            &aux.dpy,
+            client.win,
+            &ChangeWindowAttributesAux::new().border_pixel(client.border_pixel(aux, focused)),
+        )?;
+        Ok(())
+    }
+
     pub fn set_floating(&mut self, aux: &Aux, client: usize, arg: &SetArg<bool>) -> Result<()> {
+        self.remember_floating(aux, client);
         if arg.apply(&mut self.clients[client].flags.floating) {
+            if self.clients[client].flags.floating {
+                self.restore_floating(aux, client);
+            }
             self.switch_layer(aux, client)?;
         }
         Ok(())
     }
 
+    // dialogs and windows without a class don't share a geometry with the rest of their class
+    fn float_key(&self, client: usize) -> Option<(&str, Option<&str>)> {
+        let client = &self.clients[client];
+        match (&client.class, client.transient_for) {
+            (Some(class), None) => Some((class, client.instance.as_deref())),
+            _ => None,
+        }
+    }
+
+    pub fn remember_floating(&self, aux: &Aux, client: usize) {
+        let flags = &self.clients[client].flags;
+        if !aux.theme.remember_floating || !flags.floating || flags.fullscreen {
+            return;
+        }
+        if let Some((class, instance)) = self.float_key(client) {
+            let rect = self.get_client_rect(client);
+            aux.float_store.remember(class, instance, rect, &self.size);
+        }
+    }
+
+    fn restore_floating(&mut self, aux: &Aux, client: usize) {
+        if !aux.theme.remember_floating {
+            return;
+        }
+        if let Some(rect) = self
+            .float_key(client)
+            .and_then(|(class, instance)| aux.float_store.get(class, instance, &self.size))
+        {
+            self.set_floating_rect(client, &rect);
+        }
+    }
+
+    // centered in the tiling area, or offset from the last cascaded client while that fits
+    fn place_floating(&mut self, placement: FloatPlacement, size: (u16, u16)) -> Rect {
+        let centered = Rect::new(
+            self.tiling_size.x + (self.tiling_size.width as i16 - size.0 as i16) / 2,
+            self.tiling_size.y + (self.tiling_size.height as i16 - size.1 as i16) / 2,
+            size.0,
+            size.1,
+        );
+        match placement {
+            FloatPlacement::Center => centered,
+            FloatPlacement::Cascade(dx, dy) => {
+                let rect = match self.cascade {
+                    Some((x, y)) => Rect::new(x + dx, y + dy, size.0, size.1),
+                    None => centered.clone(),
+                };
+                let rect = if self.tiling_size.contains_rect(&rect) {
+                    rect
+                } else {
+                    centered
+                };
+                self.cascade = Some((rect.x, rect.y));
+                rect
+            }
+        }
+    }
+
+    pub fn urgent_clients(&self) -> Vec<usize> {
+        self.urgent.union(&self.psuedo_urgent).copied().collect()
+    }
+
+    // true if the client was urgent
+    pub fn clear_urgent(&mut self, client_: usize) -> bool {
+        let flags = &mut self.clients[client_].flags;
+        flags.urgent = false;
+        flags.psuedo_urgent = false;
+        self.urgent.remove(&client_) | self.psuedo_urgent.remove(&client_)
+    }
+
     pub fn set_hidden(&mut self, aux: &mut Aux, client_: usize, arg: &SetArg<bool>) -> Result<()> {
         let client = &mut self.clients[client_];
         if arg.apply(&mut client.flags.hidden) {
+            client.set_net_hidden(aux, client.flags.hidden)?;
             if client.flags.hidden {
                 client.hide(aux, self.id)?;
                 self.focus_stack.remove_node(client.stack_pos);
@@ -342,25 +526,106 @@ impl Tag {
             self.focus_client(aux, client)?;
         } else {
             set_input_focus(&aux.dpy, InputFocus::POINTER_ROOT, aux.root, CURRENT_TIME)?;
+            aux.update_passthrough(false)?;
             self.set_active_window(None, &mut aux.hooks);
             self.focused.take();
         }
         Ok(())
     }
 
+    // the client keeps the front of the focus stack, so it gets the inactive color
     pub fn unset_focus(&mut self, aux: &Aux) -> Result<()> {
-        if let Some(client) = self.focused.take() {
-            let client = &self.clients[client];
+        if self.focused.take().is_some() {
+            self.show_inactive(aux)?;
+        }
+        Ok(())
+    }
+
+    // marks the client that gets focus once the monitor of this tag is focused again
+    pub fn show_inactive(&self, aux: &Aux) -> Result<()> {
+        if self.focused.is_some() {
+            return Ok(());
+        }
+        if let Some(client) = self.focus_stack.front() {
+            let client = &self.clients[*client];
             change_window_attributes(
                 &aux.dpy,
                 client.win,
-                &ChangeWindowAttributesAux::new().border_pixel(aux.theme.border_color_unfocused),
+                &ChangeWindowAttributesAux::new().border_pixel(client.inactive_border_pixel(aux)),
             )?;
         }
         Ok(())
     }
 
-    pub fn cycle(&mut self, aux: &mut Aux, rev: bool) -> Result<()> {
+    // like cycle, but only through the window group of the focused client
+    pub fn cycle_group(&mut self, aux: &mut Aux, rev: bool) -> Result<()> {
+        let group = match self
+            .focused_client()
+            .and_then(|client| self.clients[client].group)
+        {
+            Some(group) => group,
+            None => return Ok(()),
+        };
+        self.cycle_matching(aux, rev, |client| client.group == Some(group))
+    }
+
+    // only the matching clients move in the focus stack, the focused client goes to the back if
+    // it matches too
+    fn cycle_matching(
+        &mut self,
+        aux: &mut Aux,
+        rev: bool,
+        matches: impl Fn(&Client) -> bool,
+    ) -> Result<()> {
+        let focused = match self.focus_stack.front() {
+            Some(focused) => *focused,
+            None => return Ok(()),
+        };
+        let members = self
+            .focus_stack
+            .iter()
+            .copied()
+            .filter(|client| *client != focused && matches(&self.clients[*client]))
+            .collect::<Vec<_>>();
+        let next = if rev { members.last() } else { members.first() };
+        if let Some(&next) = next {
+            if !rev && matches(&self.clients[focused]) {
+                let client = &mut self.clients[focused];
+                self.focus_stack.remove_node(client.stack_pos);
+                client.stack_pos = self.focus_stack.push_back(focused);
+            }
+            let client = &mut self.clients[next];
+            self.focus_stack.remove_node(client.stack_pos);
+            client.stack_pos = self.focus_stack.push_front(next);
+            self.switch_layer(aux, next)?;
+            self.set_focus(aux)?;
+        }
+        Ok(())
+    }
+
+    pub fn cycle(&mut self, aux: &mut Aux, rev: bool, filter: CycleFilter) -> Result<()> {
+        match filter {
+            CycleFilter::All => self.cycle_all(aux, rev),
+            CycleFilter::Floating => self.cycle_matching(aux, rev, |client| {
+                client.flags.floating && !client.flags.fullscreen
+            }),
+            CycleFilter::Tiled => self.cycle_matching(aux, rev, |client| {
+                !(client.flags.floating || client.flags.fullscreen)
+            }),
+            CycleFilter::Class => {
+                match self
+                    .focused_client()
+                    .and_then(|client| self.clients[client].class.clone())
+                {
+                    Some(class) => self
+                        .cycle_matching(aux, rev, |client| client.class.as_ref() == Some(&class)),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn cycle_all(&mut self, aux: &mut Aux, rev: bool) -> Result<()> {
         if self.focus_stack.len() >= 2 {
             let client_ = if rev {
                 *self.focus_stack.back().unwrap()
@@ -390,7 +655,10 @@ impl WindowManager {
         let (win, node) = {
             let client = &mut tag.clients[client_];
             let (layer, layer_pos) = client.layer_pos;
-            tag.layers[layer].remove(layer_pos);
+            // a manage that failed part way might not have stacked the client yet
+            if tag.layers[layer].vec().contains(&client_) {
+                tag.layers[layer].remove(layer_pos);
+            }
             if !client.flags.hidden {
                 tag.focus_stack.remove_node(client.stack_pos);
             } else {
@@ -399,6 +667,10 @@ impl WindowManager {
             client.flags.hidden = true;
             (client.win, client.node)
         };
+        // the window is leaving, so there is no border to reset
+        if tag.focused == Some(client_) {
+            tag.focused.take();
+        }
         if tag.id
             == self
                 .monitors
@@ -421,8 +693,27 @@ impl WindowManager {
     }
 
     pub fn unmanage_client(&mut self, tag: Atom, client: usize) -> Result<()> {
+        let parent = {
+            let tag = self.tags.get(&tag).unwrap();
+            tag.remember_floating(&self.aux, client);
+            tag.client(client)
+                .transient_for
+                .filter(|_| tag.focused_client() == Some(client))
+        };
         let win = self.remove_client(tag, client)?;
+        if let Some(WindowLocation::Client(parent_tag, parent)) =
+            parent.and_then(|parent| self.windows.get(&parent).copied())
+        {
+            self.focus_parent(parent_tag, parent)?;
+        }
+        self.aux
+            .hooks
+            .client_unmanaged(win, &self.tags.get(&tag).unwrap().name);
+        self.aux.hooks.forget_properties(win);
+        self.aux.x11_clients.remove(&win);
         info!("Unmanaging and removing client {}, {}", win, client);
+        self.aux.animations.forget(win);
+        self.hold_layout(tag)?;
         delete_property(&self.aux.dpy, win, self.aux.atoms.WM_STATE)?;
         delete_property(&self.aux.dpy, win, self.aux.atoms._NET_WM_STATE)?;
         self.aux
@@ -431,7 +722,325 @@ impl WindowManager {
         Ok(())
     }
 
+    // the gap a closed client leaves stays empty so compositors can fade it out
+    fn hold_layout(&mut self, tag: Atom) -> Result<()> {
+        let ms = self.aux.theme.close_fade_ms;
+        let tag = self.tags.get_mut(&tag).unwrap();
+        if ms != 0 && tag.monitor.is_some() {
+            tag.layout_held = true;
+            self.aux
+                .timers
+                .set(Timeout::CloseFade(tag.id), Duration::from_millis(ms.into()))?;
+        }
+        Ok(())
+    }
+
+    pub fn release_layout(&mut self, tag: Atom) -> Result<()> {
+        if let Some(tag) = self.tags.get_mut(&tag) {
+            tag.layout_held = false;
+            if tag.monitor.is_some() {
+                tag.resize_tiled(&self.aux, 0, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    // every managed window sharing the group leader of a window, including the window itself
+    pub fn group_windows(&self, win: Window) -> Vec<Window> {
+        let group = match self.windows.get(&win) {
+            Some(WindowLocation::Client(tag, client)) => {
+                self.tags.get(tag).unwrap().client(*client).group
+            }
+            _ => return Vec::new(),
+        };
+        match group {
+            Some(group) => self
+                .tags
+                .values()
+                .flat_map(|tag| {
+                    tag.used_clients()
+                        .into_iter()
+                        .map(move |client| tag.client(client))
+                })
+                .filter(|client| client.group == Some(group))
+                .map(|client| client.win)
+                .collect(),
+            None => vec![win],
+        }
+    }
+
+    // with group hiding the rest of the group ends up in the same state as the window
+    pub fn set_window_hidden(&mut self, win: Window, arg: &SetArg<bool>) -> Result<()> {
+        let mut hidden = match self.windows.get(&win) {
+            Some(WindowLocation::Client(tag, client)) => {
+                self.tags.get(tag).unwrap().client(*client).flags.hidden
+            }
+            _ => return Ok(()),
+        };
+        arg.apply(&mut hidden);
+        let windows = if self.aux.theme.group_hide {
+            self.group_windows(win)
+        } else {
+            vec![win]
+        };
+        for win in windows {
+            if let Some(WindowLocation::Client(tag, client)) = self.windows.get(&win).copied() {
+                self.tags.get_mut(&tag).unwrap().set_hidden(
+                    &mut self.aux,
+                    client,
+                    &SetArg(hidden, false),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // false if the press wasn't on the border of a client, protected clients aren't closed
+    pub fn frame_button(
+        &mut self,
+        win: Window,
+        x: i16,
+        y: i16,
+        action: FrameAction,
+    ) -> Result<bool> {
+        match self.frame_client(win, x, y)? {
+            Some((tag, client)) => {
+                self.frame_action(tag, client, action)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // the client whose border is at the position
+    pub fn frame_client(&self, win: Window, x: i16, y: i16) -> Result<Option<(Atom, usize)>> {
+        let (tag, client) = match self.windows.get(&win) {
+            Some(WindowLocation::Client(tag, client)) => (*tag, *client),
+            _ => return Ok(None),
+        };
+        let geom = match get_geometry(&self.aux.dpy, win)?.reply() {
+            Ok(geom) => geom,
+            Err(_) => return Ok(None),
+        };
+        let border = geom.border_width as i32;
+        let (x, y) = ((x - geom.x) as i32, (y - geom.y) as i32);
+        if (border..border + geom.width as i32).contains(&x)
+            && (border..border + geom.height as i32).contains(&y)
+        {
+            return Ok(None);
+        }
+        Ok(Some((tag, client)))
+    }
+
+    pub fn frame_action(&mut self, tag: Atom, client: usize, action: FrameAction) -> Result<()> {
+        let win = self.tags.get(&tag).unwrap().client(client).win;
+        info!("frame action {:?} on {}", action, win);
+        let toggle = SetArg(true, true);
+        match action {
+            FrameAction::Close | FrameAction::Kill => {
+                let client = self.tags.get(&tag).unwrap().client(client);
+                if !client.flags.protected {
+                    client.close(&self.aux, action == FrameAction::Kill)?;
+                }
+            }
+            FrameAction::Hide => self.set_window_hidden(win, &SetArg(true, false))?,
+            FrameAction::Floating => self
+                .tags
+                .get_mut(&tag)
+                .unwrap()
+                .set_floating(&self.aux, client, &toggle)?,
+            FrameAction::Fullscreen => self
+                .tags
+                .get_mut(&tag)
+                .unwrap()
+                .set_fullscreen(&self.aux, client, &toggle)?,
+            FrameAction::Sticky => self.set_sticky(tag, client, &toggle),
+            FrameAction::Raise | FrameAction::Lower => {
+                let tag = self.tags.get_mut(&tag).unwrap();
+                let layer = match (tag.client(client).layer, action) {
+                    (StackLayer::Below, FrameAction::Raise) => StackLayer::Normal,
+                    (StackLayer::Normal, FrameAction::Raise) => StackLayer::Above,
+                    (StackLayer::Above, FrameAction::Lower) => StackLayer::Normal,
+                    (StackLayer::Normal, FrameAction::Lower) => StackLayer::Below,
+                    (layer, _) => layer,
+                };
+                tag.set_stack_layer(&self.aux, client, &SetArg(layer, false))?;
+            }
+        }
+        Ok(())
+    }
+
+    // shows where focus went after a tag switch
+    pub fn flash_focus(&mut self) -> Result<()> {
+        if self.aux.theme.focus_flash_ms == 0 {
+            return Ok(());
+        }
+        let tag = self.tags.get(&self.focused_tag()).unwrap();
+        if let Some(client) = tag.focused_client() {
+            let win = tag.client(client).win;
+            let theme = &self.aux.theme;
+            self.flash_border(win, theme.focus_flash_color, theme.focus_flash_ms)?;
+        }
+        Ok(())
+    }
+
+    pub fn visual_bell(&mut self, client: Option<u32>) -> Result<()> {
+        if self.aux.theme.bell_flash_ms == 0 {
+            return Ok(());
+        }
+        if let Some((tag, client)) = self.get_client(client) {
+            let win = self.tags.get(&tag).unwrap().client(client).win;
+            let theme = &self.aux.theme;
+            self.flash_border(win, theme.bell_flash_color, theme.bell_flash_ms)?;
+        }
+        Ok(())
+    }
+
+    fn flash_border(&self, win: Window, color: u32, ms: u32) -> Result<()> {
+        change_window_attributes(
+            &self.aux.dpy,
+            win,
+            &ChangeWindowAttributesAux::new().border_pixel(color),
+        )?;
+        self.aux
+            .timers
+            .set(Timeout::FocusFlash(win), Duration::from_millis(ms.into()))
+    }
+
+    pub fn end_focus_flash(&mut self, win: Window) -> Result<()> {
+        if let Some(WindowLocation::Client(tag, client)) = self.windows.get(&win) {
+            let tag = self.tags.get(tag).unwrap();
+            let focused = tag.focused == Some(*client);
+            change_window_attributes(
+                &self.aux.dpy,
+                win,
+                &ChangeWindowAttributesAux::new()
+                    .border_pixel(tag.client(*client).border_pixel(&self.aux, focused)),
+            )?;
+        }
+        Ok(())
+    }
+
+    // a closed dialog hands focus back to the window it belongs to
+    fn focus_parent(&mut self, tag: Atom, parent: usize) -> Result<()> {
+        let focused_tag = self
+            .monitors
+            .get(&self.focused_monitor)
+            .unwrap()
+            .focused_tag;
+        let tag = self.tags.get_mut(&tag).unwrap();
+        if tag.id == focused_tag && !tag.client(parent).flags.hidden {
+            tag.switch_layer(&self.aux, parent)?;
+            tag.focus_client(&mut self.aux, parent)?;
+        }
+        Ok(())
+    }
+
     pub fn process_args(&mut self, win: Window, args: &mut ClientArgs) -> Result<()> {
+        let x11 = self.read_args(win, args)?;
+        self.aux.x11_clients.insert(win, x11);
+        // a volatile assignment creates its tag for the first window of the class
+        if let Some((tag, true)) = args
+            .class
+            .as_ref()
+            .and_then(|class| self.aux.class_tags.get(class))
+            .copied()
+        {
+            if args.tag.is_none() && !self.tags.contains_key(&tag) {
+                let name = get_atom_name(&self.aux.dpy, tag)?.reply()?.name;
+                self.add_tag(String::from_utf8_lossy(&name), true)?;
+                args.tag = Some(tag);
+            }
+        }
+        if let Some((tag, split)) = args
+            .class
+            .as_ref()
+            .and_then(|class| self.aux.class_pins.remove(class))
+        {
+            if self.tags.contains_key(&tag) {
+                args.tag = Some(tag);
+                args.split = split.map(|(side, ratio)| (side, ratio.clamp(Side::MIN, Side::MAX)));
+            }
+        }
+        self.aux
+            .rules
+            .retain(|r| if args == r { !r.apply(args) } else { true });
+        let mon_id = args
+            .tag
+            .and_then(|tag| self.tags.get(&tag))
+            .and_then(|tag| tag.monitor)
+            .unwrap_or(self.focused_monitor);
+        let mon = self.monitors.get(&mon_id).unwrap().size.clone();
+        if let Some(geometry) = args.geometry.take() {
+            // the geometry includes the border like the rest of the floating geometry commands
+            let border = args
+                .border_width
+                .unwrap_or_else(|| self.aux.theme.border_width(Some(mon_id)));
+            let (x, y, width, height) = geometry.resolve((mon.x, mon.y, mon.width, mon.height));
+            args.pos = Some((x + border as i16, y + border as i16));
+            args.size = Some((
+                width.saturating_sub(border * 2).max(1),
+                height.saturating_sub(border * 2).max(1),
+            ));
+        } else if args.pos_relative {
+            if let Some(pos) = &mut args.pos {
+                pos.0 += mon.x;
+                pos.1 += mon.y;
+            }
+        }
+        if self.aux.theme.remember_floating
+            && args.flags.floating
+            && args.pos.is_none()
+            && args.transient_for.is_none()
+        {
+            if let Some(rect) = args.class.as_ref().and_then(|class| {
+                self.aux
+                    .float_store
+                    .get(class, args.instance.as_deref(), &mon)
+            }) {
+                let border = args
+                    .border_width
+                    .unwrap_or_else(|| self.aux.theme.border_width(Some(mon_id)));
+                args.pos = Some((rect.x + border as i16, rect.y + border as i16));
+                args.size = Some((
+                    rect.width.saturating_sub(border * 2).max(1),
+                    rect.height.saturating_sub(border * 2).max(1),
+                ));
+            }
+        }
+        if args.force_fullscreen && args.flags.floating && !args.flags.fullscreen {
+            let size = (mon.width, mon.height);
+            // asking for exactly the size of the monitor is asking for fullscreen
+            if args.min_size == size || args.max_size == size || args.size == Some(size) {
+                info!("putting {:?} on the fullscreen layer", args.class);
+                args.flags.fullscreen = true;
+            }
+        }
+        Ok(())
+    }
+
+    // what the rules would do to a window if it was mapped now, nothing is applied
+    pub fn test_rules(&self, win: Window) -> Result<RuleTest> {
+        let mut args = ClientArgs::new(&self.aux);
+        self.read_args(win, &mut args)?;
+        Ok(RuleTest {
+            rules: self
+                .aux
+                .rules
+                .iter()
+                .map(|rule| (rule.clone(), args == *rule))
+                .collect(),
+            tag: args
+                .tag
+                .map(|tag| self.tags.get(&tag).unwrap().name.clone()),
+            class: args.class,
+            instance: args.instance,
+            name: args.name,
+        })
+    }
+
+    // the x11 state of the window is returned, it is kept once the window is managed
+    fn read_args(&self, win: Window, args: &mut ClientArgs) -> Result<X11Client> {
         let state_cookie = get_property(
             &self.aux.dpy,
             false,
@@ -449,7 +1058,7 @@ impl WindowManager {
             false,
             win,
             AtomEnum::WM_NAME,
-            self.aux.atoms.UTF8_STRING,
+            AtomEnum::ANY,
             0,
             2048,
         )?;
@@ -458,7 +1067,7 @@ impl WindowManager {
             false,
             win,
             self.aux.atoms._NET_WM_NAME,
-            self.aux.atoms.UTF8_STRING,
+            AtomEnum::ANY,
             0,
             2048,
         )?;
@@ -480,6 +1089,7 @@ impl WindowManager {
             0,
             32,
         )?;
+        let icon_cookie = Icon::get_net_wm_icon(&self.aux.dpy, win, self.aux.atoms._NET_WM_ICON)?;
 
         if let Ok(states) = state_cookie.reply() {
             if let Some(states) = states.value32() {
@@ -488,38 +1098,56 @@ impl WindowManager {
                 }
             }
         }
-        let _ = hints_cookie.reply().map(|hints| args.process_hints(hints));
+        let hints = hints_cookie.reply().ok();
+        if let Some(hints) = hints {
+            args.process_hints(hints);
+        }
         let _ = size_hints_cookie
             .reply()
             .map(|size_hints| args.prcoess_size_hints(size_hints));
         let _ = class_cookie.reply().map(|class| args.process_class(class));
+        let mut x11 = X11Client::default();
         let _ = name_cookie
             .reply()
-            .map(|name| args.process_name(name, false));
-        let _ = wm_name_cookie
+            .map(|name| args.process_name(&self.aux, name));
+        x11.net_name = wm_name_cookie
             .reply()
-            .map(|name| args.process_name(name, true));
+            .map(|name| args.process_name(&self.aux, name))
+            .unwrap_or(false);
         let _ = transient_cookie
             .reply()
             .map(|transient| args.process_transient(transient));
         if let Ok(protocols) = protocols_cookie.reply() {
             if let Some(protocols) = protocols.value32() {
                 for protocol in protocols {
-                    args.process_protocol(&self.aux, protocol);
+                    x11.process_protocol(&self.aux.atoms, protocol);
                 }
             }
         }
-
-        self.aux
-            .rules
-            .retain(|r| if args == r { !r.apply(args) } else { true });
-        Ok(())
+        args.icon = Icon::from_replies(&self.aux.dpy, icon_cookie.reply().ok(), hints.as_ref());
+        if let Some((tag, _)) = args
+            .class
+            .as_ref()
+            .and_then(|class| self.aux.class_tags.get(class))
+        {
+            if self.tags.contains_key(tag) {
+                args.tag = Some(*tag);
+            }
+        }
+        // dialogs open on the tag of their parent
+        if let Some(WindowLocation::Client(tag, _)) = args
+            .transient_for
+            .and_then(|parent| self.windows.get(&parent))
+        {
+            args.tag = Some(*tag);
+        }
+        Ok(x11)
     }
 
     pub fn manage_client(&mut self, win: Window, args: ClientArgs) -> Result<()> {
         let ClientArgs {
             focus,
-            flags,
+            mut flags,
             centered,
             managed: _,
             min_size,
@@ -529,17 +1157,25 @@ impl WindowManager {
             class,
             instance,
             name,
-            net_name,
             tag,
             mut pos,
-            parent,
-            protocols,
+            border_width: border_override,
+            border_color,
+            split,
+            force_fullscreen,
+            geometry: _,
+            pos_relative: _,
+            mut parent,
+            transient_for,
+            group,
+            icon,
         } = args;
         let tag_idx = tag
             .and_then(|tag| self.tags.contains_key(&tag).then(|| tag))
             .unwrap_or_else(|| self.focused_tag());
+        let tag_idx = self.overflow(tag_idx, &mut flags);
         let tag = self.tags.get_mut(&tag_idx).unwrap();
-        let border_width = self.aux.theme.border_width;
+        let border_width = border_override.unwrap_or(self.aux.theme.border_width(tag.monitor));
         let mut size = if let Some(size) = size {
             size
         } else {
@@ -550,12 +1186,13 @@ impl WindowManager {
         size.0 += border_width * 2;
         size.1 += border_width * 2;
         let mut floating_rect = if centered || pos.is_none() {
-            Rect::new(
-                tag.tiling_size.x + (tag.tiling_size.width as i16 - size.0 as i16) / 2,
-                tag.tiling_size.y + (tag.tiling_size.height as i16 - size.1 as i16) / 2,
-                size.0,
-                size.1,
-            )
+            // dialogs stay centered and tiled clients don't move the cascade along
+            let placement = if flags.floating && transient_for.is_none() {
+                self.aux.theme.float_placement
+            } else {
+                FloatPlacement::Center
+            };
+            tag.place_floating(placement, size)
         } else {
             let mut pos = pos.unwrap();
             pos.0 -= border_width as i16;
@@ -564,29 +1201,59 @@ impl WindowManager {
         };
         tag.tiling_size.clamp(&mut floating_rect);
 
+        self.aux
+            .hooks
+            .client_managed(win, class.as_deref(), &tag.name);
+        let waits = self.aux.hooks.window_managed(
+            win,
+            class.as_deref(),
+            instance.as_deref(),
+            name.as_deref(),
+        );
+        for id in waits {
+            self.aux.timers.cancel(Timeout::WindowWait(id))?;
+        }
         let hidden = flags.hidden;
+        if self.aux.theme.insert_at_pointer && parent.is_none() && tag.monitor.is_some() {
+            if let Ok(pointer) = query_pointer(&self.aux.dpy, self.aux.root)?.reply() {
+                parent = tag.client_under_cursor(0, &(pointer.root_x, pointer.root_y));
+            }
+        }
         let client = Client {
             name,
-            net_name,
             node: 0,
             class,
             instance,
             border_width,
+            border_override,
+            border_color: border_color.map(mul_alpha),
             layer,
             last_layer: layer,
             stack_pos: 0,
             layer_pos: (0, 0),
             flags,
             win,
-            protocols,
-            ignore_unmaps: 0,
+            applied: RefCell::new(None),
+            transient_for,
+            group,
+            icon,
+            prev_tag: None,
+            force_fullscreen,
+            pip: None,
         };
 
         info!("adding client {:?}", client);
         let info = NodeContents::leaf(0, min_size, max_size, floating_rect);
 
         info!("currennt node state {:?}, {:?}", tag.free_nodes, tag.nodes);
+        tag.next_split = split;
+        // a new client takes the space of a fading one right away
+        tag.layout_held = false;
         let client = tag.add_client(&mut self.aux, client, parent, info, focus)?;
+        tag.next_split = None;
+        // tracked before anything else can fail, so a destroy notify still finds the client
+        self.windows
+            .insert(win, WindowLocation::Client(tag.id, client));
 
         change_window_attributes(
             &self.aux.dpy,
@@ -602,6 +1269,7 @@ impl WindowManager {
         tag.set_layer(&self.aux, client, focus)?;
         if let Some(client) = tag.clients.get_mut(client) {
             if hidden {
+                client.set_net_hidden(&self.aux, true)?;
                 client.hide(&mut self.aux, tag.id)?
             } else {
                 client.show(&self.aux)?
@@ -614,21 +1282,57 @@ impl WindowManager {
                 &self.aux.dpy,
                 win,
                 &ChangeWindowAttributesAux::new()
-                    .border_pixel(self.aux.theme.border_color_unfocused),
+                    .border_pixel(tag.client(client).border_pixel(&self.aux, false)),
             )?;
         }
         let tag = tag.id;
         self.ewmh_set_client_tag(client, tag)?;
 
-        self.aux.dpy.flush()?;
-        self.windows
-            .insert(win, WindowLocation::Client(tag, client));
         self.aux
             .hooks
             .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
         Ok(())
     }
 
+    // where a new client goes when its tag already has the most tiled clients allowed
+    fn overflow(&self, tag: Atom, flags: &mut ClientFlags) -> Atom {
+        let limit = match self.aux.theme.tiled_limit {
+            Some(limit) if !flags.absent() => limit,
+            _ => return tag,
+        };
+        let tag_ = self.tags.get(&tag).unwrap();
+        let tiled = tag_
+            .used_clients()
+            .into_iter()
+            .filter(|client| !tag_.client(*client).flags.absent())
+            .count();
+        if tiled < limit {
+            return tag;
+        }
+        if self.aux.theme.overflow_policy == OverflowPolicy::NextEmpty {
+            let pos = self.tag_order.iter().position(|x| *x == tag).unwrap_or(0);
+            let (before, after) = self.tag_order.split_at(pos);
+            if let Some(empty) = after
+                .iter()
+                .chain(before)
+                .find(|tag| self.tags.get(tag).unwrap().empty())
+            {
+                return *empty;
+            }
+        }
+        info!("tag {} is full, hiding the new client", tag_.name);
+        flags.hidden = true;
+        tag
+    }
+
+    // the tag a client was last moved from, if it still exists
+    pub fn client_prev_tag(&self, tag: Atom, client: usize) -> Option<Atom> {
+        self.tags
+            .get(&tag)
+            .and_then(|tag| tag.client(client).prev_tag)
+            .filter(|prev| self.tags.contains_key(prev))
+    }
+
     pub fn move_client(
         &mut self,
         tag: Atom,
@@ -636,7 +1340,9 @@ impl WindowManager {
         set_dest: SetArg<Atom>,
     ) -> Result<usize> {
         let mut dest = tag;
-        if let Some(mon) = self.tags.get(&tag).and_then(|tag| tag.monitor) {
+        if let Some(prev) = self.client_prev_tag(tag, client) {
+            set_dest.apply_arg(&mut dest, prev);
+        } else if let Some(mon) = self.tags.get(&tag).and_then(|tag| tag.monitor) {
             let prev = self.monitors.get(&mon).unwrap().prev_tag;
             set_dest.apply_arg(&mut dest, prev);
         } else {
@@ -649,13 +1355,20 @@ impl WindowManager {
             "Moving client, src {}, dst: {}, client: {}",
             tag, dest, client
         );
-        let (client_, mut info, focus, old_size, show) = {
+        let transients = {
+            let tag = self.tags.get(&tag).unwrap();
+            tag.transients(tag.client(client).win)
+                .into_iter()
+                .map(|transient| tag.client(transient).win)
+                .collect::<Vec<_>>()
+        };
+        let (mut client_, mut info, focus, old_size, show) = {
             let hide = self.tags.get(&dest).unwrap().monitor.is_none();
             let tag = self.tags.get_mut(&tag).unwrap();
             let focus = Some(client) == tag.focused_client();
             let client = &mut tag.clients[client];
             if hide {
-                client.hide(&mut self.aux, tag.id)?;
+                client.park(&mut self.aux, tag.id)?;
             }
             let node = &tag.nodes[client.node];
             (
@@ -667,12 +1380,17 @@ impl WindowManager {
             )
         };
         self.remove_client(tag, client)?;
+        client_.prev_tag.replace(tag);
         let hidden = client_.flags.hidden;
         let win = client_.win;
         let tag = self.tags.get_mut(&dest).unwrap();
         if let NodeContents::Leaf(leaf) = &mut info {
             leaf.floating.reposition(&old_size, &tag.size);
         }
+        // the border follows the monitor of the tag it is moved to
+        client_.border_width = client_
+            .border_override
+            .unwrap_or(self.aux.theme.border_width(tag.monitor));
         let client = tag.add_client(&mut self.aux, client_, None, info, focus)?;
         tag.set_layer(&self.aux, client, focus)?;
         if show {
@@ -692,27 +1410,123 @@ impl WindowManager {
                     &self.aux.dpy,
                     win,
                     &ChangeWindowAttributesAux::new()
-                        .border_pixel(self.aux.theme.border_color_unfocused),
+                        .border_pixel(tag.client(client).border_pixel(&self.aux, false)),
                 )?;
             }
         }
         let tag = tag.id;
         self.ewmh_set_client_tag(client, tag)?;
-        self.aux.dpy.flush()?;
         self.windows
             .insert(win, WindowLocation::Client(tag, client));
+        // dialogs follow their parent
+        for transient in transients {
+            if let Some(WindowLocation::Client(src, transient)) =
+                self.windows.get(&transient).copied()
+            {
+                self.move_client(src, transient, SetArg(dest, false))?;
+            }
+        }
         self.aux
             .hooks
             .tag_update(&self.tags, &self.tag_order, self.focused_monitor);
         Ok(client)
     }
 
+    pub fn focus_window(&mut self, win: Window) -> Result<()> {
+        if let Some(WindowLocation::Client(tag, client)) = self.windows.get(&win).copied() {
+            let mon = if let Some(mon) = self.tags.get(&tag).unwrap().monitor {
+                mon
+            } else {
+                self.switch_monitor_tag(
+                    self.focused_monitor,
+                    SetArg(tag, false),
+                    TagSwitchMode::Swap,
+                )?;
+                self.focused_monitor
+            };
+            self.set_focus(mon)?;
+            self.tags
+                .get_mut(&tag)
+                .unwrap()
+                .focus_client(&mut self.aux, client)?;
+        }
+        Ok(())
+    }
+
+    // windows of the focused client's class on every tag, in tag order
+    pub fn cycle_class(&mut self, rev: bool) -> Result<()> {
+        let tag = self.tags.get(&self.focused_tag()).unwrap();
+        let (focused, class) = match tag.focused_client().and_then(|client| {
+            let client = tag.client(client);
+            client.class.clone().map(|class| (client.win, class))
+        }) {
+            Some(focused) => focused,
+            None => return Ok(()),
+        };
+        let wins: Vec<Window> = self
+            .tag_order
+            .iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .flat_map(|tag| {
+                tag.used_clients()
+                    .into_iter()
+                    .map(move |client| tag.client(client))
+            })
+            .filter(|client| !client.flags.hidden && client.class.as_ref() == Some(&class))
+            .map(|client| client.win)
+            .collect();
+        if let Some(pos) = wins.iter().position(|win| *win == focused) {
+            if wins.len() >= 2 {
+                let pos = if rev {
+                    (pos + wins.len() - 1) % wins.len()
+                } else {
+                    (pos + 1) % wins.len()
+                };
+                self.focus_window(wins[pos])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn urgent_timeout(&self, win: Window) {
+        let ms = self.aux.theme.urgent_timeout_ms;
+        if ms != 0 {
+            self.aux
+                .timers
+                .set(Timeout::Urgent(win), Duration::from_millis(ms.into()))
+                .ok();
+        }
+    }
+
+    pub fn urgent_expired(&mut self, win: Window) {
+        if let Some(WindowLocation::Client(tag, client)) = self.windows.get(&win) {
+            if self.tags.get_mut(tag).unwrap().clear_urgent(*client) {
+                self.aux
+                    .hooks
+                    .tag_update(&self.tags, &self.tag_order, self.focused_monitor)
+            }
+        }
+    }
+
+    // the focused client, when it isn't the one asking, holds back fullscreen requests
+    fn fullscreen_inhibited(&self, tag: Atom, client: usize) -> bool {
+        let focused = self.tags.get(&self.focused_tag()).unwrap();
+        match focused.focused_client() {
+            Some(focused_) if focused.id != tag || focused_ != client => {
+                focused.client(focused_).flags.inhibit_fullscreen
+            }
+            _ => false,
+        }
+    }
+
     pub fn client_state(&mut self, tag: Atom, client_: usize, state: Atom, action: Atom) {
         let name = get_atom_name(&self.aux.dpy, state)
             .unwrap()
             .reply()
             .unwrap();
-        info!("Client state, {}", String::from_utf8(name.name).unwrap());
+        info!("Client state, {}", String::from_utf8_lossy(&name.name));
+        let inhibited = state == self.aux.atoms._NET_WM_STATE_FULLSCREEN
+            && self.fullscreen_inhibited(tag, client_);
         let tag = self.tags.get_mut(&tag).unwrap();
         let client = &mut tag.clients[client_];
         let arg = match action {
@@ -721,31 +1535,52 @@ impl WindowManager {
             2 => SetArg(false, true),
             _ => return,
         };
-        if state == self.aux.atoms._NET_WM_STATE_DEMANDS_ATTENTION
-            && tag.focused != Some(client_)
-            && arg.apply(&mut client.flags.psuedo_urgent)
-        {
+        let mut fullscreen = client.flags.fullscreen;
+        let enter = SetArg(true, false);
+        // an inhibited request to enter fullscreen demands attention instead
+        let attention = if state == self.aux.atoms._NET_WM_STATE_DEMANDS_ATTENTION {
+            Some(&arg)
+        } else if inhibited && client.force_fullscreen && arg.apply(&mut fullscreen) && fullscreen {
+            info!(
+                "fullscreen of {} inhibited by the focused client",
+                client.win
+            );
+            Some(&enter)
+        } else {
+            None
+        };
+        if let Some(arg) = attention {
+            if tag.focused == Some(client_) || !arg.apply(&mut client.flags.psuedo_urgent) {
+                return;
+            }
+            let win = client.win;
             if client.flags.psuedo_urgent {
-                tag.psuedo_urgent.insert(client_)
+                tag.psuedo_urgent.insert(client_);
+                self.urgent_timeout(win);
             } else {
-                tag.psuedo_urgent.remove(&client_)
-            };
+                tag.psuedo_urgent.remove(&client_);
+            }
             self.aux
                 .hooks
                 .tag_update(&self.tags, &self.tag_order, self.focused_monitor)
+        } else if state == self.aux.atoms._NET_WM_STATE_FULLSCREEN && client.force_fullscreen {
+            // cwm ignores fullscreen requests after mapping, except from windows like utility
+            // windows of video players that a force-fullscreen-layer rule matched
+            tag.set_fullscreen(&self.aux, client_, &arg).ok();
         }
     }
 
     pub fn client_property(&mut self, tag: Atom, client_: usize, atom: Atom) {
         let tag = self.tags.get_mut(&tag).unwrap();
         let client = &mut tag.clients[client_];
-        if !client.net_name && atom == AtomEnum::WM_NAME.into() {
+        let x11 = self.aux.x11_clients.entry(client.win).or_default();
+        if !x11.net_name && atom == u32::from(AtomEnum::WM_NAME) {
             if let Some(name) = get_property(
                 &self.aux.dpy,
                 false,
                 client.win,
                 AtomEnum::WM_NAME,
-                self.aux.atoms.UTF8_STRING,
+                AtomEnum::ANY,
                 0,
                 2048,
             )
@@ -753,7 +1588,7 @@ impl WindowManager {
             .and_then(|cookie| cookie.reply().ok())
             {
                 if name.length > 0 {
-                    let name = String::from_utf8(name.value).unwrap();
+                    let name = decode_text(&self.aux.atoms, &name);
                     client.name.replace(name.clone());
                     if tag.focus_stack.front() == Some(&client_) {
                         tag.set_active_window(Some(name), &mut self.aux.hooks)
@@ -766,7 +1601,7 @@ impl WindowManager {
                 false,
                 client.win,
                 self.aux.atoms._NET_WM_NAME,
-                self.aux.atoms.UTF8_STRING,
+                AtomEnum::ANY,
                 0,
                 2048,
             )
@@ -774,15 +1609,20 @@ impl WindowManager {
             .and_then(|cookie| cookie.reply().ok())
             {
                 if name.length > 0 {
-                    client.net_name = true;
-                    let name = String::from_utf8(name.value).unwrap();
+                    x11.net_name = true;
+                    let name = decode_text(&self.aux.atoms, &name);
                     client.name.replace(name.clone());
                     if tag.focus_stack.front() == Some(&client_) {
                         tag.set_active_window(Some(name), &mut self.aux.hooks)
                     }
                 }
             }
-        } else if atom == AtomEnum::WM_HINTS.into() {
+        } else if atom == self.aux.atoms._NET_WM_ICON {
+            client.icon = Icon::get(&self.aux.dpy, client.win, self.aux.atoms._NET_WM_ICON);
+        } else if atom == u32::from(AtomEnum::WM_HINTS) {
+            if client.icon.is_none() {
+                client.icon = Icon::get(&self.aux.dpy, client.win, self.aux.atoms._NET_WM_ICON);
+            }
             if let Some(hints) = WmHints::get(&self.aux.dpy, client.win)
                 .ok()
                 .and_then(|cookie| cookie.reply().ok())
@@ -795,6 +1635,10 @@ impl WindowManager {
                 };
                 if changed {
                     tag.clients[client_].flags.urgent = hints.urgent;
+                    if hints.urgent {
+                        let win = tag.clients[client_].win;
+                        self.urgent_timeout(win);
+                    }
                     self.aux
                         .hooks
                         .tag_update(&self.tags, &self.tag_order, self.focused_monitor)
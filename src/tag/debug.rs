@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::core::{NodeContents, Tag};
+
+impl Tag {
+    pub fn used_clients(&self) -> Vec<usize> {
+        (0..self.clients.len())
+            .filter(|i| !self.free_clients.contains(i))
+            .collect()
+    }
+
+    pub fn debug_dump(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "tag {} ({})", self.name, self.id);
+        let _ = writeln!(
+            out,
+            "  monitor: {:?}, monocle: {}",
+            self.monitor, self.monocle
+        );
+        let _ = writeln!(out, "  free nodes: {:?}", self.free_nodes);
+        let _ = writeln!(out, "  free clients: {:?}", self.free_clients);
+        for (i, node) in self.nodes.iter().enumerate() {
+            let _ = writeln!(out, "  node {}: {:?}", i, node);
+        }
+        for (i, client) in self.clients.iter().enumerate() {
+            let _ = writeln!(out, "  client {}: {:?}", i, client);
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            let _ = writeln!(out, "  layer {}: {:?}", i, layer.vec());
+        }
+        let _ = writeln!(out, "  focus stack: {:?}", self.get_stack());
+        let _ = writeln!(out, "  hidden: {:?}", self.hidden);
+        out
+    }
+
+    // returns a description of every broken invariant
+    pub fn check_invariants(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut err = |msg: String| errors.push(format!("tag {}: {}", self.name, msg));
+
+        let free_nodes = self.free_nodes.iter().copied().collect::<HashSet<_>>();
+        if free_nodes.len() != self.free_nodes.len() {
+            err(format!("duplicate free nodes {:?}", self.free_nodes));
+        }
+        for &node in free_nodes.iter().filter(|&&x| x >= self.nodes.len()) {
+            err(format!("free node {} is out of range", node));
+        }
+        for &client in self
+            .free_clients
+            .iter()
+            .filter(|&&x| x >= self.clients.len())
+        {
+            err(format!("free client {} is out of range", client));
+        }
+
+        // walk the tree from the root checking parent links
+        let mut reachable = HashSet::new();
+        let mut q = vec![0];
+        while let Some(node) = q.pop() {
+            if !reachable.insert(node) {
+                err(format!("node {} is reachable more than once", node));
+                continue;
+            }
+            if free_nodes.contains(&node) {
+                err(format!("node {} is in the tree and the free list", node));
+            }
+            match &self.nodes[node].info {
+                NodeContents::Node(info) => {
+                    for (child, first) in [(info.first_child, true), (info.second_child, false)] {
+                        if child >= self.nodes.len() {
+                            err(format!("node {} has out of range child {}", node, child));
+                            continue;
+                        }
+                        if self.nodes[child].parent != Some((node, first)) {
+                            err(format!(
+                                "node {} has parent {:?}, expected {:?}",
+                                child,
+                                self.nodes[child].parent,
+                                Some((node, first))
+                            ));
+                        }
+                        q.push(child);
+                    }
+                }
+                NodeContents::Leaf(..) => {
+                    let client = self.get_node_client(node).unwrap();
+                    if client >= self.clients.len() || self.free_clients.contains(&client) {
+                        err(format!("leaf {} points at unused client {}", node, client));
+                    } else if self.clients[client].node != node {
+                        err(format!(
+                            "leaf {} points at client {} which points at node {}",
+                            node, client, self.clients[client].node
+                        ));
+                    }
+                }
+                NodeContents::Empty => {
+                    if node != 0 {
+                        err(format!("non root node {} is empty", node));
+                    }
+                }
+            }
+        }
+        for node in
+            (0..self.nodes.len()).filter(|x| !reachable.contains(x) && !free_nodes.contains(x))
+        {
+            err(format!("node {} is orphaned", node));
+        }
+
+        let focus_stack = self.get_stack();
+        let in_stack = focus_stack.iter().copied().collect::<HashSet<_>>();
+        if in_stack.len() != focus_stack.len() {
+            err(format!(
+                "duplicate clients in the focus stack {:?}",
+                focus_stack
+            ));
+        }
+        let layers = self.get_layers();
+        for (i, client) in self.clients.iter().enumerate() {
+            if self.free_clients.contains(&i) {
+                if in_stack.contains(&i) {
+                    err(format!("free client {} is in the focus stack", i));
+                }
+                if layers.iter().any(|layer| layer.contains(&i)) {
+                    err(format!("free client {} is in a layer", i));
+                }
+                continue;
+            }
+            if !reachable.contains(&client.node) {
+                err(format!(
+                    "client {} points at node {} outside the tree",
+                    i, client.node
+                ));
+            } else if self.get_node_client(client.node) != Some(i) {
+                err(format!(
+                    "client {} points at node {} which doesn't point back",
+                    i, client.node
+                ));
+            }
+            if !in_stack.contains(&i) {
+                err(format!("client {} is missing from the focus stack", i));
+            }
+            let (layer, _) = client.layer_pos;
+            if !layers.get(layer).is_some_and(|x| x.contains(&i)) {
+                err(format!("client {} is missing from layer {}", i, layer));
+            }
+        }
+        errors
+    }
+}
@@ -1,99 +1,37 @@
 use anyhow::Result;
 use log::info;
-use std::collections::{hash_map::Entry, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
 use x11rb::protocol::xproto::*;
 
 use super::Monitor;
-use crate::connections::{HiddenSelection, SetArg};
-use crate::utils::{pop_set_ord, Rect, Stack};
+use crate::config::TagSwitchMode;
+use crate::connections::{HiddenClient, HiddenSelection, SetArg, TagTemplate};
+use crate::core::Tag;
+use crate::hooks;
+use crate::utils::pop_set_ord;
 use crate::{Aux, Hooks, WindowManager};
 
+// the x11 side of tags and clients, the layout itself is in core
 mod client;
-mod layer;
-mod node;
-use layer::Layer;
-use node::Node;
-
-pub use node::NodeContents;
-
-pub use client::{Client, ClientArgs, ClientFlags};
-pub use layer::StackLayer;
-pub use node::{Side, Split};
-
-pub struct Tag {
-    pub id: Atom,
-    pub name: String,
-    nodes: Vec<Node>,
-    clients: Vec<Client>,
-    free_nodes: Vec<usize>,
-    free_clients: HashSet<usize>,
-    focus_stack: Stack<usize>,
-    layers: [Layer; Layer::COUNT * Layer::SUBCOUNT],
-    pub size: Rect,
-    tiling_size: Rect,
-    focused: Option<usize>,
-    pub monitor: Option<Atom>,
-    urgent: HashSet<usize>,
-    psuedo_urgent: HashSet<usize>,
-    hidden: VecDeque<usize>,
-    monocle: bool,
-    temp: bool,
-    bg: Option<Window>,
-}
+mod debug;
 
 impl Tag {
-    pub fn get_layers(&self) -> Vec<Vec<usize>> {
-        self.layers.iter().map(|layer| layer.vec()).collect()
-    }
-    pub fn get_stack(&self) -> Vec<usize> {
-        self.focus_stack.iter().cloned().collect()
-    }
-    pub fn get_clients(&self) -> Vec<(usize, u32, Option<String>)> {
-        self.clients.iter().enumerate().map(|(i, x)| (i, x.win, x.name.clone())).collect()
-    }
-    pub fn empty(&self) -> bool {
-        self.clients.len() == self.free_clients.len()
-    }
-
-    pub fn urgent(&self) -> bool {
-        !(self.urgent.is_empty() && self.psuedo_urgent.is_empty())
-    }
-
-    pub fn focused_client(&self) -> Option<usize> {
-        self.focus_stack.front().copied()
-    }
-
-    pub fn client(&self, client: usize) -> &Client {
-        &self.clients[client]
-    }
-
-    pub fn client_mut(&mut self, client: usize) -> &mut Client {
-        &mut self.clients[client]
-    }
-
-    pub fn clients(&self) -> &[Client] {
-        self.clients.as_ref()
-    }
-
-    pub fn clients_mut(&mut self) -> &mut [Client] {
-        self.clients.as_mut()
-    }
-
-    pub fn node(&self, node: usize) -> &Node {
-        &self.nodes[node]
-    }
-
-    pub fn node_mut(&mut self, node: usize) -> &mut Node {
-        &mut self.nodes[node]
+    pub fn hidden_clients(&self) -> Vec<HiddenClient> {
+        self.hidden
+            .iter()
+            .map(|&client| self.clients[client].hidden_info())
+            .collect()
     }
 
     pub fn set_monitor(&mut self, aux: &mut Aux, monitor: &mut Monitor) -> Result<()> {
         if monitor.focused_tag == self.id {
             return Ok(());
         }
-        // resize the windows
+        // resize the windows, the monitor is set first so its theme overrides are used
         let available = monitor.free_rect();
         info!("resizing, {:?}, {:?}", self.size, monitor.size);
+        self.monitor.replace(monitor.id);
+        self.update_border_widths(aux);
         self.resize_all(aux, &available, &monitor.size)?;
         self.size.copy(&monitor.size);
         info!("showing windows");
@@ -104,7 +42,6 @@ impl Tag {
         }
 
         info!("done showing windows");
-        self.monitor.replace(monitor.id);
         self.bg.replace(monitor.bg);
         monitor.prev_tag = monitor.focused_tag;
         monitor.focused_tag = self.id;
@@ -119,13 +56,6 @@ impl Tag {
         Ok(())
     }
 
-    pub fn set_monocle(&mut self, aux: &Aux, arg: &SetArg<bool>) -> Result<()> {
-        if arg.apply(&mut self.monocle) {
-            self.resize_tiled(aux, 0, None)?;
-        }
-        Ok(())
-    }
-
     pub fn show_clients(&mut self, aux: &mut Aux, selection: HiddenSelection) -> Result<()> {
         match selection {
             HiddenSelection::Last => {
@@ -143,6 +73,21 @@ impl Tag {
                     self.set_hidden(aux, client, &SetArg(false, false))?
                 }
             }
+            HiddenSelection::Nth(n) => {
+                if let Some(client) = self.hidden.get(n).copied() {
+                    self.set_hidden(aux, client, &SetArg(false, false))?
+                }
+            }
+            HiddenSelection::Window(win) => {
+                if let Some(client) = self
+                    .hidden
+                    .iter()
+                    .find(|client| self.clients[**client].win == win)
+                    .copied()
+                {
+                    self.set_hidden(aux, client, &SetArg(false, false))?
+                }
+            }
         }
         Ok(())
     }
@@ -152,56 +97,32 @@ impl Tag {
         self.bg.take();
         for client in self.clients.iter_mut() {
             if !client.flags.sticky && !client.flags.hidden {
-                client.hide(aux, self.id)?;
+                client.park(aux, self.id)?;
             }
         }
         self.unset_focus(aux)?;
         Ok(())
     }
 
-    fn set_active_window(&self, name: Option<String>, hooks: &mut Hooks) {
-        if let Some(monitor) = self.monitor {
-            hooks.monitor_focus(monitor, name)
+    // a selection left on `from` moves to the focused client of this tag or is cancelled
+    pub fn follow_selection(&self, aux: &mut Aux, from: Atom) -> Result<()> {
+        if aux.selection.tag() != Some(from) {
+            return Ok(());
+        }
+        match self.focused.filter(|_| aux.theme.selection_follow) {
+            Some(client) => {
+                aux.selection.anchor(self.id, self.clients[client].node);
+                aux.resize_selection(self)?;
+                aux.selection.show(&aux.dpy)?;
+            }
+            None => aux.selection.hide(&aux.dpy, Some(from), None)?,
         }
+        Ok(())
     }
-}
 
-impl Default for Tag {
-    fn default() -> Self {
-        Tag {
-            id: 0,
-            name: String::new(),
-            nodes: vec![Node {
-                absent: false,
-                info: NodeContents::empty(),
-                parent: None,
-                rect: Rect::new(0, 0, 1920, 1080),
-            }],
-            clients: Vec::new(),
-            free_nodes: Vec::new(),
-            free_clients: HashSet::new(),
-            focus_stack: Stack::default(),
-            layers: [
-                Layer::Multi(Stack::default()),
-                Layer::Multi(Stack::default()),
-                Layer::Single(None),
-                Layer::Multi(Stack::default()),
-                Layer::Multi(Stack::default()),
-                Layer::Single(None),
-                Layer::Multi(Stack::default()),
-                Layer::Multi(Stack::default()),
-                Layer::Single(None),
-            ],
-            size: Rect::new(0, 0, 1920, 1080),
-            tiling_size: Rect::default(),
-            focused: None,
-            monitor: None,
-            urgent: HashSet::new(),
-            psuedo_urgent: HashSet::new(),
-            hidden: VecDeque::new(),
-            temp: false,
-            monocle: false,
-            bg: None,
+    fn set_active_window(&self, name: Option<String>, hooks: &mut Hooks) {
+        if let Some(monitor) = self.monitor {
+            hooks.monitor_focus(monitor, name)
         }
     }
 }
@@ -235,7 +156,7 @@ impl WindowManager {
         Ok(id)
     }
 
-    pub fn add_tag(&mut self, name: impl Into<String>) -> Result<bool> {
+    pub fn add_tag(&mut self, name: impl Into<String>, volatile: bool) -> Result<bool> {
         let name = name.into();
         let id = intern_atom(&self.aux.dpy, false, name.as_ref())?
             .reply()?
@@ -243,6 +164,7 @@ impl WindowManager {
         let tag = Tag {
             id,
             name,
+            volatile,
             ..Tag::default()
         };
         match self.tags.entry(id) {
@@ -262,6 +184,45 @@ impl WindowManager {
         Ok(true)
     }
 
+    // nothing is spawned if a tag with the name already exists
+    pub fn add_tag_from_template(&mut self, template: TagTemplate) -> Result<()> {
+        if !self.add_tag(template.name.as_str(), false)? {
+            info!(
+                "tag {} already exists, not applying its template",
+                template.name
+            );
+            return Ok(());
+        }
+        let id = intern_atom(&self.aux.dpy, false, template.name.as_bytes())?
+            .reply()?
+            .atom;
+        self.tags.get_mut(&id).unwrap().monocle = template.monocle;
+        for spawn in template.spawn {
+            self.aux.class_pins.insert(spawn.class, (id, spawn.split));
+            hooks::spawn(&spawn.command);
+        }
+        Ok(())
+    }
+
+    pub fn remove_volatile_tags(&mut self) -> Result<()> {
+        let mut empty = Vec::new();
+        for tag in self.tags.values_mut().filter(|tag| tag.volatile) {
+            if !tag.empty() {
+                tag.occupied = true;
+            } else if tag.occupied && tag.monitor.is_none() {
+                empty.push(tag.id);
+            }
+        }
+        for tag in empty {
+            info!(
+                "removing empty volatile tag {}",
+                self.tags.get(&tag).unwrap().name
+            );
+            self.remove_tag(tag)?;
+        }
+        Ok(())
+    }
+
     pub fn remove_tag(&mut self, tag: Atom) -> Result<()> {
         if self.free_tags.is_empty() {
             return Ok(());
@@ -274,7 +235,7 @@ impl WindowManager {
             } else {
                 pop_set_ord(&mut self.free_tags, &self.tag_order).unwrap()
             };
-            self.switch_monitor_tag(mon_, SetArg(new_tag, false))?;
+            self.switch_monitor_tag(mon_, SetArg(new_tag, false), TagSwitchMode::Swap)?;
             self.monitors.get_mut(&mon_).unwrap().prev_tag = new_tag;
             new_tag
         } else {
@@ -292,6 +253,7 @@ impl WindowManager {
             self.move_client(tag, client, SetArg(new_tag, false))?;
         }
         self.tag_order.retain(|id| id != &tag);
+        self.free_tags.remove(&tag);
         let tag = self.tags.remove(&tag).unwrap();
         if tag.temp {
             self.temp_tags.retain(|id| id != &tag.id);
@@ -0,0 +1,303 @@
+// helpers for driving the wm against a nested x server, used by integration tests
+use anyhow::{bail, Result};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::COPY_DEPTH_FROM_PARENT;
+
+use crate::connections::{ClientRequest, CwmResponse, Hello, HelloReply, Stream, PROTOCOL_VERSION};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// tests run in parallel, two servers must not pick the same free display
+static STARTING: Mutex<()> = Mutex::new(());
+
+fn wait_for(mut ready: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while Instant::now() < deadline {
+        if ready() {
+            return true;
+        }
+        sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+// Xvfb, or Xephyr when there is a display to open its window on
+fn server_program() -> Option<&'static str> {
+    if in_path("Xvfb") {
+        Some("Xvfb")
+    } else if in_path("Xephyr") && std::env::var_os("DISPLAY").is_some() {
+        Some("Xephyr")
+    } else {
+        None
+    }
+}
+
+// an Xvfb or Xephyr server on the first free display, killed when dropped
+pub struct NestedServer {
+    child: Child,
+    pub display: String,
+}
+
+impl NestedServer {
+    // integration tests are skipped when this is false
+    pub fn available() -> bool {
+        server_program().is_some()
+    }
+
+    pub fn start(width: u16, height: u16) -> Result<Self> {
+        let program = match server_program() {
+            Some(program) => program,
+            None => bail!("neither Xvfb nor Xephyr could be found"),
+        };
+        let _starting = STARTING.lock().unwrap_or_else(|e| e.into_inner());
+        let num = (10..100)
+            .find(|num| {
+                !Path::new(&format!("/tmp/.X11-unix/X{}", num)).exists()
+                    && !Path::new(&format!("/tmp/.X{}-lock", num)).exists()
+            })
+            .unwrap_or(99);
+        let display = format!(":{}", num);
+        let mut command = Command::new(program);
+        command.arg(&display);
+        if program == "Xvfb" {
+            command.args(["-screen", "0", &format!("{}x{}x24", width, height)]);
+        } else {
+            command.args(["-screen", &format!("{}x{}", width, height)]);
+        }
+        let child = command
+            .args(["+extension", "RANDR"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut server = Self { child, display };
+        if !wait_for(|| RustConnection::connect(Some(&server.display)).is_ok()) {
+            let _ = server.child.kill();
+            bail!("{} did not start on {}", program, server.display)
+        }
+        Ok(server)
+    }
+
+    pub fn connect(&self) -> Result<(RustConnection, Window)> {
+        let (dpy, screen) = RustConnection::connect(Some(&self.display))?;
+        let root = dpy.setup().roots[screen].root;
+        Ok((dpy, root))
+    }
+
+    // simulates a monitor being plugged in, or resized if it exists
+    pub fn set_monitor(&self, name: &str, x: i16, y: i16, width: u16, height: u16) -> Result<()> {
+        let (dpy, root) = self.connect()?;
+        let name = intern_atom(&dpy, false, name.as_bytes())?.reply()?.atom;
+        dpy.randr_set_monitor(
+            root,
+            randr::MonitorInfo {
+                name,
+                primary: false,
+                automatic: false,
+                x,
+                y,
+                width,
+                height,
+                width_in_millimeters: 0,
+                height_in_millimeters: 0,
+                outputs: Vec::new(),
+            },
+        )?;
+        dpy.sync()?;
+        Ok(())
+    }
+
+    // simulates a monitor being unplugged
+    pub fn remove_monitor(&self, name: &str) -> Result<()> {
+        let (dpy, root) = self.connect()?;
+        let name = intern_atom(&dpy, false, name.as_bytes())?.reply()?.atom;
+        dpy.randr_delete_monitor(root, name)?;
+        dpy.sync()?;
+        Ok(())
+    }
+}
+
+impl Drop for NestedServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// the wm binary running headless on a nested server, with its own socket and no user config
+pub struct TestWm {
+    child: Child,
+    home: PathBuf,
+    pub socket: PathBuf,
+}
+
+impl TestWm {
+    pub fn start(wm: &Path, server: &NestedServer) -> Result<Self> {
+        let home = std::env::temp_dir().join(format!(
+            "cwm-test-{}-{}",
+            std::process::id(),
+            server.display.trim_start_matches(':')
+        ));
+        std::fs::create_dir_all(&home)?;
+        let socket = home.join("cwm.sock");
+        let child = Command::new(wm)
+            .env("DISPLAY", &server.display)
+            .env("CWM_SOCKET", &socket)
+            .env("HOME", &home)
+            .env("XDG_STATE_HOME", &home)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut wm = Self {
+            child,
+            home,
+            socket,
+        };
+        if !wait_for(|| wm.request(&ClientRequest::Ping).is_ok()) {
+            let _ = wm.child.kill();
+            bail!("the wm did not start on {}", server.display)
+        }
+        Ok(wm)
+    }
+
+    pub fn connect(&self) -> Result<Stream> {
+        let stream = UnixStream::connect(&self.socket)?;
+        stream.set_read_timeout(Some(Duration::from_millis(50)))?;
+        let mut stream = Stream::new(stream);
+        stream.send(&Hello {
+            version: PROTOCOL_VERSION,
+        });
+        match stream.recieve_timeout(REPLY_TIMEOUT) {
+            Some(HelloReply::Accepted { .. }) => Ok(stream),
+            Some(HelloReply::Rejected { reason, .. }) => bail!("wm rejected client: {}", reason),
+            None => bail!("no handshake from the wm"),
+        }
+    }
+
+    // sends a request and waits briefly for a response, requests without one return None
+    pub fn request(&self, request: &ClientRequest) -> Result<Option<CwmResponse>> {
+        let mut stream = self.connect()?;
+        if !stream.send(request) {
+            bail!("could not send {:?}", request)
+        }
+        Ok(stream.recieve_timeout(Duration::from_millis(200)))
+    }
+
+    // a round trip through the event loop, everything sent before it has been handled after
+    pub fn sync(&self) -> Result<()> {
+        match self.request(&ClientRequest::Ping)? {
+            Some(CwmResponse::Pong) => Ok(()),
+            response => bail!("expected pong, got {:?}", response),
+        }
+    }
+
+    // fails with the broken invariants if the internal state is inconsistent
+    pub fn check(&self) -> Result<()> {
+        match self.request(&ClientRequest::DebugDump)? {
+            Some(CwmResponse::DebugDump(_, errors)) if errors.is_empty() => Ok(()),
+            Some(CwmResponse::DebugDump(dump, errors)) => {
+                bail!("{}\n{}", errors.join("\n"), dump)
+            }
+            response => bail!("expected a debug dump, got {:?}", response),
+        }
+    }
+}
+
+impl Drop for TestWm {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.home);
+    }
+}
+
+// a plain window on its own connection, like a separate application
+pub struct DummyClient {
+    dpy: RustConnection,
+    pub win: Window,
+}
+
+impl DummyClient {
+    pub fn new(server: &NestedServer, class: &str) -> Result<Self> {
+        let (dpy, root) = server.connect()?;
+        let win = dpy.generate_id()?;
+        create_window(
+            &dpy,
+            COPY_DEPTH_FROM_PARENT,
+            win,
+            root,
+            0,
+            0,
+            200,
+            100,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+        )?;
+        // instance and class, both set to the class
+        let wm_class = format!("{}\0{}\0", class, class);
+        dpy.change_property8(
+            PropMode::REPLACE,
+            win,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            wm_class.as_bytes(),
+        )?;
+        dpy.change_property8(
+            PropMode::REPLACE,
+            win,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            class.as_bytes(),
+        )?;
+        dpy.sync()?;
+        Ok(Self { dpy, win })
+    }
+
+    pub fn map(&self) -> Result<()> {
+        map_window(&self.dpy, self.win)?;
+        self.dpy.sync()?;
+        Ok(())
+    }
+
+    pub fn unmap(&self) -> Result<()> {
+        unmap_window(&self.dpy, self.win)?;
+        self.dpy.sync()?;
+        Ok(())
+    }
+
+    pub fn geometry(&self) -> Result<GetGeometryReply> {
+        Ok(get_geometry(&self.dpy, self.win)?.reply()?)
+    }
+
+    pub fn is_viewable(&self) -> Result<bool> {
+        Ok(get_window_attributes(&self.dpy, self.win)?
+            .reply()?
+            .map_state
+            == MapState::VIEWABLE)
+    }
+}
+
+impl Drop for DummyClient {
+    fn drop(&mut self) {
+        let _ = destroy_window(&self.dpy, self.win);
+        let _ = self.dpy.sync();
+    }
+}
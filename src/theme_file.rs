@@ -0,0 +1,424 @@
+use anyhow::{bail, Result};
+use log::info;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env::var;
+use std::ffi::OsStr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+
+use crate::config::{
+    FloatPlacement, FocusIndicator, FrameAction, HideStrategy, OverflowPolicy, PointerBindings,
+    Swipe, TagSwitchMode, ThemeField,
+};
+use crate::connections::ClientRequest;
+use crate::core::Side;
+use crate::utils::mul_alpha;
+use crate::WindowManager;
+
+const FILE_NAME: &str = "theme.toml";
+
+fn config_dir() -> Option<PathBuf> {
+    var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            var("HOME")
+                .ok()
+                .map(|home| PathBuf::from(home).join(".config"))
+        })
+        .map(|dir| dir.join("cwm"))
+}
+
+// the directory is watched instead of the file since editors usually replace the file on save
+pub struct ThemeWatch {
+    inotify: Inotify,
+    dir: Option<PathBuf>,
+}
+
+impl ThemeWatch {
+    pub fn new() -> Result<Self> {
+        let watch = Self {
+            inotify: Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?,
+            dir: config_dir(),
+        };
+        watch.watch();
+        Ok(watch)
+    }
+
+    // the directory might not exist yet, so this is tried again on every reload
+    pub fn watch(&self) {
+        if let Some(dir) = &self.dir {
+            let _ = self.inotify.add_watch(
+                dir,
+                AddWatchFlags::IN_CLOSE_WRITE
+                    | AddWatchFlags::IN_MOVED_TO
+                    | AddWatchFlags::IN_DELETE,
+            );
+        }
+    }
+
+    pub fn path(&self) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(FILE_NAME))
+    }
+
+    // drains the pending events, true if any of them were for the theme file
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        while let Ok(events) = self.inotify.read_events() {
+            if events.is_empty() {
+                break;
+            }
+            changed |= events
+                .iter()
+                .any(|event| event.name.as_deref() == Some(OsStr::new(FILE_NAME)));
+        }
+        changed
+    }
+}
+
+// nix leaves closing the inotify instance to its owner
+impl Drop for ThemeWatch {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.inotify.as_raw_fd());
+    }
+}
+
+impl AsRawFd for ThemeWatch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct MonitorTheme {
+    gap: Option<u16>,
+    border_width: Option<u16>,
+    top_margin: Option<i16>,
+    bottom_margin: Option<i16>,
+    left_margin: Option<i16>,
+    right_margin: Option<i16>,
+}
+
+// every field of the theme, fields left out keep whatever the config or ipc set last
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    border_width: Option<u16>,
+    gap: Option<u16>,
+    top_margin: Option<i16>,
+    bottom_margin: Option<i16>,
+    left_margin: Option<i16>,
+    right_margin: Option<i16>,
+    window_width: Option<u16>,
+    window_height: Option<u16>,
+    window_min_width: Option<u16>,
+    window_min_height: Option<u16>,
+    border_color_focused: Option<u32>,
+    border_color_unfocused: Option<u32>,
+    border_color_inactive: Option<u32>,
+    selection_gap: Option<u16>,
+    presel_color: Option<u32>,
+    sel_color: Option<u32>,
+    tag_switch_mode: Option<String>,
+    hide_strategy: Option<String>,
+    tiled_limit: Option<usize>, // 0 removes the limit
+    overflow_policy: Option<String>,
+    monocle_floating: Option<bool>,
+    monocle_borderless: Option<bool>,
+    monocle_gapless: Option<bool>,
+    floating_shadows_only: Option<bool>,
+    group_hide: Option<bool>,
+    insert_at_pointer: Option<bool>,
+    focus_flash_color: Option<u32>,
+    focus_flash_ms: Option<u32>,
+    bell_flash_color: Option<u32>,
+    bell_flash_ms: Option<u32>,
+    focus_indicator: Option<String>, // off, edge or corner
+    focus_indicator_size: Option<u16>,
+    focus_indicator_color: Option<u32>,
+    min_tile_size: Option<(u16, u16)>, // 0x0 stops collapsing tiled clients
+    urgent_timeout_ms: Option<u32>,
+    idle_timeout_ms: Option<u32>,
+    presel_timeout_ms: Option<u32>,
+    focus_settle_ms: Option<u32>,
+    close_fade_ms: Option<u32>,
+    remember_floating: Option<bool>,
+    float_placement: Option<String>, // center or cascade
+    float_cascade_offset: Option<(i16, i16)>,
+    selection_follow: Option<bool>,
+    refocus_off_monitors: Option<bool>,
+    pointer_bindings: Option<PointerBindings>,
+    frame_buttons: Vec<(u8, u16, FrameAction)>, // button, modifiers, action
+    frame_double_click: Option<FrameAction>,
+    double_click_ms: Option<u32>,
+    swipe: Option<Swipe>,
+    panels_above_fullscreen: Option<bool>,
+    desktop_click_through: Option<bool>,
+    monitors: HashMap<String, MonitorTheme>, // by output name
+}
+
+impl WindowManager {
+    // the fields that have a config request go through it so the change shows up right away
+    fn theme_requests(&mut self, file: ThemeFile) -> Result<Vec<ClientRequest>> {
+        let theme = &mut self.aux.theme;
+        let mut requests = Vec::new();
+        if let Some(width) = file.border_width {
+            requests.push(ClientRequest::ConfigBorderWidth(width));
+        }
+        if let Some(gap) = file.gap {
+            requests.push(ClientRequest::ConfigGap(gap));
+        }
+        for (side, marg) in [
+            (Side::Top, file.top_margin),
+            (Side::Bottom, file.bottom_margin),
+            (Side::Left, file.left_margin),
+            (Side::Right, file.right_margin),
+        ] {
+            if let Some(marg) = marg {
+                requests.push(ClientRequest::ConfigMargin(side, marg));
+            }
+        }
+        if let Some(color) = file.border_color_focused {
+            requests.push(ClientRequest::ConfigBorderFocused(color));
+        }
+        if let Some(color) = file.border_color_unfocused {
+            requests.push(ClientRequest::ConfigBorderUnfocused(color));
+        }
+        if let Some(color) = file.border_color_inactive {
+            requests.push(ClientRequest::ConfigBorderInactive(color));
+        }
+        if let Some(mode) = file.tag_switch_mode {
+            requests.push(ClientRequest::ConfigTagSwitchMode(match mode.as_str() {
+                "swap" => TagSwitchMode::Swap,
+                "greedy" => TagSwitchMode::Greedy,
+                "noop" => TagSwitchMode::Noop,
+                s => bail!("invalid tag switch mode: {}", s),
+            }));
+        }
+        if let Some(strategy) = file.hide_strategy {
+            requests.push(ClientRequest::ConfigHideStrategy(match strategy.as_str() {
+                "unmap" => HideStrategy::Unmap,
+                "offscreen" => HideStrategy::Offscreen,
+                s => bail!("invalid hide strategy: {}", s),
+            }));
+        }
+        if file.tiled_limit.is_some() || file.overflow_policy.is_some() {
+            let limit = match file.tiled_limit {
+                Some(limit) => Some(limit).filter(|limit| *limit != 0),
+                None => theme.tiled_limit,
+            };
+            let policy = match file.overflow_policy.as_deref() {
+                Some("hide") => OverflowPolicy::Hide,
+                Some("next-empty") => OverflowPolicy::NextEmpty,
+                Some(s) => bail!("invalid overflow policy: {}", s),
+                None => theme.overflow_policy,
+            };
+            requests.push(ClientRequest::ConfigOverflow(limit, policy));
+        }
+        if file.monocle_floating.is_some()
+            || file.monocle_borderless.is_some()
+            || file.monocle_gapless.is_some()
+        {
+            requests.push(ClientRequest::ConfigMonocle(
+                file.monocle_floating.unwrap_or(theme.monocle_floating),
+                file.monocle_borderless.unwrap_or(theme.monocle_borderless),
+                file.monocle_gapless.unwrap_or(theme.monocle_gapless),
+            ));
+        }
+        if let Some(enabled) = file.floating_shadows_only {
+            requests.push(ClientRequest::ConfigFloatingShadowsOnly(enabled));
+        }
+        if let Some(enabled) = file.group_hide {
+            requests.push(ClientRequest::ConfigGroupHide(enabled));
+        }
+        if let Some(enabled) = file.insert_at_pointer {
+            requests.push(ClientRequest::ConfigInsertAtPointer(enabled));
+        }
+        // the theme only has the premultiplied color, so it can't be sent back through the request
+        match (file.focus_flash_ms, file.focus_flash_color) {
+            (ms, Some(color)) => requests.push(ClientRequest::ConfigFocusFlash(
+                ms.unwrap_or(theme.focus_flash_ms),
+                color,
+            )),
+            (Some(ms), None) => theme.focus_flash_ms = ms,
+            (None, None) => (),
+        }
+        match (file.bell_flash_ms, file.bell_flash_color) {
+            (ms, Some(color)) => requests.push(ClientRequest::ConfigVisualBell(
+                ms.unwrap_or(theme.bell_flash_ms),
+                color,
+            )),
+            (Some(ms), None) => theme.bell_flash_ms = ms,
+            (None, None) => (),
+        }
+        if file.focus_indicator.is_some()
+            || file.focus_indicator_size.is_some()
+            || file.focus_indicator_color.is_some()
+        {
+            let current = match theme.focus_indicator {
+                FocusIndicator::Off => (None, 2),
+                FocusIndicator::Edge(size) => (Some(false), size),
+                FocusIndicator::Corner(size) => (Some(true), size),
+            };
+            let corner = match file.focus_indicator.as_deref() {
+                Some("off") => None,
+                Some("edge") => Some(false),
+                Some("corner") => Some(true),
+                Some(s) => bail!("invalid focus indicator: {}", s),
+                None => current.0,
+            };
+            let size = file.focus_indicator_size.unwrap_or(current.1);
+            requests.push(ClientRequest::ConfigFocusIndicator(
+                match corner {
+                    None => FocusIndicator::Off,
+                    Some(false) => FocusIndicator::Edge(size),
+                    Some(true) => FocusIndicator::Corner(size),
+                },
+                file.focus_indicator_color,
+            ));
+        }
+        if let Some(size) = file.min_tile_size {
+            requests.push(ClientRequest::ConfigMinTileSize(
+                Some(size).filter(|size| *size != (0, 0)),
+            ));
+        }
+        if let Some(ms) = file.urgent_timeout_ms {
+            requests.push(ClientRequest::ConfigUrgentTimeout(ms));
+        }
+        if let Some(ms) = file.idle_timeout_ms {
+            requests.push(ClientRequest::ConfigIdleTimeout(ms));
+        }
+        if let Some(ms) = file.presel_timeout_ms {
+            requests.push(ClientRequest::ConfigPreselTimeout(ms));
+        }
+        if let Some(ms) = file.focus_settle_ms {
+            requests.push(ClientRequest::ConfigFocusSettle(ms));
+        }
+        if let Some(ms) = file.close_fade_ms {
+            requests.push(ClientRequest::ConfigCloseFade(ms));
+        }
+        if let Some(enabled) = file.remember_floating {
+            requests.push(ClientRequest::ConfigRememberFloating(enabled));
+        }
+        if file.float_placement.is_some() || file.float_cascade_offset.is_some() {
+            let current = match theme.float_placement {
+                FloatPlacement::Center => (false, (32, 32)),
+                FloatPlacement::Cascade(dx, dy) => (true, (dx, dy)),
+            };
+            let cascade = match file.float_placement.as_deref() {
+                Some("center") => false,
+                Some("cascade") => true,
+                Some(s) => bail!("invalid float placement: {}", s),
+                None => current.0,
+            };
+            let (dx, dy) = file.float_cascade_offset.unwrap_or(current.1);
+            requests.push(ClientRequest::ConfigFloatPlacement(if cascade {
+                FloatPlacement::Cascade(dx, dy)
+            } else {
+                FloatPlacement::Center
+            }));
+        }
+        if let Some(follow) = file.selection_follow {
+            requests.push(ClientRequest::ConfigSelectionFollow(follow));
+        }
+        if let Some(enabled) = file.refocus_off_monitors {
+            requests.push(ClientRequest::ConfigRefocusOffMonitors(enabled));
+        }
+        if let Some(bindings) = file.pointer_bindings {
+            requests.push(ClientRequest::ConfigPointerBindings(bindings));
+        }
+        if let Some(swipe) = file.swipe {
+            requests.push(ClientRequest::ConfigSwipe(swipe));
+        }
+        for (button, mods, action) in file.frame_buttons {
+            requests.push(ClientRequest::BindFrameButton(button, mods, Some(action)));
+        }
+        if file.frame_double_click.is_some() || file.double_click_ms.is_some() {
+            requests.push(ClientRequest::ConfigFrameDoubleClick(
+                file.frame_double_click.or(theme.frame_double_click),
+                file.double_click_ms.unwrap_or(theme.double_click_ms),
+            ));
+        }
+        if file.panels_above_fullscreen.is_some() || file.desktop_click_through.is_some() {
+            requests.push(ClientRequest::ConfigPanelLayer(
+                file.panels_above_fullscreen
+                    .unwrap_or(theme.panels_above_fullscreen),
+                file.desktop_click_through
+                    .unwrap_or(theme.desktop_click_through),
+            ));
+        }
+        for (name, mon) in file.monitors {
+            for (field, value) in [
+                (ThemeField::Gap, mon.gap.map(|x| x as i16)),
+                (ThemeField::BorderWidth, mon.border_width.map(|x| x as i16)),
+                (ThemeField::Margin(Side::Top), mon.top_margin),
+                (ThemeField::Margin(Side::Bottom), mon.bottom_margin),
+                (ThemeField::Margin(Side::Left), mon.left_margin),
+                (ThemeField::Margin(Side::Right), mon.right_margin),
+            ] {
+                if value.is_some() {
+                    requests.push(ClientRequest::ConfigMonitorTheme(
+                        name.clone(),
+                        field,
+                        value,
+                    ));
+                }
+            }
+        }
+        // nothing has to be redrawn for these, new windows and selections pick them up
+        if let Some(width) = file.window_width {
+            theme.window_width = width;
+        }
+        if let Some(height) = file.window_height {
+            theme.window_height = height;
+        }
+        if let Some(width) = file.window_min_width {
+            theme.window_min_width = width;
+        }
+        if let Some(height) = file.window_min_height {
+            theme.window_min_height = height;
+        }
+        if let Some(gap) = file.selection_gap {
+            theme.selection_gap = gap;
+        }
+        if let Some(color) = file.presel_color {
+            theme.presel_color = mul_alpha(color);
+        }
+        if let Some(color) = file.sel_color {
+            theme.sel_color = mul_alpha(color);
+        }
+        Ok(requests)
+    }
+
+    // a missing file is fine, a broken one is logged and leaves the theme as it was
+    pub fn load_theme_file(&mut self) -> Result<()> {
+        self.aux.theme_watch.watch();
+        let path = match self.aux.theme_watch.path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(()),
+        };
+        info!("loading theme from {}", path.display());
+        let file: ThemeFile = match toml::from_str(&std::fs::read_to_string(&path)?) {
+            Ok(file) => file,
+            Err(e) => {
+                info!("invalid theme file {}: {}", path.display(), e);
+                return Ok(());
+            }
+        };
+        for request in self.theme_requests(file)? {
+            self.apply_request(request)?;
+        }
+        Ok(())
+    }
+
+    pub fn update_theme_file(&mut self) -> Result<()> {
+        if self.aux.theme_watch.changed() {
+            self.load_theme_file()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use nix::sys::timerfd::{ClockId, Expiration, TimerFd, TimerFlags, TimerSetTimeFlags};
+use nix::unistd::read;
+use std::cell::RefCell;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timeout {
+    AnimationFrame,
+    PanelHide(u32),
+    WindowWait(u32),
+    FocusFlash(u32),
+    Urgent(u32),
+    Idle,
+    Presel,
+    Manage(u32),
+    Motion,
+    TagSettle,
+    CloseFade(u32),
+}
+
+// a single timerfd in the poll loop, armed for whichever pending timeout is due first
+pub struct Timers {
+    fd: TimerFd,
+    pending: RefCell<Vec<(Instant, Timeout)>>,
+}
+
+impl Timers {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            fd: TimerFd::new(
+                ClockId::CLOCK_MONOTONIC,
+                TimerFlags::TFD_NONBLOCK | TimerFlags::TFD_CLOEXEC,
+            )?,
+            pending: RefCell::new(Vec::new()),
+        })
+    }
+
+    // replaces any pending timeout that is equal to this one
+    pub fn set(&self, timeout: Timeout, after: Duration) -> Result<()> {
+        {
+            let mut pending = self.pending.borrow_mut();
+            pending.retain(|x| x.1 != timeout);
+            pending.push((Instant::now() + after, timeout));
+        }
+        self.arm()
+    }
+
+    pub fn cancel(&self, timeout: Timeout) -> Result<()> {
+        self.pending.borrow_mut().retain(|x| x.1 != timeout);
+        self.arm()
+    }
+
+    pub fn expired(&self) -> Result<Vec<Timeout>> {
+        let _ = read(self.fd.as_raw_fd(), &mut [0u8; 8]);
+        let now = Instant::now();
+        let (expired, pending): (Vec<_>, Vec<_>) = self
+            .pending
+            .borrow_mut()
+            .drain(..)
+            .partition(|x| x.0 <= now);
+        self.pending.replace(pending);
+        self.arm()?;
+        Ok(expired.into_iter().map(|x| x.1).collect())
+    }
+
+    fn arm(&self) -> Result<()> {
+        if let Some(next) = self.pending.borrow().iter().map(|x| x.0).min() {
+            // a zero expiration would disarm the timer
+            let delay = next
+                .saturating_duration_since(Instant::now())
+                .max(Duration::from_micros(1));
+            self.fd.set(
+                Expiration::OneShot(delay.into()),
+                TimerSetTimeFlags::empty(),
+            )?;
+        } else {
+            self.fd.unset()?;
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Timers {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::Result;
+use log::info;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE};
+
+use crate::{WindowLocation, WindowManager};
+
+// opcodes of _NET_SYSTEM_TRAY_OPCODE and _XEMBED messages
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const BACKGROUND: u32 = 0x000000;
+
+struct Icon {
+    win: Window,
+    ignore_unmaps: usize,
+}
+
+// owns the system tray selection and embeds icons in a row at the top right of a monitor,
+// the window is registered as a panel while it has icons so it stacks with the other panels
+pub struct Tray {
+    win: Window,
+    selection: Atom,
+    mon: Atom,
+    icon_size: u16,
+    icons: Vec<Icon>,
+}
+
+impl Tray {
+    pub fn width(&self) -> u16 {
+        self.icons.len() as u16 * self.icon_size
+    }
+}
+
+impl WindowManager {
+    pub fn tray_start(&mut self, icon_size: u16) -> Result<()> {
+        if let Some(tray) = &mut self.tray {
+            tray.icon_size = icon_size.max(1);
+            return self.tray_relayout();
+        }
+        let dpy = &self.aux.dpy;
+        let screen = dpy
+            .setup()
+            .roots
+            .iter()
+            .position(|screen| screen.root == self.aux.root)
+            .unwrap_or(0);
+        let selection = intern_atom(
+            dpy,
+            false,
+            format!("_NET_SYSTEM_TRAY_S{}", screen).as_bytes(),
+        )?
+        .reply()?
+        .atom;
+        if get_selection_owner(dpy, selection)?.reply()?.owner != NONE {
+            info!("another system tray is running");
+            return Ok(());
+        }
+        let win = dpy.generate_id()?;
+        create_window(
+            dpy,
+            COPY_DEPTH_FROM_PARENT,
+            win,
+            self.aux.root,
+            0,
+            0,
+            icon_size,
+            icon_size,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new().background_pixel(BACKGROUND),
+        )?;
+        dpy.change_property32(
+            PropMode::REPLACE,
+            win,
+            self.aux.atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            &[self.aux.atoms._NET_WM_WINDOW_TYPE_DOCK],
+        )?;
+        // horizontal
+        dpy.change_property32(
+            PropMode::REPLACE,
+            win,
+            self.aux.atoms._NET_SYSTEM_TRAY_ORIENTATION,
+            AtomEnum::CARDINAL,
+            &[0],
+        )?;
+        set_selection_owner(dpy, win, selection, CURRENT_TIME)?;
+        if get_selection_owner(dpy, selection)?.reply()?.owner != win {
+            destroy_window(dpy, win)?;
+            return Ok(());
+        }
+        let event = ClientMessageEvent {
+            response_type: CLIENT_MESSAGE_EVENT,
+            format: 32,
+            sequence: 0,
+            window: self.aux.root,
+            type_: self.aux.atoms.MANAGER,
+            data: [CURRENT_TIME, selection, win, 0, 0].into(),
+        };
+        send_event(
+            dpy,
+            false,
+            self.aux.root,
+            EventMask::STRUCTURE_NOTIFY,
+            event,
+        )?;
+        info!("system tray started {}", win);
+        self.tray = Some(Tray {
+            win,
+            selection,
+            mon: self.focused_monitor,
+            icon_size: icon_size.max(1),
+            icons: Vec::new(),
+        });
+        Ok(())
+    }
+
+    // gives the icons back to the root window so another tray can embed them
+    pub fn tray_stop(&mut self) -> Result<()> {
+        if let Some(tray) = self.tray.take() {
+            if let Some(WindowLocation::Panel(mon)) = self.windows.remove(&tray.win) {
+                self.panel_unregister(mon, tray.win)?;
+            }
+            for icon in tray.icons {
+                self.windows.remove(&icon.win);
+                unmap_window(&self.aux.dpy, icon.win)?;
+                reparent_window(&self.aux.dpy, icon.win, self.aux.root, 0, 0)?;
+            }
+            if get_selection_owner(&self.aux.dpy, tray.selection)?
+                .reply()?
+                .owner
+                == tray.win
+            {
+                set_selection_owner(&self.aux.dpy, NONE, tray.selection, CURRENT_TIME)?;
+            }
+            destroy_window(&self.aux.dpy, tray.win)?;
+            info!("system tray stopped");
+        }
+        Ok(())
+    }
+
+    pub fn tray_message(&mut self, e: &ClientMessageEvent) -> Result<()> {
+        let data = e.data.as_data32();
+        match &self.tray {
+            Some(tray) if tray.win == e.window && data[1] == SYSTEM_TRAY_REQUEST_DOCK => {
+                self.tray_dock(data[2])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn tray_dock(&mut self, icon: Window) -> Result<()> {
+        let tray = match &mut self.tray {
+            Some(tray) if !self.windows.contains_key(&icon) => tray,
+            _ => return Ok(()),
+        };
+        let dpy = &self.aux.dpy;
+        let attrs = match get_window_attributes(dpy, icon)?.reply() {
+            Ok(attrs) => attrs,
+            Err(_) => return Ok(()),
+        };
+        info!("docking tray icon {}", icon);
+        change_save_set(dpy, SetMode::INSERT, icon)?;
+        reparent_window(dpy, icon, tray.win, 0, 0)?;
+        // selected after reparenting so only the root hears the unmap it causes
+        change_window_attributes(
+            dpy,
+            icon,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+        )?;
+        let event = ClientMessageEvent {
+            response_type: CLIENT_MESSAGE_EVENT,
+            format: 32,
+            sequence: 0,
+            window: icon,
+            type_: self.aux.atoms._XEMBED,
+            data: [CURRENT_TIME, XEMBED_EMBEDDED_NOTIFY, 0, tray.win, 0].into(),
+        };
+        send_event(dpy, false, icon, EventMask::NO_EVENT, event)?;
+        map_window(dpy, icon)?;
+        tray.icons.push(Icon {
+            win: icon,
+            // reparenting a mapped window unmaps it
+            ignore_unmaps: (attrs.map_state != MapState::UNMAPPED) as usize,
+        });
+        self.windows.insert(icon, WindowLocation::TrayIcon);
+        self.tray_relayout()
+    }
+
+    // true if the unmap came from docking the icon
+    pub fn tray_ignore_unmap(&mut self, win: Window) -> bool {
+        match self
+            .tray
+            .as_mut()
+            .and_then(|tray| tray.icons.iter_mut().find(|icon| icon.win == win))
+        {
+            Some(icon) if icon.ignore_unmaps > 0 => {
+                icon.ignore_unmaps -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn tray_remove(&mut self, win: Window) -> Result<()> {
+        if let Some(tray) = &mut self.tray {
+            tray.icons.retain(|icon| icon.win != win);
+            // fails harmlessly when the icon was destroyed
+            reparent_window(&self.aux.dpy, win, self.aux.root, 0, 0)?;
+            self.tray_relayout()?;
+        }
+        Ok(())
+    }
+
+    fn tray_relayout(&mut self) -> Result<()> {
+        let tray = match &self.tray {
+            Some(tray) => tray,
+            None => return Ok(()),
+        };
+        let win = tray.win;
+        let registered = self.windows.get(&win).copied();
+        if tray.icons.is_empty() {
+            if let Some(WindowLocation::Panel(mon)) = registered {
+                self.windows.remove(&win);
+                self.panel_unregister(mon, win)?;
+                unmap_window(&self.aux.dpy, win)?;
+            }
+            return Ok(());
+        }
+        let size = tray.icon_size;
+        for (i, icon) in tray.icons.iter().enumerate() {
+            configure_window(
+                &self.aux.dpy,
+                icon.win,
+                &ConfigureWindowAux::new()
+                    .x(i as i32 * size as i32)
+                    .y(0)
+                    .width(size as u32)
+                    .height(size as u32),
+            )?;
+        }
+        let mon = self
+            .monitors
+            .get(&tray.mon)
+            .or_else(|| self.monitors.get(&self.focused_monitor))
+            .unwrap();
+        let width = tray.width();
+        configure_window(
+            &self.aux.dpy,
+            win,
+            &ConfigureWindowAux::new()
+                .x((mon.size.x + mon.size.width as i16 - width as i16) as i32)
+                .y(mon.size.y as i32)
+                .width(width as u32)
+                .height(size as u32),
+        )?;
+        if registered.is_none() {
+            let mon = mon.id;
+            self.panel_register(mon, win)?;
+        }
+        Ok(())
+    }
+
+    pub fn tray_owns(&self, win: Window) -> bool {
+        matches!(&self.tray, Some(tray) if tray.win == win)
+    }
+
+    // the width a bar has to leave free at the right of the monitor of the tray
+    pub fn tray_width(&self) -> u16 {
+        self.tray.as_ref().map(Tray::width).unwrap_or(0)
+    }
+}
@@ -1,7 +1,6 @@
 use std::collections::HashSet;
-use x11rb::protocol::xproto::*;
 
-use crate::tag::Split;
+use crate::core::Split;
 
 pub use stack::{Stack, StackElem};
 
@@ -17,6 +16,87 @@ pub fn mul_alpha(color: u32) -> u32 {
     }
 }
 
+// STRING is latin-1 but plenty of clients put utf-8 in it anyway
+pub fn latin1_or_utf8(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+// only the ascii, latin-1 and utf-8 charsets are understood, anything else is replaced
+pub fn decode_compound_text(bytes: &[u8]) -> String {
+    #[derive(PartialEq, Clone, Copy)]
+    enum Charset {
+        Latin1,
+        Utf8,
+        Unknown,
+    }
+    let push = |text: &mut String, charset: Charset, bytes: &[u8]| match charset {
+        Charset::Utf8 => text.push_str(&String::from_utf8_lossy(bytes)),
+        Charset::Latin1 => text.extend(bytes.iter().map(|&byte| byte as char)),
+        Charset::Unknown => text.extend(bytes.iter().map(|&byte| match byte {
+            0..=0x7F => byte as char,
+            _ => char::REPLACEMENT_CHARACTER,
+        })),
+    };
+    let mut text = String::new();
+    let mut charset = Charset::Latin1;
+    let mut utf8 = Vec::new();
+    let mut bytes = bytes.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        if byte != 0x1B {
+            match charset {
+                Charset::Utf8 => utf8.push(byte),
+                _ => push(&mut text, charset, &[byte]),
+            }
+            continue;
+        }
+        // intermediate bytes followed by a final byte
+        let mut seq = Vec::new();
+        while let Some(byte) = bytes.next_if(|byte| (0x20..=0x2F).contains(byte)) {
+            seq.push(byte);
+        }
+        seq.extend(bytes.next_if(|byte| (0x30..=0x7E).contains(byte)));
+        // an extended segment carries its own length and charset name, the charset in use
+        // before it stays in use after it
+        if let [b'%', b'/', _] = seq.as_slice() {
+            let len = match (bytes.next(), bytes.next()) {
+                (Some(m), Some(l)) => (m as usize & 0x7F) * 128 + (l as usize & 0x7F),
+                _ => break,
+            };
+            let segment: Vec<u8> = bytes.by_ref().take(len).collect();
+            let (name, data) = match segment.iter().position(|&byte| byte == 0x02) {
+                Some(i) => (&segment[..i], &segment[i + 1..]),
+                None => (&segment[..], &[][..]),
+            };
+            let segment_charset = if name.eq_ignore_ascii_case(b"utf-8") {
+                Charset::Utf8
+            } else if name.eq_ignore_ascii_case(b"iso8859-1") {
+                Charset::Latin1
+            } else {
+                Charset::Unknown
+            };
+            push(&mut text, Charset::Utf8, &utf8);
+            utf8.clear();
+            push(&mut text, segment_charset, data);
+            continue;
+        }
+        let next = match seq.as_slice() {
+            b"%G" => Charset::Utf8,
+            b"%@" | b"(B" | b"-A" => Charset::Latin1,
+            _ => Charset::Unknown,
+        };
+        if charset == Charset::Utf8 && next != Charset::Utf8 {
+            push(&mut text, Charset::Utf8, &utf8);
+            utf8.clear();
+        }
+        charset = next;
+    }
+    push(&mut text, Charset::Utf8, &utf8);
+    text
+}
+
 pub fn pop_set_ord<T: Clone + Eq + std::hash::Hash>(
     set: &mut HashSet<T>,
     order: &[T],
@@ -95,15 +175,6 @@ impl Rect {
         }
     }
 
-    pub fn aux(&self, width: u16) -> ConfigureWindowAux {
-        ConfigureWindowAux::new()
-            .x(self.x as i32)
-            .y(self.y as i32)
-            .width((self.width - width * 2) as u32)
-            .height((self.height - width * 2) as u32)
-            .border_width(width as u32)
-    }
-
     pub fn copy(&mut self, other: &Rect) {
         self.x = other.x;
         self.y = other.y;
@@ -173,12 +244,6 @@ impl Rect {
     }
 }
 
-impl From<GetGeometryReply> for Rect {
-    fn from(other: GetGeometryReply) -> Self {
-        Self::new(other.x, other.y, other.width, other.height)
-    }
-}
-
 pub mod stack {
     #[derive(Debug)]
     pub struct StackElem<T> {
@@ -295,3 +360,56 @@ pub mod stack {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compound_text_switches_charsets() {
+        // latin-1 by default, then utf-8, then latin-1 again
+        let value = b"\xE9\x1B%G\xC3\xA9\x1B-A\xE9";
+        assert_eq!(decode_compound_text(value), "ééé");
+        // a utf-8 character is only decoded once it is complete
+        assert_eq!(decode_compound_text(b"\x1B%G\xC3\x1B-A"), "\u{FFFD}");
+        // ascii of an unknown charset is kept, the rest is replaced
+        assert_eq!(
+            decode_compound_text(b"\x1B$(Bab\xA4\xA2"),
+            "ab\u{FFFD}\u{FFFD}"
+        );
+        assert_eq!(decode_compound_text(b"\x1B(Bx"), "x");
+    }
+
+    #[test]
+    fn a_trailing_escape_is_dropped() {
+        assert_eq!(decode_compound_text(b"abc\x1B"), "abc");
+        assert_eq!(decode_compound_text(b"\x1B%G\xC3\xA9\x1B"), "é");
+        assert_eq!(decode_compound_text(b"\x1B"), "");
+    }
+
+    #[test]
+    fn extended_segments_are_decoded_by_their_charset_name() {
+        let segment = |name: &str, data: &[u8]| {
+            let len = name.len() + 1 + data.len();
+            let mut bytes = vec![0x1B, b'%', b'/', b'1'];
+            bytes.push(0x80 | (len / 128) as u8);
+            bytes.push(0x80 | (len % 128) as u8);
+            bytes.extend(name.as_bytes());
+            bytes.push(0x02);
+            bytes.extend(data);
+            bytes
+        };
+        let mut value = b"a".to_vec();
+        value.extend(segment("UTF-8", "é".as_bytes()));
+        value.push(0xE9);
+        assert_eq!(decode_compound_text(&value), "aéé");
+        let mut value = segment("iso8859-1", b"\xE9");
+        value.extend(segment("big5", b"x\xA4\x40"));
+        assert_eq!(decode_compound_text(&value), "éx\u{FFFD}@");
+        // a segment cut short keeps what there is of it
+        let mut value = segment("utf-8", b"abc");
+        value.truncate(value.len() - 1);
+        assert_eq!(decode_compound_text(&value), "ab");
+        assert_eq!(decode_compound_text(b"\x1B%/1\x80"), "");
+    }
+}
@@ -0,0 +1,205 @@
+// the x11 side of what the layout core shares with the backend
+use anyhow::Result;
+use x11rb::connection::Connection;
+use x11rb::cookie::Cookie;
+use x11rb::properties::WmHints;
+use x11rb::protocol::xproto::*;
+
+use crate::display::Dpy;
+use crate::icon::Icon;
+use crate::utils::{decode_compound_text, latin1_or_utf8, Rect};
+use crate::AtomCollection;
+
+// maybe replace with a bit field?
+#[derive(Default, Debug, Clone)]
+pub struct ClientProtocols {
+    pub(crate) delete: bool,
+}
+
+// what only the x11 backend keeps about a managed window, kept by window on Aux
+#[derive(Default, Debug, Clone)]
+pub struct X11Client {
+    // the name came from _NET_WM_NAME, WM_NAME no longer replaces it
+    pub(crate) net_name: bool,
+    pub(crate) protocols: ClientProtocols,
+    pub ignore_unmaps: usize,
+}
+
+impl X11Client {
+    pub fn process_protocol(&mut self, atoms: &AtomCollection, protocol: Atom) {
+        if protocol == atoms.WM_DELETE_WINDOW {
+            self.protocols.delete = true;
+        }
+    }
+}
+
+// a text property of any type, invalid bytes become replacement characters
+pub fn decode_text(atoms: &AtomCollection, prop: &GetPropertyReply) -> String {
+    if prop.type_ == atoms.UTF8_STRING {
+        String::from_utf8_lossy(&prop.value).into_owned()
+    } else if prop.type_ == atoms.COMPOUND_TEXT {
+        decode_compound_text(&prop.value)
+    } else {
+        latin1_or_utf8(&prop.value)
+    }
+}
+
+impl Rect {
+    pub fn aux(&self, width: u16) -> ConfigureWindowAux {
+        ConfigureWindowAux::new()
+            .x(self.x as i32)
+            .y(self.y as i32)
+            .width((self.width - width * 2) as u32)
+            .height((self.height - width * 2) as u32)
+            .border_width(width as u32)
+    }
+}
+
+impl From<GetGeometryReply> for Rect {
+    fn from(other: GetGeometryReply) -> Self {
+        Self::new(other.x, other.y, other.width, other.height)
+    }
+}
+
+impl Icon {
+    // the WM_HINTS icon pixmap, only 24 and 32 bit depths are supported
+    pub fn from_pixmap(dpy: &Dpy, pixmap: Pixmap, mask: Option<Pixmap>) -> Result<Option<Self>> {
+        let setup = dpy.setup();
+        if setup.image_byte_order != ImageOrder::LSB_FIRST {
+            return Ok(None);
+        }
+        let geom = get_geometry(dpy, pixmap)?.reply()?;
+        let (width, height) = (geom.width as usize, geom.height as usize);
+        if width == 0 || height == 0 || (geom.depth != 24 && geom.depth != 32) {
+            return Ok(None);
+        }
+        let image = get_image(
+            dpy,
+            ImageFormat::Z_PIXMAP,
+            pixmap,
+            0,
+            0,
+            geom.width,
+            geom.height,
+            !0,
+        )?
+        .reply()?;
+        if image.data.len() < width * height * 4 {
+            return Ok(None);
+        }
+        let mut argb = image
+            .data
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]) | 0xff000000)
+            .collect::<Vec<_>>();
+        if let Some(mask) = mask {
+            let mask = get_image(
+                dpy,
+                ImageFormat::XY_PIXMAP,
+                mask,
+                0,
+                0,
+                geom.width,
+                geom.height,
+                1,
+            )?
+            .reply()?;
+            let pad = setup.bitmap_format_scanline_pad as usize;
+            let stride = width.div_ceil(pad) * pad / 8;
+            let lsb = setup.bitmap_format_bit_order == ImageOrder::LSB_FIRST;
+            for y in 0..height {
+                for x in 0..width {
+                    let byte = mask.data.get(y * stride + x / 8).copied().unwrap_or(0xff);
+                    let bit = if lsb { x % 8 } else { 7 - x % 8 };
+                    if byte >> bit & 1 == 0 {
+                        argb[y * width + x] &= 0x00ffffff;
+                    }
+                }
+            }
+        }
+        Ok(Some(Self::scaled(width, height, &argb)))
+    }
+
+    pub fn get_net_wm_icon(
+        dpy: &Dpy,
+        win: Window,
+        atom: Atom,
+    ) -> Result<Cookie<'_, Dpy, GetPropertyReply>> {
+        Ok(get_property(
+            dpy,
+            false,
+            win,
+            atom,
+            AtomEnum::CARDINAL,
+            0,
+            0x100000,
+        )?)
+    }
+
+    // prefers _NET_WM_ICON and falls back to the pixmap from WM_HINTS
+    pub fn from_replies(
+        dpy: &Dpy,
+        net_icon: Option<GetPropertyReply>,
+        hints: Option<&WmHints>,
+    ) -> Option<Self> {
+        net_icon
+            .and_then(|reply| {
+                reply
+                    .value32()
+                    .and_then(|values| Self::from_net_wm_icon(&values.collect::<Vec<_>>()))
+            })
+            .or_else(|| {
+                hints
+                    .and_then(|hints| hints.icon_pixmap.map(|pixmap| (pixmap, hints.icon_mask)))
+                    .and_then(|(pixmap, mask)| Self::from_pixmap(dpy, pixmap, mask).ok().flatten())
+            })
+    }
+
+    pub fn get(dpy: &Dpy, win: Window, atom: Atom) -> Option<Self> {
+        let net_icon = Self::get_net_wm_icon(dpy, win, atom)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        let hints = WmHints::get(dpy, win)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        Self::from_replies(dpy, net_icon, hints.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn text(type_: &str, value: &[u8]) -> String {
+        let wm = mock::wm(&[(0, 0, 100, 100)]);
+        let atoms = &wm.aux.atoms;
+        let type_ = match type_ {
+            "UTF8_STRING" => atoms.UTF8_STRING,
+            "COMPOUND_TEXT" => atoms.COMPOUND_TEXT,
+            _ => AtomEnum::STRING.into(),
+        };
+        let prop = GetPropertyReply {
+            format: 8,
+            sequence: 0,
+            length: 0,
+            type_,
+            bytes_after: 0,
+            value_len: value.len() as u32,
+            value: value.to_vec(),
+        };
+        decode_text(atoms, &prop)
+    }
+
+    #[test]
+    fn invalid_utf8_string_is_replaced() {
+        assert_eq!(text("UTF8_STRING", "héllo".as_bytes()), "héllo");
+        assert_eq!(text("UTF8_STRING", b"a\xFFb\xC3"), "a\u{FFFD}b\u{FFFD}");
+    }
+
+    #[test]
+    fn string_is_latin1_unless_it_is_utf8() {
+        assert_eq!(text("STRING", b"caf\xE9 \xB5"), "café µ");
+        assert_eq!(text("STRING", "café".as_bytes()), "café");
+    }
+}
@@ -2,12 +2,63 @@ use anyhow::{Error, Result};
 
 pub use struct_args_derive::*;
 
+// one way of invoking a command, help comes from the doc comments of the derive
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub args: String,
+    pub help: String,
+}
+
+impl Usage {
+    pub fn new(args: impl Into<String>, help: impl Into<String>) -> Self {
+        Self {
+            args: args.into(),
+            help: help.into(),
+        }
+    }
+
+    pub fn arg(args: impl Into<String>) -> Vec<Self> {
+        vec![Self::new(args, "")]
+    }
+
+    // every line of `lines` followed by every line of `next`, the more specific help wins
+    pub fn chain(lines: Vec<Self>, next: Vec<Self>) -> Vec<Self> {
+        lines
+            .iter()
+            .flat_map(|line| {
+                next.iter().map(move |next| Self {
+                    args: format!("{} {}", line.args, next.args).trim().to_string(),
+                    help: if next.help.is_empty() {
+                        line.help.clone()
+                    } else {
+                        next.help.clone()
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
 pub trait Arg: Sized {
     fn parse_args(args: &mut Vec<String>) -> Result<Self>;
     fn from_args() -> Result<Self> {
         let mut args = std::env::args().skip(1).rev().collect();
         Self::parse_args(&mut args)
     }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<arg>")
+    }
+    fn print_usage() {
+        let lines = Self::usage();
+        let width = lines.iter().map(|x| x.args.len()).max().unwrap_or(0);
+        for line in lines {
+            if line.help.is_empty() {
+                println!("    {}", line.args);
+            } else {
+                println!("    {:width$}    {}", line.args, line.help, width = width);
+            }
+        }
+    }
 }
 
 impl<E: std::error::Error + Sync + Send + 'static, T: std::str::FromStr<Err = E> + Sized> Arg
@@ -20,8 +71,35 @@ impl<E: std::error::Error + Sync + Send + 'static, T: std::str::FromStr<Err = E>
             .as_str()
             .parse()?)
     }
+    fn usage() -> Vec<Usage> {
+        let name = std::any::type_name::<T>();
+        Usage::arg(format!("<{}>", name.rsplit("::").next().unwrap_or(name)))
+    }
+}
+
+
+// "10" or "10px" for pixels, "50%" for a percentage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Amount {
+    Pixels(i32),
+    Percent(f32),
 }
 
+impl Arg for Amount {
+    fn parse_args(args: &mut Vec<String>) -> Result<Self> {
+        let item = args
+            .pop()
+            .ok_or_else(|| Error::msg("amount: No argument provided"))?;
+        Ok(if let Some(percent) = item.strip_suffix('%') {
+            Self::Percent(percent.parse()?)
+        } else {
+            Self::Pixels(item.strip_suffix("px").unwrap_or(&item).parse()?)
+        })
+    }
+    fn usage() -> Vec<Usage> {
+        Usage::arg("<n[px|%]>")
+    }
+}
 
 pub fn parse_u32(string: &str) -> Result<u32> {
     Ok(if let Some(string) = string.strip_prefix("0x") {
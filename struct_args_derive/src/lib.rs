@@ -4,11 +4,12 @@ use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::{
-    parse_macro_input, parse_quote, Data, DeriveInput, Fields, GenericParam, Generics, Ident,
-    Index, LitStr, Token,
+    parenthesized, parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Expr, Field,
+    Fields, GenericArgument, GenericParam, Generics, Ident, Lit, LitStr, Meta, PathArguments,
+    Token, Type,
 };
 
-#[proc_macro_derive(Arg, attributes(struct_args_match))]
+#[proc_macro_derive(Arg, attributes(struct_args_match, struct_args))]
 pub fn derive_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input tokens into a syntax tree.
     let input = parse_macro_input!(input as DeriveInput);
@@ -21,6 +22,7 @@ pub fn derive_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
     // Generate an expression to sum up the heap size of each field.
     let body = arg_parse(&input.data);
+    let usage = arg_usage(&input.data, &doc_string(&input.attrs));
 
     let expanded = quote! {
         // The generated impl.
@@ -28,6 +30,9 @@ pub fn derive_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
             fn parse_args(args: &mut Vec<String>) -> anyhow::Result<Self> {
                 Ok(#body)
             }
+            fn usage() -> Vec<struct_args::Usage> {
+                #usage
+            }
         }
     };
 
@@ -44,13 +49,155 @@ fn add_trait_bounds(mut generics: Generics) -> Generics {
     generics
 }
 
+// how a single field is consumed from the args
+enum FieldKind<'a> {
+    Plain,
+    // only consumed if it parses, otherwise None
+    Optional(&'a Type),
+    // a bool that is set if the next arg is one of the names
+    Flag(Vec<String>),
+}
+
+fn field_kind(f: &Field) -> FieldKind<'_> {
+    let mut names = vec![];
+    for item in match_items(&f.attrs) {
+        match item {
+            Item::LitStr(name) => names.push(name.value()),
+            Item::Ident(ident) => {
+                if ident == "flag" {
+                    if let Some(name) = &f.ident {
+                        names.push(format!("--{}", name.to_string().replace('_', "-")));
+                    }
+                }
+            }
+        }
+    }
+    if !names.is_empty() {
+        return FieldKind::Flag(names);
+    }
+    if let Type::Path(ty) = &f.ty {
+        if let Some(segment) = ty.path.segments.last() {
+            if segment.ident == "Option" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let Some(GenericArgument::Type(ty)) = args.args.first() {
+                        return FieldKind::Optional(ty);
+                    }
+                }
+            }
+        }
+    }
+    FieldKind::Plain
+}
+
+// #[struct_args(range(lo, hi))], both ends inclusive
+struct Range {
+    lo: Expr,
+    hi: Expr,
+}
+
+impl Parse for Range {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ident: Ident = input.parse()?;
+        if ident != "range" {
+            return Err(syn::Error::new(ident.span(), "expected range(lo, hi)"));
+        }
+        let content;
+        parenthesized!(content in input);
+        let lo = content.parse()?;
+        content.parse::<Token![,]>()?;
+        let hi = content.parse()?;
+        Ok(Self { lo, hi })
+    }
+}
+
+fn field_range(f: &Field) -> Option<Range> {
+    f.attrs
+        .iter()
+        .find(|a| a.path.is_ident("struct_args"))
+        .map(|a| a.parse_args::<Range>().unwrap())
+}
+
+fn range_check(f: &Field, val: TokenStream) -> TokenStream {
+    match field_range(f) {
+        Some(Range { lo, hi }) => quote_spanned! {f.span()=>
+            if !(#lo..=#hi).contains(#val) {
+                anyhow::bail!("{} is not in the range {} to {}", #val, #lo, #hi)
+            }
+        },
+        None => quote!(),
+    }
+}
+
+fn parse_field(f: &Field) -> TokenStream {
+    match field_kind(f) {
+        FieldKind::Plain => {
+            let ty = &f.ty;
+            let check = range_check(f, quote!(&val));
+            quote_spanned! {f.span()=>
+                {
+                    let val: #ty = struct_args::Arg::parse_args(args)?;
+                    #check
+                    val
+                }
+            }
+        }
+        FieldKind::Optional(ty) => {
+            let check = range_check(f, quote!(&val));
+            quote_spanned! {f.span()=>
+                {
+                    let mut peek = args.clone();
+                    match <#ty as struct_args::Arg>::parse_args(&mut peek) {
+                        Ok(val) => {
+                            #check
+                            *args = peek;
+                            Some(val)
+                        }
+                        Err(_) => None,
+                    }
+                }
+            }
+        }
+        FieldKind::Flag(names) => quote_spanned! {f.span()=>
+            match args.last().map(|x| x.as_str()) {
+                Some(#( #names )|*) => {
+                    args.pop();
+                    true
+                }
+                _ => false,
+            }
+        },
+    }
+}
+
+fn field_usage(f: &Field) -> TokenStream {
+    let ty = &f.ty;
+    match field_kind(f) {
+        FieldKind::Plain => quote! {
+            <#ty as struct_args::Arg>::usage()
+        },
+        FieldKind::Optional(ty) => quote! {
+            <#ty as struct_args::Arg>::usage()
+                .into_iter()
+                .map(|x| struct_args::Usage::new(format!("[{}]", x.args), x.help))
+                .collect::<Vec<_>>()
+        },
+        FieldKind::Flag(names) => {
+            let names = format!("[{}]", names.join("|"));
+            quote! {
+                struct_args::Usage::arg(#names)
+            }
+        }
+    }
+}
+
 fn parse_fields(fields: &Fields) -> TokenStream {
     match fields {
         Fields::Named(ref fields) => {
             let recurse = fields.named.iter().map(|f| {
                 let name = &f.ident;
+                let parse = parse_field(f);
                 quote_spanned! {f.span()=>
-                    #name: struct_args::Arg::parse_args(args)?
+                    #name: #parse
                 }
             });
             quote! {
@@ -58,12 +205,7 @@ fn parse_fields(fields: &Fields) -> TokenStream {
             }
         }
         Fields::Unnamed(ref fields) => {
-            let recurse = fields.unnamed.iter().enumerate().map(|(i, f)| {
-                let _index = Index::from(i);
-                quote_spanned! {f.span()=>
-                    struct_args::Arg::parse_args(args)?
-                }
-            });
+            let recurse = fields.unnamed.iter().map(parse_field);
             quote! {
                 (#(#recurse, )*)
             }
@@ -74,6 +216,80 @@ fn parse_fields(fields: &Fields) -> TokenStream {
     }
 }
 
+fn doc_string(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("doc"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(Meta::NameValue(meta)) => match meta.lit {
+                Lit::Str(doc) => Some(doc.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn fields_usage(fields: &Fields, args: &str, help: &str) -> TokenStream {
+    let usages = fields.iter().map(field_usage);
+    quote! {
+        {
+            let mut lines = vec![struct_args::Usage::new(#args, #help)];
+            #( lines = struct_args::Usage::chain(lines, #usages); )*
+            lines
+        }
+    }
+}
+
+fn match_items(attrs: &[Attribute]) -> Vec<Item> {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("struct_args_match"))
+        .flat_map(|a| {
+            a.parse_args_with(Punctuated::<Item, Token![,]>::parse_terminated)
+                .unwrap()
+        })
+        .collect()
+}
+
+fn match_names(v: &syn::Variant) -> Vec<String> {
+    let mut names = vec![];
+    let mut no_default = false;
+    for item in match_items(&v.attrs) {
+        match item {
+            Item::LitStr(name) => names.push(name.value()),
+            Item::Ident(ident) => {
+                if ident == "ND" {
+                    no_default = true;
+                }
+            }
+        }
+    }
+    if !no_default {
+        names.push(v.ident.to_string().to_lowercase())
+    }
+    names
+}
+
+fn arg_usage(data: &Data, help: &str) -> TokenStream {
+    match *data {
+        Data::Struct(ref data) => fields_usage(&data.fields, "", help),
+        Data::Enum(ref data) => {
+            let recurse = data
+                .variants
+                .iter()
+                .map(|v| fields_usage(&v.fields, &match_names(v).join("|"), &doc_string(&v.attrs)));
+            quote! {
+                let mut lines = Vec::new();
+                #( lines.extend(#recurse); )*
+                lines
+            }
+        }
+        Data::Union(_) => unimplemented!(),
+    }
+}
+
 enum Item {
     LitStr(LitStr),
     Ident(Ident),
@@ -106,31 +322,9 @@ fn arg_parse(data: &Data) -> TokenStream {
                 .variants
                 .iter()
                 .map(|v| {
-                    let mut names = vec![];
-                    let mut no_default = false;
-                    for item in v
-                        .attrs
-                        .iter()
-                        .filter(|a| a.path.is_ident("struct_args_match"))
-                        .flat_map(|a| {
-                            a.parse_args_with(Punctuated::<Item, Token![,]>::parse_terminated)
-                                .unwrap()
-                        })
-                    {
-                        match item {
-                            Item::LitStr(name) => names.push(name.value()),
-                            Item::Ident(ident) => {
-                                if ident == "ND" {
-                                    no_default = true;
-                                }
-                            }
-                        }
-                    }
+                    let names = match_names(v);
                     let name = &v.ident;
                     let data = parse_fields(&v.fields);
-                    if !no_default {
-                        names.push(name.to_string().to_lowercase())
-                    }
                     let out = quote_spanned! {v.span()=>
                         #( #names )|* => Self::#name#data
                     };
@@ -0,0 +1,237 @@
+// the wm binary against a nested x server, every test is skipped when there is no Xvfb or Xephyr
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use cwm::connections::{ClientRequest, CwmResponse, TagSelection};
+use cwm::testing::{DummyClient, NestedServer, TestWm};
+
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn start(width: u16, height: u16) -> Result<Option<(NestedServer, TestWm)>> {
+    if !NestedServer::available() {
+        eprintln!("neither Xvfb nor Xephyr found, skipping");
+        return Ok(None);
+    }
+    let server = NestedServer::start(width, height)?;
+    let wm = TestWm::start(Path::new(env!("CARGO_BIN_EXE_cwm")), &server)?;
+    Ok(Some((server, wm)))
+}
+
+// the wm handles x events and requests from other clients in its own time
+fn eventually(what: &str, mut done: impl FnMut() -> Result<bool>) -> Result<()> {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    while !done()? {
+        if Instant::now() > deadline {
+            bail!("timed out waiting for {}", what)
+        }
+        sleep(Duration::from_millis(20));
+    }
+    Ok(())
+}
+
+fn tag_windows(wm: &TestWm, tag: usize) -> Result<Vec<u32>> {
+    let tag = || TagSelection::Index(tag);
+    let stack = match wm.request(&ClientRequest::ViewStack(tag()))? {
+        Some(CwmResponse::ViewStack(stack)) => stack,
+        response => bail!("expected a stack, got {:?}", response),
+    };
+    // freed client slots keep the window of whatever used them last
+    match wm.request(&ClientRequest::ViewClients(tag()))? {
+        Some(CwmResponse::ViewClients(clients)) => Ok(clients
+            .into_iter()
+            .filter(|(i, _, _)| stack.contains(i))
+            .map(|(_, win, _)| win)
+            .collect()),
+        response => bail!("expected clients, got {:?}", response),
+    }
+}
+
+fn managed_win(wm: &TestWm, win: u32) -> Result<bool> {
+    let tags = match wm.request(&ClientRequest::TagState)? {
+        Some(CwmResponse::TagState(tags, _)) => tags.len(),
+        response => bail!("expected tag state, got {:?}", response),
+    };
+    for tag in 0..tags {
+        if tag_windows(wm, tag)?.contains(&win) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn managed(wm: &TestWm, client: &DummyClient) -> Result<bool> {
+    managed_win(wm, client.win)
+}
+
+fn monitors(wm: &TestWm) -> Result<Vec<String>> {
+    match wm.request(&ClientRequest::ViewMonitorLayers)? {
+        Some(CwmResponse::MonitorLayers(monitors)) => {
+            Ok(monitors.into_iter().map(|mon| mon.monitor).collect())
+        }
+        response => bail!("expected monitor layers, got {:?}", response),
+    }
+}
+
+fn focus_tag(wm: &TestWm, tag: TagSelection) -> Result<()> {
+    wm.request(&ClientRequest::FocusTag(None, tag, false, None))?;
+    Ok(())
+}
+
+#[test]
+fn windows_are_managed_when_mapped_and_dropped_when_gone() -> Result<()> {
+    let (server, wm) = match start(1000, 600)? {
+        Some(started) => started,
+        None => return Ok(()),
+    };
+    let a = DummyClient::new(&server, "a")?;
+    let b = DummyClient::new(&server, "b")?;
+    assert!(!managed(&wm, &a)?);
+    a.map()?;
+    b.map()?;
+    eventually("a and b to be managed", || {
+        Ok(managed(&wm, &a)? && managed(&wm, &b)?)
+    })?;
+    assert!(a.is_viewable()? && b.is_viewable()?);
+    wm.check()?;
+
+    a.unmap()?;
+    eventually("a to be unmanaged", || Ok(!managed(&wm, &a)?))?;
+    wm.check()?;
+
+    let win = b.win;
+    drop(b);
+    eventually("b to be unmanaged", || Ok(!managed_win(&wm, win)?))?;
+    wm.check()
+}
+
+#[test]
+fn switching_tags_hides_and_shows_their_windows() -> Result<()> {
+    let (server, wm) = match start(1000, 600)? {
+        Some(started) => started,
+        None => return Ok(()),
+    };
+    let first = TagSelection::Index(0);
+    let second = || TagSelection::Name("second".into());
+    wm.request(&ClientRequest::AddTag("second".into(), false))?;
+    let a = DummyClient::new(&server, "a")?;
+    a.map()?;
+    eventually("a to be managed", || managed(&wm, &a))?;
+
+    focus_tag(&wm, second())?;
+    eventually("a to be hidden", || Ok(!a.is_viewable()?))?;
+    let b = DummyClient::new(&server, "b")?;
+    b.map()?;
+    eventually("b to be shown", || b.is_viewable())?;
+    wm.check()?;
+
+    focus_tag(&wm, first)?;
+    eventually(
+        "a to be shown",
+        || Ok(a.is_viewable()? && !b.is_viewable()?),
+    )?;
+
+    // a follows b to the second tag
+    wm.request(&ClientRequest::SetWindowTag(
+        Some(a.win),
+        second(),
+        false,
+        false,
+    ))?;
+    eventually("a to be moved", || Ok(!a.is_viewable()?))?;
+    focus_tag(&wm, second())?;
+    eventually("a and b to be shown", || {
+        Ok(a.is_viewable()? && b.is_viewable()?)
+    })?;
+    wm.check()
+}
+
+#[test]
+fn monitors_follow_hotplug() -> Result<()> {
+    let (server, wm) = match start(2000, 600)? {
+        Some(started) => started,
+        None => return Ok(()),
+    };
+    let before = monitors(&wm)?;
+    let a = DummyClient::new(&server, "a")?;
+    a.map()?;
+    eventually("a to be managed", || managed(&wm, &a))?;
+
+    server.set_monitor("LEFT", 0, 0, 1000, 600)?;
+    eventually("LEFT to be added", || {
+        Ok(monitors(&wm)?.iter().any(|mon| mon == "LEFT"))
+    })?;
+    assert_eq!(monitors(&wm)?.len(), before.len() + 1);
+    wm.check()?;
+
+    // resized in place
+    server.set_monitor("LEFT", 0, 0, 800, 600)?;
+    wm.sync()?;
+    wm.check()?;
+
+    server.remove_monitor("LEFT")?;
+    eventually("LEFT to be removed", || Ok(monitors(&wm)? == before))?;
+    assert!(managed(&wm, &a)?);
+    wm.check()
+}
+
+// sends a request on its own connection and waits for the answer
+fn ask(wm: &TestWm, request: &ClientRequest) -> Result<CwmResponse> {
+    let mut stream = wm.connect()?;
+    if !stream.send(request) {
+        bail!("could not send {:?}", request)
+    }
+    match stream.recieve_timeout(SETTLE_TIMEOUT) {
+        Some(response) => Ok(response),
+        None => bail!("no answer to {:?}", request),
+    }
+}
+
+#[test]
+fn dozens_of_simultaneous_clients_are_all_answered() -> Result<()> {
+    let (server, wm) = match start(1000, 600)? {
+        Some(started) => started,
+        None => return Ok(()),
+    };
+    wm.request(&ClientRequest::AddTag("second".into(), false))?;
+    let a = DummyClient::new(&server, "a")?;
+    a.map()?;
+    eventually("a to be managed", || managed(&wm, &a))?;
+    // subscribed to every tag switch below and never reading
+    let mut subscriber = wm.connect()?;
+    subscriber.send(&ClientRequest::TagState);
+
+    let (wm, win) = (&wm, a.win);
+    let failures: Vec<String> = std::thread::scope(|scope| {
+        let clients: Vec<_> = (0..48)
+            .map(|i| {
+                scope.spawn(move || -> Result<()> {
+                    let tag = match i % 2 {
+                        0 => TagSelection::Index(0),
+                        _ => TagSelection::Name("second".into()),
+                    };
+                    for _ in 0..5 {
+                        let mut stream = wm.connect()?;
+                        stream.send(&ClientRequest::FocusTag(None, tag.clone(), false, None));
+                        match ask(wm, &ClientRequest::GetFlags(Some(win)))? {
+                            CwmResponse::Flags(Some(_)) => (),
+                            response => bail!("expected flags, got {:?}", response),
+                        }
+                    }
+                    match ask(wm, &ClientRequest::Ping)? {
+                        CwmResponse::Pong => Ok(()),
+                        response => bail!("expected pong, got {:?}", response),
+                    }
+                })
+            })
+            .collect();
+        clients
+            .into_iter()
+            .filter_map(|client| client.join().unwrap().err())
+            .map(|e| e.to_string())
+            .collect()
+    });
+    assert!(failures.is_empty(), "{:#?}", failures);
+    wm.check()
+}